@@ -0,0 +1,26 @@
+use gamemaker_plus::lsp::{read_message, write_message, Server};
+
+fn main() {
+    // Panics are caught and reported to the editor, not printed over the
+    // protocol's output.
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    let mut server = Server::new();
+
+    while let Some(message) = read_message(&mut input) {
+        match message {
+            Ok(message) => {
+                for reply in server.handle(&message) {
+                    write_message(&mut output, &reply).expect("Couldn't write to the client");
+                }
+            }
+            Err(error) => eprintln!("error: {error}"),
+        }
+
+        if let Some(code) = server.exit_code {
+            std::process::exit(code);
+        }
+    }
+}
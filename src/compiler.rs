@@ -1,3 +1,19 @@
 pub mod ast;
+pub mod attributes;
+pub mod checker;
+pub mod codegen;
+pub mod declarations;
+pub mod driver;
+pub mod error;
+pub mod formatter;
+pub mod incremental;
+pub mod ir;
+pub mod lint;
+pub mod migrate;
+pub mod optimizer;
 pub mod parser;
+pub mod printer;
+pub mod source_map;
+pub mod target;
+pub mod testing;
 pub mod tokenizer;
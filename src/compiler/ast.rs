@@ -6,111 +6,900 @@ pub enum Node {
     Panic,
     Ignore,
     BinaryExpression(Box<Node>, Box<Node>, Box<Node>),
-    NumericLiteral(i32),
+    /// The value and how it was written, like `$FF` or `1.50`.
+    NumericLiteral(f64, String),
+    StringLiteral(String),
+    BooleanLiteral(bool),
+    TemplateString(Vec<Box<Node>>),
+    TemplateExpression(Box<Node>, Option<usize>),
     Identifier(String),
     BinaryOperator(String),
-    VariableDeclaration(Box<Node>, String, Box<Node>),
+    VariableDeclaration(Box<Node>, String, Option<Box<Node>>, Box<Node>),
     DeclarationType(TokenType),
     FunctionCall(Box<Node>, Box<Node>),
-    FunctionParameter(String),
+    FunctionParameter(String, Option<Box<Node>>),
+    OptionalParameter(Box<Node>),
+    RestParameter(Box<Node>),
+    /// A parameter and the value it takes when the argument is left out.
+    DefaultParameter(Box<Node>, Box<Node>),
     Arguments(Vec<Box<Node>>),
-    FunctionDeclaration(String, Vec<Box<Node>>, Box<Node>),
+    FunctionDeclaration(String, Vec<Box<Node>>, Option<Box<Node>>, Box<Node>),
+    TypeName(String, Vec<Box<Node>>),
+    FunctionType(Vec<Box<Node>>, Box<Node>),
+    ExternFunction(String, Vec<Box<Node>>, Option<Box<Node>>),
+    ExternVariable(Box<Node>, String, Box<Node>),
     ReturnStatement(Box<Node>),
+    ImportDeclaration(Vec<String>, String),
+    ExportDeclaration(Box<Node>),
+    /// `@name` before a declaration, with its arguments.
+    Attribute(String, Vec<Box<Node>>),
+    /// A declaration and the attributes written before it.
+    Attributed(Vec<Box<Node>>, Box<Node>),
+    UnaryExpression(String, Box<Node>),
+    /// `i++` or `--i`, the flag tells whether the operator comes first.
+    UpdateExpression(String, Box<Node>, bool),
+    TernaryExpression(Box<Node>, Box<Node>, Box<Node>),
+    MemberAccess(Box<Node>, String),
+    /// `value[index]`, with the accessor symbol of `list[| i]`, `map[? key]`...
+    IndexAccess(Box<Node>, Option<String>, Vec<Box<Node>>),
+    ArrayLiteral(Vec<Box<Node>>),
+    StructLiteral(Vec<(String, Box<Node>)>),
+    FunctionExpression(Vec<Box<Node>>, Box<Node>),
+    /// `fn(params) -> type => expr` or `fn(params) { ... }`, the body being
+    /// a `Program` for the latter.
+    ArrowFunction(Vec<Box<Node>>, Option<Box<Node>>, Box<Node>),
+    /// Fields, methods and the `new` constructor of a class.
+    ClassDeclaration(String, Vec<Box<Node>>),
+    /// `name: type = value;` inside a class.
+    ClassField(String, Option<Box<Node>>, Option<Box<Node>>),
+    NewExpression(Box<Node>),
+    Assignment(Box<Node>, String, Box<Node>),
+    /// `var a = 1, b = 2;` declares several variables in one statement.
+    DeclarationList(Vec<Box<Node>>),
+    GlobalVarDeclaration(Vec<String>),
+    /// Name and the raw text of the value, which may not be an expression.
+    MacroDeclaration(String, String),
+    EnumDeclaration(String, Vec<(String, Option<Box<Node>>)>),
+    /// A function declared with `constructor`, and the call to its parent.
+    ConstructorDeclaration(Box<Node>, Option<Box<Node>>),
+    Block(Vec<Box<Node>>),
+    IfStatement(Box<Node>, Box<Node>, Option<Box<Node>>),
+    WhileStatement(Box<Node>, Box<Node>),
+    DoUntilStatement(Box<Node>, Box<Node>),
+    RepeatStatement(Box<Node>, Box<Node>),
+    ForStatement(
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Box<Node>,
+    ),
+    WithStatement(Box<Node>, Box<Node>),
+    SwitchStatement(Box<Node>, Vec<Box<Node>>),
+    /// A `case value:` or, without a value, `default:`.
+    SwitchCase(Option<Box<Node>>, Vec<Box<Node>>),
+    /// `try { ... } catch (e) { ... } finally { ... }`, where either of
+    /// `catch`, with the name of the exception, and `finally` can be left out.
+    TryStatement(Box<Node>, Option<(String, Box<Node>)>, Option<Box<Node>>),
+    ThrowStatement(Box<Node>),
+    /// `delete value;`, which frees a struct by dropping the reference.
+    DeleteStatement(Box<Node>),
+    Break,
+    Continue,
+    Exit,
+    /// A comment kept by the formatter, and whether it ends the line of the
+    /// statement before it.
+    Comment(String, bool),
+    BlankLine,
 }
 
 impl Node {
+    /// Prints the tree, one node per line.
     pub fn display_program(self: &Node, indent: usize) {
+        print!("{}", self.program_tree(indent));
+    }
+
+    /// The tree as `display_program` prints it.
+    pub fn program_tree(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.write_program(&mut out, indent);
+        out
+    }
+
+    fn write_program(&self, out: &mut String, indent: usize) {
+        macro_rules! emit {
+            ($($arg:tt)*) => {{
+                out.push_str(&format!($($arg)*));
+                out.push('\n');
+            }};
+        }
+
         const INDENT_SIZE: usize = 2;
         let indent_space = " ".repeat(indent * INDENT_SIZE);
         match self {
             Node::Program(body) => {
-                println!("{}Program {{{indent}", indent_space);
+                emit!("{}Program {{{indent}", indent_space);
                 for node in body {
-                    node.display_program(indent + 1);
+                    node.write_program(out, indent + 1);
                 }
-                println!("{}}}{indent}", indent_space);
+                emit!("{}}}{indent}", indent_space);
             }
             Node::Panic | Node::Ignore => {
                 panic!("Trying to display a node that shouldn't exist: {:?}", self)
             }
             Node::BinaryExpression(left, operator, right) => {
-                println!("{}Binary Expression {{{indent}", indent_space);
-                left.display_program(indent + 1);
-                operator.display_program(indent + 1);
-                right.display_program(indent + 1);
-                println!("{}}}{indent}", indent_space)
+                emit!("{}Binary Expression {{{indent}", indent_space);
+                left.write_program(out, indent + 1);
+                operator.write_program(out, indent + 1);
+                right.write_program(out, indent + 1);
+                emit!("{}}}{indent}", indent_space)
+            }
+            Node::NumericLiteral(numb, _) => {
+                emit!("{}NumericLiteral: {}", indent_space, numb)
+            }
+            Node::StringLiteral(txt) => {
+                emit!("{}StringLiteral: \"{}\"", indent_space, txt)
+            }
+            Node::BooleanLiteral(value) => emit!("{indent_space}BooleanLiteral: {value}"),
+            Node::TemplateString(parts) => {
+                emit!("{indent_space}TemplateString {{{indent}");
+                for part in parts {
+                    part.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
             }
-            Node::NumericLiteral(numb) => {
-                println!("{}NumericLiteral: {}", indent_space, numb)
+            Node::TemplateExpression(expr, precision) => {
+                emit!("{indent_space}TemplateExpression {{{indent}");
+                expr.write_program(out, indent + 1);
+                if let Some(precision) = precision {
+                    emit!(
+                        "{indent_space}{}Precision: {precision}",
+                        " ".repeat(INDENT_SIZE)
+                    );
+                }
+                emit!("{indent_space}}}{indent}");
             }
             Node::Identifier(id) => {
-                println!("{}Identifier: {}", indent_space, id)
+                emit!("{}Identifier: {}", indent_space, id)
             }
             Node::BinaryOperator(operator) => {
-                println!("{}Binary Operator: {}", indent_space, operator)
+                emit!("{}Binary Operator: {}", indent_space, operator)
             }
-            Node::VariableDeclaration(declaration_type, name, expression) => {
-                println!("{indent_space}Variable Declaration {{{indent}");
-                declaration_type.display_program(indent + 1);
-                println!(
+            Node::VariableDeclaration(declaration_type, name, annotation, expression) => {
+                emit!("{indent_space}Variable Declaration {{{indent}");
+                declaration_type.write_program(out, indent + 1);
+                emit!(
                     "{}Name: {}",
-                    indent_space.clone() + " ".repeat(INDENT_SIZE.clone()).as_str(),
+                    indent_space.clone() + " ".repeat(INDENT_SIZE).as_str(),
                     name
                 );
-                println!(
+                if let Some(annotation) = annotation {
+                    annotation.write_program(out, indent + 1);
+                }
+                emit!(
                     "{}Expression {{{}",
                     indent_space.to_string() + " ".repeat(INDENT_SIZE).as_str(),
                     indent + 1
                 );
-                expression.display_program(indent + 2);
-                println!(
+                expression.write_program(out, indent + 2);
+                emit!(
                     "{}}}{}",
                     indent_space.to_string() + " ".repeat(INDENT_SIZE).as_str(),
                     indent + 1
                 );
-                println!("{indent_space}}}{indent}");
+                emit!("{indent_space}}}{indent}");
             }
-            Node::DeclarationType(token) => println!("{indent_space}DeclarationType: {:?}", token),
+            Node::DeclarationType(token) => emit!("{indent_space}DeclarationType: {:?}", token),
             Node::FunctionCall(identifier, args) => {
-                println!("{indent_space}FunctionCall {{{indent}");
-                identifier.display_program(indent + 1);
-                args.display_program(indent + 1);
-                println!("{indent_space}}}{indent}");
-            }
-            Node::FunctionDeclaration(name, params, code) => {
-                println!("{indent_space}FunctionDeclaration {{{indent}");
-                println!("{indent_space}{}name: {name}", " ".repeat(INDENT_SIZE));
-                println!(
+                emit!("{indent_space}FunctionCall {{{indent}");
+                identifier.write_program(out, indent + 1);
+                args.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::FunctionDeclaration(name, params, return_type, code) => {
+                emit!("{indent_space}FunctionDeclaration {{{indent}");
+                emit!("{indent_space}{}name: {name}", " ".repeat(INDENT_SIZE));
+                emit!(
                     "{indent_space}{}Parameters: [{}",
                     " ".repeat(INDENT_SIZE),
                     indent + 1
                 );
                 for param in params {
-                    param.display_program(indent + 2);
+                    param.write_program(out, indent + 2);
                 }
-                println!("{indent_space}{}]{}", " ".repeat(INDENT_SIZE), indent + 1);
-                code.display_program(indent + 1);
-                println!("{indent_space}}}{indent}");
+                emit!("{indent_space}{}]{}", " ".repeat(INDENT_SIZE), indent + 1);
+                if let Some(return_type) = return_type {
+                    return_type.write_program(out, indent + 1);
+                }
+                code.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::FunctionParameter(name, annotation) => match annotation {
+                Some(annotation) => emit!(
+                    "{indent_space}FunctionParameter: {name}: {}",
+                    annotation.display_type()
+                ),
+                None => emit!("{indent_space}FunctionParameter: {name}"),
+            },
+            Node::OptionalParameter(param) => {
+                emit!("{indent_space}Optional {{{indent}");
+                param.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
             }
-            Node::FunctionParameter(name) => {
-                println!("{indent_space}FunctionParameter: {name}");
+            Node::RestParameter(param) => {
+                emit!("{indent_space}Rest {{{indent}");
+                param.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::DefaultParameter(param, default) => {
+                emit!("{indent_space}Default {{{indent}");
+                param.write_program(out, indent + 1);
+                default.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::TypeName(_, _) | Node::FunctionType(_, _) => {
+                emit!("{indent_space}Type: {}", self.display_type());
+            }
+            Node::ExternFunction(name, params, return_type) => {
+                emit!("{indent_space}ExternFunction {{{indent}");
+                emit!("{indent_space}{}name: {name}", " ".repeat(INDENT_SIZE));
+                for param in params {
+                    param.write_program(out, indent + 1);
+                }
+                if let Some(return_type) = return_type {
+                    return_type.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ExternVariable(declaration_type, name, annotation) => {
+                emit!("{indent_space}ExternVariable {{{indent}");
+                declaration_type.write_program(out, indent + 1);
+                emit!("{indent_space}{}name: {name}", " ".repeat(INDENT_SIZE));
+                annotation.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
             }
             Node::Arguments(args) => {
-                println!("{indent_space}Arguments {{{indent}");
+                emit!("{indent_space}Arguments {{{indent}");
                 for arg in args {
-                    arg.display_program(indent + 1);
+                    arg.write_program(out, indent + 1);
                 }
-                println!("{indent_space}}}{indent}");
+                emit!("{indent_space}}}{indent}");
             }
             Node::ReturnStatement(expr) => {
-                println!("{indent_space}Return Statement {{{indent}");
-                expr.display_program(indent + 1);
-                println!("{indent_space}}}{indent}");
+                emit!("{indent_space}Return Statement {{{indent}");
+                expr.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ImportDeclaration(names, module) => {
+                emit!(
+                    "{indent_space}Import: {{{}}} from \"{module}\"",
+                    names.join(", ")
+                );
+            }
+            Node::ExportDeclaration(declaration) => {
+                emit!("{indent_space}Export {{{indent}");
+                declaration.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::Attribute(name, args) if args.is_empty() => {
+                emit!("{indent_space}Attribute: @{name}")
+            }
+            Node::Attribute(name, args) => {
+                emit!("{indent_space}Attribute: @{name} {{{indent}");
+                for arg in args {
+                    arg.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::Attributed(attributes, declaration) => {
+                emit!("{indent_space}Attributed {{{indent}");
+                for attribute in attributes {
+                    attribute.write_program(out, indent + 1);
+                }
+                declaration.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::UnaryExpression(operator, operand) => {
+                emit!("{indent_space}Unary Expression: {operator} {{{indent}");
+                operand.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::UpdateExpression(operator, target, prefix) => {
+                let position = if *prefix { "prefix" } else { "postfix" };
+                emit!("{indent_space}Update Expression: {operator} ({position}) {{{indent}");
+                target.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => {
+                emit!("{indent_space}Ternary Expression {{{indent}");
+                condition.write_program(out, indent + 1);
+                if_true.write_program(out, indent + 1);
+                if_false.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::MemberAccess(object, member) => {
+                emit!("{indent_space}MemberAccess: .{member} {{{indent}");
+                object.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::IndexAccess(value, accessor, indices) => {
+                let accessor = accessor.as_deref().unwrap_or("");
+                emit!("{indent_space}IndexAccess: [{accessor}] {{{indent}");
+                value.write_program(out, indent + 1);
+                for index in indices {
+                    index.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ArrayLiteral(items) | Node::Block(items) => {
+                let name = if let Node::Block(_) = self {
+                    "Block"
+                } else {
+                    "ArrayLiteral"
+                };
+                emit!("{indent_space}{name} {{{indent}");
+                for item in items {
+                    item.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::StructLiteral(fields) => {
+                emit!("{indent_space}StructLiteral {{{indent}");
+                for (name, value) in fields {
+                    emit!("{indent_space}{}{name}:", " ".repeat(INDENT_SIZE));
+                    value.write_program(out, indent + 2);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::FunctionExpression(params, code) => {
+                emit!("{indent_space}FunctionExpression {{{indent}");
+                for param in params {
+                    param.write_program(out, indent + 1);
+                }
+                code.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ArrowFunction(params, return_type, code) => {
+                emit!("{indent_space}ArrowFunction {{{indent}");
+                for param in params {
+                    param.write_program(out, indent + 1);
+                }
+                if let Some(return_type) = return_type {
+                    return_type.write_program(out, indent + 1);
+                }
+                code.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ClassDeclaration(name, members) => {
+                emit!("{indent_space}Class: {name} {{{indent}");
+                for member in members {
+                    member.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ClassField(name, annotation, value) => {
+                match annotation {
+                    Some(annotation) => emit!(
+                        "{indent_space}Field: {name}: {} {{{indent}",
+                        annotation.display_type()
+                    ),
+                    None => emit!("{indent_space}Field: {name} {{{indent}"),
+                }
+                if let Some(value) = value {
+                    value.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::NewExpression(call) => {
+                emit!("{indent_space}New {{{indent}");
+                call.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::Assignment(target, operator, value) => {
+                emit!("{indent_space}Assignment: {operator} {{{indent}");
+                target.write_program(out, indent + 1);
+                value.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::DeclarationList(declarations) => {
+                for declaration in declarations {
+                    declaration.write_program(out, indent);
+                }
+            }
+            Node::GlobalVarDeclaration(names) => {
+                emit!("{indent_space}GlobalVar: {}", names.join(", "));
+            }
+            Node::MacroDeclaration(name, value) => {
+                emit!("{indent_space}Macro: {name} = {value}");
+            }
+            Node::EnumDeclaration(name, members) => {
+                emit!("{indent_space}Enum: {name} {{{indent}");
+                for (member, value) in members {
+                    emit!("{indent_space}{}{member}", " ".repeat(INDENT_SIZE));
+                    if let Some(value) = value {
+                        value.write_program(out, indent + 2);
+                    }
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ConstructorDeclaration(function, parent) => {
+                emit!("{indent_space}Constructor {{{indent}");
+                function.write_program(out, indent + 1);
+                if let Some(parent) = parent {
+                    parent.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                emit!("{indent_space}If {{{indent}");
+                condition.write_program(out, indent + 1);
+                then.write_program(out, indent + 1);
+                if let Some(otherwise) = otherwise {
+                    emit!("{indent_space}Else");
+                    otherwise.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::WhileStatement(first, second)
+            | Node::DoUntilStatement(first, second)
+            | Node::RepeatStatement(first, second)
+            | Node::WithStatement(first, second) => {
+                let name = match self {
+                    Node::WhileStatement(_, _) => "While",
+                    Node::DoUntilStatement(_, _) => "DoUntil",
+                    Node::RepeatStatement(_, _) => "Repeat",
+                    _ => "With",
+                };
+                emit!("{indent_space}{name} {{{indent}");
+                first.write_program(out, indent + 1);
+                second.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ForStatement(init, condition, step, code) => {
+                emit!("{indent_space}For {{{indent}");
+                for part in [init, condition, step].into_iter().flatten() {
+                    part.write_program(out, indent + 1);
+                }
+                code.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::SwitchStatement(value, cases) => {
+                emit!("{indent_space}Switch {{{indent}");
+                value.write_program(out, indent + 1);
+                for case in cases {
+                    case.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::SwitchCase(value, body) => {
+                match value {
+                    Some(value) => {
+                        emit!("{indent_space}Case {{{indent}");
+                        value.write_program(out, indent + 1);
+                    }
+                    None => emit!("{indent_space}Default {{{indent}"),
+                }
+                for stmt in body {
+                    stmt.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::TryStatement(body, catch, finally) => {
+                emit!("{indent_space}Try {{{indent}");
+                body.write_program(out, indent + 1);
+                if let Some((name, handler)) = catch {
+                    emit!("{indent_space}Catch: {name}");
+                    handler.write_program(out, indent + 1);
+                }
+                if let Some(finally) = finally {
+                    emit!("{indent_space}Finally");
+                    finally.write_program(out, indent + 1);
+                }
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::ThrowStatement(value) => {
+                emit!("{indent_space}Throw {{{indent}");
+                value.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
+            }
+            Node::DeleteStatement(value) => {
+                emit!("{indent_space}Delete {{{indent}");
+                value.write_program(out, indent + 1);
+                emit!("{indent_space}}}{indent}");
             }
+            Node::Break => emit!("{indent_space}Break"),
+            Node::Continue => emit!("{indent_space}Continue"),
+            Node::Exit => emit!("{indent_space}Exit"),
+            Node::Comment(text, _) => emit!("{indent_space}Comment: {text}"),
+            Node::BlankLine => emit!("{indent_space}BlankLine"),
         }
     }
 
-    pub fn to_box(&self) -> Box<Node> {
-        Box::new(self.to_owned())
+    /// Writes a type annotation back the way it is spelled in the source.
+    pub fn display_type(&self) -> String {
+        match self {
+            Node::TypeName(name, args) if name == "Array" && args.len() == 1 => {
+                format!("{}[]", args[0].display_type())
+            }
+            Node::TypeName(name, args) if args.is_empty() => name.clone(),
+            Node::TypeName(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.display_type()).collect();
+                format!("{name}<{}>", args.join(", "))
+            }
+            Node::FunctionType(params, return_type) => {
+                let params: Vec<String> = params.iter().map(|param| param.display_type()).collect();
+                format!("Fn({})<{}>", params.join(", "), return_type.display_type())
+            }
+            _ => format!("{:?}", self),
+        }
+    }
+
+    /// The declaration under `export` and attributes.
+    pub fn declaration(&self) -> &Node {
+        match self {
+            Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+                declaration.declaration()
+            }
+            node => node,
+        }
+    }
+
+    /// Same as `declaration`, for rewriting it in place.
+    pub fn declaration_mut(&mut self) -> &mut Node {
+        match self {
+            Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+                declaration.declaration_mut()
+            }
+            node => node,
+        }
+    }
+
+    /// Whether a top-level declaration is exported.
+    pub fn is_exported(&self) -> bool {
+        match self {
+            Node::ExportDeclaration(_) => true,
+            Node::Attributed(_, declaration) => declaration.is_exported(),
+            _ => false,
+        }
+    }
+
+    /// The attributes written before a declaration.
+    pub fn attributes(&self) -> &[Box<Node>] {
+        match self {
+            Node::Attributed(attributes, _) => attributes,
+            Node::ExportDeclaration(declaration) => declaration.attributes(),
+            _ => &[],
+        }
+    }
+
+    /// Name introduced by a top-level declaration, looking through `export`
+    /// and attributes. Local `var` and `static` variables don't count.
+    pub fn declared_name(&self) -> Option<&String> {
+        match self {
+            Node::VariableDeclaration(declaration_type, _, _, _)
+                if matches!(
+                    declaration_type.as_ref(),
+                    Node::DeclarationType(TokenType::Var | TokenType::Static)
+                ) =>
+            {
+                None
+            }
+            Node::VariableDeclaration(_, name, _, _)
+            | Node::FunctionDeclaration(name, _, _, _)
+            | Node::MacroDeclaration(name, _)
+            | Node::EnumDeclaration(name, _)
+            | Node::ClassDeclaration(name, _)
+            | Node::ExternFunction(name, _, _)
+            | Node::ExternVariable(_, name, _) => Some(name),
+            Node::ExportDeclaration(declaration)
+            | Node::Attributed(_, declaration)
+            | Node::ConstructorDeclaration(declaration, _) => declaration.declared_name(),
+            _ => None,
+        }
+    }
+
+    /// The nodes directly nested in this one, in source order.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Program(nodes)
+            | Node::TemplateString(nodes)
+            | Node::Arguments(nodes)
+            | Node::ArrayLiteral(nodes)
+            | Node::DeclarationList(nodes)
+            | Node::Block(nodes)
+            | Node::Attribute(_, nodes) => unbox(nodes),
+            Node::Attributed(attributes, declaration) => {
+                let mut children = unbox(attributes);
+                children.push(declaration);
+                children
+            }
+            Node::BinaryExpression(left, operator, right) => vec![left, operator, right],
+            Node::TemplateExpression(node, _)
+            | Node::OptionalParameter(node)
+            | Node::RestParameter(node)
+            | Node::ReturnStatement(node)
+            | Node::ExportDeclaration(node)
+            | Node::UnaryExpression(_, node)
+            | Node::UpdateExpression(_, node, _)
+            | Node::MemberAccess(node, _)
+            | Node::NewExpression(node)
+            | Node::ThrowStatement(node)
+            | Node::DeleteStatement(node) => vec![node],
+            Node::VariableDeclaration(declaration_type, _, annotation, expr) => {
+                let mut children = vec![declaration_type.as_ref()];
+                children.extend(annotation.as_deref());
+                children.push(expr);
+                children
+            }
+            Node::FunctionCall(first, second)
+            | Node::DefaultParameter(first, second)
+            | Node::Assignment(first, _, second)
+            | Node::WhileStatement(first, second)
+            | Node::DoUntilStatement(first, second)
+            | Node::RepeatStatement(first, second)
+            | Node::WithStatement(first, second) => vec![first, second],
+            Node::FunctionParameter(_, annotation) => annotation.as_deref().into_iter().collect(),
+            Node::FunctionDeclaration(_, params, return_type, code) => {
+                let mut children = unbox(params);
+                children.extend(return_type.as_deref());
+                children.push(code);
+                children
+            }
+            Node::TypeName(_, args) => unbox(args),
+            Node::FunctionType(params, return_type) => {
+                let mut children = unbox(params);
+                children.push(return_type);
+                children
+            }
+            Node::ExternFunction(_, params, return_type) => {
+                let mut children = unbox(params);
+                children.extend(return_type.as_deref());
+                children
+            }
+            Node::ExternVariable(declaration_type, _, annotation) => {
+                vec![declaration_type, annotation]
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => {
+                vec![condition, if_true, if_false]
+            }
+            Node::IndexAccess(value, _, indices) => {
+                let mut children = vec![value.as_ref()];
+                children.extend(unbox(indices));
+                children
+            }
+            Node::StructLiteral(fields) => fields.iter().map(|(_, value)| value.as_ref()).collect(),
+            Node::FunctionExpression(params, code) => {
+                let mut children = unbox(params);
+                children.push(code);
+                children
+            }
+            Node::ArrowFunction(params, return_type, code) => {
+                let mut children = unbox(params);
+                children.extend(return_type.as_deref());
+                children.push(code);
+                children
+            }
+            Node::ClassDeclaration(_, members) => unbox(members),
+            Node::ClassField(_, annotation, value) => {
+                let mut children: Vec<&Node> = annotation.as_deref().into_iter().collect();
+                children.extend(value.as_deref());
+                children
+            }
+            Node::EnumDeclaration(_, members) => members
+                .iter()
+                .filter_map(|(_, value)| value.as_deref())
+                .collect(),
+            Node::ConstructorDeclaration(function, parent) => {
+                let mut children = vec![function.as_ref()];
+                children.extend(parent.as_deref());
+                children
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                let mut children = vec![condition.as_ref(), then.as_ref()];
+                children.extend(otherwise.as_deref());
+                children
+            }
+            Node::ForStatement(init, condition, step, code) => {
+                let mut children: Vec<&Node> = [init, condition, step]
+                    .into_iter()
+                    .flatten()
+                    .map(|part| part.as_ref())
+                    .collect();
+                children.push(code);
+                children
+            }
+            Node::SwitchStatement(value, cases) => {
+                let mut children = vec![value.as_ref()];
+                children.extend(unbox(cases));
+                children
+            }
+            Node::SwitchCase(value, body) => {
+                let mut children: Vec<&Node> = value.as_deref().into_iter().collect();
+                children.extend(unbox(body));
+                children
+            }
+            Node::TryStatement(body, catch, finally) => {
+                let mut children = vec![body.as_ref()];
+                children.extend(catch.as_ref().map(|(_, handler)| handler.as_ref()));
+                children.extend(finally.as_deref());
+                children
+            }
+            Node::Panic
+            | Node::Ignore
+            | Node::NumericLiteral(_, _)
+            | Node::StringLiteral(_)
+            | Node::BooleanLiteral(_)
+            | Node::Identifier(_)
+            | Node::BinaryOperator(_)
+            | Node::DeclarationType(_)
+            | Node::ImportDeclaration(_, _)
+            | Node::GlobalVarDeclaration(_)
+            | Node::MacroDeclaration(_, _)
+            | Node::Break
+            | Node::Continue
+            | Node::Exit
+            | Node::Comment(_, _)
+            | Node::BlankLine => Vec::new(),
+        }
     }
+
+    /// Same as `children`, for rewriting the tree in place.
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        match self {
+            Node::Program(nodes)
+            | Node::TemplateString(nodes)
+            | Node::Arguments(nodes)
+            | Node::ArrayLiteral(nodes)
+            | Node::DeclarationList(nodes)
+            | Node::Block(nodes)
+            | Node::Attribute(_, nodes) => unbox_mut(nodes),
+            Node::Attributed(attributes, declaration) => {
+                let mut children = unbox_mut(attributes);
+                children.push(declaration.as_mut());
+                children
+            }
+            Node::BinaryExpression(left, operator, right) => vec![left.as_mut(), operator, right],
+            Node::TemplateExpression(node, _)
+            | Node::OptionalParameter(node)
+            | Node::RestParameter(node)
+            | Node::ReturnStatement(node)
+            | Node::ExportDeclaration(node)
+            | Node::UnaryExpression(_, node)
+            | Node::UpdateExpression(_, node, _)
+            | Node::MemberAccess(node, _)
+            | Node::NewExpression(node)
+            | Node::ThrowStatement(node)
+            | Node::DeleteStatement(node) => vec![node.as_mut()],
+            Node::VariableDeclaration(declaration_type, _, annotation, expr) => {
+                let mut children = vec![declaration_type.as_mut()];
+                children.extend(annotation.as_deref_mut());
+                children.push(expr);
+                children
+            }
+            Node::FunctionCall(first, second)
+            | Node::DefaultParameter(first, second)
+            | Node::Assignment(first, _, second)
+            | Node::WhileStatement(first, second)
+            | Node::DoUntilStatement(first, second)
+            | Node::RepeatStatement(first, second)
+            | Node::WithStatement(first, second) => vec![first.as_mut(), second],
+            Node::FunctionParameter(_, annotation) => {
+                annotation.as_deref_mut().into_iter().collect()
+            }
+            Node::FunctionDeclaration(_, params, return_type, code) => {
+                let mut children = unbox_mut(params);
+                children.extend(return_type.as_deref_mut());
+                children.push(code);
+                children
+            }
+            Node::TypeName(_, args) => unbox_mut(args),
+            Node::FunctionType(params, return_type) => {
+                let mut children = unbox_mut(params);
+                children.push(return_type.as_mut());
+                children
+            }
+            Node::ExternFunction(_, params, return_type) => {
+                let mut children = unbox_mut(params);
+                children.extend(return_type.as_deref_mut());
+                children
+            }
+            Node::ExternVariable(declaration_type, _, annotation) => {
+                vec![declaration_type.as_mut(), annotation]
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => {
+                vec![condition.as_mut(), if_true, if_false]
+            }
+            Node::IndexAccess(value, _, indices) => {
+                let mut children = vec![value.as_mut()];
+                children.extend(unbox_mut(indices));
+                children
+            }
+            Node::StructLiteral(fields) => {
+                fields.iter_mut().map(|(_, value)| value.as_mut()).collect()
+            }
+            Node::FunctionExpression(params, code) => {
+                let mut children = unbox_mut(params);
+                children.push(code);
+                children
+            }
+            Node::ArrowFunction(params, return_type, code) => {
+                let mut children = unbox_mut(params);
+                children.extend(return_type.as_deref_mut());
+                children.push(code);
+                children
+            }
+            Node::ClassDeclaration(_, members) => unbox_mut(members),
+            Node::ClassField(_, annotation, value) => {
+                let mut children: Vec<&mut Node> = annotation.as_deref_mut().into_iter().collect();
+                children.extend(value.as_deref_mut());
+                children
+            }
+            Node::EnumDeclaration(_, members) => members
+                .iter_mut()
+                .filter_map(|(_, value)| value.as_deref_mut())
+                .collect(),
+            Node::ConstructorDeclaration(function, parent) => {
+                let mut children = vec![function.as_mut()];
+                children.extend(parent.as_deref_mut());
+                children
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                let mut children = vec![condition.as_mut(), then.as_mut()];
+                children.extend(otherwise.as_deref_mut());
+                children
+            }
+            Node::ForStatement(init, condition, step, code) => {
+                let mut children: Vec<&mut Node> = [init, condition, step]
+                    .into_iter()
+                    .flatten()
+                    .map(|part| part.as_mut())
+                    .collect();
+                children.push(code);
+                children
+            }
+            Node::SwitchStatement(value, cases) => {
+                let mut children = vec![value.as_mut()];
+                children.extend(unbox_mut(cases));
+                children
+            }
+            Node::SwitchCase(value, body) => {
+                let mut children: Vec<&mut Node> = value.as_deref_mut().into_iter().collect();
+                children.extend(unbox_mut(body));
+                children
+            }
+            Node::TryStatement(body, catch, finally) => {
+                let mut children = vec![body.as_mut()];
+                children.extend(catch.as_mut().map(|(_, handler)| handler.as_mut()));
+                children.extend(finally.as_deref_mut());
+                children
+            }
+            Node::Panic
+            | Node::Ignore
+            | Node::NumericLiteral(_, _)
+            | Node::StringLiteral(_)
+            | Node::BooleanLiteral(_)
+            | Node::Identifier(_)
+            | Node::BinaryOperator(_)
+            | Node::DeclarationType(_)
+            | Node::ImportDeclaration(_, _)
+            | Node::GlobalVarDeclaration(_)
+            | Node::MacroDeclaration(_, _)
+            | Node::Break
+            | Node::Continue
+            | Node::Exit
+            | Node::Comment(_, _)
+            | Node::BlankLine => Vec::new(),
+        }
+    }
+
+    pub fn to_box(self) -> Box<Node> {
+        Box::new(self)
+    }
+}
+
+fn unbox(nodes: &[Box<Node>]) -> Vec<&Node> {
+    nodes.iter().map(|node| node.as_ref()).collect()
+}
+
+fn unbox_mut(nodes: &mut [Box<Node>]) -> Vec<&mut Node> {
+    nodes.iter_mut().map(|node| node.as_mut()).collect()
 }
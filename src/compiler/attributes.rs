@@ -0,0 +1,434 @@
+use crate::compiler::ast::Node;
+use crate::compiler::error::Error;
+use crate::compiler::parser::Positions;
+use crate::compiler::tokenizer::TokenType;
+
+/// The kinds of declaration an attribute can be written before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Function,
+    Constructor,
+    Class,
+    Variable,
+    Enum,
+    Macro,
+    Extern,
+}
+
+impl Target {
+    /// What kind of declaration a top-level statement is, looking through
+    /// `export` and its attributes.
+    pub fn of(stmt: &Node) -> Option<Target> {
+        match stmt.declaration() {
+            Node::FunctionDeclaration(_, _, _, _) => Some(Target::Function),
+            Node::ConstructorDeclaration(_, _) => Some(Target::Constructor),
+            Node::ClassDeclaration(_, _) => Some(Target::Class),
+            Node::VariableDeclaration(declaration_type, _, _, _) => match declaration_type.as_ref()
+            {
+                Node::DeclarationType(TokenType::Let | TokenType::Const) => Some(Target::Variable),
+                _ => None,
+            },
+            Node::EnumDeclaration(_, _) => Some(Target::Enum),
+            Node::MacroDeclaration(_, _) => Some(Target::Macro),
+            Node::ExternFunction(_, _, _) | Node::ExternVariable(_, _, _) => Some(Target::Extern),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Function => "a function",
+            Target::Constructor => "a constructor",
+            Target::Class => "a class",
+            Target::Variable => "a let or const variable",
+            Target::Enum => "an enum",
+            Target::Macro => "a macro",
+            Target::Extern => "an extern declaration",
+        }
+    }
+}
+
+/// What an attribute takes between parentheses.
+#[derive(Clone, Copy)]
+pub enum Arguments {
+    Nothing,
+    /// An optional string, like the message of `@deprecated("use foo")`.
+    OptionalString,
+    /// One name out of a list, like the event of `@event(Step)`.
+    OneOf(&'static [&'static str]),
+}
+
+/// An attribute the compiler knows.
+pub struct Attribute {
+    pub name: &'static str,
+    pub arguments: Arguments,
+    pub targets: &'static [Target],
+    pub description: &'static str,
+}
+
+const FUNCTIONS: &[Target] = &[Target::Function];
+const EVERYTHING: &[Target] = &[
+    Target::Function,
+    Target::Constructor,
+    Target::Class,
+    Target::Variable,
+    Target::Enum,
+    Target::Macro,
+    Target::Extern,
+];
+
+/// Object events a function can be the code of.
+pub const EVENTS: [&str; 20] = [
+    "Create",
+    "Destroy",
+    "CleanUp",
+    "Step",
+    "BeginStep",
+    "EndStep",
+    "Draw",
+    "DrawBegin",
+    "DrawEnd",
+    "DrawGUI",
+    "DrawGUIBegin",
+    "DrawGUIEnd",
+    "PreDraw",
+    "PostDraw",
+    "WindowResize",
+    "RoomStart",
+    "RoomEnd",
+    "GameStart",
+    "GameEnd",
+    "AnimationEnd",
+];
+
+pub const ATTRIBUTES: [Attribute; 7] = [
+    Attribute {
+        name: "inline",
+        arguments: Arguments::Nothing,
+        targets: FUNCTIONS,
+        description: "Inline the calls to the function at -O1 too, whatever its size",
+    },
+    Attribute {
+        name: "noinline",
+        arguments: Arguments::Nothing,
+        targets: FUNCTIONS,
+        description: "Never inline the calls to the function",
+    },
+    Attribute {
+        name: "pure",
+        arguments: Arguments::Nothing,
+        targets: &[Target::Function, Target::Extern],
+        description: "The function has no side effects",
+    },
+    Attribute {
+        name: "test",
+        arguments: Arguments::Nothing,
+        targets: FUNCTIONS,
+        description: "The function is a test",
+    },
+    Attribute {
+        name: "deprecated",
+        arguments: Arguments::OptionalString,
+        targets: EVERYTHING,
+        description: "Using the declaration is reported by the linter, with the message",
+    },
+    Attribute {
+        name: "global",
+        arguments: Arguments::Nothing,
+        targets: &[Target::Variable],
+        description: "Not supported yet, warned about: the variable belongs to the game",
+    },
+    Attribute {
+        name: "event",
+        arguments: Arguments::OneOf(&EVENTS),
+        targets: FUNCTIONS,
+        description: "Not supported yet, warned about: the function is the code of an object event",
+    },
+];
+
+pub fn find_attribute(name: &str) -> Option<&'static Attribute> {
+    ATTRIBUTES.iter().find(|attribute| attribute.name == name)
+}
+
+/// Checks that every attribute is known, takes the right arguments and is
+/// written once, before a top-level declaration of a kind it applies to.
+pub fn validate(program: &Node, positions: &Positions) -> Vec<Error> {
+    let mut found = Vec::new();
+    let Node::Program(body) = program else {
+        return found;
+    };
+
+    for stmt in body {
+        let mut errors = Vec::new();
+        let attributes = stmt.attributes();
+        if !attributes.is_empty() {
+            let name = stmt.declared_name().map_or("", String::as_str);
+            let target = Target::of(stmt);
+            let mut seen: Vec<&str> = Vec::new();
+
+            for attribute in attributes {
+                if let Node::Attribute(attribute, args) = attribute.as_ref() {
+                    if seen.contains(&attribute.as_str()) {
+                        errors.push(format!("'@{attribute}' is written twice on '{name}'"));
+                    }
+                    seen.push(attribute);
+                    check_attribute(attribute, args, target, &mut errors);
+                }
+            }
+
+            if seen.contains(&"inline") && seen.contains(&"noinline") {
+                errors.push(format!(
+                    "'@inline' and '@noinline' can't be used together on '{name}'"
+                ));
+            }
+        }
+
+        nested_attributes(stmt.declaration(), &mut errors);
+
+        let span = positions.get(&(stmt.as_ref() as *const Node)).copied();
+        found.extend(
+            errors
+                .into_iter()
+                .map(|error| Error::new(error).or_at(span)),
+        );
+    }
+
+    found
+}
+
+fn check_attribute(
+    name: &str,
+    args: &[Box<Node>],
+    target: Option<Target>,
+    errors: &mut Vec<String>,
+) {
+    let Some(attribute) = find_attribute(name) else {
+        errors.push(format!(
+            "Unknown attribute '@{name}', expected one of: {}",
+            ATTRIBUTES
+                .map(|known| format!("@{}", known.name))
+                .join(", ")
+        ));
+        return;
+    };
+
+    if let Some(target) = target.filter(|target| !attribute.targets.contains(target)) {
+        let targets: Vec<&str> = attribute
+            .targets
+            .iter()
+            .map(|target| target.name())
+            .collect();
+        errors.push(format!(
+            "'@{name}' can't be written before {}, only before {}",
+            target.name(),
+            targets.join(" or ")
+        ));
+    }
+
+    match attribute.arguments {
+        Arguments::Nothing if !args.is_empty() => {
+            errors.push(format!("'@{name}' takes no arguments"));
+        }
+        Arguments::OptionalString
+            if args.len() > 1
+                || args
+                    .iter()
+                    .any(|arg| !matches!(arg.as_ref(), Node::StringLiteral(_))) =>
+        {
+            errors.push(format!(
+                "'@{name}' takes at most one string, like @{name}(\"message\")"
+            ));
+        }
+        Arguments::OneOf(names) => {
+            let valid = match args {
+                [arg] => {
+                    matches!(arg.as_ref(), Node::Identifier(arg) if names.contains(&arg.as_str()))
+                }
+                _ => false,
+            };
+            if !valid {
+                errors.push(format!("'@{name}' takes one of: {}", names.join(", ")));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Warns about the attributes that are checked but don't change the output
+/// yet, so nobody relies on them doing what their name says.
+pub fn unsupported(program: &Node, positions: &Positions) -> Vec<Error> {
+    let mut found = Vec::new();
+    let Node::Program(body) = program else {
+        return found;
+    };
+
+    for stmt in body {
+        let name = stmt.declared_name().map_or("", String::as_str);
+        let span = positions.get(&(stmt.as_ref() as *const Node)).copied();
+        if has_attribute(stmt, "global") {
+            found.push(
+                Error::new(format!(
+                    "'@global' has no effect yet, '{name}' still belongs to the instance running it, use global.{name} to share it"
+                ))
+                .or_at(span),
+            );
+        }
+        if has_attribute(stmt, "event") {
+            found.push(
+                Error::new(format!(
+                    "'@event' has no effect yet, '{name}' is compiled as a plain function that has to be called from the event"
+                ))
+                .or_at(span),
+            );
+        }
+    }
+
+    found
+}
+
+/// Attributes inside functions and classes, which aren't supported.
+fn nested_attributes(node: &Node, errors: &mut Vec<String>) {
+    for child in node.children() {
+        if let Node::Attributed(_, declaration) = child {
+            errors.push(format!(
+                "Attributes can only be written before a top-level declaration, not before '{}'",
+                declaration.declared_name().map_or("", String::as_str)
+            ));
+        }
+        nested_attributes(child, errors);
+    }
+}
+
+/// Whether an attribute is written before a top-level statement.
+pub fn has_attribute(stmt: &Node, name: &str) -> bool {
+    stmt.attributes()
+        .iter()
+        .any(|attribute| matches!(attribute.as_ref(), Node::Attribute(known, _) if known == name))
+}
+
+/// The attributes of the top-level declarations of a program, for the passes
+/// that act on them.
+pub struct Registry<'a> {
+    declarations: Vec<(&'a str, &'a [Box<Node>])>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn collect(program: &'a Node) -> Registry<'a> {
+        let mut declarations = Vec::new();
+        if let Node::Program(body) = program {
+            for stmt in body {
+                if let (Some(name), [_, ..]) = (stmt.declared_name(), stmt.attributes()) {
+                    declarations.push((name.as_str(), stmt.attributes()));
+                }
+            }
+        }
+        Registry { declarations }
+    }
+
+    /// The arguments of an attribute written before a declaration.
+    pub fn get(&self, declaration: &str, attribute: &str) -> Option<&'a [Box<Node>]> {
+        self.declarations
+            .iter()
+            .filter(|(name, _)| *name == declaration)
+            .flat_map(|(_, attributes)| attributes.iter())
+            .find_map(|known| match known.as_ref() {
+                Node::Attribute(name, args) if name == attribute => Some(args.as_slice()),
+                _ => None,
+            })
+    }
+
+    pub fn has(&self, declaration: &str, attribute: &str) -> bool {
+        self.get(declaration, attribute).is_some()
+    }
+
+    /// The declarations marked with an attribute, in the order they are written.
+    pub fn marked(&self, attribute: &str) -> Vec<&'a str> {
+        self.declarations
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| self.has(name, attribute))
+            .collect()
+    }
+
+    /// The message of a `@deprecated` declaration, empty when it has none.
+    pub fn deprecation(&self, declaration: &str) -> Option<&'a str> {
+        let args = self.get(declaration, "deprecated")?;
+        match args.first().map(|arg| arg.as_ref()) {
+            Some(Node::StringLiteral(message)) => Some(message),
+            _ => Some(""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parse_with_positions;
+    use crate::compiler::tokenizer::tokenize;
+
+    fn messages(code: &str, pass: fn(&Node, &Positions) -> Vec<Error>) -> Vec<String> {
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        pass(&program, &positions)
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    #[test]
+    fn known_attributes_are_accepted() {
+        let code = "@inline\n@deprecated(\"use area()\")\nfunction twice(n: number) -> number { return n * 2; }\n\
+                    @pure\nextern function sqrt(n: number) -> number;\n\
+                    @event(Step)\nfunction step() {}\n";
+        assert!(messages(code, validate).is_empty());
+    }
+
+    #[test]
+    fn attribute_errors() {
+        assert_eq!(
+            messages("@fast\nfunction f() {}", validate),
+            ["Unknown attribute '@fast', expected one of: @inline, @noinline, @pure, @test, @deprecated, @global, @event"]
+        );
+        assert_eq!(
+            messages("@inline\nlet x = 1;", validate),
+            ["'@inline' can't be written before a let or const variable, only before a function"]
+        );
+        assert_eq!(
+            messages("@inline(2)\nfunction f() {}", validate),
+            ["'@inline' takes no arguments"]
+        );
+        assert_eq!(
+            messages("@deprecated(old)\nfunction f() {}", validate),
+            ["'@deprecated' takes at most one string, like @deprecated(\"message\")"]
+        );
+        assert_eq!(
+            messages("@event(Jump)\nfunction f() {}", validate)[0],
+            format!("'@event' takes one of: {}", EVENTS.join(", "))
+        );
+        assert_eq!(
+            messages("@pure\n@pure\nfunction f() {}", validate),
+            ["'@pure' is written twice on 'f'"]
+        );
+        assert_eq!(
+            messages("@inline\n@noinline\nfunction f() {}", validate),
+            ["'@inline' and '@noinline' can't be used together on 'f'"]
+        );
+        assert_eq!(
+            messages("function f() {\n@inline\nfunction g() {}\n}", validate),
+            ["Attributes can only be written before a top-level declaration, not before 'g'"]
+        );
+    }
+
+    #[test]
+    fn unsupported_attributes_are_warned_about() {
+        assert_eq!(
+            messages("@global\nlet score = 0;\n@event(Step)\nfunction step() {}", unsupported),
+            [
+                "'@global' has no effect yet, 'score' still belongs to the instance running it, use global.score to share it",
+                "'@event' has no effect yet, 'step' is compiled as a plain function that has to be called from the event",
+            ]
+        );
+        assert!(messages("@pure\nfunction f() {}", unsupported).is_empty());
+    }
+}
@@ -0,0 +1,1665 @@
+use crate::compiler::ast::Node;
+use crate::compiler::attributes;
+use crate::compiler::error::{Error, Span};
+use crate::compiler::parser::Positions;
+use crate::compiler::testing;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Array(Box<Type>),
+    /// Assets, handles and data structures, like `Sprite` or `DsList<number>`.
+    Named(String, Vec<Type>),
+    Function(Vec<Type>, Box<Type>),
+    /// A parameter that can be left out, only found in function parameters.
+    Optional(Box<Type>),
+    /// A parameter taking every remaining argument, only found last.
+    Rest(Box<Type>),
+    Void,
+    /// `any`, which is never checked.
+    Untyped,
+    /// `unknown`, which takes any value but has to be narrowed to a type with
+    /// `is_string(x)`, `typeof(x) == "number"`, ... before it is used.
+    Unknown,
+}
+
+impl Type {
+    pub fn name(&self) -> String {
+        match self {
+            Type::Number => "number".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Array(item) => format!("{}[]", item.name()),
+            Type::Named(name, args) if args.is_empty() => name.clone(),
+            Type::Named(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.name()).collect();
+                format!("{name}<{}>", args.join(", "))
+            }
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|param| param.name()).collect();
+                format!("Fn({})<{}>", params.join(", "), ret.name())
+            }
+            Type::Optional(param) => format!("{}?", param.name()),
+            Type::Rest(param) => format!("...{}", param.name()),
+            Type::Void => "void".to_string(),
+            Type::Untyped => "untyped".to_string(),
+            Type::Unknown => "unknown".to_string(),
+        }
+    }
+
+    /// Turns a type annotation from the AST into a `Type`.
+    pub fn from_annotation(annotation: &Node) -> Type {
+        match annotation {
+            Node::TypeName(name, args) => match (name.as_str(), args.as_slice()) {
+                ("number" | "int" | "float" | "real" | "int64", _) => Type::Number,
+                ("string" | "char", _) => Type::String,
+                ("bool", _) => Type::Bool,
+                ("void" | "undefined", _) => Type::Void,
+                ("any", _) => Type::Untyped,
+                ("unknown", _) => Type::Unknown,
+                ("Array", [item]) => Type::Array(Box::new(Type::from_annotation(item))),
+                _ => Type::Named(
+                    name.clone(),
+                    args.iter().map(|arg| Type::from_annotation(arg)).collect(),
+                ),
+            },
+            Node::FunctionType(params, ret) => Type::Function(
+                params
+                    .iter()
+                    .map(|param| Type::from_annotation(param))
+                    .collect(),
+                Box::new(Type::from_annotation(ret)),
+            ),
+            _ => Type::Untyped,
+        }
+    }
+
+    /// Turns a `Type` back into an annotation, for generated source code.
+    pub fn to_annotation(&self) -> Node {
+        let type_name = |name: &str, args: Vec<Box<Node>>| Node::TypeName(name.to_string(), args);
+
+        match self {
+            Type::Number => type_name("number", Vec::new()),
+            Type::String => type_name("string", Vec::new()),
+            Type::Bool => type_name("bool", Vec::new()),
+            Type::Array(item) => type_name("Array", vec![item.to_annotation().to_box()]),
+            Type::Named(name, args) => type_name(
+                name,
+                args.iter()
+                    .map(|arg| arg.to_annotation().to_box())
+                    .collect(),
+            ),
+            Type::Function(params, ret) => Node::FunctionType(
+                params
+                    .iter()
+                    .map(|param| param.to_annotation().to_box())
+                    .collect(),
+                ret.to_annotation().to_box(),
+            ),
+            Type::Optional(inner) | Type::Rest(inner) => inner.to_annotation(),
+            Type::Void => type_name("void", Vec::new()),
+            Type::Untyped => type_name("any", Vec::new()),
+            Type::Unknown => type_name("unknown", Vec::new()),
+        }
+    }
+
+    /// Builds the type of a function from its parameters and return type.
+    pub fn from_signature(
+        params: &[Box<Node>],
+        return_type: &Option<Box<Node>>,
+        default_return: Type,
+    ) -> Type {
+        let params = params.iter().map(|param| parameter_type(param)).collect();
+        let ret = match return_type {
+            Some(annotation) => Type::from_annotation(annotation),
+            None => default_return,
+        };
+
+        Type::Function(params, Box::new(ret))
+    }
+
+    /// Whether a value of this type can be used where `expected` is wanted.
+    pub fn is_assignable_to(&self, expected: &Type) -> bool {
+        match (self, expected) {
+            (Type::Untyped, _) | (_, Type::Untyped) => true,
+            (_, Type::Unknown) => true,
+            (Type::Optional(inner), expected) => inner.is_assignable_to(expected),
+            (kind, Type::Optional(expected)) => kind.is_assignable_to(expected),
+            // Assets and handles are plain numbers at runtime, and so are bools.
+            (Type::Number, Type::Named(_, _)) | (Type::Named(_, _), Type::Number) => true,
+            (Type::Bool, Type::Number) => true,
+            (Type::Array(item), Type::Array(expected)) => item.is_assignable_to(expected),
+            (Type::Named(name, _), Type::Named(expected, _)) => name == expected,
+            (Type::Function(_, _), Type::Function(_, _)) => true,
+            (kind, expected) => kind == expected,
+        }
+    }
+}
+
+pub fn parameter_type(param: &Node) -> Type {
+    match param {
+        Node::FunctionParameter(_, Some(annotation)) => Type::from_annotation(annotation),
+        Node::OptionalParameter(param) | Node::DefaultParameter(param, _) => {
+            Type::Optional(Box::new(parameter_type(param)))
+        }
+        Node::RestParameter(param) => Type::Rest(Box::new(parameter_type(param))),
+        _ => Type::Untyped,
+    }
+}
+
+pub fn parameter_name(param: &Node) -> Option<&String> {
+    match param {
+        Node::FunctionParameter(name, _) => Some(name),
+        Node::OptionalParameter(param)
+        | Node::RestParameter(param)
+        | Node::DefaultParameter(param, _) => parameter_name(param),
+        _ => None,
+    }
+}
+
+/// How the checks of strict mode are reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strictness {
+    /// Not reported at all, for plain GML which can't be annotated.
+    Off,
+    Warn,
+    /// Reported as errors, with `--strict` or `strict = true`.
+    Deny,
+}
+
+impl Strictness {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strictness::Off => "off",
+            Strictness::Warn => "warn",
+            Strictness::Deny => "deny",
+        }
+    }
+}
+
+/// Whether a parameter is written with a type.
+fn is_annotated(param: &Node) -> bool {
+    match param {
+        Node::FunctionParameter(_, annotation) => annotation.is_some(),
+        Node::OptionalParameter(param)
+        | Node::RestParameter(param)
+        | Node::DefaultParameter(param, _) => is_annotated(param),
+        _ => true,
+    }
+}
+
+/// Variables and the type a condition proves they have.
+type Facts = Vec<(String, Type)>;
+
+/// Whether running a statement always leaves the block it is in.
+pub fn always_exits(node: &Node) -> bool {
+    match node {
+        Node::ReturnStatement(_)
+        | Node::ThrowStatement(_)
+        | Node::Exit
+        | Node::Break
+        | Node::Continue => true,
+        Node::Program(body) | Node::Block(body) => {
+            body.last().is_some_and(|stmt| always_exits(stmt))
+        }
+        Node::IfStatement(_, then, Some(otherwise)) => {
+            always_exits(then) && always_exits(otherwise)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a type is, or holds, `untyped`.
+fn has_untyped(kind: &Type) -> bool {
+    match kind {
+        Type::Untyped => true,
+        Type::Array(item) | Type::Optional(item) | Type::Rest(item) => has_untyped(item),
+        Type::Named(_, args) => args.iter().any(has_untyped),
+        Type::Function(params, ret) => params.iter().any(has_untyped) || has_untyped(ret),
+        _ => false,
+    }
+}
+
+/// Whether a function body reads `argument0`, `argument[i]` or
+/// `argument_count`, which lets it take more arguments than it declares.
+fn uses_arguments(code: &Node) -> bool {
+    match code {
+        Node::Identifier(name) => name.starts_with("argument"),
+        Node::FunctionDeclaration(_, _, _, _)
+        | Node::FunctionExpression(_, _)
+        | Node::ArrowFunction(_, _, _)
+        | Node::ClassDeclaration(_, _) => false,
+        node => node.children().into_iter().any(uses_arguments),
+    }
+}
+
+struct Checker<'a> {
+    variables: HashMap<String, Type>,
+    /// Variables declared with a type, which every assignment has to respect.
+    annotated: HashMap<String, Type>,
+    /// Name and declared return type of the function being checked.
+    current_function: Option<(String, Type)>,
+    /// Types returned so far by the function being checked.
+    returns: Vec<Type>,
+    /// Fields and methods of every class, by class name.
+    classes: HashMap<String, HashMap<String, Type>>,
+    /// Every variable and parameter declared, in the order they were checked.
+    locals: Vec<(String, Type)>,
+    /// Variables narrowed by the conditions around the code being checked,
+    /// with the types they had before, outermost first.
+    narrowed: Vec<(String, Option<Type>)>,
+    /// Members of the enums declared in the module, by enum name.
+    enums: HashMap<String, Vec<String>>,
+    /// Functions of plain GML scripts that no declaration gives a type to.
+    gml_functions: HashSet<String>,
+    strictness: Strictness,
+    /// Where the statements of the module are, to point errors at them.
+    positions: &'a Positions,
+    /// The innermost statement being checked whose position is known.
+    span: Option<Span>,
+    errors: Vec<Error>,
+    warnings: Vec<Error>,
+}
+
+/// What checking a module found out, errors included, for editor tooling.
+pub struct Analysis {
+    /// Types of the top-level symbols, imports included.
+    pub globals: HashMap<String, Type>,
+    /// Variables and parameters in the order they are declared.
+    pub locals: Vec<(String, Type)>,
+    pub classes: HashMap<String, HashMap<String, Type>>,
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Error>,
+}
+
+/// Types of the top-level declarations of a module, or its errors.
+pub type Checked = Result<HashMap<String, Type>, Vec<Error>>;
+
+pub fn check(program: &Node) -> Result<(), Vec<Error>> {
+    check_module(program, &HashMap::new()).map(|_| ())
+}
+
+/// Checks a module given the types of the symbols it imports, returning the
+/// types of its own top-level declarations.
+pub fn check_module(program: &Node, imports: &HashMap<String, Type>) -> Checked {
+    let positions = Positions::new();
+    check_module_as(
+        program,
+        &positions,
+        imports,
+        &HashSet::new(),
+        Strictness::Off,
+    )
+    .0
+}
+
+/// Like `check_module`, reporting the checks of strict mode as `strictness`
+/// says. `gml_functions` are the imported functions of plain GML scripts.
+/// Errors point at the statements `positions` knows the place of. Warnings
+/// are returned even when the module has errors.
+pub fn check_module_as(
+    program: &Node,
+    positions: &Positions,
+    imports: &HashMap<String, Type>,
+    gml_functions: &HashSet<String>,
+    strictness: Strictness,
+) -> (Checked, Vec<Error>) {
+    let mut checker = Checker::new(imports, positions);
+    checker.gml_functions = gml_functions.clone();
+    checker.strictness = strictness;
+
+    checker
+        .errors
+        .extend(attributes::validate(program, positions));
+    checker
+        .warnings
+        .extend(attributes::unsupported(program, positions));
+    checker
+        .errors
+        .extend(testing::validate(program, positions, gml_functions));
+    checker.declare_functions(program);
+    checker.check_statement(program);
+
+    if !checker.errors.is_empty() {
+        return (Err(checker.errors), checker.warnings);
+    }
+
+    let mut declarations = HashMap::new();
+    if let Node::Program(body) = program {
+        for stmt in body {
+            if let Some(name) = stmt.declared_name() {
+                declarations.insert(name.clone(), checker.variables[name].clone());
+            }
+        }
+    }
+
+    (Ok(declarations), checker.warnings)
+}
+
+/// Types of the fields and methods of every class in a module, with the
+/// return types of methods inferred from their body.
+pub fn class_members(
+    program: &Node,
+    imports: &HashMap<String, Type>,
+) -> HashMap<String, HashMap<String, Type>> {
+    let positions = Positions::new();
+    let mut checker = Checker::new(imports, &positions);
+
+    checker.declare_functions(program);
+    checker.check_statement(program);
+
+    checker.classes
+}
+
+/// Checks a module without stopping at errors, keeping the types it found.
+pub fn analyze(
+    program: &Node,
+    positions: &Positions,
+    imports: &HashMap<String, Type>,
+    strictness: Strictness,
+) -> Analysis {
+    let mut checker = Checker::new(imports, positions);
+    checker.strictness = strictness;
+
+    checker
+        .errors
+        .extend(attributes::validate(program, positions));
+    checker
+        .warnings
+        .extend(attributes::unsupported(program, positions));
+    checker
+        .errors
+        .extend(testing::validate(program, positions, &HashSet::new()));
+    checker.declare_functions(program);
+    checker.check_statement(program);
+
+    Analysis {
+        globals: checker.variables,
+        locals: checker.locals,
+        classes: checker.classes,
+        errors: checker.errors,
+        warnings: checker.warnings,
+    }
+}
+
+/// Infers the type of an expression given the types of the variables it can
+/// see, ignoring any error in it.
+pub fn infer_type(expr: &Node, variables: &HashMap<String, Type>) -> Type {
+    Checker::new(variables, &Positions::new()).infer(expr)
+}
+
+impl<'a> Checker<'a> {
+    fn new(variables: &HashMap<String, Type>, positions: &'a Positions) -> Checker<'a> {
+        Checker {
+            variables: variables.clone(),
+            annotated: HashMap::new(),
+            current_function: None,
+            returns: Vec::new(),
+            classes: HashMap::new(),
+            locals: Vec::new(),
+            narrowed: Vec::new(),
+            enums: HashMap::new(),
+            gml_functions: HashSet::new(),
+            strictness: Strictness::Off,
+            positions,
+            span: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Reports an error in the statement being checked.
+    fn error(&mut self, message: String) {
+        let error = Error::new(message).or_at(self.span);
+        self.errors.push(error);
+    }
+
+    /// Reports something strict mode forbids, an error or a warning
+    /// depending on the strictness.
+    fn strict(&mut self, message: String) {
+        let error = Error::new(message).or_at(self.span);
+        match self.strictness {
+            Strictness::Off => {}
+            Strictness::Warn => self.warnings.push(error),
+            Strictness::Deny => self.errors.push(error),
+        }
+    }
+
+    /// Bools are numbers at runtime, but strict mode wants the conversion
+    /// written out.
+    fn check_coercion(&mut self, kind: &Type, expected: &Type, context: &str) {
+        if *kind == Type::Bool && *expected == Type::Number {
+            self.strict(format!(
+                "{context} is a bool used as a number, convert it explicitly"
+            ));
+        }
+    }
+
+    /// GML functions can be called before the line declaring them, so their
+    /// signatures are known before checking any statement.
+    fn declare_functions(&mut self, program: &Node) {
+        if let Node::Program(body) = program {
+            for stmt in body {
+                match stmt.declaration() {
+                    Node::FunctionDeclaration(name, params, return_type, code) => {
+                        let kind = function_type(params, return_type, Type::Untyped, code);
+                        self.variables.insert(name.clone(), kind);
+                    }
+                    Node::ConstructorDeclaration(function, _) => {
+                        if let Node::FunctionDeclaration(name, params, _, code) = function.as_ref()
+                        {
+                            let instance = Some(Node::TypeName(name.clone(), Vec::new()).to_box());
+                            let kind = function_type(params, &instance, Type::Untyped, code);
+                            self.variables.insert(name.clone(), kind);
+                        }
+                    }
+                    Node::ClassDeclaration(name, members) => {
+                        let mut member_types = HashMap::new();
+                        for member in members {
+                            match member.as_ref() {
+                                Node::ClassField(field, annotation, _) => {
+                                    let kind = annotation
+                                        .as_ref()
+                                        .map(|annotation| Type::from_annotation(annotation))
+                                        .unwrap_or(Type::Untyped);
+                                    member_types.insert(field.clone(), kind);
+                                }
+                                Node::FunctionDeclaration(method, params, return_type, _) => {
+                                    let kind =
+                                        Type::from_signature(params, return_type, Type::Untyped);
+                                    member_types.insert(method.clone(), kind);
+                                }
+                                _ => {}
+                            }
+                        }
+
+                        self.variables
+                            .insert(name.clone(), class_type(name, members));
+                        self.classes.insert(name.clone(), member_types);
+                    }
+                    Node::ExternFunction(name, params, return_type) => {
+                        let kind = Type::from_signature(params, return_type, Type::Void);
+                        self.variables.insert(name.clone(), kind);
+                    }
+                    Node::ExternVariable(_, name, annotation) => {
+                        let kind = Type::from_annotation(annotation);
+                        self.variables.insert(name.clone(), kind.clone());
+                        self.annotated.insert(name.clone(), kind);
+                    }
+                    Node::EnumDeclaration(name, members) => {
+                        let members = members.iter().map(|(member, _)| member.clone()).collect();
+                        self.enums.insert(name.clone(), members);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn check_statement(&mut self, node: &Node) {
+        let outer = self.span;
+        if let Some(span) = self.positions.get(&(node as *const Node)) {
+            self.span = Some(*span);
+        }
+        self.check_statement_kind(node);
+        self.span = outer;
+    }
+
+    fn check_statement_kind(&mut self, node: &Node) {
+        match node {
+            Node::Program(body) | Node::Block(body) | Node::DeclarationList(body) => {
+                for stmt in body {
+                    self.check_statement(stmt);
+                }
+            }
+            Node::ImportDeclaration(_, _)
+            | Node::ExternFunction(_, _, _)
+            | Node::ExternVariable(_, _, _)
+            | Node::Break
+            | Node::Continue
+            | Node::Exit => {}
+            Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+                self.check_statement(declaration)
+            }
+            Node::VariableDeclaration(_, name, annotation, expr) => {
+                let kind = self.infer(expr);
+
+                let kind = match annotation {
+                    Some(annotation) => {
+                        let declared = Type::from_annotation(annotation);
+                        if !kind.is_assignable_to(&declared) {
+                            self.error(format!(
+                                "Can't assign a {} to '{name}', which is declared as {}",
+                                kind.name(),
+                                declared.name()
+                            ));
+                        }
+                        self.check_coercion(&kind, &declared, &format!("The value of '{name}'"));
+                        self.annotated.insert(name.clone(), declared.clone());
+                        declared
+                    }
+                    None => {
+                        if has_untyped(&kind) && !matches!(kind, Type::Function(_, _)) {
+                            self.strict(format!(
+                                "'{name}' needs a type annotation, its type can't be inferred"
+                            ));
+                        }
+                        kind
+                    }
+                };
+
+                self.locals.push((name.clone(), kind.clone()));
+                self.variables.insert(name.clone(), kind);
+            }
+            Node::GlobalVarDeclaration(names) => {
+                for name in names {
+                    self.variables.insert(name.clone(), Type::Untyped);
+                }
+            }
+            Node::MacroDeclaration(name, _) | Node::EnumDeclaration(name, _) => {
+                for child in node.children() {
+                    self.infer(child);
+                }
+                self.variables.insert(name.clone(), Type::Untyped);
+            }
+            Node::FunctionDeclaration(name, params, return_type, code) => {
+                let kind = self.check_function(name, params, return_type, code);
+                self.check_return_annotation(name, return_type, &kind);
+                self.variables.insert(name.clone(), kind);
+            }
+            Node::ConstructorDeclaration(function, parent) => {
+                if let Some(parent) = parent {
+                    self.infer(parent);
+                }
+
+                if let Node::FunctionDeclaration(name, params, _, code) = function.as_ref() {
+                    let instance = Some(Node::TypeName(name.clone(), Vec::new()).to_box());
+                    self.check_function(name, params, &None, code);
+                    let kind = function_type(params, &instance, Type::Untyped, code);
+                    self.variables.insert(name.clone(), kind);
+                } else {
+                    self.infer(function);
+                }
+            }
+            Node::ClassDeclaration(name, members) => self.check_class(name, members),
+            Node::ReturnStatement(expr) => {
+                let kind = self.infer(expr);
+
+                if let Some((name, declared)) = self.current_function.clone() {
+                    if !kind.is_assignable_to(&declared) {
+                        self.error(format!(
+                            "'{name}' should return {}, but returns a {}",
+                            declared.name(),
+                            kind.name()
+                        ));
+                    }
+                    self.check_coercion(
+                        &kind,
+                        &declared,
+                        &format!("The value returned by '{name}'"),
+                    );
+                }
+
+                self.returns.push(kind);
+            }
+            Node::Assignment(target, operator, value) => {
+                let value_type = self.infer(value);
+                let target_type = self.infer(target);
+
+                // `a += b` is checked as `a = a + b`.
+                let kind = match operator.strip_suffix('=') {
+                    Some(binary) if !binary.is_empty() => {
+                        self.infer_binary(binary, target_type, value_type)
+                    }
+                    _ => value_type,
+                };
+
+                if let Node::Identifier(name) = target.as_ref() {
+                    match self.annotated.get(name) {
+                        Some(declared) if !kind.is_assignable_to(declared) => {
+                            self.error(format!(
+                                "Can't assign a {} to '{name}', which is declared as {}",
+                                kind.name(),
+                                declared.name()
+                            ));
+                        }
+                        Some(declared) => {
+                            let declared = declared.clone();
+                            self.check_coercion(
+                                &kind,
+                                &declared,
+                                &format!("The value of '{name}'"),
+                            );
+                        }
+                        None => {
+                            self.variables.entry(name.clone()).or_insert(kind);
+                        }
+                    }
+                    self.forget_narrowing(name);
+                }
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                self.infer(condition);
+                let (when_true, when_false) = self.narrowings(condition);
+
+                let outer = self.narrow(&when_true);
+                self.check_statement(then);
+                self.restore(outer);
+
+                let outer = self.narrow(&when_false);
+                if let Some(otherwise) = otherwise {
+                    self.check_statement(otherwise);
+                }
+                // After `if (!is_string(x)) return;`, `x` stays a string.
+                if !always_exits(then) {
+                    self.restore(outer);
+                }
+            }
+            Node::WhileStatement(_, _)
+            | Node::DoUntilStatement(_, _)
+            | Node::RepeatStatement(_, _)
+            | Node::WithStatement(_, _)
+            | Node::ForStatement(_, _, _, _)
+            | Node::SwitchCase(_, _) => {
+                for child in node.children() {
+                    self.check_statement(child);
+                }
+            }
+            Node::SwitchStatement(_, cases) => {
+                for child in node.children() {
+                    self.check_statement(child);
+                }
+                self.check_exhaustive(cases);
+            }
+            Node::TryStatement(body, catch, finally) => {
+                self.check_statement(body);
+                if let Some((name, handler)) = catch {
+                    // Anything can be thrown, not only the structs of runtime
+                    // errors.
+                    self.locals.push((name.clone(), Type::Untyped));
+                    self.variables.insert(name.clone(), Type::Untyped);
+                    self.check_statement(handler);
+                }
+                if let Some(finally) = finally {
+                    self.check_statement(finally);
+                }
+            }
+            Node::ThrowStatement(value) | Node::DeleteStatement(value) => {
+                self.infer(value);
+            }
+            expr => {
+                self.infer(expr);
+            }
+        }
+    }
+
+    /// Checks the body of a function and returns its type. Without a return
+    /// type annotation, the return type is inferred from its `return`s.
+    fn check_function(
+        &mut self,
+        name: &str,
+        params: &[Box<Node>],
+        return_type: &Option<Box<Node>>,
+        code: &Node,
+    ) -> Type {
+        let outer_variables = self.variables.clone();
+        let outer_annotated = self.annotated.clone();
+        let outer_function = self.current_function.take();
+        let outer_returns = std::mem::take(&mut self.returns);
+
+        for param in params {
+            if let Node::DefaultParameter(_, default) = param.as_ref() {
+                self.infer(default);
+            }
+
+            if let Some(param_name) = parameter_name(param) {
+                if !is_annotated(param) {
+                    let owner = match name {
+                        "fn" | "function" => "this function".to_string(),
+                        name => format!("'{name}'"),
+                    };
+                    self.strict(format!(
+                        "Parameter '{param_name}' of {owner} has no type annotation"
+                    ));
+                }
+
+                let kind = match parameter_type(param) {
+                    Type::Optional(inner) => *inner,
+                    Type::Rest(inner) => Type::Array(inner),
+                    kind => kind,
+                };
+                if kind != Type::Untyped {
+                    self.annotated.insert(param_name.clone(), kind.clone());
+                }
+                self.locals.push((param_name.clone(), kind.clone()));
+                self.variables.insert(param_name.clone(), kind);
+            }
+        }
+
+        let declared_return = return_type
+            .as_ref()
+            .map(|annotation| Type::from_annotation(annotation))
+            .unwrap_or(Type::Untyped);
+        self.current_function = Some((name.to_string(), declared_return));
+
+        self.declare_functions(code);
+        self.check_statement(code);
+
+        let returns = std::mem::replace(&mut self.returns, outer_returns);
+        let inferred = match returns.first() {
+            None => Type::Void,
+            Some(first) if returns.iter().all(|kind| kind == first) => first.clone(),
+            Some(first) => {
+                if return_type.is_none() {
+                    let other = returns.iter().find(|kind| *kind != first).unwrap();
+                    self.strict(format!(
+                        "'{name}' returns both a {} and a {}, so its return type is untyped",
+                        first.name(),
+                        other.name()
+                    ));
+                }
+                Type::Untyped
+            }
+        };
+
+        self.variables = outer_variables;
+        self.annotated = outer_annotated;
+        self.current_function = outer_function;
+
+        function_type(params, return_type, inferred, code)
+    }
+
+    /// Fields and methods are visible from every method of the class, and
+    /// fields declared with a type can only be assigned values of that type.
+    fn check_class(&mut self, name: &str, members: &[Box<Node>]) {
+        let outer_variables = self.variables.clone();
+        let outer_annotated = self.annotated.clone();
+
+        for member in members {
+            match member.as_ref() {
+                Node::ClassField(field, annotation, value) => {
+                    let value_type = value
+                        .as_ref()
+                        .map(|value| self.infer(value))
+                        .unwrap_or(Type::Untyped);
+
+                    let kind = match annotation {
+                        Some(annotation) => {
+                            let declared = Type::from_annotation(annotation);
+                            if !value_type.is_assignable_to(&declared) {
+                                self.error(format!(
+                                    "Can't assign a {} to '{name}.{field}', which is declared as {}",
+                                    value_type.name(),
+                                    declared.name()
+                                ));
+                            }
+                            self.check_coercion(
+                                &value_type,
+                                &declared,
+                                &format!("The value of '{name}.{field}'"),
+                            );
+                            self.annotated.insert(field.clone(), declared.clone());
+                            declared
+                        }
+                        None => {
+                            if has_untyped(&value_type) {
+                                self.strict(format!(
+                                    "Field '{name}.{field}' needs a type annotation, its type can't be inferred"
+                                ));
+                            }
+                            value_type
+                        }
+                    };
+                    self.variables.insert(field.clone(), kind);
+                }
+                Node::FunctionDeclaration(method, params, return_type, _) => {
+                    let kind = Type::from_signature(params, return_type, Type::Untyped);
+                    self.variables.insert(method.clone(), kind);
+                }
+                _ => {}
+            }
+        }
+
+        for member in members {
+            match member.as_ref() {
+                Node::ConstructorDeclaration(constructor, parent) => {
+                    if let Some(parent) = parent {
+                        self.infer(parent);
+                    }
+                    if let Node::FunctionDeclaration(_, params, _, code) = constructor.as_ref() {
+                        self.check_function(name, params, &None, code);
+                    }
+                }
+                Node::FunctionDeclaration(method, params, return_type, code) => {
+                    let kind = self.check_function(method, params, return_type, code);
+                    self.check_return_annotation(method, return_type, &kind);
+                    if let Some(member_types) = self.classes.get_mut(name) {
+                        member_types.insert(method.clone(), kind);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.variables = outer_variables;
+        self.annotated = outer_annotated;
+        self.variables
+            .insert(name.to_string(), class_type(name, members));
+    }
+
+    fn infer(&mut self, node: &Node) -> Type {
+        match node {
+            Node::NumericLiteral(_, _) => Type::Number,
+            Node::BooleanLiteral(_) => Type::Bool,
+            Node::StringLiteral(_) | Node::TemplateString(_) => {
+                if let Node::TemplateString(parts) = node {
+                    for part in parts {
+                        self.infer(part);
+                    }
+                }
+                Type::String
+            }
+            Node::TemplateExpression(expr, precision) => {
+                let kind = self.infer(expr);
+
+                if let (Some(precision), Type::String) = (precision, &kind) {
+                    self.error(format!(
+                        "Format specifier '.{precision}' can only be applied to numbers, but the template expression is a {}",
+                        kind.name()
+                    ));
+                }
+
+                Type::String
+            }
+            Node::Identifier(name) => self.variables.get(name).cloned().unwrap_or(Type::Untyped),
+            Node::BinaryExpression(left, operator, right) => {
+                let left_type = self.infer(left);
+
+                // The right side of `is_string(x) && ...` knows `x` is a string.
+                let facts = match operator.as_ref() {
+                    Node::BinaryOperator(operator) if operator == "&&" => self.narrowings(left).0,
+                    Node::BinaryOperator(operator) if operator == "||" => self.narrowings(left).1,
+                    _ => Vec::new(),
+                };
+                let outer = self.narrow(&facts);
+                let right_type = self.infer(right);
+                self.restore(outer);
+
+                match operator.as_ref() {
+                    Node::BinaryOperator(operator) => {
+                        self.infer_binary(operator, left_type, right_type)
+                    }
+                    _ => Type::Untyped,
+                }
+            }
+            Node::UnaryExpression(operator, operand) => {
+                if self.infer(operand) == Type::Unknown && operator != "!" {
+                    self.error(format!(
+                        "Can't use '{operator}' on an unknown value, narrow it first with is_real()"
+                    ));
+                }
+
+                if operator == "!" {
+                    Type::Bool
+                } else {
+                    Type::Number
+                }
+            }
+            Node::UpdateExpression(operator, target, _) => {
+                if self.infer(target) == Type::Unknown {
+                    self.error(format!(
+                        "Can't use '{operator}' on an unknown value, narrow it first with is_real()"
+                    ));
+                }
+                Type::Number
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => {
+                self.infer(condition);
+                let (when_true, when_false) = self.narrowings(condition);
+
+                let outer = self.narrow(&when_true);
+                let if_true = self.infer(if_true);
+                self.restore(outer);
+
+                let outer = self.narrow(&when_false);
+                let if_false = self.infer(if_false);
+                self.restore(outer);
+
+                if if_true == if_false {
+                    if_true
+                } else {
+                    if if_true != Type::Untyped && if_false != Type::Untyped {
+                        self.strict(format!(
+                            "The branches of this ?: are a {} and a {}, so its type is untyped",
+                            if_true.name(),
+                            if_false.name()
+                        ));
+                    }
+                    Type::Untyped
+                }
+            }
+            Node::MemberAccess(object, member) => {
+                let object = self.infer(object);
+                self.primitive_member(&object, member, false);
+                self.member_type(object, member)
+            }
+            Node::IndexAccess(value, accessor, indices) => {
+                let kind = self.infer(value);
+                for index in indices {
+                    self.infer(index);
+                }
+
+                match (accessor.as_deref(), kind) {
+                    (None | Some("@"), Type::Array(item)) => *item,
+                    (Some("|"), Type::Named(name, args)) if name == "DsList" && args.len() == 1 => {
+                        args[0].clone()
+                    }
+                    (Some("?"), Type::Named(name, args)) if name == "DsMap" && args.len() == 2 => {
+                        args[1].clone()
+                    }
+                    (_, Type::Unknown) => {
+                        self.error(String::from(
+                            "Can't index an unknown value, narrow it first with is_array()",
+                        ));
+                        Type::Untyped
+                    }
+                    _ => Type::Untyped,
+                }
+            }
+            Node::ArrayLiteral(items) => {
+                let kinds: Vec<Type> = items.iter().map(|item| self.infer(item)).collect();
+
+                match kinds.first() {
+                    Some(first) if kinds.iter().all(|kind| kind == first) => {
+                        Type::Array(Box::new(first.clone()))
+                    }
+                    Some(first) => {
+                        if !kinds.contains(&Type::Untyped) {
+                            let other = kinds.iter().find(|kind| *kind != first).unwrap();
+                            self.strict(format!(
+                                "This array mixes {} and {} items, so its items are untyped",
+                                first.name(),
+                                other.name()
+                            ));
+                        }
+                        Type::Array(Box::new(Type::Untyped))
+                    }
+                    None => Type::Array(Box::new(Type::Untyped)),
+                }
+            }
+            Node::StructLiteral(fields) => {
+                for (_, value) in fields {
+                    self.infer(value);
+                }
+                Type::Untyped
+            }
+            Node::FunctionExpression(params, code) => {
+                self.check_function("function", params, &None, code)
+            }
+            Node::ArrowFunction(params, return_type, code) => match code.as_ref() {
+                Node::Program(_) => self.check_function("fn", params, return_type, code),
+                expr => {
+                    let code =
+                        Node::Program(vec![Node::ReturnStatement(expr.clone().to_box()).to_box()]);
+                    self.check_function("fn", params, return_type, &code)
+                }
+            },
+            Node::ConstructorDeclaration(_, _) => {
+                self.check_statement(node);
+                Type::Untyped
+            }
+            Node::NewExpression(call) => self.infer(call),
+            Node::FunctionCall(callee, args) => {
+                let callee_type = match callee.as_ref() {
+                    Node::MemberAccess(object, member) => {
+                        let object = self.infer(object);
+                        self.primitive_member(&object, member, true);
+                        self.member_type(object, member)
+                    }
+                    _ => self.infer(callee),
+                };
+                let mut arg_types = Vec::new();
+
+                if let Node::Arguments(args) = args.as_ref() {
+                    for arg in args {
+                        arg_types.push(self.infer(arg));
+                    }
+                }
+
+                if let Node::Identifier(name) = callee.as_ref() {
+                    if !self.variables.contains_key(name) {
+                        self.strict(format!(
+                            "'{name}' isn't declared, so calls to it can't be checked, declare it with extern"
+                        ));
+                    } else if self.gml_functions.contains(name) {
+                        self.strict(format!(
+                            "'{name}' comes from a GML script, declare its types with extern in a .d.gmpp file"
+                        ));
+                    }
+                }
+
+                match callee_type {
+                    Type::Function(params, ret) => {
+                        self.check_arguments(callee, &params, &arg_types);
+                        // `method` binds a function to a context, which keeps
+                        // the signature of the function.
+                        match (callee.as_ref(), arg_types.get(1)) {
+                            (Node::Identifier(name), Some(bound @ Type::Function(_, _)))
+                                if name == "method" =>
+                            {
+                                bound.clone()
+                            }
+                            _ => *ret,
+                        }
+                    }
+                    Type::Untyped => Type::Untyped,
+                    Type::Unknown => {
+                        self.error(format!(
+                            "{} is unknown, narrow it with is_callable() before calling it",
+                            describe_callee(callee)
+                        ));
+                        Type::Untyped
+                    }
+                    kind => {
+                        self.error(format!(
+                            "{} is a {}, it can't be called",
+                            describe_callee(callee),
+                            kind.name()
+                        ));
+                        Type::Untyped
+                    }
+                }
+            }
+            _ => Type::Untyped,
+        }
+    }
+
+    /// The type of `member` read from a value of type `object`.
+    /// Strings, bools and arrays have no members. Numbers only do when they
+    /// are instance ids, which strict mode wants declared as `Instance`.
+    fn primitive_member(&mut self, object: &Type, member: &str, called: bool) {
+        let (action, has) = if called {
+            ("called on", "methods")
+        } else {
+            ("used on", "fields")
+        };
+        match object {
+            Type::Array(_) => {
+                let function = format!("array_{member}");
+                let hint = match self.variables.contains_key(&function) {
+                    true => format!(", use {function}() instead"),
+                    false => String::new(),
+                };
+                self.error(format!(
+                    "'{member}' can't be {action} an array, which has no {has}{hint}"
+                ));
+            }
+            Type::String | Type::Bool => {
+                let hint = match object {
+                    Type::String => ", use the string functions or '+' instead",
+                    _ => "",
+                };
+                self.error(format!(
+                    "'{member}' can't be {action} a {}, which has no {has}{hint}",
+                    object.name()
+                ));
+            }
+            Type::Number => self.strict(format!(
+                "'{member}' is {action} a number, which only works for instance ids, declare it as Instance"
+            )),
+            _ => {}
+        }
+    }
+
+    fn member_type(&mut self, object: Type, member: &str) -> Type {
+        match object {
+            Type::Named(name, _) => self
+                .classes
+                .get(&name)
+                .and_then(|members| members.get(member))
+                .cloned()
+                .unwrap_or(Type::Untyped),
+            Type::Unknown => {
+                self.error(format!(
+                    "Can't read '{member}' of an unknown value, narrow it first with is_struct() or instanceof()"
+                ));
+                Type::Untyped
+            }
+            _ => Type::Untyped,
+        }
+    }
+
+    fn infer_binary(&mut self, operator: &str, left: Type, right: Type) -> Type {
+        let comparison = matches!(operator, "==" | "!=" | "<" | "<=" | ">" | ">=");
+        let arithmetic = !comparison && !matches!(operator, "&&" | "||" | "^^" | "??");
+
+        let equality = matches!(operator, "==" | "!=");
+        if (left == Type::Unknown || right == Type::Unknown)
+            && (arithmetic || !equality && comparison)
+        {
+            self.error(format!(
+                "Can't use '{operator}' on an unknown value, narrow it first with is_real(), is_string(), ..."
+            ));
+            return Type::Untyped;
+        }
+
+        match (&left, &right) {
+            (Type::Number, Type::String) | (Type::String, Type::Number) if comparison => {
+                self.strict(format!(
+                    "Comparing a {} with a {} converts one of them, convert it explicitly",
+                    left.name(),
+                    right.name()
+                ));
+            }
+            (Type::String, _) | (_, Type::String) if arithmetic && operator != "+" => {
+                self.strict(format!("'{operator}' can't be used on a string"));
+            }
+            (Type::Bool, Type::Number | Type::Bool) | (Type::Number, Type::Bool) if arithmetic => {
+                self.strict(format!(
+                    "'{operator}' uses a bool as a number, convert it explicitly"
+                ));
+            }
+            _ => {}
+        }
+
+        match (operator, left, right) {
+            ("==" | "!=" | "<" | "<=" | ">" | ">=" | "&&" | "||" | "^^", _, _) => Type::Bool,
+            ("??", Type::Untyped, right) => right,
+            ("??", left, _) => left,
+            (_, Type::Number, Type::Number) => Type::Number,
+            ("+", Type::String, Type::String) => Type::String,
+            ("+", left @ Type::String, right @ (Type::Number | Type::Bool))
+            | ("+", left @ (Type::Number | Type::Bool), right @ Type::String) => {
+                // GML doesn't turn numbers into strings on its own, this
+                // would fail at runtime.
+                self.error(format!(
+                    "Can't add a {} to a {}, use string() to convert it first",
+                    right.name(),
+                    left.name()
+                ));
+                Type::String
+            }
+            ("+", Type::String, _) | ("+", _, Type::String) => Type::String,
+            _ => Type::Untyped,
+        }
+    }
+
+    /// Gives variables the types a condition proved, returning the types
+    /// they had before for `restore`.
+    fn narrow(&mut self, facts: &Facts) -> Vec<(String, Option<Type>)> {
+        let outer: Vec<(String, Option<Type>)> = facts
+            .iter()
+            .map(|(name, kind)| {
+                (
+                    name.clone(),
+                    self.variables.insert(name.clone(), kind.clone()),
+                )
+            })
+            .collect();
+        self.narrowed.extend(outer.iter().cloned());
+        outer
+    }
+
+    fn restore(&mut self, outer: Vec<(String, Option<Type>)>) {
+        let kept = self.narrowed.len().saturating_sub(outer.len());
+        self.narrowed.truncate(kept);
+
+        for (name, kind) in outer.into_iter().rev() {
+            match kind {
+                Some(kind) => self.variables.insert(name, kind),
+                None => self.variables.remove(&name),
+            };
+        }
+    }
+
+    /// Gives an assigned variable back the type it had before any condition
+    /// narrowed it, as what was proved held for the old value only.
+    fn forget_narrowing(&mut self, name: &str) {
+        let before = self
+            .narrowed
+            .iter()
+            .find(|(narrowed, _)| narrowed == name)
+            .and_then(|(_, kind)| kind.clone());
+        if let Some(before) = before {
+            self.variables.insert(name.to_string(), before);
+        }
+    }
+
+    /// What a condition proves about the types of variables: the types they
+    /// have when it holds, and when it doesn't.
+    fn narrowings(&self, condition: &Node) -> (Facts, Facts) {
+        match condition {
+            Node::UnaryExpression(operator, operand) if operator == "!" => {
+                let (when_true, when_false) = self.narrowings(operand);
+                (when_false, when_true)
+            }
+            Node::BinaryExpression(left, operator, right) => {
+                let Node::BinaryOperator(operator) = operator.as_ref() else {
+                    return (Vec::new(), Vec::new());
+                };
+                let (left_true, left_false) = self.narrowings(left);
+                let (right_true, right_false) = self.narrowings(right);
+
+                match operator.as_str() {
+                    "&&" => ([left_true, right_true].concat(), Vec::new()),
+                    "||" => (Vec::new(), [left_false, right_false].concat()),
+                    "==" | "!=" => {
+                        let fact = self
+                            .compared_type(left, right)
+                            .or_else(|| self.compared_type(right, left));
+                        match fact {
+                            Some(fact) if operator == "==" => (vec![fact], Vec::new()),
+                            Some(fact) => (Vec::new(), vec![fact]),
+                            None => (Vec::new(), Vec::new()),
+                        }
+                    }
+                    _ => (Vec::new(), Vec::new()),
+                }
+            }
+            Node::FunctionCall(callee, args) => match (callee.as_ref(), args.as_ref()) {
+                (Node::Identifier(check), Node::Arguments(args)) => match args.as_slice() {
+                    [arg] => match arg.as_ref() {
+                        Node::Identifier(name) => {
+                            let kind = match check.as_str() {
+                                "is_string" => Some(Type::String),
+                                "is_real" | "is_numeric" | "is_int32" | "is_int64" => {
+                                    Some(Type::Number)
+                                }
+                                "is_bool" => Some(Type::Bool),
+                                "is_array" => Some(Type::Array(Box::new(self.contents(name)))),
+                                "is_struct" => Some(Type::Untyped),
+                                "is_method" | "is_callable" => Some(self.callable(name)),
+                                _ => None,
+                            };
+                            (
+                                kind.map(|kind| vec![(name.clone(), kind)])
+                                    .unwrap_or_default(),
+                                Vec::new(),
+                            )
+                        }
+                        _ => (Vec::new(), Vec::new()),
+                    },
+                    _ => (Vec::new(), Vec::new()),
+                },
+                _ => (Vec::new(), Vec::new()),
+            },
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    /// The type `typeof(x) == "name"` or `instanceof(x) == "Name"` proves.
+    fn compared_type(&self, call: &Node, literal: &Node) -> Option<(String, Type)> {
+        let (Node::FunctionCall(callee, args), Node::StringLiteral(text)) = (call, literal) else {
+            return None;
+        };
+        let (Node::Identifier(function), Node::Arguments(args)) = (callee.as_ref(), args.as_ref())
+        else {
+            return None;
+        };
+        let [arg] = args.as_slice() else {
+            return None;
+        };
+        let Node::Identifier(name) = arg.as_ref() else {
+            return None;
+        };
+
+        let kind = match (function.as_str(), text.as_str()) {
+            ("typeof", "number" | "int32" | "int64") => Type::Number,
+            ("typeof", "string") => Type::String,
+            ("typeof", "bool") => Type::Bool,
+            ("typeof", "array") => Type::Array(Box::new(self.contents(name))),
+            ("typeof", "struct") => Type::Untyped,
+            ("typeof", "method") => self.callable(name),
+            ("instanceof", class) => Type::Named(class.to_string(), Vec::new()),
+            _ => return None,
+        };
+
+        Some((name.clone(), kind))
+    }
+
+    /// What is inside a value once narrowed: still unknown if it was unknown.
+    fn contents(&self, name: &str) -> Type {
+        match self.variables.get(name) {
+            Some(Type::Unknown) => Type::Unknown,
+            _ => Type::Untyped,
+        }
+    }
+
+    fn callable(&self, name: &str) -> Type {
+        Type::Function(
+            vec![Type::Rest(Box::new(Type::Untyped))],
+            Box::new(self.contents(name)),
+        )
+    }
+
+    /// Functions returning a value have to say what they return.
+    fn check_return_annotation(
+        &mut self,
+        name: &str,
+        return_type: &Option<Box<Node>>,
+        kind: &Type,
+    ) {
+        if let (None, Type::Function(_, ret)) = (return_type, kind) {
+            if **ret != Type::Void {
+                self.strict(format!(
+                    "'{name}' returns a value, but has no return type annotation"
+                ));
+            }
+        }
+    }
+
+    /// A `switch` whose cases are members of an enum has to handle all of
+    /// them, or have a default case.
+    fn check_exhaustive(&mut self, cases: &[Box<Node>]) {
+        let mut switched: Option<&String> = None;
+        let mut handled = HashSet::new();
+
+        for case in cases {
+            let Node::SwitchCase(Some(value), _) = case.as_ref() else {
+                return;
+            };
+            let Node::MemberAccess(object, member) = value.as_ref() else {
+                return;
+            };
+            match object.as_ref() {
+                Node::Identifier(name)
+                    if self.enums.contains_key(name)
+                        && switched.is_none_or(|switched| switched == name) =>
+                {
+                    switched = Some(name);
+                    handled.insert(member);
+                }
+                _ => return,
+            }
+        }
+
+        let Some(name) = switched else {
+            return;
+        };
+        let missing: Vec<String> = self.enums[name]
+            .iter()
+            .filter(|member| !handled.contains(member))
+            .map(|member| format!("{name}.{member}"))
+            .collect();
+
+        if !missing.is_empty() {
+            self.strict(format!(
+                "This switch on '{name}' doesn't handle {}, add them or a default case",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    fn check_arguments(&mut self, callee: &Node, params: &[Type], args: &[Type]) {
+        let required = params
+            .iter()
+            .filter(|param| !matches!(param, Type::Optional(_) | Type::Rest(_)))
+            .count();
+        let is_variadic = matches!(params.last(), Some(Type::Rest(_)));
+
+        let expected = if is_variadic {
+            format!("at least {required}")
+        } else if required != params.len() {
+            format!("{required} to {}", params.len())
+        } else {
+            required.to_string()
+        };
+
+        if args.len() < required || (!is_variadic && args.len() > params.len()) {
+            self.error(format!(
+                "{} expects {expected} arguments, but {} were given",
+                describe_callee(callee),
+                args.len()
+            ));
+            return;
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let param = match params.get(i).or(params.last()) {
+                Some(Type::Optional(param)) | Some(Type::Rest(param)) => param.as_ref(),
+                Some(param) => param,
+                None => break,
+            };
+
+            if !arg.is_assignable_to(param) {
+                self.error(format!(
+                    "Argument {} of {} should be a {}, but a {} was given",
+                    i + 1,
+                    describe_callee(callee),
+                    param.name(),
+                    arg.name()
+                ));
+            }
+            self.check_coercion(
+                arg,
+                param,
+                &format!("Argument {} of {}", i + 1, describe_callee(callee)),
+            );
+        }
+    }
+}
+
+/// Type of a function, which also accepts any number of extra arguments when
+/// its body reads them through `argument`.
+fn function_type(
+    params: &[Box<Node>],
+    return_type: &Option<Box<Node>>,
+    default_return: Type,
+    code: &Node,
+) -> Type {
+    match Type::from_signature(params, return_type, default_return) {
+        Type::Function(mut params, ret) if uses_arguments(code) => {
+            if !matches!(params.last(), Some(Type::Rest(_))) {
+                params.push(Type::Rest(Box::new(Type::Untyped)));
+            }
+            Type::Function(params, ret)
+        }
+        kind => kind,
+    }
+}
+
+/// A class is called like a constructor, with the parameters of `new`.
+fn class_type(name: &str, members: &[Box<Node>]) -> Type {
+    let params = members
+        .iter()
+        .find_map(|member| match member.as_ref() {
+            Node::ConstructorDeclaration(constructor, _) => match constructor.as_ref() {
+                Node::FunctionDeclaration(_, params, _, _) => Some(params.as_slice()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap_or(&[]);
+
+    let instance = Some(Node::TypeName(name.to_string(), Vec::new()).to_box());
+    Type::from_signature(params, &instance, Type::Untyped)
+}
+
+fn describe_callee(callee: &Node) -> String {
+    match callee {
+        Node::Identifier(name) => format!("'{name}'"),
+        _ => "This expression".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::declarations::standard_declarations;
+    use crate::compiler::parser::parse_with_positions;
+    use crate::compiler::tokenizer::tokenize;
+
+    fn check_code(code: &str) -> Result<(), Vec<Error>> {
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        let imports = HashMap::new();
+        check_module_as(
+            &program,
+            &positions,
+            &imports,
+            &HashSet::new(),
+            Strictness::Off,
+        )
+        .0
+        .map(|_| ())
+    }
+
+    fn check_with_runtime(code: &str) -> Result<(), Vec<Error>> {
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        check_module_as(
+            &program,
+            &positions,
+            &standard_declarations(),
+            &HashSet::new(),
+            Strictness::Off,
+        )
+        .0
+        .map(|_| ())
+    }
+
+    fn returned(code: &str) -> Span {
+        let start = code.find("return v;").unwrap();
+        Span::new(start, start + "return v;".len())
+    }
+
+    #[test]
+    fn conditions_narrow_unknown_values() {
+        let code = "\
+function m(v: unknown) -> number {
+    if (is_real(v)) {
+        return v + 1;
+    }
+    if (!is_string(v)) {
+        return 0;
+    }
+    return string_length(v);
+}";
+        assert_eq!(check_code(code), Ok(()));
+    }
+
+    #[test]
+    fn assigning_a_variable_forgets_its_narrowing() {
+        let code = "\
+function m(v: unknown) -> number {
+    if (is_real(v)) {
+        v = \"now a string\";
+        return v;
+    }
+    return 0;
+}";
+        assert_eq!(
+            check_code(code),
+            Err(vec![Error::at(
+                "'m' should return number, but returns a unknown",
+                returned(code)
+            )])
+        );
+
+        let code = "\
+function m(v: unknown) -> number {
+    if (!is_real(v)) {
+        return 0;
+    }
+    v = \"now a string\";
+    return v;
+}";
+        assert_eq!(
+            check_code(code),
+            Err(vec![Error::at(
+                "'m' should return number, but returns a unknown",
+                returned(code)
+            )])
+        );
+    }
+
+    #[test]
+    fn errors_point_at_their_statement() {
+        let code = "let a = 1;\nfunction f() {\n    let b: string = a;\n}\n";
+        let start = code.find("let b").unwrap();
+        assert_eq!(
+            check_code(code),
+            Err(vec![Error::at(
+                "Can't assign a number to 'b', which is declared as string",
+                Span::new(start, start + "let b: string = a;".len())
+            )])
+        );
+    }
+
+    #[test]
+    fn method_keeps_the_signature_of_the_function() {
+        let code = "\
+let cb = method(self, function(s: number) -> number { return s * 2; });
+let n: number = cb(3);
+let t: string = cb(4);";
+        let start = code.find("let t").unwrap();
+        assert_eq!(
+            check_with_runtime(code),
+            Err(vec![Error::at(
+                "Can't assign a number to 't', which is declared as string",
+                Span::new(start, start + "let t: string = cb(4);".len())
+            )])
+        );
+    }
+
+    #[test]
+    fn strings_have_no_methods() {
+        let code = "let hello = \"hello\";\nshow_message(hello.concat(\" world\"));";
+        let errors = check_with_runtime(code).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "'concat' can't be called on a string, which has no methods, \
+             use the string functions or '+' instead"
+        );
+    }
+
+    #[test]
+    fn strict_mode_rejects_members_of_numbers() {
+        let code = "let s = \"abc\";\nlet n = string_length(s.len);\nlet i = 3;\ni.foo(1);";
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        let (result, warnings) = check_module_as(
+            &program,
+            &positions,
+            &standard_declarations(),
+            &HashSet::new(),
+            Strictness::Deny,
+        );
+        let messages: Vec<String> = result
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'len' can't be used on a string, which has no fields, \
+                 use the string functions or '+' instead"
+                    .to_string(),
+                "'foo' is called on a number, which only works for instance ids, \
+                 declare it as Instance"
+                    .to_string(),
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn arrays_have_no_methods() {
+        let code =
+            "let values = [1, 2];\nlet doubled = values.map(fn(n: number) -> number => n * 2);";
+        let errors = check_with_runtime(code).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "'map' can't be called on an array, which has no methods, use array_map() instead"
+        );
+    }
+}
@@ -0,0 +1,599 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{analyze, Strictness, Type};
+use crate::compiler::declarations::standard_declarations;
+use crate::compiler::error::Error;
+use crate::compiler::ir::lower::lower;
+use crate::compiler::ir::validate::validate;
+use crate::compiler::ir::{
+    passes, Expr, Function, Module, Stmt, StmtKind, SymbolId, Symbols, TemplatePart,
+};
+use crate::compiler::optimizer::{optimize, OptLevel};
+use crate::compiler::parser::Positions;
+use crate::compiler::target::{GmlVersion, VersionedBuiltins};
+use crate::compiler::testing::LOCATION;
+use crate::front_end::get_indent;
+use std::collections::HashMap;
+
+const INDENT_SIZE: usize = 4;
+
+/// Put around the source offset of a statement, before its code, until
+/// `take_marks` removes it.
+const MARK: char = '\u{1}';
+
+/// The source offset of the statement every line of generated GML comes from.
+pub type LineOffsets = Vec<Option<usize>>;
+
+#[derive(Debug)]
+pub struct CodegenOptions {
+    pub target: GmlVersion,
+    pub opt_level: OptLevel,
+    /// Keeps the `@test` functions, which are left out otherwise, and has
+    /// their assertions call the helpers of `testing::runner`.
+    pub tests: bool,
+}
+
+impl CodegenOptions {
+    pub fn new(target: GmlVersion, opt_level: OptLevel) -> CodegenOptions {
+        CodegenOptions {
+            target,
+            opt_level,
+            tests: false,
+        }
+    }
+}
+
+pub fn generate(program: &Node, options: &CodegenOptions) -> Result<String, Vec<Error>> {
+    let (output, polyfills) = generate_module(program, options)?;
+
+    Ok(generate_polyfills(&polyfills) + &output)
+}
+
+/// Generates a program without its polyfills, returning the names of the
+/// ones it needs instead.
+pub fn generate_module(
+    program: &Node,
+    options: &CodegenOptions,
+) -> Result<(String, Vec<String>), Vec<Error>> {
+    generate_marked(program, options, Positions::new())
+}
+
+/// Like `generate_module`, also returning where every line of the GML comes
+/// from, given the positions `parse_with_positions` found.
+pub fn generate_mapped(
+    program: &Node,
+    options: &CodegenOptions,
+    positions: &Positions,
+) -> Result<(String, Vec<String>, LineOffsets), Vec<Error>> {
+    let (marked, polyfills) = generate_marked(program, options, positions.clone())?;
+    let (output, lines) = take_marks(&marked);
+
+    Ok((output, polyfills, lines))
+}
+
+/// Removes the marks of the statements, returning the code and the offset of
+/// each of its lines. A line without a mark is part of the statement before.
+fn take_marks(marked: &str) -> (String, LineOffsets) {
+    let mut code = String::with_capacity(marked.len());
+    let mut lines = Vec::new();
+    let mut current = None;
+
+    for line in marked.split_inclusive('\n') {
+        let mut parts = line.split(MARK);
+        code.push_str(parts.next().unwrap_or_default());
+
+        let mut first = None;
+        while let (Some(offset), Some(rest)) = (parts.next(), parts.next()) {
+            first = first.or(offset.parse().ok());
+            code.push_str(rest);
+        }
+        current = first.or(current);
+        lines.push(current);
+    }
+
+    (code, lines)
+}
+
+fn generate_marked(
+    program: &Node,
+    options: &CodegenOptions,
+    positions: Positions,
+) -> Result<(String, Vec<String>), Vec<Error>> {
+    let module = lower_module(program, options, positions, &standard_declarations())?;
+    let mut emitter = Emitter {
+        symbols: &module.symbols,
+        indent: 0,
+    };
+    let mut output = String::new();
+
+    for stmt in &module.body {
+        output.push_str(&emitter.statement(stmt, 0));
+        output.push('\n');
+    }
+
+    Ok((output, module.polyfills))
+}
+
+/// Optimizes a program as `options` say, then lowers it to the core of the
+/// IR, which is validated. `imports` are the types of what the program uses
+/// from other modules and the runtime.
+pub fn lower_module(
+    program: &Node,
+    options: &CodegenOptions,
+    mut positions: Positions,
+    imports: &HashMap<String, Type>,
+) -> Result<Module, Vec<Error>> {
+    let optimized;
+    let program = match options.opt_level {
+        OptLevel::O0 => program,
+        OptLevel::O1 | OptLevel::O2 => {
+            let mut copy = program.clone();
+            copy_positions(&mut positions, program, &copy);
+            optimize(&mut copy, options.opt_level);
+            optimized = copy;
+            &optimized
+        }
+    };
+
+    let globals = analyze(program, &positions, imports, Strictness::Off).globals;
+    let mut module =
+        lower(program, &positions, &globals, options.tests).map_err(|error| vec![error])?;
+    passes::run(&mut module, options)?;
+    validate(&module, options).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| Error {
+                message: format!("Invalid IR: {}", error.message),
+                ..error
+            })
+            .collect::<Vec<Error>>()
+    })?;
+
+    Ok(module)
+}
+
+/// Gives the statements of a copy of `original` the positions of the
+/// statements they were copied from. Only the root of the copy is matched
+/// when the two trees don't have the same shape.
+fn copy_positions(positions: &mut Positions, original: &Node, copy: &Node) {
+    if positions.is_empty() {
+        return;
+    }
+    if let Some(offset) = positions.get(&(original as *const Node)).copied() {
+        positions.insert(copy as *const Node, offset);
+    }
+    if std::mem::discriminant(original) == std::mem::discriminant(copy) {
+        for (original, copy) in original.children().into_iter().zip(copy.children()) {
+            copy_positions(positions, original, copy);
+        }
+    }
+}
+
+pub fn generate_polyfills(names: &[String]) -> String {
+    let mut polyfill_code = String::new();
+
+    for name in names {
+        polyfill_code.push_str(VersionedBuiltins::polyfill(name).unwrap());
+        polyfill_code.push_str("\n\n");
+    }
+
+    polyfill_code
+}
+
+/// The GML of an expression of the core of the IR, for messages.
+pub fn expression_code(symbols: &Symbols, expr: &Expr) -> String {
+    Emitter { symbols, indent: 0 }.expression(expr)
+}
+
+/// Writes the core of the IR as GML, marking where the statements come from.
+struct Emitter<'a> {
+    symbols: &'a Symbols,
+    /// Indentation of the statement being generated, so function
+    /// expressions nested in it line up.
+    indent: usize,
+}
+
+impl<'a> Emitter<'a> {
+    fn statement(&mut self, stmt: &Stmt, indent: usize) -> String {
+        let outer_indent = self.indent;
+        self.indent = indent;
+        let code = self.statement_code(stmt, indent);
+        self.indent = outer_indent;
+
+        match stmt.span {
+            Some(span) => {
+                let start = code.len() - code.trim_start().len();
+                format!(
+                    "{}{MARK}{}{MARK}{}",
+                    &code[..start],
+                    span.start,
+                    &code[start..]
+                )
+            }
+            None => code,
+        }
+    }
+
+    fn statement_code(&mut self, stmt: &Stmt, indent: usize) -> String {
+        let indent_space = get_indent(INDENT_SIZE, indent);
+
+        match &stmt.kind {
+            StmtKind::Var(id, None) => format!("{indent_space}var {};", self.name(*id)),
+            StmtKind::Var(id, Some(value)) => format!(
+                "{indent_space}var {} = {};",
+                self.name(*id),
+                self.expression(value)
+            ),
+            StmtKind::Static(id, value) => format!(
+                "{indent_space}static {} = {};",
+                self.name(*id),
+                self.expression(value)
+            ),
+            StmtKind::GlobalVar(ids) => {
+                let names: Vec<&str> = ids.iter().map(|id| self.name(*id)).collect();
+                format!("{indent_space}globalvar {};", names.join(", "))
+            }
+            StmtKind::Macro(id, value) => {
+                format!("{indent_space}#macro {} {value}", self.name(*id))
+            }
+            StmtKind::Enum(id, members) => {
+                let member_indent = get_indent(INDENT_SIZE, indent + 1);
+                let lines: Vec<String> = members
+                    .iter()
+                    .map(|(member, value)| match value {
+                        Some(value) => {
+                            format!("{member_indent}{member} = {}", self.expression(value))
+                        }
+                        None => format!("{member_indent}{member}"),
+                    })
+                    .collect();
+
+                format!(
+                    "{indent_space}enum {} {{\n{}\n{indent_space}}}",
+                    self.name(*id),
+                    lines.join(",\n")
+                )
+            }
+            StmtKind::Function(id, function) => {
+                let name = self.name(*id).to_string();
+                format!("{indent_space}{}", self.function(&name, function))
+            }
+            StmtKind::Assign(target, operator, value) => format!(
+                "{indent_space}{} {operator} {};",
+                self.expression(target),
+                self.expression(value)
+            ),
+            StmtKind::Expr(value) => format!("{indent_space}{};", self.expression(value)),
+            StmtKind::Block(body) => format!("{indent_space}{}", self.body(body, indent)),
+            StmtKind::If(condition, then, otherwise) => {
+                let mut code = format!(
+                    "{indent_space}if ({}) {}",
+                    self.expression(condition),
+                    self.body(then, indent)
+                );
+
+                match otherwise.as_deref() {
+                    Some(
+                        otherwise @ Stmt {
+                            kind: StmtKind::If(_, _, _),
+                            ..
+                        },
+                    ) => {
+                        let else_if = self.statement(otherwise, indent);
+                        code.push_str(&format!(" else {}", else_if.trim_start()));
+                    }
+                    Some(Stmt {
+                        kind: StmtKind::Block(body),
+                        ..
+                    }) => {
+                        code.push_str(&format!(" else {}", self.body(body, indent)));
+                    }
+                    Some(otherwise) => {
+                        let body = std::slice::from_ref(otherwise);
+                        code.push_str(&format!(" else {}", self.body(body, indent)));
+                    }
+                    None => {}
+                }
+
+                code
+            }
+            StmtKind::While(value, body)
+            | StmtKind::Repeat(value, body)
+            | StmtKind::With(value, body) => {
+                let keyword = match &stmt.kind {
+                    StmtKind::While(_, _) => "while",
+                    StmtKind::Repeat(_, _) => "repeat",
+                    _ => "with",
+                };
+
+                format!(
+                    "{indent_space}{keyword} ({}) {}",
+                    self.expression(value),
+                    self.body(body, indent)
+                )
+            }
+            StmtKind::DoUntil(body, condition) => format!(
+                "{indent_space}do {} until ({});",
+                self.body(body, indent),
+                self.expression(condition)
+            ),
+            StmtKind::For(init, condition, step, body) => {
+                let mut header = Vec::new();
+
+                header.push(match init {
+                    Some(init) => self.statement(init, 0).trim_end_matches(';').to_string(),
+                    None => String::new(),
+                });
+                header.push(match condition {
+                    Some(condition) => self.expression(condition),
+                    None => String::new(),
+                });
+                header.push(match step {
+                    Some(step) => self.statement(step, 0).trim_end_matches(';').to_string(),
+                    None => String::new(),
+                });
+
+                format!(
+                    "{indent_space}for ({}) {}",
+                    header.join("; ").trim_end(),
+                    self.body(body, indent)
+                )
+            }
+            StmtKind::Switch(value, cases) => {
+                let case_indent = get_indent(INDENT_SIZE, indent + 1);
+                let mut code = format!("{indent_space}switch ({}) {{\n", self.expression(value));
+
+                for (value, body) in cases {
+                    match value {
+                        Some(value) => code
+                            .push_str(&format!("{case_indent}case {}:\n", self.expression(value))),
+                        None => code.push_str(&format!("{case_indent}default:\n")),
+                    }
+
+                    for stmt in body {
+                        code.push_str(&self.statement(stmt, indent + 2));
+                        code.push('\n');
+                    }
+                }
+
+                code.push_str(&format!("{indent_space}}}"));
+                code
+            }
+            StmtKind::Try(body, catch, finally) => {
+                let mut code = format!("{indent_space}try {}", self.body(body, indent));
+
+                if let Some((id, handler)) = catch {
+                    let handler = self.body(handler, indent);
+                    code.push_str(&format!(" catch ({}) {handler}", self.name(*id)));
+                }
+                if let Some(finally) = finally {
+                    code.push_str(&format!(" finally {}", self.body(finally, indent)));
+                }
+
+                code
+            }
+            StmtKind::Throw(value) => format!("{indent_space}throw {};", self.expression(value)),
+            StmtKind::Delete(value) => format!("{indent_space}delete {};", self.expression(value)),
+            StmtKind::Return(None) => format!("{indent_space}return;"),
+            StmtKind::Return(Some(value)) => {
+                format!("{indent_space}return {};", self.expression(value))
+            }
+            StmtKind::Break => format!("{indent_space}break;"),
+            StmtKind::Continue => format!("{indent_space}continue;"),
+            StmtKind::Exit => format!("{indent_space}exit;"),
+            StmtKind::Class(_, _) => unreachable!("classes are lowered before GML is generated"),
+        }
+    }
+
+    fn name(&self, id: SymbolId) -> &'a str {
+        self.symbols.name(id)
+    }
+
+    /// Generates the body of an `if`, a loop... always between braces, with
+    /// the closing brace at `indent`.
+    fn body(&mut self, body: &[Stmt], indent: usize) -> String {
+        if body.is_empty() {
+            return "{}".to_string();
+        }
+
+        let mut code = String::from("{\n");
+        for stmt in body {
+            code.push_str(&self.statement(stmt, indent + 1));
+            code.push('\n');
+        }
+        code.push_str(&get_indent(INDENT_SIZE, indent));
+        code.push('}');
+
+        code
+    }
+
+    /// `function name(params) suffix { body }`, where the name is empty for
+    /// function expressions and the suffix holds `constructor`.
+    fn function(&mut self, name: &str, function: &Function) -> String {
+        let indent = self.indent;
+        let mut params = Vec::new();
+        let mut body = String::new();
+
+        for param in &function.params {
+            let name = self.name(param.symbol).to_string();
+            params.push(match &param.default {
+                Some(default) => format!("{name} = {}", self.expression(default)),
+                None => name,
+            });
+        }
+
+        let suffix = match &function.constructor {
+            Some(Some(parent)) => format!(" : {} constructor", self.expression(parent)),
+            Some(None) => " constructor".to_string(),
+            None => String::new(),
+        };
+
+        for stmt in &function.body {
+            body.push_str(&self.statement(stmt, indent + 1));
+            body.push('\n');
+        }
+
+        let name = if name.is_empty() {
+            String::new()
+        } else {
+            format!(" {name}")
+        };
+
+        format!(
+            "function{name}({}){suffix} {{\n{body}{}}}",
+            params.join(", "),
+            get_indent(INDENT_SIZE, indent)
+        )
+    }
+
+    fn expressions(&mut self, exprs: &[Expr]) -> String {
+        let codes: Vec<String> = exprs.iter().map(|expr| self.expression(expr)).collect();
+        codes.join(", ")
+    }
+
+    fn expression(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number(value) => value.to_string(),
+            Expr::String(txt) => format!("\"{txt}\""),
+            Expr::Bool(value) => value.to_string(),
+            Expr::Symbol(id) => self.name(*id).to_string(),
+            Expr::Binary(left, operator, right) => {
+                let left_code = self.operand(left, operator, false);
+                let right_code = self.operand(right, operator, true);
+
+                format!("{left_code} {operator} {right_code}")
+            }
+            Expr::Unary(operator, value) => {
+                format!("{operator}{}", self.postfix_operand(value))
+            }
+            Expr::Update(operator, target, true) => {
+                format!("{operator}{}", self.postfix_operand(target))
+            }
+            Expr::Update(operator, target, false) => {
+                format!("{}{operator}", self.postfix_operand(target))
+            }
+            Expr::Ternary(condition, if_true, if_false) => format!(
+                "{} ? {} : {}",
+                self.operand(condition, "?", false),
+                self.operand(if_true, "?", false),
+                self.operand(if_false, "?", false)
+            ),
+            Expr::Field(value, field) => format!("{}.{field}", self.postfix_operand(value)),
+            Expr::Index(value, accessor, indices) => {
+                let accessor = match accessor {
+                    Some(accessor) => format!("{accessor} "),
+                    None => String::new(),
+                };
+
+                format!(
+                    "{}[{accessor}{}]",
+                    self.postfix_operand(value),
+                    self.expressions(indices)
+                )
+            }
+            Expr::Array(items) => format!("[{}]", self.expressions(items)),
+            Expr::Struct(fields) if fields.is_empty() => "{}".to_string(),
+            Expr::Struct(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}", self.expression(value)))
+                    .collect();
+
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Expr::Call(callee, args) => {
+                let callee_code = match callee.as_ref() {
+                    Expr::Symbol(id) => self.name(*id).to_string(),
+                    callee => self.postfix_operand(callee),
+                };
+
+                format!("{callee_code}({})", self.expressions(args))
+            }
+            Expr::New(call) => format!("new {}", self.expression(call)),
+            Expr::Function(function) => self.function("", function),
+            Expr::Template(parts) => self.template(parts),
+            Expr::Location => LOCATION.to_string(),
+            Expr::Arrow(_, _, _) | Expr::Nullish(_, _, _) => {
+                unreachable!("arrow functions and '??' are lowered before GML is generated")
+            }
+        }
+    }
+
+    /// Generates the operand of a unary operator, a call, a member or index
+    /// access, wrapping anything that binds less tightly in parenthesis.
+    fn postfix_operand(&mut self, expr: &Expr) -> String {
+        let code = self.expression(expr);
+
+        match expr {
+            Expr::Binary(_, _, _)
+            | Expr::Ternary(_, _, _)
+            | Expr::Unary(_, _)
+            | Expr::Function(_)
+            | Expr::New(_) => format!("({code})"),
+            _ => code,
+        }
+    }
+
+    fn operand(&mut self, expr: &Expr, parent_op: &str, is_right: bool) -> String {
+        let code = self.expression(expr);
+
+        let needs_parenthesis = match expr {
+            Expr::Binary(_, operator, _) => {
+                let precedence = operator_precedence(operator);
+                let parent_precedence = operator_precedence(parent_op);
+                let is_associative =
+                    ["+", "*", "&&", "||", "^^", "&", "|", "^", "??"].contains(&parent_op);
+
+                precedence < parent_precedence
+                    || (is_right && precedence == parent_precedence && !is_associative)
+            }
+            Expr::Ternary(_, _, _) => true,
+            _ => false,
+        };
+
+        if needs_parenthesis {
+            format!("({code})")
+        } else {
+            code
+        }
+    }
+
+    fn template(&mut self, parts: &[TemplatePart]) -> String {
+        let mut code = String::from("$\"");
+
+        for part in parts {
+            match part {
+                TemplatePart::Text(txt) => {
+                    for char in txt.chars() {
+                        match char {
+                            '{' | '}' => code.push_str(&format!("{{\"{char}\"}}")),
+                            _ => code.push(char),
+                        }
+                    }
+                }
+                TemplatePart::Value(value, _) => {
+                    code.push('{');
+                    code.push_str(&self.expression(value));
+                    code.push('}');
+                }
+            }
+        }
+
+        code.push('"');
+        code
+    }
+}
+
+pub fn operator_precedence(op: &str) -> usize {
+    match op {
+        "*" | "/" | "%" | "div" => 9,
+        "+" | "-" => 8,
+        "<<" | ">>" => 7,
+        "|" | "&" | "^" => 6,
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => 5,
+        "&&" => 4,
+        "^^" => 3,
+        "||" => 2,
+        "??" => 1,
+        _ => 0,
+    }
+}
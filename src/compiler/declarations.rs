@@ -0,0 +1,87 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{check_module_as, Strictness, Type};
+use crate::compiler::error::Error;
+use crate::compiler::parser::parse_with_positions;
+use crate::compiler::tokenizer::tokenize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Signatures of GameMaker's built-in functions, constants and variables.
+pub const STANDARD_DECLARATIONS: &str = include_str!("declarations/gamemaker.d.gmpp");
+
+/// Reads a declaration file (`.d.gmpp`), which may only contain `extern`
+/// declarations, and returns the type of everything it declares.
+pub fn parse_declarations(source_code: String) -> Result<HashMap<String, Type>, Vec<Error>> {
+    let (program, positions) = tokenize(source_code)
+        .and_then(parse_with_positions)
+        .map_err(|error| vec![error])?;
+
+    if let Node::Program(body) = &program {
+        let errors: Vec<Error> = body
+            .iter()
+            .filter(|stmt| {
+                !matches!(
+                    stmt.as_ref(),
+                    Node::ExternFunction(_, _, _) | Node::ExternVariable(_, _, _)
+                )
+            })
+            .map(|stmt| {
+                Error::new("Declaration files can only contain extern declarations")
+                    .or_at(positions.get(&(stmt.as_ref() as *const Node)).copied())
+            })
+            .collect();
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+    }
+
+    check_module_as(
+        &program,
+        &positions,
+        &HashMap::new(),
+        &HashSet::new(),
+        Strictness::Off,
+    )
+    .0
+}
+
+pub fn standard_declarations() -> HashMap<String, Type> {
+    static STANDARD: OnceLock<HashMap<String, Type>> = OnceLock::new();
+
+    STANDARD
+        .get_or_init(|| {
+            parse_declarations(STANDARD_DECLARATIONS.to_string())
+                .expect("The bundled GameMaker declarations should be valid")
+        })
+        .clone()
+}
+
+/// Loads the standard declarations followed by the given declaration files,
+/// so extensions can add to or override the built-in ones.
+pub fn load_declarations(paths: &[String]) -> Result<HashMap<String, Type>, Vec<Error>> {
+    let mut declarations = standard_declarations();
+    let mut errors = Vec::new();
+
+    for path in paths {
+        let result = std::fs::read_to_string(path)
+            .map_err(|err| vec![Error::new(err.to_string())])
+            .and_then(parse_declarations);
+
+        match result {
+            Ok(file_declarations) => declarations.extend(file_declarations),
+            Err(file_errors) => errors.extend(
+                file_errors
+                    .into_iter()
+                    .map(|error| error.in_file(Path::new(path))),
+            ),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(declarations)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::Type;
+use crate::compiler::error::{Error, Span};
+use crate::compiler::parser::Positions;
+use crate::compiler::tokenizer::Language;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModuleKind {
+    /// Top-level declarations end up in GameMaker's global namespace.
+    Script,
+    /// Object event code, whose declarations belong to the instance.
+    Event,
+}
+
+/// An import statement: the names, the module and where it is written.
+pub type Import = (Vec<String>, String, Option<Span>);
+
+/// Names declared at the top level of a module, and whether they are exported.
+pub fn top_level_declarations(ast: &Node, language: Language) -> Vec<(String, bool)> {
+    let mut declarations = Vec::new();
+
+    if let Node::Program(body) = ast {
+        for stmt in body {
+            if let Some(name) = stmt.declared_name() {
+                // Plain GML has no `export`, everything it declares is global.
+                let exported = language == Language::Gml || stmt.is_exported();
+                declarations.push((name.clone(), exported));
+            }
+        }
+    }
+
+    declarations
+}
+
+/// The `import { names } from "module"` statements of a module, with where
+/// they are.
+pub fn import_statements(ast: &Node, positions: &Positions) -> Vec<Import> {
+    let mut imports = Vec::new();
+
+    if let Node::Program(body) = ast {
+        for stmt in body {
+            if let Node::ImportDeclaration(names, module) = stmt.as_ref() {
+                let span = positions.get(&(stmt.as_ref() as *const Node)).copied();
+                imports.push((names.clone(), module.clone(), span));
+            }
+        }
+    }
+
+    imports
+}
+
+/// Finds an imported module next to the importing file (`shapes.gmpp` or
+/// `../shapes/shapes.gmpp`, matching GameMaker's script folders) and then in
+/// `search_paths`. Plain `.gml` files are found the same way when there is no
+/// `.gmpp`.
+pub fn resolve_module(module: &str, importer: &Path, search_paths: &[PathBuf]) -> Option<PathBuf> {
+    let importer_dir = importer.parent()?;
+    let mut candidates = Vec::new();
+
+    for extension in ["gmpp", "gml"] {
+        let file_name = format!("{module}.{extension}");
+        let folder_file = format!("{module}/{module}.{extension}");
+
+        candidates.push(importer_dir.join(&file_name));
+        candidates.push(importer_dir.join("..").join(&folder_file));
+        for search_path in search_paths {
+            candidates.push(search_path.join(&file_name));
+            candidates.push(search_path.join(&folder_file));
+        }
+    }
+
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+/// Errors for every global name declared by more than one script, given the
+/// name and declarations of each script.
+pub fn duplicate_globals(scripts: &[(String, Vec<(String, bool)>)]) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let mut globals: HashMap<&String, &String> = HashMap::new();
+
+    for (module, declarations) in scripts {
+        for (name, _) in declarations {
+            match globals.get(name) {
+                Some(other) => errors.push(
+                    Error::new(format!(
+                        "'{name}' is declared in both {other} and {module}, scripts share GameMaker's global namespace"
+                    ))
+                    .in_file(Path::new(module)),
+                ),
+                None => {
+                    globals.insert(name, module);
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Adds the types of the names `module` imports from `dependency` to
+/// `imports`, returning an error at the import for each name it doesn't
+/// export.
+pub fn import_types(
+    module: &str,
+    (names, span): (&[String], Option<Span>),
+    dependency: &str,
+    declarations: &[(String, bool)],
+    types: Option<&HashMap<String, Type>>,
+    imports: &mut HashMap<String, Type>,
+) -> Vec<Error> {
+    let mut errors = Vec::new();
+
+    for name in names {
+        match declarations.iter().find(|(declared, _)| declared == name) {
+            Some((_, true)) => {}
+            Some((_, false)) => {
+                errors.push(
+                    Error::new(format!("'{name}' isn't exported by {dependency}"))
+                        .or_at(span)
+                        .in_file(Path::new(module)),
+                );
+                continue;
+            }
+            None => {
+                errors.push(
+                    Error::new(format!("{dependency} has no declaration named '{name}'"))
+                        .or_at(span)
+                        .in_file(Path::new(module)),
+                );
+                continue;
+            }
+        }
+
+        let kind = types
+            .and_then(|types| types.get(name))
+            .cloned()
+            .unwrap_or(Type::Untyped);
+        imports.insert(name.clone(), kind);
+    }
+
+    errors
+}
+
+/// Joins the GML of several modules into a single script, each under a
+/// comment naming its file. Also returns the line, counted from 0, each
+/// module starts at.
+pub fn join_modules(modules: &[(&Path, &str)], polyfills: String) -> (String, Vec<usize>) {
+    if let [(_, code)] = modules {
+        let first_line = polyfills.matches('\n').count();
+        return (polyfills + code, vec![first_line]);
+    }
+
+    let mut gml = polyfills;
+    let mut first_lines = Vec::new();
+    for (path, code) in modules {
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        gml.push_str(&format!("// {file_name}\n"));
+        first_lines.push(gml.matches('\n').count());
+        gml.push_str(&format!("{code}\n"));
+    }
+
+    (gml, first_lines)
+}
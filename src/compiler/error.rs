@@ -0,0 +1,105 @@
+use std::fmt;
+use std::path::Path;
+
+/// The bytes of a source some code takes, from `start` up to `end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end.max(self.start))
+    }
+
+    /// First and last line and column in `text`, counted from 1, the end
+    /// column being just past the code. Columns count characters.
+    pub fn lines(&self, text: &str) -> (usize, usize, usize, usize) {
+        let (line_start, column_start) = line_column(text, self.start);
+        let (line_end, column_end) = line_column(text, self.end);
+        (line_start, column_start, line_end, column_end)
+    }
+}
+
+/// Line and column of a byte of `text`, counted from 1. Offsets past the
+/// end, or inside a character, are moved back to where they can be.
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// An error or a warning of the compiler, with the file it is in and the
+/// code it is about when they are known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub message: String,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+}
+
+impl Error {
+    pub fn new(message: impl Into<String>) -> Error {
+        Error {
+            message: message.into(),
+            file: None,
+            span: None,
+        }
+    }
+
+    /// An error about the code at `span`.
+    pub fn at(message: impl Into<String>, span: Span) -> Error {
+        Error {
+            span: Some(span),
+            ..Error::new(message)
+        }
+    }
+
+    /// Says which file the error is in, unless it already does.
+    pub fn in_file(mut self, file: &Path) -> Error {
+        self.file.get_or_insert_with(|| file.display().to_string());
+        self
+    }
+
+    /// Points the error at `span`, unless it already points somewhere.
+    pub fn or_at(mut self, span: Option<Span>) -> Error {
+        self.span = self.span.or(span);
+        self
+    }
+}
+
+/// `file: message`, the way errors were printed before they had a span.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{file}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::new(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::new(message)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::compiler::ast::Node;
+use crate::compiler::error::Error;
+use crate::compiler::parser::parse;
+use crate::compiler::printer::{print_program, FormatOptions};
+use crate::compiler::tokenizer::{tokenize_lossless, Language, Token, Trivia};
+
+/// Reformats GML++ source, keeping its comments and single blank lines.
+/// Fails rather than lose a comment or change what the code means.
+pub fn format_source(source: String, options: &FormatOptions) -> Result<String, Error> {
+    let tokens = tokenize_lossless(source, Language::GmlPlusPlus)?;
+    let expected = token_comments(&tokens);
+    let program = parse(tokens)?;
+
+    let mut found = Vec::new();
+    collect_comments(&program, &mut found);
+    if let Some(lost) = lost_comment(expected, found) {
+        return Err(Error::new(format!(
+            "Can't keep the comment '{lost}', move it next to a statement"
+        )));
+    }
+
+    let code = print_program(&program, options)?;
+
+    let mut reparsed = tokenize_lossless(code.clone(), Language::GmlPlusPlus).and_then(parse)?;
+    let mut program = program;
+    strip_trivia(&mut program);
+    strip_trivia(&mut reparsed);
+    if reparsed != program {
+        return Err(Error::new("Formatting would change the program"));
+    }
+
+    Ok(code)
+}
+
+fn token_comments(tokens: &[Token]) -> Vec<String> {
+    let mut comments = Vec::new();
+    for token in tokens {
+        for trivia in &token.leading {
+            if let Trivia::Comment(text) = trivia {
+                comments.push(text.clone());
+            }
+        }
+        comments.extend(token.trailing.clone());
+    }
+    comments
+}
+
+fn collect_comments(node: &Node, comments: &mut Vec<String>) {
+    if let Node::Comment(text, _) = node {
+        comments.push(text.clone());
+    }
+    for child in node.children() {
+        collect_comments(child, comments);
+    }
+}
+
+/// A comment found in the tokens but not in the tree, which happens to the
+/// ones sitting inside an expression.
+fn lost_comment(mut expected: Vec<String>, mut found: Vec<String>) -> Option<String> {
+    expected.sort();
+    found.sort();
+
+    let mut found = found.into_iter().peekable();
+    for comment in expected {
+        match found.peek() {
+            Some(next) if *next == comment => {
+                found.next();
+            }
+            _ => return Some(comment),
+        }
+    }
+    None
+}
+
+fn strip_trivia(node: &mut Node) {
+    if let Node::Program(body)
+    | Node::Block(body)
+    | Node::SwitchCase(_, body)
+    | Node::ClassDeclaration(_, body) = node
+    {
+        body.retain(|stmt| !matches!(stmt.as_ref(), Node::Comment(_, _) | Node::BlankLine));
+    }
+
+    for child in node.children_mut() {
+        strip_trivia(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+// Player movement
+let speed:number=4;
+
+
+/* Moves the player */
+function move(dx:number,dy:number){ // trailing
+  x+=dx*speed;   y += dy * speed;
+    // done
+}
+let total = move(100000000, 200000000) + move(300000000, 400000000);
+";
+
+    const FORMATTED: &str = "\
+// Player movement
+let speed: number = 4;
+
+/* Moves the player */
+function move(dx: number, dy: number) {
+    // trailing
+    x += dx * speed;
+    y += dy * speed;
+    // done
+}
+
+let total = move(100000000, 200000000) + move(300000000, 400000000);
+";
+
+    fn format(source: &str, options: &FormatOptions) -> Result<String, Error> {
+        format_source(source.to_string(), options)
+    }
+
+    #[test]
+    fn comments_and_single_blank_lines_are_kept() {
+        assert_eq!(
+            format(SOURCE, &FormatOptions::default()),
+            Ok(FORMATTED.to_string())
+        );
+    }
+
+    #[test]
+    fn formatting_twice_changes_nothing() {
+        for options in [FormatOptions::default(), FormatOptions::new(2, 40)] {
+            let once = format(SOURCE, &options).unwrap();
+            assert_eq!(format(&once, &options), Ok(once));
+        }
+    }
+
+    #[test]
+    fn comments_inside_expressions_are_an_error() {
+        let source = "let x = 1 + /* one */ 2;\n";
+        assert_eq!(
+            format(source, &FormatOptions::default()).map_err(|error| error.message),
+            Err(String::from(
+                "Can't keep the comment '/* one */', move it next to a statement"
+            ))
+        );
+    }
+}
@@ -0,0 +1,1071 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{check_module_as, Strictness, Type};
+use crate::compiler::codegen::{generate_mapped, generate_polyfills, CodegenOptions, LineOffsets};
+use crate::compiler::driver::{
+    duplicate_globals, import_statements, import_types, resolve_module, top_level_declarations,
+    Import, ModuleKind,
+};
+use crate::compiler::error::{Error, Span};
+use crate::compiler::parser::{parse_with_positions_as, Positions};
+use crate::compiler::source_map::LineTable;
+use crate::compiler::testing::fill_locations;
+use crate::compiler::tokenizer::{tokenize_as, Language};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A stable FNV-1a hash of `parts`, salted with the compiler version so a new
+/// release never reuses what an older one cached.
+pub fn content_hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for part in std::iter::once(env!("CARGO_PKG_VERSION")).chain(parts.iter().copied()) {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    hash
+}
+
+/// What is remembered about a source file between builds.
+struct FileRecord {
+    path: PathBuf,
+    source_hash: u64,
+    language: Language,
+    /// Imports as written, `(names, module, span)`.
+    imports: Vec<Import>,
+    declarations: Vec<(String, bool)>,
+    /// Types the file declares and the warnings found checking it, with the
+    /// key of the inputs they were checked against.
+    checked: Option<(u64, HashMap<String, Type>, Vec<Error>)>,
+    /// Generated GML, the polyfills it needs and where each of its lines
+    /// comes from, with the key of its inputs.
+    generated: Option<(u64, String, Vec<String>, LineTable)>,
+}
+
+/// A file reached from the entries during one build.
+struct LoadedFile {
+    path: PathBuf,
+    kind: ModuleKind,
+    source: String,
+    /// The names imported, the file they come from and where the import is.
+    imports: Vec<(Vec<String>, PathBuf, Option<Span>)>,
+}
+
+/// Where a build spent its time and how much work it could skip.
+#[derive(Default)]
+pub struct Timings {
+    pub load: Duration,
+    pub check: Duration,
+    pub generate: Duration,
+    pub files: usize,
+    pub parsed: usize,
+    pub checked: usize,
+    pub generated: usize,
+}
+
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let files = self.files;
+        writeln!(
+            f,
+            "load      {:>10.2?}  {files} files, {} parsed",
+            self.load, self.parsed
+        )?;
+        writeln!(
+            f,
+            "check     {:>10.2?}  {} of {files} files checked",
+            self.check, self.checked
+        )?;
+        write!(
+            f,
+            "generate  {:>10.2?}  {} of {files} files generated",
+            self.generate, self.generated
+        )
+    }
+}
+
+/// A module of the build together with its GML.
+pub struct BuiltModule {
+    pub path: PathBuf,
+    pub kind: ModuleKind,
+    pub language: Language,
+    pub gml: String,
+    /// The source line and column of every line of `gml`.
+    pub lines: LineTable,
+}
+
+/// Memoizes parsing, checking and code generation per file. Every result is
+/// keyed by the hash of what it was computed from, so a file is only parsed
+/// again when it changes, and only checked again when it or the types it
+/// imports change. Records can be kept in a cache directory between runs.
+pub struct Database {
+    cache_dir: Option<PathBuf>,
+    records: HashMap<PathBuf, FileRecord>,
+    /// Syntax trees and the positions of their statements by source hash,
+    /// for files parsed in this process.
+    trees: HashMap<u64, (Node, Positions)>,
+    /// Records changed since they were last written to the cache directory.
+    dirty: HashSet<PathBuf>,
+    /// Whether a `.gmpp` file is checked in strict mode.
+    strict: Box<dyn Fn(&Path) -> bool>,
+    pub timings: Timings,
+    /// Warnings of the last build.
+    pub warnings: Vec<Error>,
+}
+
+impl Database {
+    /// A database only living in memory, for a single run or a watcher.
+    pub fn new() -> Database {
+        Database {
+            cache_dir: None,
+            records: HashMap::new(),
+            trees: HashMap::new(),
+            dirty: HashSet::new(),
+            strict: Box::new(|_| false),
+            timings: Timings::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Sets which files strict mode applies to. Files outside of it report
+    /// what strict mode rejects as warnings.
+    pub fn set_strict(&mut self, strict: impl Fn(&Path) -> bool + 'static) {
+        self.strict = Box::new(strict);
+    }
+
+    /// A database reading and writing its records in `cache_dir`. Records that
+    /// can't be read are ignored, their files are simply compiled again.
+    pub fn open(cache_dir: &Path) -> Database {
+        let mut database = Database::new();
+        database.cache_dir = Some(cache_dir.to_path_buf());
+
+        let entries = std::fs::read_dir(cache_dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_else(|_| Vec::new());
+
+        for entry in entries {
+            if entry
+                .extension()
+                .is_none_or(|extension| extension != "cache")
+            {
+                continue;
+            }
+
+            let record = std::fs::read_to_string(&entry)
+                .ok()
+                .and_then(|text| read_record(&text));
+            if let Some(record) = record {
+                database.records.insert(record.path.clone(), record);
+            }
+        }
+
+        database
+    }
+
+    /// Loads the entry files and everything they import, checks them against
+    /// the runtime `declarations` the way `gmpp` always has, and generates
+    /// their GML. Returns the modules, dependencies first, and the polyfills
+    /// they need. `language` overrides the language of the entry files.
+    pub fn build(
+        &mut self,
+        entries: &[(PathBuf, ModuleKind)],
+        search_paths: &[PathBuf],
+        language: Option<Language>,
+        declarations: &HashMap<String, Type>,
+        options: &CodegenOptions,
+    ) -> Result<(Vec<BuiltModule>, String), Vec<Error>> {
+        let result = self
+            .load_and_check(entries, search_paths, language, declarations)
+            .and_then(|files| {
+                let start = Instant::now();
+                let generated = self.generate(&files, options);
+                self.timings.generate = start.elapsed();
+                generated
+            });
+
+        self.finish();
+        result
+    }
+
+    /// Like `build`, without generating any GML.
+    pub fn check(
+        &mut self,
+        entries: &[(PathBuf, ModuleKind)],
+        search_paths: &[PathBuf],
+        language: Option<Language>,
+        declarations: &HashMap<String, Type>,
+    ) -> Result<(), Vec<Error>> {
+        let result = self
+            .load_and_check(entries, search_paths, language, declarations)
+            .map(|_| ());
+
+        self.finish();
+        result
+    }
+
+    /// Types of the top-level declarations of every checked file.
+    pub fn exported_types(&self) -> HashMap<String, Type> {
+        self.records
+            .values()
+            .filter_map(|record| record.checked.as_ref())
+            .flat_map(|(_, types, _)| types.clone())
+            .collect()
+    }
+
+    fn load_and_check(
+        &mut self,
+        entries: &[(PathBuf, ModuleKind)],
+        search_paths: &[PathBuf],
+        language: Option<Language>,
+        declarations: &HashMap<String, Type>,
+    ) -> Result<Vec<LoadedFile>, Vec<Error>> {
+        self.timings = Timings::default();
+        self.warnings.clear();
+
+        let start = Instant::now();
+        let files = self.load(entries, search_paths, language);
+        self.timings.load = start.elapsed();
+        let files = files?;
+        self.timings.files = files.len();
+
+        let start = Instant::now();
+        let checked = self.check_files(&files, declarations);
+        self.timings.check = start.elapsed();
+
+        checked.map(|_| files)
+    }
+
+    /// Forgets the trees of files that changed and saves the records.
+    fn finish(&mut self) {
+        let used: HashSet<u64> = self
+            .records
+            .values()
+            .map(|record| record.source_hash)
+            .collect();
+        self.trees.retain(|hash, _| used.contains(hash));
+        self.save();
+    }
+
+    fn load(
+        &mut self,
+        entries: &[(PathBuf, ModuleKind)],
+        search_paths: &[PathBuf],
+        language: Option<Language>,
+    ) -> Result<Vec<LoadedFile>, Vec<Error>> {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+
+        for (path, kind) in entries {
+            let visited = self.visit(
+                path,
+                *kind,
+                language,
+                search_paths,
+                &mut Vec::new(),
+                &mut files,
+            );
+            if let Err(error) = visited {
+                errors.push(error);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(files)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn visit(
+        &mut self,
+        path: &Path,
+        kind: ModuleKind,
+        language: Option<Language>,
+        search_paths: &[PathBuf],
+        stack: &mut Vec<PathBuf>,
+        files: &mut Vec<LoadedFile>,
+    ) -> Result<(), Error> {
+        let path =
+            std::fs::canonicalize(path).map_err(|err| Error::new(err.to_string()).in_file(path))?;
+
+        if let Some(index) = stack.iter().position(|visiting| *visiting == path) {
+            let cycle: Vec<String> = stack[index..]
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|module| module.display().to_string())
+                .collect();
+
+            return Err(Error::new(format!(
+                "Import cycle detected: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        if files.iter().any(|file| file.path == path) {
+            return Ok(());
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|err| Error::new(err.to_string()).in_file(&path))?;
+        let language = language.unwrap_or(Language::from_path(&path));
+        let source_hash = content_hash(&[&source, language.name()]);
+
+        let unchanged = self
+            .records
+            .get(&path)
+            .is_some_and(|record| record.source_hash == source_hash);
+
+        if !unchanged {
+            let tree = tokenize_as(source.clone(), language)
+                .and_then(|tokens| parse_with_positions_as(tokens, language))
+                .map_err(|error| error.in_file(&path))?;
+            self.timings.parsed += 1;
+
+            let record = FileRecord {
+                path: path.clone(),
+                source_hash,
+                language,
+                imports: import_statements(&tree.0, &tree.1),
+                declarations: top_level_declarations(&tree.0, language),
+                checked: None,
+                generated: None,
+            };
+            self.trees.insert(source_hash, tree);
+            self.records.insert(path.clone(), record);
+            self.dirty.insert(path.clone());
+        }
+
+        let mut imports = Vec::new();
+        for (names, module, span) in &self.records[&path].imports {
+            let resolved = resolve_module(module, &path, search_paths).ok_or_else(|| {
+                Error::new(format!("Couldn't find the module '{module}'"))
+                    .or_at(*span)
+                    .in_file(&path)
+            })?;
+            imports.push((names.clone(), resolved, *span));
+        }
+
+        stack.push(path.clone());
+        for (_, dependency, span) in &imports {
+            self.visit(
+                dependency,
+                ModuleKind::Script,
+                None,
+                search_paths,
+                stack,
+                files,
+            )
+            // A cycle is reported at the import closing it.
+            .map_err(|error| match error.file {
+                None => error.or_at(*span).in_file(&path),
+                Some(_) => error,
+            })?;
+        }
+        stack.pop();
+
+        let imports = imports
+            .into_iter()
+            .map(|(names, dependency, span)| {
+                (names, std::fs::canonicalize(dependency).unwrap(), span)
+            })
+            .collect();
+
+        files.push(LoadedFile {
+            path,
+            kind,
+            source,
+            imports,
+        });
+
+        Ok(())
+    }
+
+    fn check_files(
+        &mut self,
+        files: &[LoadedFile],
+        declarations: &HashMap<String, Type>,
+    ) -> Result<(), Vec<Error>> {
+        let scripts: Vec<(String, Vec<(String, bool)>)> = files
+            .iter()
+            .filter(|file| file.kind == ModuleKind::Script)
+            .map(|file| {
+                let record = &self.records[&file.path];
+                (file.path.display().to_string(), record.declarations.clone())
+            })
+            .collect();
+        let mut errors = duplicate_globals(&scripts);
+
+        let declarations_hash = content_hash(&[&type_map_key(declarations)]);
+        let mut checked: HashMap<&PathBuf, HashMap<String, Type>> = HashMap::new();
+        let mut gml_globals: HashMap<String, Type> = HashMap::new();
+
+        let (gml_scripts, others): (Vec<&LoadedFile>, Vec<&LoadedFile>) =
+            files.iter().partition(|file| {
+                self.records[&file.path].language == Language::Gml
+                    && file.kind == ModuleKind::Script
+            });
+        let gml_script_count = gml_scripts.len();
+
+        for (index, file) in gml_scripts.iter().chain(&others).enumerate() {
+            let name = file.path.display().to_string();
+            // A declaration of a GML script function gives it the types the
+            // script can't.
+            let mut visible: HashMap<String, Type> = gml_globals
+                .iter()
+                .filter(|(global, _)| !declarations.contains_key(*global))
+                .map(|(global, kind)| (global.clone(), kind.clone()))
+                .collect();
+            let gml_functions: HashSet<String> = visible
+                .iter()
+                .filter(|(_, kind)| matches!(kind, Type::Function(_, _)))
+                .map(|(global, _)| global.clone())
+                .collect();
+
+            for (names, dependency, span) in &file.imports {
+                errors.extend(import_types(
+                    &name,
+                    (names, *span),
+                    &dependency.display().to_string(),
+                    &self.records[dependency].declarations,
+                    checked.get(dependency),
+                    &mut visible,
+                ));
+            }
+
+            let record = &self.records[&file.path];
+            let strictness = match record.language {
+                Language::Gml => Strictness::Off,
+                Language::GmlPlusPlus if (self.strict)(&file.path) => Strictness::Deny,
+                Language::GmlPlusPlus => Strictness::Warn,
+            };
+            let key = content_hash(&[
+                &record.source_hash.to_string(),
+                &declarations_hash.to_string(),
+                &type_map_key(&visible),
+                strictness.name(),
+            ]);
+
+            let (types, warnings) = match &record.checked {
+                Some((checked_key, types, warnings)) if *checked_key == key => {
+                    (Ok(types.clone()), warnings.clone())
+                }
+                _ => {
+                    self.timings.checked += 1;
+                    let (tree, positions) = self.tree(file)?;
+
+                    let mut imports = declarations.clone();
+                    imports.extend(visible);
+                    check_module_as(tree, positions, &imports, &gml_functions, strictness)
+                }
+            };
+
+            for warning in &warnings {
+                self.warnings.push(warning.clone().in_file(&file.path));
+            }
+
+            match types {
+                Ok(types) => {
+                    if index < gml_script_count {
+                        gml_globals.extend(types.clone());
+                    }
+
+                    let record = self.records.get_mut(&file.path).unwrap();
+                    if record
+                        .checked
+                        .as_ref()
+                        .is_none_or(|(old, _, _)| *old != key)
+                    {
+                        record.checked = Some((key, types.clone(), warnings));
+                        self.dirty.insert(file.path.clone());
+                    }
+                    checked.insert(&file.path, types);
+                }
+                Err(file_errors) => errors.extend(
+                    file_errors
+                        .into_iter()
+                        .map(|error| error.in_file(&file.path)),
+                ),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn generate(
+        &mut self,
+        files: &[LoadedFile],
+        options: &CodegenOptions,
+    ) -> Result<(Vec<BuiltModule>, String), Vec<Error>> {
+        let options_key = format!("{options:?}");
+        let mut outputs = Vec::new();
+        let mut polyfills: Vec<String> = Vec::new();
+        let mut errors = Vec::new();
+
+        for file in files {
+            let record = &self.records[&file.path];
+            let language = record.language;
+            let key = content_hash(&[&record.source_hash.to_string(), &options_key]);
+
+            let generated = match &record.generated {
+                Some((generated_key, gml, names, lines)) if *generated_key == key => {
+                    Ok((gml.clone(), names.clone(), lines.clone()))
+                }
+                _ => {
+                    self.timings.generated += 1;
+                    self.tree(file)
+                        .and_then(|(tree, positions)| generate_mapped(tree, options, positions))
+                        .map(|(gml, names, offsets)| {
+                            let lines = line_table(&file.source, &offsets);
+                            // Failures are read where the tests run, so paths
+                            // are shown from there when they can be.
+                            let gml = if options.tests {
+                                let path = std::env::current_dir()
+                                    .ok()
+                                    .and_then(|cwd| file.path.strip_prefix(cwd).ok())
+                                    .unwrap_or(&file.path);
+                                fill_locations(&gml, &path.to_string_lossy(), &lines)
+                            } else {
+                                gml
+                            };
+                            (gml, names, lines)
+                        })
+                        .inspect(|(gml, names, lines)| {
+                            let record = self.records.get_mut(&file.path).unwrap();
+                            record.generated =
+                                Some((key, gml.clone(), names.clone(), lines.clone()));
+                            self.dirty.insert(file.path.clone());
+                        })
+                }
+            };
+
+            match generated {
+                Ok((gml, module_polyfills, lines)) => {
+                    for polyfill in module_polyfills {
+                        if !polyfills.contains(&polyfill) {
+                            polyfills.push(polyfill);
+                        }
+                    }
+                    outputs.push(BuiltModule {
+                        path: file.path.clone(),
+                        kind: file.kind,
+                        language,
+                        gml,
+                        lines,
+                    });
+                }
+                Err(failed) => {
+                    errors.extend(failed.into_iter().map(|error| error.in_file(&file.path)))
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok((outputs, generate_polyfills(&polyfills)))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The syntax tree of a loaded file, parsing it if only its record was
+    /// known.
+    fn tree(&mut self, file: &LoadedFile) -> Result<&(Node, Positions), Vec<Error>> {
+        let record = &self.records[&file.path];
+        let hash = record.source_hash;
+
+        if !self.trees.contains_key(&hash) {
+            let tree = tokenize_as(file.source.clone(), record.language)
+                .and_then(|tokens| parse_with_positions_as(tokens, record.language))
+                .map_err(|error| vec![error.in_file(&file.path)])?;
+            self.timings.parsed += 1;
+            self.trees.insert(hash, tree);
+        }
+
+        Ok(&self.trees[&hash])
+    }
+
+    /// Writes the records that changed to the cache directory. The cache only
+    /// saves time, so failing to write it doesn't fail the build.
+    fn save(&mut self) {
+        let Some(cache_dir) = &self.cache_dir else {
+            self.dirty.clear();
+            return;
+        };
+
+        if std::fs::create_dir_all(cache_dir).is_err() {
+            return;
+        }
+        let ignore = cache_dir.join(".gitignore");
+        if !ignore.exists() {
+            let _ = std::fs::write(ignore, "*\n");
+        }
+
+        for path in self.dirty.drain() {
+            if let Some(record) = self.records.get(&path) {
+                let file_name = format!("{:016x}.cache", content_hash(&[&path.to_string_lossy()]));
+                let _ = std::fs::write(cache_dir.join(file_name), write_record(record));
+            }
+        }
+    }
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database::new()
+    }
+}
+
+/// Turns the source offsets of generated lines into lines and columns.
+fn line_table(source: &str, offsets: &LineOffsets) -> LineTable {
+    offsets
+        .iter()
+        .map(|offset| {
+            let before = source.get(..(*offset)?)?;
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            Some((
+                before.matches('\n').count(),
+                before[line_start..].chars().count(),
+            ))
+        })
+        .collect()
+}
+
+/// A key for a set of types, the same whatever order they were inserted in.
+fn type_map_key(types: &HashMap<String, Type>) -> String {
+    let mut entries: Vec<(&String, &Type)> = types.iter().collect();
+    entries.sort_by_key(|(name, _)| *name);
+
+    let mut key = String::new();
+    for (name, kind) in entries {
+        key.push_str(name);
+        key.push(' ');
+        write_type(kind, &mut key);
+        key.push('\n');
+    }
+    key
+}
+
+/// Records are text files of tab separated lines, ending with the generated
+/// GML after a `gml` line. The `lines` line lists where each line of it comes
+/// from, as `line:column` or `-`.
+fn write_record(record: &FileRecord) -> String {
+    let mut text = format!(
+        "path\t{}\nsource\t{}\nlanguage\t{}\n",
+        record.path.display(),
+        record.source_hash,
+        record.language.name()
+    );
+
+    for (names, module, span) in &record.imports {
+        text.push_str(&format!(
+            "import\t{module}\t{}\t{}\n",
+            names.join(","),
+            write_span(*span)
+        ));
+    }
+    for (name, exported) in &record.declarations {
+        text.push_str(&format!("declare\t{name}\t{exported}\n"));
+    }
+
+    if let Some((key, types, warnings)) = &record.checked {
+        text.push_str(&format!("checked\t{key}\n"));
+        for warning in warnings {
+            text.push_str(&format!(
+                "warning\t{}\t{}\n",
+                write_span(warning.span),
+                warning.message.replace(['\t', '\n'], " ")
+            ));
+        }
+
+        let mut types: Vec<(&String, &Type)> = types.iter().collect();
+        types.sort_by_key(|(name, _)| *name);
+        for (name, kind) in types {
+            let mut serialized = String::new();
+            write_type(kind, &mut serialized);
+            text.push_str(&format!("type\t{name}\t{serialized}\n"));
+        }
+    }
+
+    if let Some((key, gml, polyfills, lines)) = &record.generated {
+        text.push_str(&format!("generated\t{key}\n"));
+        for polyfill in polyfills {
+            text.push_str(&format!("polyfill\t{polyfill}\n"));
+        }
+        let lines: Vec<String> = lines
+            .iter()
+            .map(|position| match position {
+                Some((line, column)) => format!("{line}:{column}"),
+                None => String::from("-"),
+            })
+            .collect();
+        text.push_str(&format!("lines\t{}\n", lines.join(",")));
+        text.push_str("gml\n");
+        text.push_str(gml);
+    }
+
+    text
+}
+
+fn read_record(text: &str) -> Option<FileRecord> {
+    let mut path = None;
+    let mut source_hash = None;
+    let mut language = None;
+    let mut imports = Vec::new();
+    let mut declarations = Vec::new();
+    let mut checked: Option<(u64, HashMap<String, Type>, Vec<Error>)> = None;
+    let mut generated: Option<(u64, String, Vec<String>, Option<LineTable>)> = None;
+
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        rest = next;
+
+        let mut fields = line.split('\t');
+        match (fields.next()?, fields.next(), fields.next()) {
+            ("path", Some(value), None) => path = Some(PathBuf::from(value)),
+            ("source", Some(value), None) => source_hash = Some(value.parse().ok()?),
+            ("language", Some(value), None) => language = Language::from_name(value).ok(),
+            ("import", Some(module), Some(names)) => imports.push((
+                names.split(',').map(|name| name.to_string()).collect(),
+                module.to_string(),
+                read_span(fields.next()?)?,
+            )),
+            ("declare", Some(name), Some(exported)) => {
+                declarations.push((name.to_string(), exported.parse().ok()?))
+            }
+            ("checked", Some(key), None) => {
+                checked = Some((key.parse().ok()?, HashMap::new(), Vec::new()))
+            }
+            ("warning", Some(span), Some(message)) => {
+                let warning = Error::new(message).or_at(read_span(span)?);
+                checked.as_mut()?.2.push(warning);
+            }
+            ("type", Some(name), Some(kind)) => {
+                let kind = read_type(&mut kind.split(' '))?;
+                checked.as_mut()?.1.insert(name.to_string(), kind);
+            }
+            ("generated", Some(key), None) => {
+                generated = Some((key.parse().ok()?, String::new(), Vec::new(), None))
+            }
+            ("polyfill", Some(name), None) => generated.as_mut()?.2.push(name.to_string()),
+            ("lines", Some(lines), None) => generated.as_mut()?.3 = Some(read_lines(lines)?),
+            ("gml", None, None) => {
+                generated.as_mut()?.1 = rest.to_string();
+                break;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(FileRecord {
+        path: path?,
+        source_hash: source_hash?,
+        language: language?,
+        imports,
+        declarations,
+        checked,
+        // Records from before source maps are generated again.
+        generated: generated
+            .and_then(|(key, gml, polyfills, lines)| Some((key, gml, polyfills, lines?))),
+    })
+}
+
+/// A span written `start-end`, or `-` for none.
+fn write_span(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{}-{}", span.start, span.end),
+        None => String::from("-"),
+    }
+}
+
+fn read_span(text: &str) -> Option<Option<Span>> {
+    if text == "-" {
+        return Some(None);
+    }
+    let (start, end) = text.split_once('-')?;
+    Some(Some(Span::new(start.parse().ok()?, end.parse().ok()?)))
+}
+
+fn read_lines(text: &str) -> Option<LineTable> {
+    text.split(',')
+        .filter(|position| !position.is_empty())
+        .map(|position| match position.split_once(':') {
+            Some((line, column)) => Some(Some((line.parse().ok()?, column.parse().ok()?))),
+            None if position == "-" => Some(None),
+            None => None,
+        })
+        .collect()
+}
+
+/// Writes a type in prefix notation, unlike `Type::name` keeping everything
+/// needed to read it back.
+fn write_type(kind: &Type, out: &mut String) {
+    match kind {
+        Type::Number => out.push_str("number"),
+        Type::String => out.push_str("string"),
+        Type::Bool => out.push_str("bool"),
+        Type::Void => out.push_str("void"),
+        Type::Untyped => out.push_str("untyped"),
+        Type::Unknown => out.push_str("unknown"),
+        Type::Array(item) => {
+            out.push_str("array ");
+            write_type(item, out);
+        }
+        Type::Optional(inner) => {
+            out.push_str("optional ");
+            write_type(inner, out);
+        }
+        Type::Rest(inner) => {
+            out.push_str("rest ");
+            write_type(inner, out);
+        }
+        Type::Named(name, args) => {
+            out.push_str(&format!("named {name} {}", args.len()));
+            for arg in args {
+                out.push(' ');
+                write_type(arg, out);
+            }
+        }
+        Type::Function(params, ret) => {
+            out.push_str(&format!("fn {}", params.len()));
+            for param in params {
+                out.push(' ');
+                write_type(param, out);
+            }
+            out.push(' ');
+            write_type(ret, out);
+        }
+    }
+}
+
+fn read_type<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<Type> {
+    match words.next()? {
+        "number" => Some(Type::Number),
+        "string" => Some(Type::String),
+        "bool" => Some(Type::Bool),
+        "void" => Some(Type::Void),
+        "untyped" => Some(Type::Untyped),
+        "unknown" => Some(Type::Unknown),
+        "array" => Some(Type::Array(Box::new(read_type(words)?))),
+        "optional" => Some(Type::Optional(Box::new(read_type(words)?))),
+        "rest" => Some(Type::Rest(Box::new(read_type(words)?))),
+        "named" => {
+            let name = words.next()?.to_string();
+            let count = words.next()?.parse().ok()?;
+            Some(Type::Named(name, read_types(words, count)?))
+        }
+        "fn" => {
+            let count = words.next()?.parse().ok()?;
+            let params = read_types(words, count)?;
+            Some(Type::Function(params, Box::new(read_type(words)?)))
+        }
+        _ => None,
+    }
+}
+
+fn read_types<'a>(words: &mut impl Iterator<Item = &'a str>, count: usize) -> Option<Vec<Type>> {
+    (0..count).map(|_| read_type(words)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A folder of its own in the temporary directory, holding `files`.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("gmpp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        for (file, code) in files {
+            std::fs::write(folder.join(file), code).unwrap();
+        }
+        std::fs::canonicalize(folder).unwrap()
+    }
+
+    fn check(folder: &Path, entries: &[&str]) -> Result<(), Vec<Error>> {
+        check_with(&mut Database::new(), folder, entries)
+    }
+
+    fn check_with(
+        database: &mut Database,
+        folder: &Path,
+        entries: &[&str],
+    ) -> Result<(), Vec<Error>> {
+        let entries: Vec<(PathBuf, ModuleKind)> = entries
+            .iter()
+            .map(|entry| (folder.join(entry), ModuleKind::Script))
+            .collect();
+        database.check(&entries, &[], None, &HashMap::new())
+    }
+
+    fn at(folder: &Path, file: &str, message: &str, code: &str, written: &str) -> Error {
+        let start = code.find(written).unwrap();
+        Error::at(message, Span::new(start, start + written.len())).in_file(&folder.join(file))
+    }
+
+    #[test]
+    fn import_errors_point_at_the_import() {
+        let main = "let a = 1;\nimport { b } from \"missing\";\n";
+        let folder = project("missing-import", &[("main.gmpp", main)]);
+        assert_eq!(
+            check(&folder, &["main.gmpp"]),
+            Err(vec![at(
+                &folder,
+                "main.gmpp",
+                "Couldn't find the module 'missing'",
+                main,
+                "import { b } from \"missing\";"
+            )])
+        );
+
+        let main = "import { hidden, shown } from \"lib\";\n";
+        let lib = "let hidden = 1;\nexport let shown = 2;\n";
+        let folder = project(
+            "unexported-import",
+            &[("main.gmpp", main), ("lib.gmpp", lib)],
+        );
+        let lib_path = folder.join("lib.gmpp").display().to_string();
+        assert_eq!(
+            check(&folder, &["main.gmpp"]),
+            Err(vec![at(
+                &folder,
+                "main.gmpp",
+                &format!("'hidden' isn't exported by {lib_path}"),
+                main,
+                main.trim_end()
+            )])
+        );
+    }
+
+    #[test]
+    fn detects_import_cycles() {
+        let a = "import { b } from \"b\";\nexport let a = 1;\n";
+        let b = "import { a } from \"a\";\nexport let b = 2;\n";
+        let folder = project("cycle", &[("a.gmpp", a), ("b.gmpp", b)]);
+        let (a_path, b_path) = (folder.join("a.gmpp"), folder.join("b.gmpp"));
+        assert_eq!(
+            check(&folder, &["a.gmpp"]),
+            Err(vec![at(
+                &folder,
+                "b.gmpp",
+                &format!(
+                    "Import cycle detected: {} -> {} -> {}",
+                    a_path.display(),
+                    b_path.display(),
+                    a_path.display()
+                ),
+                b,
+                "import { a } from \"a\";"
+            )])
+        );
+    }
+
+    #[test]
+    fn detects_globals_declared_by_two_scripts() {
+        let folder = project(
+            "collision",
+            &[
+                ("one.gmpp", "export function shared() {}\n"),
+                ("two.gmpp", "function shared() {}\n"),
+            ],
+        );
+        let (one, two) = (folder.join("one.gmpp"), folder.join("two.gmpp"));
+        assert_eq!(
+            check(&folder, &["one.gmpp", "two.gmpp"]),
+            Err(vec![Error::new(format!(
+                "'shared' is declared in both {} and {}, scripts share GameMaker's global namespace",
+                one.display(),
+                two.display()
+            ))
+            .in_file(&two)])
+        );
+    }
+
+    #[test]
+    fn enums_and_macros_can_be_exported() {
+        let main = "import { Color, SIZE } from \"lib\";\nlet n = Color.Green + SIZE;\n";
+        let lib = "export enum Color { Red, Green }\nexport #macro SIZE 32\n";
+        let folder = project("export-enum", &[("main.gmpp", main), ("lib.gmpp", lib)]);
+        assert_eq!(check(&folder, &["main.gmpp"]), Ok(()));
+    }
+
+    #[test]
+    fn only_files_whose_imports_changed_are_checked_again() {
+        let main = "import { area } from \"lib\";\nlet a: number = area(2);\n";
+        let lib = "export function area(n: number) -> number {\n    return n * n;\n}\n";
+        let other = "let b = 1;\n";
+        let folder = project(
+            "invalidation",
+            &[
+                ("main.gmpp", main),
+                ("lib.gmpp", lib),
+                ("other.gmpp", other),
+            ],
+        );
+        let entries = ["main.gmpp", "other.gmpp"];
+        let mut database = Database::new();
+        let checked = |database: &mut Database| {
+            let result = check_with(database, &folder, &entries);
+            (result, database.timings.parsed, database.timings.checked)
+        };
+
+        assert_eq!(checked(&mut database), (Ok(()), 3, 3));
+        assert_eq!(checked(&mut database), (Ok(()), 0, 0));
+
+        // A new body with the same types leaves the importer alone.
+        let lib = "export function area(n: number) -> number {\n    return n * n * 1;\n}\n";
+        std::fs::write(folder.join("lib.gmpp"), lib).unwrap();
+        assert_eq!(checked(&mut database), (Ok(()), 1, 1));
+
+        // New types check the importer again, which now has an error.
+        let lib = "export function area(n: number) -> string {\n    return \"big\";\n}\n";
+        std::fs::write(folder.join("lib.gmpp"), lib).unwrap();
+        let (result, parsed, checked_files) = checked(&mut database);
+        let files: Vec<Option<String>> = result
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.file)
+            .collect();
+        let main_path = folder.join("main.gmpp").display().to_string();
+        assert_eq!(files, [Some(main_path)]);
+        assert_eq!((parsed, checked_files), (1, 2));
+    }
+
+    #[test]
+    fn records_are_reused_between_runs() {
+        let main = "import { size } from \"lib\";\nlet a = size + 1;\n";
+        let lib = "export let size = 2;\n";
+        let folder = project("cache-dir", &[("main.gmpp", main), ("lib.gmpp", lib)]);
+        let cache_dir = folder.join(".gmpp-cache");
+        std::fs::create_dir_all(&cache_dir).unwrap();
+
+        let mut database = Database::open(&cache_dir);
+        assert_eq!(check_with(&mut database, &folder, &["main.gmpp"]), Ok(()));
+        assert_eq!(database.timings.checked, 2);
+
+        let mut database = Database::open(&cache_dir);
+        assert_eq!(check_with(&mut database, &folder, &["main.gmpp"]), Ok(()));
+        assert_eq!(database.timings.checked, 0);
+
+        // The cached importer is checked against the new type of `size`.
+        std::fs::write(folder.join("lib.gmpp"), "export let size = \"big\";\n").unwrap();
+        let mut database = Database::open(&cache_dir);
+        assert_eq!(
+            check_with(&mut database, &folder, &["main.gmpp"]),
+            Err(vec![at(
+                &folder,
+                "main.gmpp",
+                "Can't add a number to a string, use string() to convert it first",
+                main,
+                "let a = size + 1;"
+            )])
+        );
+        assert_eq!(database.timings.checked, 2);
+    }
+}
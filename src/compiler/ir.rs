@@ -0,0 +1,1108 @@
+pub mod lower;
+pub mod passes;
+pub mod validate;
+
+use crate::compiler::checker::Type;
+use crate::compiler::error::{Error, Span};
+use std::fmt::{self, Display, Formatter};
+
+/// Index of a symbol in `Module::symbols`.
+pub type SymbolId = usize;
+
+/// A module lowered from its checked syntax tree: every name resolved to a
+/// typed symbol, and the sugar of GML++ in nodes of its own until the passes
+/// of `passes` spell it out, leaving the small core GML is generated from.
+#[derive(Debug, Default)]
+pub struct Module {
+    pub symbols: Symbols,
+    pub body: Vec<Stmt>,
+    /// Built-ins the target lacks, called through their polyfill.
+    pub polyfills: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Symbols(Vec<Symbol>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub value_type: Type,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolKind {
+    /// `var`, local to the function.
+    Local,
+    Parameter,
+    /// `static`, kept between calls of the function.
+    Static,
+    /// `let`, `const` and undeclared names, which are variables of the
+    /// running instance.
+    Instance,
+    /// `globalvar`.
+    Global,
+    /// A function or class declared by the module.
+    Function,
+    Enum,
+    Macro,
+    /// Something the runtime or another module declares.
+    External,
+    /// A local variable a pass needs, numbered in the module.
+    Temp(usize),
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymbolKind::Local => "local",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Static => "static",
+            SymbolKind::Instance => "instance",
+            SymbolKind::Global => "global",
+            SymbolKind::Function => "function",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Macro => "macro",
+            SymbolKind::External => "external",
+            SymbolKind::Temp(_) => "temp",
+        }
+    }
+
+    /// Whether the symbol belongs to a function rather than to the module.
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Local | SymbolKind::Parameter | SymbolKind::Static | SymbolKind::Temp(_)
+        )
+    }
+}
+
+impl Symbols {
+    pub fn add(&mut self, name: &str, kind: SymbolKind, value_type: Type) -> SymbolId {
+        self.0.push(Symbol {
+            name: name.to_string(),
+            kind,
+            value_type,
+        });
+        self.0.len() - 1
+    }
+
+    /// The symbol of something the runtime declares, added the first time
+    /// it is asked for.
+    pub fn external(&mut self, name: &str) -> SymbolId {
+        match self
+            .0
+            .iter()
+            .position(|symbol| symbol.kind == SymbolKind::External && symbol.name == name)
+        {
+            Some(id) => id,
+            None => self.add(name, SymbolKind::External, Type::Untyped),
+        }
+    }
+
+    /// A new temporary holding values of `value_type`.
+    pub fn temp(&mut self, value_type: Type) -> SymbolId {
+        let number = self
+            .0
+            .iter()
+            .filter(|symbol| matches!(symbol.kind, SymbolKind::Temp(_)))
+            .count();
+        self.add(
+            &format!("__gmpp_t{number}"),
+            SymbolKind::Temp(number),
+            value_type,
+        )
+    }
+
+    pub fn get(&self, id: SymbolId) -> Option<&Symbol> {
+        self.0.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: SymbolId) -> &mut Symbol {
+        &mut self.0[id]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// How a symbol is written in GML.
+    pub fn name(&self, id: SymbolId) -> &str {
+        &self.0[id].name
+    }
+}
+
+/// A statement, with where it is written when that is known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    /// `var name = value;`, or `var name;` without a value.
+    Var(SymbolId, Option<Expr>),
+    Static(SymbolId, Expr),
+    GlobalVar(Vec<SymbolId>),
+    /// A macro and the raw text of its value.
+    Macro(SymbolId, String),
+    Enum(SymbolId, Vec<(String, Option<Expr>)>),
+    Function(SymbolId, Function),
+    /// Sugar, see `passes`.
+    Class(SymbolId, Class),
+    /// A place, the assignment operator and the value.
+    Assign(Expr, String, Expr),
+    Expr(Expr),
+    Block(Vec<Stmt>),
+    /// The `else` branch is an `If` for `else if`, a `Block` otherwise.
+    If(Expr, Vec<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Vec<Stmt>),
+    Repeat(Expr, Vec<Stmt>),
+    With(Expr, Vec<Stmt>),
+    DoUntil(Vec<Stmt>, Expr),
+    For(
+        Option<Box<Stmt>>,
+        Option<Expr>,
+        Option<Box<Stmt>>,
+        Vec<Stmt>,
+    ),
+    /// Cases fall through to the next one until a `break`, a case without
+    /// a value being the default.
+    Switch(Expr, Vec<(Option<Expr>, Vec<Stmt>)>),
+    /// The body, the exception variable and its handler, and `finally`.
+    Try(Vec<Stmt>, Option<(SymbolId, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    Throw(Expr),
+    Delete(Expr),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Exit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub params: Vec<Param>,
+    pub return_type: Type,
+    /// `Some` for constructors, with the call to the parent constructor.
+    pub constructor: Option<Option<Expr>>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub symbol: SymbolId,
+    pub default: Option<Expr>,
+    /// Sugar, see `passes`.
+    pub rest: bool,
+}
+
+/// A class as it is written, which `passes` turn into a constructor.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Class {
+    /// The parameters of `new`, and the call to the parent class.
+    pub params: Vec<Param>,
+    pub parent: Option<Expr>,
+    /// Fields with an initial value.
+    pub fields: Vec<(SymbolId, Expr, Option<Span>)>,
+    /// The body of `new`.
+    pub body: Vec<Stmt>,
+    pub methods: Vec<(SymbolId, Function, Option<Span>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// The text of the string, escapes included.
+    String(String),
+    Bool(bool),
+    Symbol(SymbolId),
+    Binary(Box<Expr>, String, Box<Expr>),
+    Unary(String, Box<Expr>),
+    /// `i++` or `--i`, the flag tells whether the operator comes first.
+    Update(String, Box<Expr>, bool),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Field(Box<Expr>, String),
+    /// `value[index]`, with the accessor of `list[| i]`, `map[? key]`...
+    Index(Box<Expr>, Option<String>, Vec<Expr>),
+    Array(Vec<Expr>),
+    Struct(Vec<(String, Expr)>),
+    Call(Box<Expr>, Vec<Expr>),
+    /// `new` and the call to the constructor.
+    New(Box<Expr>),
+    Function(Box<Function>),
+    /// A template string, only left for targets having them.
+    Template(Vec<TemplatePart>),
+    /// Where the statement is written, for the assertions of tests.
+    Location,
+    /// Sugar: `fn(params) -> type => value`, see `passes`.
+    Arrow(Vec<Param>, Type, Box<Expr>),
+    /// Sugar: `left ?? right` and the type of `left`, see `passes`.
+    Nullish(Box<Expr>, Box<Expr>, Type),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplatePart {
+    Text(String),
+    /// An embedded expression, with the number of decimals to show.
+    Value(Expr, Option<usize>),
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Option<Span>) -> Stmt {
+        Stmt { kind, span }
+    }
+}
+
+impl Expr {
+    pub fn call(callee: Expr, args: Vec<Expr>) -> Expr {
+        Expr::Call(Box::new(callee), args)
+    }
+
+    pub fn binary(left: Expr, operator: &str, right: Expr) -> Expr {
+        Expr::Binary(Box::new(left), operator.to_string(), Box::new(right))
+    }
+}
+
+/// Rewrites a module in place. Every method walks into what it is given by
+/// default, so a pass only overrides the nodes it lowers.
+pub trait Rewrite {
+    fn body(&mut self, body: &mut Vec<Stmt>) -> Result<(), Vec<Error>> {
+        walk_body(self, body)
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) -> Result<(), Vec<Error>> {
+        walk_stmt(self, stmt)
+    }
+
+    fn function(&mut self, function: &mut Function) -> Result<(), Vec<Error>> {
+        walk_function(self, function)
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        walk_expr(self, expr)
+    }
+}
+
+/// Rewrites every statement of a body, even after one fails, so that all
+/// the errors of a pass are reported, each at the statement it is in.
+pub fn walk_body<R: Rewrite + ?Sized>(
+    rewrite: &mut R,
+    body: &mut [Stmt],
+) -> Result<(), Vec<Error>> {
+    let mut errors = Vec::new();
+    for stmt in body {
+        if let Err(failed) = rewrite.stmt(stmt) {
+            errors.extend(failed.into_iter().map(|error| error.or_at(stmt.span)));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn walk_stmt<R: Rewrite + ?Sized>(rewrite: &mut R, stmt: &mut Stmt) -> Result<(), Vec<Error>> {
+    match &mut stmt.kind {
+        StmtKind::Var(_, None)
+        | StmtKind::GlobalVar(_)
+        | StmtKind::Macro(_, _)
+        | StmtKind::Return(None)
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Exit => {}
+        StmtKind::Var(_, Some(value))
+        | StmtKind::Static(_, value)
+        | StmtKind::Expr(value)
+        | StmtKind::Throw(value)
+        | StmtKind::Delete(value)
+        | StmtKind::Return(Some(value)) => rewrite.expr(value)?,
+        StmtKind::Enum(_, members) => {
+            for value in members.iter_mut().filter_map(|(_, value)| value.as_mut()) {
+                rewrite.expr(value)?;
+            }
+        }
+        StmtKind::Function(_, function) => rewrite.function(function)?,
+        StmtKind::Class(_, class) => {
+            for param in &mut class.params {
+                if let Some(default) = &mut param.default {
+                    rewrite.expr(default)?;
+                }
+            }
+            if let Some(parent) = &mut class.parent {
+                rewrite.expr(parent)?;
+            }
+            for (_, value, _) in &mut class.fields {
+                rewrite.expr(value)?;
+            }
+            rewrite.body(&mut class.body)?;
+            for (_, method, _) in &mut class.methods {
+                rewrite.function(method)?;
+            }
+        }
+        StmtKind::Assign(target, _, value) => {
+            rewrite.expr(target)?;
+            rewrite.expr(value)?;
+        }
+        StmtKind::Block(body) => rewrite.body(body)?,
+        StmtKind::If(condition, then, otherwise) => {
+            rewrite.expr(condition)?;
+            rewrite.body(then)?;
+            if let Some(otherwise) = otherwise {
+                rewrite.stmt(otherwise)?;
+            }
+        }
+        StmtKind::While(value, body)
+        | StmtKind::Repeat(value, body)
+        | StmtKind::With(value, body) => {
+            rewrite.expr(value)?;
+            rewrite.body(body)?;
+        }
+        StmtKind::DoUntil(body, condition) => {
+            rewrite.body(body)?;
+            rewrite.expr(condition)?;
+        }
+        StmtKind::For(init, condition, step, body) => {
+            if let Some(init) = init {
+                rewrite.stmt(init)?;
+            }
+            if let Some(condition) = condition {
+                rewrite.expr(condition)?;
+            }
+            if let Some(step) = step {
+                rewrite.stmt(step)?;
+            }
+            rewrite.body(body)?;
+        }
+        StmtKind::Switch(value, cases) => {
+            rewrite.expr(value)?;
+            for (value, body) in cases {
+                if let Some(value) = value {
+                    rewrite.expr(value)?;
+                }
+                rewrite.body(body)?;
+            }
+        }
+        StmtKind::Try(body, catch, finally) => {
+            rewrite.body(body)?;
+            if let Some((_, handler)) = catch {
+                rewrite.body(handler)?;
+            }
+            if let Some(finally) = finally {
+                rewrite.body(finally)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn walk_function<R: Rewrite + ?Sized>(
+    rewrite: &mut R,
+    function: &mut Function,
+) -> Result<(), Vec<Error>> {
+    for param in &mut function.params {
+        if let Some(default) = &mut param.default {
+            rewrite.expr(default)?;
+        }
+    }
+    if let Some(Some(parent)) = &mut function.constructor {
+        rewrite.expr(parent)?;
+    }
+    rewrite.body(&mut function.body)
+}
+
+pub fn walk_expr<R: Rewrite + ?Sized>(rewrite: &mut R, expr: &mut Expr) -> Result<(), Vec<Error>> {
+    match expr {
+        Expr::Number(_) | Expr::String(_) | Expr::Bool(_) | Expr::Symbol(_) | Expr::Location => {}
+        Expr::Binary(left, _, right) | Expr::Nullish(left, right, _) => {
+            rewrite.expr(left)?;
+            rewrite.expr(right)?;
+        }
+        Expr::Unary(_, value)
+        | Expr::Update(_, value, _)
+        | Expr::Field(value, _)
+        | Expr::New(value) => rewrite.expr(value)?,
+        Expr::Ternary(condition, if_true, if_false) => {
+            rewrite.expr(condition)?;
+            rewrite.expr(if_true)?;
+            rewrite.expr(if_false)?;
+        }
+        Expr::Index(value, _, indices) => {
+            rewrite.expr(value)?;
+            for index in indices {
+                rewrite.expr(index)?;
+            }
+        }
+        Expr::Array(items) => {
+            for item in items {
+                rewrite.expr(item)?;
+            }
+        }
+        Expr::Struct(fields) => {
+            for (_, value) in fields {
+                rewrite.expr(value)?;
+            }
+        }
+        Expr::Call(callee, args) => {
+            rewrite.expr(callee)?;
+            for arg in args {
+                rewrite.expr(arg)?;
+            }
+        }
+        Expr::Function(function) => rewrite.function(function)?,
+        Expr::Template(parts) => {
+            for part in parts {
+                if let TemplatePart::Value(value, _) = part {
+                    rewrite.expr(value)?;
+                }
+            }
+        }
+        Expr::Arrow(params, _, value) => {
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    rewrite.expr(default)?;
+                }
+            }
+            rewrite.expr(value)?;
+        }
+    }
+    Ok(())
+}
+
+impl Module {
+    fn write_body(&self, f: &mut Formatter<'_>, body: &[Stmt], depth: usize) -> fmt::Result {
+        for stmt in body {
+            self.write_stmt(f, stmt, depth)?;
+        }
+        Ok(())
+    }
+
+    /// `{`, the statements one level deeper, then `}` without a new line.
+    fn write_block(&self, f: &mut Formatter<'_>, body: &[Stmt], depth: usize) -> fmt::Result {
+        writeln!(f, "{{")?;
+        self.write_body(f, body, depth + 1)?;
+        write!(f, "{}}}", "  ".repeat(depth))
+    }
+
+    fn write_stmt(&self, f: &mut Formatter<'_>, stmt: &Stmt, depth: usize) -> fmt::Result {
+        write!(f, "{}", "  ".repeat(depth))?;
+        self.write_stmt_inline(f, stmt, depth)?;
+        writeln!(f)
+    }
+
+    /// A statement without its indentation and new line, as the parts of a
+    /// `for` are written.
+    fn write_stmt_inline(&self, f: &mut Formatter<'_>, stmt: &Stmt, depth: usize) -> fmt::Result {
+        match &stmt.kind {
+            StmtKind::Var(id, value) => {
+                write!(f, "var {}", self.typed(*id))?;
+                if let Some(value) = value {
+                    write!(f, " = {}", self.expr(value, depth))?;
+                }
+                Ok(())
+            }
+            StmtKind::Static(id, value) => {
+                write!(
+                    f,
+                    "static {} = {}",
+                    self.typed(*id),
+                    self.expr(value, depth)
+                )
+            }
+            StmtKind::GlobalVar(ids) => {
+                let names: Vec<String> = ids.iter().map(|id| self.typed(*id)).collect();
+                write!(f, "globalvar {}", names.join(", "))
+            }
+            StmtKind::Macro(id, value) => write!(f, "macro {} {value}", self.symbol(*id)),
+            StmtKind::Enum(id, members) => {
+                let members: Vec<String> = members
+                    .iter()
+                    .map(|(member, value)| match value {
+                        Some(value) => format!("{member} = {}", self.expr(value, depth)),
+                        None => member.clone(),
+                    })
+                    .collect();
+                write!(f, "enum {} {{ {} }}", self.symbol(*id), members.join(", "))
+            }
+            StmtKind::Function(id, function) => {
+                write!(f, "{}", self.function(Some(*id), function, depth))
+            }
+            StmtKind::Class(id, class) => {
+                write!(
+                    f,
+                    "class {}({})",
+                    self.symbol(*id),
+                    self.params(&class.params, depth)
+                )?;
+                if let Some(parent) = &class.parent {
+                    write!(f, " : {}", self.expr(parent, depth))?;
+                }
+                writeln!(f, " {{")?;
+                let indent = "  ".repeat(depth + 1);
+                for (field, value, _) in &class.fields {
+                    writeln!(
+                        f,
+                        "{indent}field {} = {}",
+                        self.typed(*field),
+                        self.expr(value, depth + 1)
+                    )?;
+                }
+                self.write_body(f, &class.body, depth + 1)?;
+                for (method, function, _) in &class.methods {
+                    writeln!(
+                        f,
+                        "{indent}{}",
+                        self.function(Some(*method), function, depth + 1)
+                    )?;
+                }
+                write!(f, "{}}}", "  ".repeat(depth))
+            }
+            StmtKind::Assign(target, operator, value) => write!(
+                f,
+                "{} {operator} {}",
+                self.expr(target, depth),
+                self.expr(value, depth)
+            ),
+            StmtKind::Expr(value) => write!(f, "{}", self.expr(value, depth)),
+            StmtKind::Block(body) => self.write_block(f, body, depth),
+            StmtKind::If(condition, then, otherwise) => {
+                write!(f, "if {} ", self.expr(condition, depth))?;
+                self.write_block(f, then, depth)?;
+                match otherwise.as_deref() {
+                    Some(
+                        otherwise @ Stmt {
+                            kind: StmtKind::If(_, _, _),
+                            ..
+                        },
+                    ) => {
+                        write!(f, " else ")?;
+                        self.write_stmt_inline(f, otherwise, depth)
+                    }
+                    Some(Stmt {
+                        kind: StmtKind::Block(body),
+                        ..
+                    }) => {
+                        write!(f, " else ")?;
+                        self.write_block(f, body, depth)
+                    }
+                    Some(otherwise) => {
+                        write!(f, " else ")?;
+                        self.write_stmt_inline(f, otherwise, depth)
+                    }
+                    None => Ok(()),
+                }
+            }
+            StmtKind::While(value, body)
+            | StmtKind::Repeat(value, body)
+            | StmtKind::With(value, body) => {
+                let keyword = match &stmt.kind {
+                    StmtKind::While(_, _) => "while",
+                    StmtKind::Repeat(_, _) => "repeat",
+                    _ => "with",
+                };
+                write!(f, "{keyword} {} ", self.expr(value, depth))?;
+                self.write_block(f, body, depth)
+            }
+            StmtKind::DoUntil(body, condition) => {
+                write!(f, "do ")?;
+                self.write_block(f, body, depth)?;
+                write!(f, " until {}", self.expr(condition, depth))
+            }
+            StmtKind::For(init, condition, step, body) => {
+                write!(f, "for (")?;
+                if let Some(init) = init {
+                    self.write_stmt_inline(f, init, depth)?;
+                }
+                write!(f, "; ")?;
+                if let Some(condition) = condition {
+                    write!(f, "{}", self.expr(condition, depth))?;
+                }
+                write!(f, "; ")?;
+                if let Some(step) = step {
+                    self.write_stmt_inline(f, step, depth)?;
+                }
+                write!(f, ") ")?;
+                self.write_block(f, body, depth)
+            }
+            StmtKind::Switch(value, cases) => {
+                writeln!(f, "switch {} {{", self.expr(value, depth))?;
+                let indent = "  ".repeat(depth);
+                for (value, body) in cases {
+                    match value {
+                        Some(value) => writeln!(f, "{indent}case {}:", self.expr(value, depth))?,
+                        None => writeln!(f, "{indent}default:")?,
+                    }
+                    self.write_body(f, body, depth + 1)?;
+                }
+                write!(f, "{indent}}}")
+            }
+            StmtKind::Try(body, catch, finally) => {
+                write!(f, "try ")?;
+                self.write_block(f, body, depth)?;
+                if let Some((exception, handler)) = catch {
+                    write!(f, " catch ({}) ", self.typed(*exception))?;
+                    self.write_block(f, handler, depth)?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " finally ")?;
+                    self.write_block(f, finally, depth)?;
+                }
+                Ok(())
+            }
+            StmtKind::Throw(value) => write!(f, "throw {}", self.expr(value, depth)),
+            StmtKind::Delete(value) => write!(f, "delete {}", self.expr(value, depth)),
+            StmtKind::Return(Some(value)) => write!(f, "return {}", self.expr(value, depth)),
+            StmtKind::Return(None) => write!(f, "return"),
+            StmtKind::Break => write!(f, "break"),
+            StmtKind::Continue => write!(f, "continue"),
+            StmtKind::Exit => write!(f, "exit"),
+        }
+    }
+
+    /// How a symbol is shown: its name, or `%n` for temporaries.
+    fn symbol(&self, id: SymbolId) -> String {
+        match self.symbols.get(id) {
+            Some(Symbol {
+                kind: SymbolKind::Temp(number),
+                ..
+            }) => format!("%{number}"),
+            Some(symbol) => symbol.name.clone(),
+            None => format!("<symbol {id}>"),
+        }
+    }
+
+    fn typed(&self, id: SymbolId) -> String {
+        match self.symbols.get(id) {
+            Some(symbol) => format!("{}: {}", self.symbol(id), symbol.value_type.name()),
+            None => self.symbol(id),
+        }
+    }
+
+    fn params(&self, params: &[Param], depth: usize) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .map(|param| {
+                let rest = if param.rest { "..." } else { "" };
+                match &param.default {
+                    Some(default) => format!(
+                        "{rest}{} = {}",
+                        self.typed(param.symbol),
+                        self.expr(default, depth)
+                    ),
+                    None => format!("{rest}{}", self.typed(param.symbol)),
+                }
+            })
+            .collect();
+        params.join(", ")
+    }
+
+    /// A function, the name being left out for function expressions.
+    fn function(&self, name: Option<SymbolId>, function: &Function, depth: usize) -> String {
+        let keyword = match function.constructor {
+            Some(_) => "constructor",
+            None => "function",
+        };
+        let name = name
+            .map(|id| format!(" {}", self.symbol(id)))
+            .unwrap_or_default();
+        let mut text = format!(
+            "{keyword}{name}({}) -> {}",
+            self.params(&function.params, depth),
+            function.return_type.name()
+        );
+        if let Some(Some(parent)) = &function.constructor {
+            text.push_str(&format!(" : {}", self.expr(parent, depth)));
+        }
+        text.push_str(" {\n");
+        text.push_str(&BodyText(self, &function.body, depth + 1).to_string());
+        text.push_str(&format!("{}}}", "  ".repeat(depth)));
+        text
+    }
+
+    /// An operand, in parenthesis when it is made of operators itself so the
+    /// order they apply in shows.
+    fn operand(&self, expr: &Expr, depth: usize) -> String {
+        let text = self.expr(expr, depth);
+        match expr {
+            Expr::Binary(_, _, _)
+            | Expr::Unary(_, _)
+            | Expr::Ternary(_, _, _)
+            | Expr::Nullish(_, _, _)
+            | Expr::New(_) => format!("({text})"),
+            _ => text,
+        }
+    }
+
+    fn exprs(&self, exprs: &[Expr], depth: usize) -> String {
+        let exprs: Vec<String> = exprs.iter().map(|expr| self.expr(expr, depth)).collect();
+        exprs.join(", ")
+    }
+
+    fn expr(&self, expr: &Expr, depth: usize) -> String {
+        match expr {
+            Expr::Number(value) => value.to_string(),
+            Expr::String(text) => format!("\"{text}\""),
+            Expr::Bool(value) => value.to_string(),
+            Expr::Symbol(id) => self.symbol(*id),
+            Expr::Binary(left, operator, right) => format!(
+                "{} {operator} {}",
+                self.operand(left, depth),
+                self.operand(right, depth)
+            ),
+            Expr::Unary(operator, value) => format!("{operator}{}", self.operand(value, depth)),
+            Expr::Update(operator, target, true) => {
+                format!("{operator}{}", self.operand(target, depth))
+            }
+            Expr::Update(operator, target, false) => {
+                format!("{}{operator}", self.operand(target, depth))
+            }
+            Expr::Ternary(condition, if_true, if_false) => format!(
+                "{} ? {} : {}",
+                self.operand(condition, depth),
+                self.operand(if_true, depth),
+                self.operand(if_false, depth)
+            ),
+            Expr::Field(value, field) => format!("{}.{field}", self.operand(value, depth)),
+            Expr::Index(value, accessor, indices) => {
+                let accessor = accessor
+                    .as_ref()
+                    .map(|accessor| format!("{accessor} "))
+                    .unwrap_or_default();
+                format!(
+                    "{}[{accessor}{}]",
+                    self.operand(value, depth),
+                    self.exprs(indices, depth)
+                )
+            }
+            Expr::Array(items) => format!("[{}]", self.exprs(items, depth)),
+            Expr::Struct(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}", self.expr(value, depth)))
+                    .collect();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Expr::Call(callee, args) => format!(
+                "call {}({})",
+                self.operand(callee, depth),
+                self.exprs(args, depth)
+            ),
+            Expr::New(call) => format!("new {}", self.expr(call, depth)),
+            Expr::Function(function) => self.function(None, function, depth),
+            Expr::Template(parts) => {
+                let mut text = String::from("$\"");
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(txt) => text.push_str(txt),
+                        TemplatePart::Value(value, None) => {
+                            text.push_str(&format!("{{{}}}", self.expr(value, depth)))
+                        }
+                        TemplatePart::Value(value, Some(precision)) => {
+                            text.push_str(&format!("{{{}:{precision}}}", self.expr(value, depth)))
+                        }
+                    }
+                }
+                text.push('"');
+                text
+            }
+            Expr::Location => String::from("location"),
+            Expr::Arrow(params, return_type, value) => format!(
+                "fn({}) -> {} => {}",
+                self.params(params, depth),
+                return_type.name(),
+                self.expr(value, depth)
+            ),
+            Expr::Nullish(left, right, _) => format!(
+                "{} ?? {}",
+                self.operand(left, depth),
+                self.operand(right, depth)
+            ),
+        }
+    }
+}
+
+/// Statements one per line at `depth`, for function bodies written inside
+/// an expression.
+struct BodyText<'a>(&'a Module, &'a [Stmt], usize);
+
+impl Display for BodyText<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.write_body(f, self.1, self.2)
+    }
+}
+
+/// The text `--emit=ir` prints: the symbols of the module and what they are,
+/// then its statements.
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for symbol in self.symbols.iter() {
+            if symbol.kind.is_local() {
+                continue;
+            }
+            writeln!(
+                f,
+                "{} {}: {}",
+                symbol.kind.name(),
+                symbol.name,
+                symbol.value_type.name()
+            )?;
+        }
+        if !self.polyfills.is_empty() {
+            writeln!(f, "polyfills {}", self.polyfills.join(", "))?;
+        }
+
+        writeln!(f)?;
+        self.write_body(f, &self.body, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate::validate;
+    use super::*;
+    use crate::compiler::codegen::{lower_module, CodegenOptions};
+    use crate::compiler::declarations::standard_declarations;
+    use crate::compiler::optimizer::OptLevel;
+    use crate::compiler::parser::parse_with_positions;
+    use crate::compiler::target::GmlVersion;
+    use crate::compiler::tokenizer::tokenize;
+
+    /// The module some code lowers to for `target`.
+    fn lowered(code: &str, target: GmlVersion) -> Result<Module, Vec<Error>> {
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        lower_module(
+            &program,
+            &CodegenOptions::new(target, OptLevel::O0),
+            positions,
+            &standard_declarations(),
+        )
+    }
+
+    /// The statements of a printed module, without its symbols.
+    fn printed(code: &str, target: GmlVersion) -> String {
+        let module = lowered(code, target).unwrap();
+        let printed = module.to_string();
+        printed
+            .split_once("\n\n")
+            .map_or(printed.clone(), |(_, body)| body.to_string())
+    }
+
+    #[test]
+    fn resolves_names_to_typed_symbols() {
+        let module = lowered(
+            "function twice(n: number) -> number { var m = n * 2; return m; }\nlet x = twice(2);",
+            GmlVersion::Latest,
+        )
+        .unwrap();
+        let kind = |name: &str| {
+            module
+                .symbols
+                .iter()
+                .find(|symbol| symbol.name == name)
+                .map(|symbol| (symbol.kind, symbol.value_type.name()))
+        };
+
+        assert_eq!(
+            kind("twice"),
+            Some((SymbolKind::Function, String::from("Fn(number)<number>")))
+        );
+        assert_eq!(
+            kind("n"),
+            Some((SymbolKind::Parameter, String::from("number")))
+        );
+        assert_eq!(kind("m"), Some((SymbolKind::Local, String::from("number"))));
+        assert_eq!(
+            kind("x"),
+            Some((SymbolKind::Instance, String::from("number")))
+        );
+    }
+
+    #[test]
+    fn lowers_classes_to_constructors() {
+        let code = "\
+class Counter {
+    count: number = 0;
+
+    bump(by: number) -> number {
+        count += by;
+        return count;
+    }
+}";
+        assert_eq!(
+            printed(code, GmlVersion::Gms23),
+            "\
+constructor Counter() -> Counter {
+  count = 0
+  static bump: Fn(number)<number> = function(by: number) -> number {
+    count += by
+    return count
+  }
+}
+"
+        );
+    }
+
+    #[test]
+    fn hoists_nullish_to_typed_temps() {
+        assert_eq!(
+            printed("let a = b() ?? \"none\";", GmlVersion::Gms23),
+            "var %0: untyped = call b()\na = call is_undefined(%0) ? \"none\" : %0\n"
+        );
+        assert_eq!(
+            printed("let a = b ?? 1;", GmlVersion::Gms23),
+            "a = call is_undefined(b) ? 1 : b\n"
+        );
+        assert_eq!(
+            printed("let a = b() ?? 1;", GmlVersion::Latest),
+            "a = call b() ?? 1\n"
+        );
+        assert_eq!(
+            lowered("f(b() ?? 1);", GmlVersion::Gms23).unwrap_err(),
+            [Error::at(
+                "'b() ?? 1' can't be lowered for GameMaker Studio 2.3",
+                Span::new(0, 12)
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_every_error_at_its_statement() {
+        let code = "\
+let a = 1;
+let s = { a: 1 };
+function f() {
+    try { g(); } catch (e) {}
+}";
+        let errors = lowered(code, GmlVersion::Gms22).unwrap_err();
+
+        assert_eq!(
+            errors,
+            [
+                Error::at(
+                    "Struct literals can't be lowered for GameMaker Studio 2.2, it needs GameMaker Studio 2.3 or newer",
+                    Span::new(11, 28)
+                ),
+                Error::at(
+                    "Function 'f' can't be lowered for GameMaker Studio 2.2, function declarations need GameMaker Studio 2.3 or newer",
+                    Span::new(29, 75)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lowers_templates_for_the_target() {
+        assert_eq!(
+            printed("let a = $\"{b} and {c:.2}\";", GmlVersion::Gms23),
+            "a = (call string(b) + \" and \") + call string_format(c, 0, 2)\n"
+        );
+    }
+
+    #[test]
+    fn calls_polyfills_of_newer_builtins() {
+        let code = "let a = array_map(xs, f);\nlet b = array_map(ys, f);";
+        let module = lowered(code, GmlVersion::Lts2023).unwrap();
+
+        assert_eq!(module.polyfills, ["array_map"]);
+        assert_eq!(
+            printed(code, GmlVersion::Lts2023),
+            "a = call __gmpp_array_map(xs, f)\nb = call __gmpp_array_map(ys, f)\n"
+        );
+        assert!(lowered(code, GmlVersion::Latest)
+            .unwrap()
+            .polyfills
+            .is_empty());
+        assert_eq!(
+            lowered("let m = method(self, f);", GmlVersion::Gms22).unwrap_err()[0].message,
+            "'method' isn't available in GameMaker Studio 2.2 and can't be polyfilled, it needs GameMaker Studio 2.3 or newer"
+        );
+        assert_eq!(
+            lowered("let a = array_map(xs, f);", GmlVersion::Gms22).unwrap_err()[0].message,
+            "'array_map' isn't available in GameMaker Studio 2.2 and can't be polyfilled, it needs the latest GameMaker or newer"
+        );
+    }
+
+    #[test]
+    fn gates_features_by_target() {
+        let gated = [
+            ("let s = { a: 1 };", "Struct literals"),
+            ("let f = function() {};", "Function expressions"),
+            ("let f = fn(x) => x;", "Arrow functions"),
+            ("let p = new Point();", "'new'"),
+            ("throw \"no\";", "'throw'"),
+        ];
+        for (code, feature) in gated {
+            assert_eq!(
+                lowered(code, GmlVersion::Gms22).unwrap_err()[0].message,
+                format!("{feature} can't be lowered for GameMaker Studio 2.2, it needs GameMaker Studio 2.3 or newer"),
+                "{code}"
+            );
+            assert!(lowered(code, GmlVersion::Gms23).is_ok(), "{code}");
+        }
+
+        assert_eq!(
+            lowered("let a = b ? 1 : 2;", GmlVersion::Gms22).unwrap_err()[0].message,
+            "Ternary expressions can't be lowered for GameMaker Studio 2.2"
+        );
+        assert_eq!(
+            printed("let a = $\"{b}!\";", GmlVersion::Lts2023),
+            "a = $\"{b}!\"\n"
+        );
+    }
+
+    #[test]
+    fn validator_rejects_what_passes_leave() {
+        let mut module = Module::default();
+        let f = module.symbols.add("f", SymbolKind::Instance, Type::Untyped);
+        let temp = module.symbols.temp(Type::Untyped);
+        module.body = vec![
+            Stmt::new(
+                StmtKind::Expr(Expr::Arrow(
+                    Vec::new(),
+                    Type::Untyped,
+                    Box::new(Expr::Bool(true)),
+                )),
+                None,
+            ),
+            Stmt::new(
+                StmtKind::Assign(Expr::Symbol(f), String::from("="), Expr::Symbol(temp)),
+                None,
+            ),
+            Stmt::new(
+                StmtKind::Expr(Expr::Ternary(
+                    Box::new(Expr::Bool(true)),
+                    Box::new(Expr::Number(1.0)),
+                    Box::new(Expr::Number(2.0)),
+                )),
+                None,
+            ),
+        ];
+
+        let errors = validate(
+            &module,
+            &CodegenOptions::new(GmlVersion::Gms22, OptLevel::O0),
+        )
+        .unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "An arrow function is left",
+                "Temporary '__gmpp_t0' is read before it is set",
+                "A ternary expression is left for GameMaker Studio 2.2",
+            ]
+        );
+    }
+}
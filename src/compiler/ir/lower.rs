@@ -0,0 +1,777 @@
+use crate::compiler::ast::Node;
+use crate::compiler::attributes::has_attribute;
+use crate::compiler::checker::{infer_type, parameter_name, parameter_type, Type};
+use crate::compiler::error::{Error, Span};
+use crate::compiler::ir::{
+    Class, Expr, Function, Module, Param, Stmt, StmtKind, SymbolId, SymbolKind, TemplatePart,
+};
+use crate::compiler::parser::Positions;
+use crate::compiler::tokenizer::TokenType;
+use std::collections::HashMap;
+
+/// Names GameMaker gives to instances and values rather than variables.
+const RUNTIME_NAMES: [&str; 6] = ["self", "other", "global", "all", "noone", "undefined"];
+
+/// Lowers a checked module to the IR, resolving every name it uses. `globals`
+/// are the types of everything the module sees, its own declarations
+/// included, as `analyze` finds them. `@test` functions are only kept with
+/// `tests`.
+pub fn lower(
+    program: &Node,
+    positions: &Positions,
+    globals: &HashMap<String, Type>,
+    tests: bool,
+) -> Result<Module, Error> {
+    let Node::Program(body) = program else {
+        return Err(Error::new(format!(
+            "Expected a program, found: {:?}",
+            program
+        )));
+    };
+
+    let mut lowerer = Lowerer {
+        module: Module::default(),
+        positions,
+        globals,
+        scopes: vec![HashMap::new()],
+        instances: HashMap::new(),
+        code: Vec::new(),
+        visible: None,
+    };
+
+    lowerer.declare_top_level(body);
+    for stmt in body {
+        if !tests && has_attribute(stmt, "test") {
+            continue;
+        }
+        // Every script shares GameMaker's global namespace, so imports and
+        // extern declarations only matter to the checker.
+        if let Node::ImportDeclaration(_, _)
+        | Node::ExternFunction(_, _, _)
+        | Node::ExternVariable(_, _, _) = stmt.as_ref()
+        {
+            continue;
+        }
+        lowerer.statement(stmt)?;
+    }
+
+    lowerer.module.body = std::mem::take(&mut lowerer.code);
+    Ok(lowerer.module)
+}
+
+struct Lowerer<'a> {
+    module: Module,
+    positions: &'a Positions,
+    globals: &'a HashMap<String, Type>,
+    /// Symbols by name, one scope per function as `var` is function-scoped,
+    /// the innermost last. The first one holds the top level of the module
+    /// and what it uses from outside.
+    scopes: Vec<HashMap<String, SymbolId>>,
+    /// Variables of the running instance, which are the same wherever they
+    /// are used.
+    instances: HashMap<String, SymbolId>,
+    /// Where the statements being lowered go.
+    code: Vec<Stmt>,
+    /// Types of the names in scope, for `infer_type`, until a symbol is added.
+    visible: Option<HashMap<String, Type>>,
+}
+
+impl Lowerer<'_> {
+    fn span(&self, node: &Node) -> Option<Span> {
+        self.positions.get(&(node as *const Node)).copied()
+    }
+
+    fn declare(&mut self, name: &str, kind: SymbolKind, value_type: Type) -> SymbolId {
+        let id = self.module.symbols.add(name, kind, value_type);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), id);
+        }
+        self.visible = None;
+        id
+    }
+
+    /// A `var`, which is the same variable when it is declared again in the
+    /// same function.
+    fn declare_local(&mut self, name: &str, value_type: Type) -> SymbolId {
+        let scope = self.scopes.last().and_then(|scope| scope.get(name));
+        match scope {
+            Some(&id)
+                if self.module.symbols.get(id).map(|symbol| symbol.kind)
+                    == Some(SymbolKind::Local) =>
+            {
+                id
+            }
+            _ => self.declare(name, SymbolKind::Local, value_type),
+        }
+    }
+
+    fn instance(&mut self, name: &str, value_type: Type) -> SymbolId {
+        if let Some(id) = self.instances.get(name) {
+            return *id;
+        }
+        let id = self
+            .module
+            .symbols
+            .add(name, SymbolKind::Instance, value_type);
+        self.instances.insert(name.to_string(), id);
+        self.visible = None;
+        id
+    }
+
+    /// The symbol a name refers to. Names the module declares nowhere are
+    /// declared by the runtime or another module when they are known, and
+    /// are variables of the running instance otherwise, unless they are
+    /// called, as functions come from other scripts.
+    fn resolve(&mut self, name: &str, called: bool) -> SymbolId {
+        if let Some(id) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return *id;
+        }
+        if let Some(id) = self.instances.get(name) {
+            return *id;
+        }
+
+        let value_type = match self.globals.get(name) {
+            Some(value_type) => value_type.clone(),
+            None if called || RUNTIME_NAMES.contains(&name) => Type::Untyped,
+            None => return self.instance(name, Type::Untyped),
+        };
+        let id = self
+            .module
+            .symbols
+            .add(name, SymbolKind::External, value_type);
+        self.scopes[0].insert(name.to_string(), id);
+        self.visible = None;
+        id
+    }
+
+    fn global_type(&self, name: &str) -> Type {
+        self.globals.get(name).cloned().unwrap_or(Type::Untyped)
+    }
+
+    /// Lowers into a separate list of statements, for the body of an `if`,
+    /// a loop...
+    fn collect(
+        &mut self,
+        lower: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<Vec<Stmt>, Error> {
+        let outer = std::mem::take(&mut self.code);
+        let result = lower(self);
+        let code = std::mem::replace(&mut self.code, outer);
+        result.map(|_| code)
+    }
+
+    fn infer(&mut self, expr: &Node) -> Type {
+        if self.visible.is_none() {
+            let mut visible = self.globals.clone();
+            for symbol in self.instances.values() {
+                if let Some(symbol) = self.module.symbols.get(*symbol) {
+                    visible.insert(symbol.name.clone(), symbol.value_type.clone());
+                }
+            }
+            for scope in &self.scopes {
+                for (name, id) in scope {
+                    if let Some(symbol) = self.module.symbols.get(*id) {
+                        visible.insert(name.clone(), symbol.value_type.clone());
+                    }
+                }
+            }
+            self.visible = Some(visible);
+        }
+
+        match &self.visible {
+            Some(visible) => infer_type(expr, visible),
+            None => Type::Untyped,
+        }
+    }
+
+    /// GML functions, enums and macros exist before any code runs, so the
+    /// code above their declaration can use them, and so do the variables
+    /// of the instance the top level declares.
+    fn declare_top_level(&mut self, body: &[Box<Node>]) {
+        for stmt in body {
+            match stmt.declaration() {
+                Node::FunctionDeclaration(name, _, _, _) | Node::ClassDeclaration(name, _) => {
+                    self.declare(name, SymbolKind::Function, self.global_type(name));
+                }
+                Node::ConstructorDeclaration(function, _) => {
+                    if let Node::FunctionDeclaration(name, _, _, _) = function.as_ref() {
+                        self.declare(name, SymbolKind::Function, self.global_type(name));
+                    }
+                }
+                Node::MacroDeclaration(name, _) => {
+                    self.declare(name, SymbolKind::Macro, self.global_type(name));
+                }
+                Node::EnumDeclaration(name, _) => {
+                    self.declare(name, SymbolKind::Enum, self.global_type(name));
+                }
+                Node::GlobalVarDeclaration(names) => {
+                    for name in names {
+                        self.declare(name, SymbolKind::Global, self.global_type(name));
+                    }
+                }
+                Node::ExternFunction(name, _, _) | Node::ExternVariable(_, name, _) => {
+                    self.declare(name, SymbolKind::External, self.global_type(name));
+                }
+                Node::ImportDeclaration(names, _) => {
+                    for name in names {
+                        self.declare(name, SymbolKind::External, self.global_type(name));
+                    }
+                }
+                Node::VariableDeclaration(declaration_type, name, _, _)
+                    if !matches!(
+                        declaration_type.as_ref(),
+                        Node::DeclarationType(TokenType::Var | TokenType::Static)
+                    ) =>
+                {
+                    self.instance(name, self.global_type(name));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn push(&mut self, kind: StmtKind, span: Option<Span>) {
+        self.code.push(Stmt::new(kind, span));
+    }
+
+    fn statement(&mut self, node: &Node) -> Result<(), Error> {
+        let span = self.span(node);
+        self.statement_at(node, span)
+            .map_err(|error| error.or_at(span))
+    }
+
+    /// Lowers a statement written at `span`, which a declaration takes from
+    /// the `export` or attributes before it.
+    fn statement_at(&mut self, node: &Node, span: Option<Span>) -> Result<(), Error> {
+        match node {
+            Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+                let inner = self.span(declaration);
+                self.statement_at(declaration, span.or(inner))?;
+            }
+            Node::VariableDeclaration(declaration_type, name, annotation, value) => {
+                let value_type = match annotation {
+                    Some(annotation) => Type::from_annotation(annotation),
+                    None => self.infer(value),
+                };
+
+                match declaration_type.as_ref() {
+                    Node::DeclarationType(TokenType::Var) => {
+                        let value = match value.as_ref() {
+                            Node::Identifier(id) if id == "undefined" => None,
+                            value => Some(self.expression(value)?),
+                        };
+                        let id = self.declare_local(name, value_type);
+                        self.push(StmtKind::Var(id, value), span);
+                    }
+                    Node::DeclarationType(TokenType::Static) => {
+                        let value = self.expression(value)?;
+                        let id = self.declare(name, SymbolKind::Static, value_type);
+                        self.push(StmtKind::Static(id, value), span);
+                    }
+                    _ => {
+                        let value = self.expression(value)?;
+                        let id = self.instance(name, value_type);
+                        self.push(
+                            StmtKind::Assign(Expr::Symbol(id), "=".to_string(), value),
+                            span,
+                        );
+                    }
+                }
+            }
+            Node::DeclarationList(declarations) => {
+                for (i, declaration) in declarations.iter().enumerate() {
+                    let own = self.span(declaration);
+                    let span = if i == 0 { span.or(own) } else { own.or(span) };
+                    self.statement_at(declaration, span)?;
+                }
+            }
+            Node::GlobalVarDeclaration(names) => {
+                let mut ids = Vec::new();
+                for name in names {
+                    ids.push(match self.scopes[0].get(name) {
+                        Some(id) => *id,
+                        None => self.declare(name, SymbolKind::Global, Type::Untyped),
+                    });
+                }
+                self.push(StmtKind::GlobalVar(ids), span);
+            }
+            Node::MacroDeclaration(name, value) => {
+                let id = self.declared(name, SymbolKind::Macro);
+                self.push(StmtKind::Macro(id, value.clone()), span);
+            }
+            Node::EnumDeclaration(name, members) => {
+                let id = self.declared(name, SymbolKind::Enum);
+                let mut lowered = Vec::new();
+                for (member, value) in members {
+                    let value = match value {
+                        Some(value) => Some(self.expression(value)?),
+                        None => None,
+                    };
+                    lowered.push((member.clone(), value));
+                }
+                self.push(StmtKind::Enum(id, lowered), span);
+            }
+            Node::FunctionDeclaration(name, params, return_type, code) => {
+                let id = self.declared(name, SymbolKind::Function);
+                let function = self.function(name, params, return_type, code, None)?;
+                self.push(StmtKind::Function(id, function), span);
+            }
+            Node::ConstructorDeclaration(function, parent) => match function.as_ref() {
+                Node::FunctionDeclaration(name, params, return_type, code) => {
+                    let id = self.declared(name, SymbolKind::Function);
+                    let function =
+                        self.function(name, params, return_type, code, Some(parent.as_deref()))?;
+                    self.push(StmtKind::Function(id, function), span);
+                }
+                _ => {
+                    let value = self.expression(node)?;
+                    self.push(StmtKind::Expr(value), span);
+                }
+            },
+            Node::ClassDeclaration(name, members) => {
+                let id = self.declared(name, SymbolKind::Function);
+                let class = self.class(name, members)?;
+                self.push(StmtKind::Class(id, class), span);
+            }
+            Node::ReturnStatement(value) => {
+                let value = match value.as_ref() {
+                    Node::Identifier(id) if id == "undefined" => None,
+                    value => Some(self.expression(value)?),
+                };
+                self.push(StmtKind::Return(value), span);
+            }
+            Node::Assignment(target, operator, value) => {
+                let target = self.expression(target)?;
+                let value = self.expression(value)?;
+                self.push(StmtKind::Assign(target, operator.clone(), value), span);
+            }
+            Node::Block(body) => {
+                let body = self.collect(|lowerer| lowerer.statements(body))?;
+                self.push(StmtKind::Block(body), span);
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                let condition = self.expression(condition)?;
+                let then = self.body(then)?;
+                let otherwise = match otherwise.as_deref() {
+                    Some(otherwise @ Node::IfStatement(_, _, _)) => {
+                        let mut lowered = self.collect(|lowerer| lowerer.statement(otherwise))?;
+                        lowered.pop().map(Box::new)
+                    }
+                    Some(otherwise) => Some(Box::new(Stmt::new(
+                        StmtKind::Block(self.body(otherwise)?),
+                        None,
+                    ))),
+                    None => None,
+                };
+                self.push(StmtKind::If(condition, then, otherwise), span);
+            }
+            Node::WhileStatement(value, body)
+            | Node::RepeatStatement(value, body)
+            | Node::WithStatement(value, body) => {
+                let value = self.expression(value)?;
+                let body = self.body(body)?;
+                let kind = match node {
+                    Node::WhileStatement(_, _) => StmtKind::While(value, body),
+                    Node::RepeatStatement(_, _) => StmtKind::Repeat(value, body),
+                    _ => StmtKind::With(value, body),
+                };
+                self.push(kind, span);
+            }
+            Node::DoUntilStatement(body, condition) => {
+                let body = self.body(body)?;
+                let condition = self.expression(condition)?;
+                self.push(StmtKind::DoUntil(body, condition), span);
+            }
+            Node::ForStatement(init, condition, step, body) => {
+                let init = self.header_statement(init.as_deref())?;
+                let condition = match condition {
+                    Some(condition) => Some(self.expression(condition)?),
+                    None => None,
+                };
+                let step = self.header_statement(step.as_deref())?;
+                let body = self.body(body)?;
+                self.push(StmtKind::For(init, condition, step, body), span);
+            }
+            Node::SwitchStatement(value, cases) => {
+                let value = self.expression(value)?;
+                let mut lowered = Vec::new();
+                for case in cases {
+                    if let Node::SwitchCase(value, body) = case.as_ref() {
+                        let value = match value {
+                            Some(value) => Some(self.expression(value)?),
+                            None => None,
+                        };
+                        let body = self.collect(|lowerer| lowerer.statements(body))?;
+                        lowered.push((value, body));
+                    }
+                }
+                self.push(StmtKind::Switch(value, lowered), span);
+            }
+            Node::TryStatement(body, catch, finally) => {
+                let body = self.body(body)?;
+                let catch = match catch {
+                    Some((name, handler)) => {
+                        let id = self.declare_local(name, Type::Untyped);
+                        Some((id, self.body(handler)?))
+                    }
+                    None => None,
+                };
+                let finally = match finally {
+                    Some(finally) => Some(self.body(finally)?),
+                    None => None,
+                };
+                self.push(StmtKind::Try(body, catch, finally), span);
+            }
+            Node::DeleteStatement(value) => {
+                let value = self.expression(value)?;
+                self.push(StmtKind::Delete(value), span);
+            }
+            Node::ThrowStatement(value) => {
+                let value = self.expression(value)?;
+                self.push(StmtKind::Throw(value), span);
+            }
+            Node::Break => self.push(StmtKind::Break, span),
+            Node::Continue => self.push(StmtKind::Continue, span),
+            Node::Exit => self.push(StmtKind::Exit, span),
+            Node::Comment(_, _) | Node::BlankLine => {}
+            expr => {
+                let value = self.expression(expr)?;
+                self.push(StmtKind::Expr(value), span);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn statements(&mut self, body: &[Box<Node>]) -> Result<(), Error> {
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// The body of an `if`, a loop... which is a block or a single statement.
+    fn body(&mut self, body: &Node) -> Result<Vec<Stmt>, Error> {
+        match body {
+            Node::Block(statements) | Node::Program(statements) => {
+                self.collect(|lowerer| lowerer.statements(statements))
+            }
+            stmt => self.collect(|lowerer| lowerer.statement(stmt)),
+        }
+    }
+
+    /// The first or last part of a `for`, which is a single statement.
+    fn header_statement(&mut self, node: Option<&Node>) -> Result<Option<Box<Stmt>>, Error> {
+        let Some(node) = node else {
+            return Ok(None);
+        };
+        let mut lowered = self.collect(|lowerer| lowerer.statement(node))?;
+        match lowered.len() {
+            1 => Ok(lowered.pop().map(Box::new)),
+            _ => Err(Error::new(format!(
+                "'for' can only run a single statement before and after its body, found: {:?}",
+                node
+            ))),
+        }
+    }
+
+    /// A symbol `declare_top_level` added, or a new one for declarations
+    /// nested in a function.
+    fn declared(&mut self, name: &str, kind: SymbolKind) -> SymbolId {
+        if let Some(id) = self.scopes.last().and_then(|scope| scope.get(name)) {
+            if self.module.symbols.get(*id).map(|symbol| symbol.kind) == Some(kind) {
+                return *id;
+            }
+        }
+        self.declare(name, kind, self.global_type(name))
+    }
+
+    fn params(&mut self, params: &[Box<Node>]) -> Result<Vec<Param>, Error> {
+        let mut lowered = Vec::new();
+
+        for param in params {
+            let (Some(name), value_type) = (parameter_name(param), parameter_type(param)) else {
+                return Err(Error::new(format!(
+                    "Invalid function parameter: {:?}",
+                    param
+                )));
+            };
+            let (value_type, rest) = match value_type {
+                Type::Rest(item) => (Type::Array(item), true),
+                value_type => (value_type, false),
+            };
+            let symbol = self.declare(name, SymbolKind::Parameter, value_type);
+            let default = match param.as_ref() {
+                Node::DefaultParameter(_, default) => Some(self.expression(default)?),
+                _ => None,
+            };
+
+            lowered.push(Param {
+                symbol,
+                default,
+                rest,
+            });
+        }
+
+        Ok(lowered)
+    }
+
+    /// Lowers a function in a scope of its own. `constructor` holds the call
+    /// to the parent of constructors.
+    fn function(
+        &mut self,
+        name: &str,
+        params: &[Box<Node>],
+        return_type: &Option<Box<Node>>,
+        code: &Node,
+        constructor: Option<Option<&Node>>,
+    ) -> Result<Function, Error> {
+        let return_type = match (return_type, self.globals.get(name)) {
+            (Some(annotation), _) => Type::from_annotation(annotation),
+            (None, Some(Type::Function(_, return_type))) if !name.is_empty() => {
+                return_type.as_ref().clone()
+            }
+            _ => Type::Untyped,
+        };
+
+        self.scopes.push(HashMap::new());
+        let result = self.function_in_scope(params, return_type, code, constructor);
+        self.scopes.pop();
+        self.visible = None;
+        result
+    }
+
+    fn function_in_scope(
+        &mut self,
+        params: &[Box<Node>],
+        return_type: Type,
+        code: &Node,
+        constructor: Option<Option<&Node>>,
+    ) -> Result<Function, Error> {
+        let params = self.params(params)?;
+        let constructor = match constructor {
+            Some(Some(parent)) => Some(Some(self.expression(parent)?)),
+            Some(None) => Some(None),
+            None => None,
+        };
+        let body = match code {
+            Node::Program(stmts) => self.collect(|lowerer| lowerer.statements(stmts))?,
+            _ => Vec::new(),
+        };
+
+        Ok(Function {
+            params,
+            return_type,
+            constructor,
+            body,
+        })
+    }
+
+    /// A class, in a scope holding the parameters of `new` and the methods.
+    fn class(&mut self, name: &str, members: &[Box<Node>]) -> Result<Class, Error> {
+        self.scopes.push(HashMap::new());
+        let result = self.class_in_scope(name, members);
+        self.scopes.pop();
+        self.visible = None;
+        result
+    }
+
+    fn class_in_scope(&mut self, name: &str, members: &[Box<Node>]) -> Result<Class, Error> {
+        let mut class = Class::default();
+        let mut constructor = None;
+
+        for member in members {
+            match member.as_ref() {
+                Node::ConstructorDeclaration(function, parent) => {
+                    if let Node::FunctionDeclaration(_, params, _, code) = function.as_ref() {
+                        class.params = self.params(params)?;
+                        constructor = Some((parent, code));
+                    }
+                }
+                Node::FunctionDeclaration(method, params, return_type, _) => {
+                    let value_type = Type::from_signature(params, return_type, Type::Untyped);
+                    self.declare(method, SymbolKind::Static, value_type);
+                }
+                Node::ClassField(field, annotation, value) => {
+                    let value_type = match (annotation, value) {
+                        (Some(annotation), _) => Type::from_annotation(annotation),
+                        (None, Some(value)) => self.infer(value),
+                        (None, None) => Type::Untyped,
+                    };
+                    self.instance(field, value_type);
+                }
+                Node::Comment(_, _) | Node::BlankLine => {}
+                _ => {
+                    return Err(Error::new(format!(
+                        "Invalid member in class '{name}': {:?}",
+                        member
+                    )))
+                }
+            }
+        }
+
+        for member in members {
+            match member.as_ref() {
+                Node::ClassField(field, _, Some(value)) => {
+                    let id = self.instance(field, Type::Untyped);
+                    let value = self.expression(value)?;
+                    class.fields.push((id, value, self.span(member)));
+                }
+                Node::FunctionDeclaration(method, params, return_type, code) => {
+                    let id = self.resolve(method, false);
+                    let function = self.function("", params, return_type, code, None)?;
+                    class.methods.push((id, function, self.span(member)));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((parent, code)) = constructor {
+            if let Some(parent) = parent {
+                class.parent = Some(self.expression(parent)?);
+            }
+            if let Node::Program(stmts) = code.as_ref() {
+                class.body = self.collect(|lowerer| lowerer.statements(stmts))?;
+            }
+        }
+
+        Ok(class)
+    }
+
+    fn expressions(&mut self, exprs: &[Box<Node>]) -> Result<Vec<Expr>, Error> {
+        exprs.iter().map(|expr| self.expression(expr)).collect()
+    }
+
+    fn expression(&mut self, node: &Node) -> Result<Expr, Error> {
+        let expr =
+            match node {
+                Node::NumericLiteral(value, _) => Expr::Number(*value),
+                Node::StringLiteral(txt) => Expr::String(txt.clone()),
+                Node::BooleanLiteral(value) => Expr::Bool(*value),
+                Node::Identifier(name) => Expr::Symbol(self.resolve(name, false)),
+                Node::BinaryExpression(left, operator, right) => {
+                    let Node::BinaryOperator(operator) = operator.as_ref() else {
+                        return Err(Error::new(format!("Invalid operator: {:?}", operator)));
+                    };
+                    if operator == "??" {
+                        let left_type = self.infer(left);
+                        Expr::Nullish(
+                            Box::new(self.expression(left)?),
+                            Box::new(self.expression(right)?),
+                            left_type,
+                        )
+                    } else {
+                        Expr::binary(self.expression(left)?, operator, self.expression(right)?)
+                    }
+                }
+                Node::UnaryExpression(operator, value) => {
+                    Expr::Unary(operator.clone(), Box::new(self.expression(value)?))
+                }
+                Node::UpdateExpression(operator, target, prefix) => Expr::Update(
+                    operator.clone(),
+                    Box::new(self.expression(target)?),
+                    *prefix,
+                ),
+                Node::TernaryExpression(condition, if_true, if_false) => Expr::Ternary(
+                    Box::new(self.expression(condition)?),
+                    Box::new(self.expression(if_true)?),
+                    Box::new(self.expression(if_false)?),
+                ),
+                Node::MemberAccess(value, member) => {
+                    Expr::Field(Box::new(self.expression(value)?), member.clone())
+                }
+                Node::IndexAccess(value, accessor, indices) => Expr::Index(
+                    Box::new(self.expression(value)?),
+                    accessor.clone(),
+                    self.expressions(indices)?,
+                ),
+                Node::ArrayLiteral(items) => Expr::Array(self.expressions(items)?),
+                Node::StructLiteral(fields) => {
+                    let mut lowered = Vec::new();
+                    for (name, value) in fields {
+                        lowered.push((name.clone(), self.expression(value)?));
+                    }
+                    Expr::Struct(lowered)
+                }
+                Node::FunctionExpression(params, code) => {
+                    Expr::Function(Box::new(self.function("", params, &None, code, None)?))
+                }
+                Node::ArrowFunction(params, return_type, code) => match code.as_ref() {
+                    Node::Program(_) => Expr::Function(Box::new(self.function(
+                        "",
+                        params,
+                        return_type,
+                        code,
+                        None,
+                    )?)),
+                    value => {
+                        let return_type = match return_type {
+                            Some(annotation) => Type::from_annotation(annotation),
+                            None => Type::Untyped,
+                        };
+                        self.scopes.push(HashMap::new());
+                        let lowered = self
+                            .params(params)
+                            .and_then(|params| Ok((params, self.expression(value)?)));
+                        self.scopes.pop();
+                        self.visible = None;
+                        let (params, value) = lowered?;
+                        Expr::Arrow(params, return_type, Box::new(value))
+                    }
+                },
+                Node::ConstructorDeclaration(function, parent) => match function.as_ref() {
+                    Node::FunctionDeclaration(name, params, return_type, code) => {
+                        Expr::Function(Box::new(self.function(
+                            name,
+                            params,
+                            return_type,
+                            code,
+                            Some(parent.as_deref()),
+                        )?))
+                    }
+                    Node::FunctionExpression(params, code) => Expr::Function(Box::new(
+                        self.function("", params, &None, code, Some(parent.as_deref()))?,
+                    )),
+                    _ => return Err(Error::new(format!("Invalid constructor: {:?}", function))),
+                },
+                Node::NewExpression(call) => Expr::New(Box::new(self.expression(call)?)),
+                Node::FunctionCall(callee, args) => {
+                    let callee = match callee.as_ref() {
+                        Node::Identifier(name) => Expr::Symbol(self.resolve(name, true)),
+                        callee => self.expression(callee)?,
+                    };
+                    let args = match args.as_ref() {
+                        Node::Arguments(args) => self.expressions(args)?,
+                        _ => Vec::new(),
+                    };
+                    Expr::call(callee, args)
+                }
+                Node::TemplateString(parts) => {
+                    let mut lowered = Vec::new();
+                    for part in parts {
+                        lowered.push(match part.as_ref() {
+                            Node::StringLiteral(txt) => TemplatePart::Text(txt.clone()),
+                            Node::TemplateExpression(value, precision) => {
+                                TemplatePart::Value(self.expression(value)?, *precision)
+                            }
+                            _ => {
+                                return Err(Error::new(format!(
+                                    "Invalid template string part: {:?}",
+                                    part
+                                )))
+                            }
+                        });
+                    }
+                    Expr::Template(lowered)
+                }
+                _ => {
+                    return Err(Error::new(format!(
+                        "Can't generate GML for node: {:?}",
+                        node
+                    )))
+                }
+            };
+
+        Ok(expr)
+    }
+}
@@ -0,0 +1,551 @@
+//! The passes spelling out the sugar of GML++ in the small core of the IR
+//! that GML is generated from, one feature each, in the order `run` applies
+//! them.
+
+use crate::compiler::checker::Type;
+use crate::compiler::codegen::{expression_code, CodegenOptions};
+use crate::compiler::error::Error;
+use crate::compiler::ir::{
+    walk_expr, walk_function, walk_stmt, Expr, Function, Module, Rewrite, Stmt, StmtKind,
+    SymbolKind, Symbols, TemplatePart,
+};
+use crate::compiler::target::{GmlVersion, VersionedBuiltins};
+use crate::compiler::testing::{assertion_arity, ASSERTIONS};
+
+pub fn run(module: &mut Module, options: &CodegenOptions) -> Result<(), Vec<Error>> {
+    let target = options.target;
+
+    check_target(module, target)?;
+    rewrite(module, |symbols| Arrows { symbols })?;
+    rewrite(module, |symbols| Classes { symbols })?;
+    rewrite(module, |symbols| RestParameters { symbols })?;
+    rewrite(module, |symbols| Templates { symbols, target })?;
+    if options.tests {
+        rewrite(module, |symbols| Assertions { symbols })?;
+    }
+
+    let mut polyfills = Polyfills {
+        symbols: &mut module.symbols,
+        target,
+        names: Vec::new(),
+    };
+    polyfills.body(&mut module.body)?;
+    module.polyfills = polyfills.names;
+
+    rewrite(module, |symbols| Nullish { symbols, target })
+}
+
+/// Runs a pass needing the symbols of the module over its statements.
+fn rewrite<'a, R: Rewrite + 'a>(
+    module: &'a mut Module,
+    pass: impl FnOnce(&'a mut Symbols) -> R,
+) -> Result<(), Vec<Error>> {
+    let mut body = std::mem::take(&mut module.body);
+    let result = pass(&mut module.symbols).body(&mut body);
+    module.body = body;
+    result
+}
+
+/// Rejects what the target can't run in any form: functions and what
+/// builds on them before GameMaker Studio 2.3.
+pub fn check_target(module: &mut Module, target: GmlVersion) -> Result<(), Vec<Error>> {
+    let mut body = std::mem::take(&mut module.body);
+    let result = TargetCheck {
+        symbols: &module.symbols,
+        target,
+    }
+    .body(&mut body);
+    module.body = body;
+    result
+}
+
+struct TargetCheck<'a> {
+    symbols: &'a Symbols,
+    target: GmlVersion,
+}
+
+impl TargetCheck<'_> {
+    fn require_functions(&self, feature: &str) -> Result<(), Vec<Error>> {
+        if self.target.supports_functions() {
+            return Ok(());
+        }
+
+        Err(vec![Error::new(format!(
+            "{feature} can't be lowered for {}, it needs GameMaker Studio 2.3 or newer",
+            self.target.name()
+        ))])
+    }
+}
+
+impl Rewrite for TargetCheck<'_> {
+    fn stmt(&mut self, stmt: &mut Stmt) -> Result<(), Vec<Error>> {
+        match &stmt.kind {
+            StmtKind::Static(id, _) => {
+                self.require_functions(&format!("Static variable '{}'", self.symbols.name(*id)))?
+            }
+            StmtKind::Function(_, function) if function.constructor.is_some() => {
+                self.require_functions("Constructors")?
+            }
+            StmtKind::Function(id, _) if !self.target.supports_functions() => {
+                return Err(vec![Error::new(format!(
+                    "Function '{}' can't be lowered for {}, function declarations need GameMaker Studio 2.3 or newer",
+                    self.symbols.name(*id),
+                    self.target.name()
+                ))]);
+            }
+            StmtKind::Class(id, _) => {
+                self.require_functions(&format!("Class '{}'", self.symbols.name(*id)))?
+            }
+            StmtKind::Try(_, _, _) => self.require_functions("'try'")?,
+            StmtKind::Throw(_) => self.require_functions("'throw'")?,
+            StmtKind::Delete(_) => self.require_functions("'delete'")?,
+            _ => {}
+        }
+        walk_stmt(self, stmt)
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        match expr {
+            Expr::Struct(_) => self.require_functions("Struct literals")?,
+            Expr::Function(function) if function.constructor.is_some() => {
+                self.require_functions("Constructors")?
+            }
+            Expr::Function(_) => self.require_functions("Function expressions")?,
+            Expr::Arrow(_, _, _) => self.require_functions("Arrow functions")?,
+            Expr::New(_) => self.require_functions("'new'")?,
+            Expr::Ternary(_, _, _) if !self.target.supports_ternary() => {
+                return Err(vec![Error::new(format!(
+                    "Ternary expressions can't be lowered for {}",
+                    self.target.name()
+                ))]);
+            }
+            _ => {}
+        }
+        walk_expr(self, expr)
+    }
+}
+
+/// `fn(params) => value` becomes a function expression returning the value.
+struct Arrows<'a> {
+    symbols: &'a mut Symbols,
+}
+
+impl Rewrite for Arrows<'_> {
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        walk_expr(self, expr)?;
+
+        if let Expr::Arrow(params, return_type, value) = expr {
+            let value = match value.as_ref() {
+                Expr::Symbol(id) if self.symbols.name(*id) == "undefined" => None,
+                value => Some(value.clone()),
+            };
+            *expr = Expr::Function(Box::new(Function {
+                params: std::mem::take(params),
+                return_type: return_type.clone(),
+                constructor: None,
+                body: vec![Stmt::new(StmtKind::Return(value), None)],
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// A class becomes a constructor setting the fields that have a value, then
+/// running the body of `new`, with every method as a static function.
+struct Classes<'a> {
+    symbols: &'a mut Symbols,
+}
+
+impl Rewrite for Classes<'_> {
+    fn stmt(&mut self, stmt: &mut Stmt) -> Result<(), Vec<Error>> {
+        walk_stmt(self, stmt)?;
+
+        let StmtKind::Class(id, class) = &mut stmt.kind else {
+            return Ok(());
+        };
+        let id = *id;
+        let class = std::mem::take(class);
+
+        let mut body = Vec::new();
+        for (field, value, span) in class.fields {
+            body.push(Stmt::new(
+                StmtKind::Assign(Expr::Symbol(field), "=".to_string(), value),
+                span,
+            ));
+        }
+        body.extend(class.body);
+        for (method, function, span) in class.methods {
+            body.push(Stmt::new(
+                StmtKind::Static(method, Expr::Function(Box::new(function))),
+                span,
+            ));
+        }
+
+        let return_type = match &self.symbols.get(id).map(|symbol| &symbol.value_type) {
+            Some(Type::Function(_, instance)) => instance.as_ref().clone(),
+            _ => Type::Named(self.symbols.name(id).to_string(), Vec::new()),
+        };
+        stmt.kind = StmtKind::Function(
+            id,
+            Function {
+                params: class.params,
+                return_type,
+                constructor: Some(class.parent),
+                body,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// GML has no rest parameters, the extra arguments are collected from
+/// `argument[]` into an array instead.
+struct RestParameters<'a> {
+    symbols: &'a mut Symbols,
+}
+
+impl Rewrite for RestParameters<'_> {
+    fn function(&mut self, function: &mut Function) -> Result<(), Vec<Error>> {
+        walk_function(self, function)?;
+
+        let Some(position) = function.params.iter().position(|param| param.rest) else {
+            return Ok(());
+        };
+        let rest = function.params.remove(position).symbol;
+        self.symbols.get_mut(rest).kind = SymbolKind::Local;
+
+        let index = self.symbols.temp(Type::Number);
+        let argument_count = self.symbols.external("argument_count");
+        let argument = self.symbols.external("argument");
+        let array_push = self.symbols.external("array_push");
+
+        let collect = vec![
+            Stmt::new(StmtKind::Var(rest, Some(Expr::Array(Vec::new()))), None),
+            Stmt::new(
+                StmtKind::For(
+                    Some(Box::new(Stmt::new(
+                        StmtKind::Var(index, Some(Expr::Number(position as f64))),
+                        None,
+                    ))),
+                    Some(Expr::binary(
+                        Expr::Symbol(index),
+                        "<",
+                        Expr::Symbol(argument_count),
+                    )),
+                    Some(Box::new(Stmt::new(
+                        StmtKind::Expr(Expr::Update(
+                            "++".to_string(),
+                            Box::new(Expr::Symbol(index)),
+                            false,
+                        )),
+                        None,
+                    ))),
+                    vec![Stmt::new(
+                        StmtKind::Expr(Expr::call(
+                            Expr::Symbol(array_push),
+                            vec![
+                                Expr::Symbol(rest),
+                                Expr::Index(
+                                    Box::new(Expr::Symbol(argument)),
+                                    None,
+                                    vec![Expr::Symbol(index)],
+                                ),
+                            ],
+                        )),
+                        None,
+                    )],
+                ),
+                None,
+            ),
+        ];
+        function.body.splice(0..0, collect);
+        Ok(())
+    }
+}
+
+/// Template strings stay for targets having them, with precisions turned
+/// into `string_format` and nested templates into concatenations, so their
+/// quotes don't end up inside the braces of the outer one. Other targets get
+/// concatenations of `string()` calls.
+struct Templates<'a> {
+    symbols: &'a mut Symbols,
+    target: GmlVersion,
+}
+
+impl Templates<'_> {
+    fn string_format(&mut self, value: Expr, precision: usize) -> Expr {
+        Expr::call(
+            Expr::Symbol(self.symbols.external("string_format")),
+            vec![value, Expr::Number(0.0), Expr::Number(precision as f64)],
+        )
+    }
+
+    fn native(&mut self, parts: Vec<TemplatePart>) -> Result<Expr, Vec<Error>> {
+        let mut lowered = Vec::new();
+
+        for part in parts {
+            lowered.push(match part {
+                TemplatePart::Value(Expr::Template(nested), _) => {
+                    TemplatePart::Value(self.concatenation(nested)?, None)
+                }
+                TemplatePart::Value(mut value, Some(precision)) => {
+                    self.expr(&mut value)?;
+                    TemplatePart::Value(self.string_format(value, precision), None)
+                }
+                TemplatePart::Value(mut value, None) => {
+                    self.expr(&mut value)?;
+                    TemplatePart::Value(value, None)
+                }
+                text => text,
+            });
+        }
+
+        Ok(Expr::Template(lowered))
+    }
+
+    fn concatenation(&mut self, parts: Vec<TemplatePart>) -> Result<Expr, Vec<Error>> {
+        let mut pieces = Vec::new();
+
+        for part in parts {
+            pieces.push(match part {
+                TemplatePart::Text(txt) => Expr::String(txt),
+                TemplatePart::Value(value @ Expr::String(_), None) => value,
+                TemplatePart::Value(Expr::Template(nested), None) => self.concatenation(nested)?,
+                TemplatePart::Value(mut value, None) => {
+                    self.expr(&mut value)?;
+                    Expr::call(Expr::Symbol(self.symbols.external("string")), vec![value])
+                }
+                TemplatePart::Value(mut value, Some(precision)) => {
+                    self.expr(&mut value)?;
+                    self.string_format(value, precision)
+                }
+            });
+        }
+
+        let mut pieces = pieces.into_iter();
+        let first = pieces.next().unwrap_or(Expr::String(String::new()));
+        Ok(pieces.fold(first, |text, piece| Expr::binary(text, "+", piece)))
+    }
+}
+
+impl Rewrite for Templates<'_> {
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        let Expr::Template(parts) = expr else {
+            return walk_expr(self, expr);
+        };
+
+        let parts = std::mem::take(parts);
+        *expr = if self.target.supports_template_strings() {
+            self.native(parts)?
+        } else {
+            self.concatenation(parts)?
+        };
+        Ok(())
+    }
+}
+
+/// Assertions the module doesn't declare itself call the helpers of
+/// `testing::runner`, with every optional argument and where they are
+/// written.
+struct Assertions<'a> {
+    symbols: &'a mut Symbols,
+}
+
+impl Rewrite for Assertions<'_> {
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        walk_expr(self, expr)?;
+
+        let Expr::Call(callee, args) = expr else {
+            return Ok(());
+        };
+        let Expr::Symbol(id) = callee.as_ref() else {
+            return Ok(());
+        };
+        let Some(symbol) = self.symbols.get(*id) else {
+            return Ok(());
+        };
+        if symbol.kind != SymbolKind::External
+            || !ASSERTIONS.iter().any(|(name, _)| *name == symbol.name)
+        {
+            return Ok(());
+        }
+
+        let name = symbol.name.clone();
+        let arity = assertion_arity(&name).unwrap_or_default();
+        let undefined = self.symbols.external("undefined");
+        args.resize(arity.max(args.len()), Expr::Symbol(undefined));
+        args.push(Expr::Location);
+        **callee = Expr::Symbol(self.symbols.external(&format!("__gmpp_{name}")));
+        Ok(())
+    }
+}
+
+/// Calls to built-ins the target lacks go to their polyfill.
+struct Polyfills<'a> {
+    symbols: &'a mut Symbols,
+    target: GmlVersion,
+    /// The polyfills the module needs, in the order they are first used.
+    names: Vec<String>,
+}
+
+impl Rewrite for Polyfills<'_> {
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        walk_expr(self, expr)?;
+
+        let Expr::Call(callee, _) = expr else {
+            return Ok(());
+        };
+        let Expr::Symbol(id) = callee.as_ref() else {
+            return Ok(());
+        };
+        let name = match self.symbols.get(*id) {
+            Some(symbol) if symbol.kind == SymbolKind::External => symbol.name.clone(),
+            _ => return Ok(()),
+        };
+        let introduced_in = match VersionedBuiltins::introduced_in(&name) {
+            Some(version) if version > self.target => version,
+            _ => return Ok(()),
+        };
+
+        if VersionedBuiltins::polyfill(&name).is_none() || !self.target.supports_functions() {
+            return Err(vec![Error::new(format!(
+                "'{name}' isn't available in {} and can't be polyfilled, it needs {} or newer",
+                self.target.name(),
+                introduced_in.name()
+            ))]);
+        }
+
+        if !self.names.contains(&name) {
+            self.names.push(name.clone());
+        }
+        **callee = Expr::Symbol(self.symbols.external(&format!("__gmpp_{name}")));
+        Ok(())
+    }
+}
+
+/// `a ?? b` stays for targets having it. Elsewhere it becomes
+/// `is_undefined(a) ? b : a` when `a` can be evaluated twice, and otherwise
+/// `a` goes to a temporary first, when the `??` is the whole value of a
+/// statement. `a ??= b` becomes `a = a ?? b` the same way.
+struct Nullish<'a> {
+    symbols: &'a mut Symbols,
+    target: GmlVersion,
+}
+
+impl Nullish<'_> {
+    fn is_simple(expr: &Expr) -> bool {
+        matches!(expr, Expr::Symbol(_) | Expr::Number(_) | Expr::String(_))
+    }
+
+    fn fallback(&mut self, value: Expr, default: Expr) -> Expr {
+        let is_undefined = Expr::Symbol(self.symbols.external("is_undefined"));
+        Expr::Ternary(
+            Box::new(Expr::call(is_undefined, vec![value.clone()])),
+            Box::new(default),
+            Box::new(value),
+        )
+    }
+
+    /// Moves the left side of a `??` which is the whole value of a statement
+    /// to a temporary declared before it, returning that declaration.
+    fn hoist(&mut self, stmt: &mut Stmt) -> Option<Stmt> {
+        if self.target.supports_nullish() || !self.target.supports_ternary() {
+            return None;
+        }
+
+        let value = match &mut stmt.kind {
+            StmtKind::Var(_, Some(value))
+            | StmtKind::Assign(Expr::Symbol(_), _, value)
+            | StmtKind::Return(Some(value))
+            | StmtKind::Throw(value) => value,
+            _ => return None,
+        };
+        let Expr::Nullish(left, right, left_type) = value else {
+            return None;
+        };
+        if Self::is_simple(left) {
+            return None;
+        }
+
+        let temp = self.symbols.temp(left_type.clone());
+        let left = std::mem::replace(left.as_mut(), Expr::Symbol(temp));
+        let right = std::mem::replace(right.as_mut(), Expr::Bool(false));
+        *value = self.fallback(Expr::Symbol(temp), right);
+
+        Some(Stmt::new(StmtKind::Var(temp, Some(left)), stmt.span))
+    }
+}
+
+impl Rewrite for Nullish<'_> {
+    fn body(&mut self, body: &mut Vec<Stmt>) -> Result<(), Vec<Error>> {
+        let mut lowered = Vec::with_capacity(body.len());
+        let mut errors = Vec::new();
+
+        for stmt in std::mem::take(body) {
+            let mut chain = vec![stmt];
+            while let Some(hoisted) = chain.last_mut().and_then(|stmt| self.hoist(stmt)) {
+                chain.push(hoisted);
+            }
+            for mut stmt in chain.into_iter().rev() {
+                if let Err(failed) = self.stmt(&mut stmt) {
+                    errors.extend(failed.into_iter().map(|error| error.or_at(stmt.span)));
+                }
+                lowered.push(stmt);
+            }
+        }
+
+        *body = lowered;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) -> Result<(), Vec<Error>> {
+        walk_stmt(self, stmt)?;
+
+        let StmtKind::Assign(target, operator, value) = &mut stmt.kind else {
+            return Ok(());
+        };
+        if operator != "??=" || self.target.supports_nullish() {
+            return Ok(());
+        }
+        if !Self::is_simple(target) || !self.target.supports_ternary() {
+            return Err(vec![Error::new(format!(
+                "'{} ??= {}' can't be lowered for {}",
+                expression_code(self.symbols, target),
+                expression_code(self.symbols, value),
+                self.target.name()
+            ))]);
+        }
+
+        let default = std::mem::replace(value, Expr::Bool(false));
+        *value = self.fallback(target.clone(), default);
+        *operator = "=".to_string();
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), Vec<Error>> {
+        walk_expr(self, expr)?;
+
+        let Expr::Nullish(left, right, _) = expr else {
+            return Ok(());
+        };
+        let (left, right) = (left.as_ref().clone(), right.as_ref().clone());
+
+        *expr = if self.target.supports_nullish() {
+            Expr::binary(left, "??", right)
+        } else if self.target.supports_ternary() && Self::is_simple(&left) {
+            self.fallback(left, right)
+        } else {
+            return Err(vec![Error::new(format!(
+                "'{} ?? {}' can't be lowered for {}",
+                expression_code(self.symbols, &left),
+                expression_code(self.symbols, &right),
+                self.target.name()
+            ))]);
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use crate::compiler::codegen::CodegenOptions;
+use crate::compiler::error::{Error, Span};
+use crate::compiler::ir::{
+    Expr, Function, Module, Stmt, StmtKind, SymbolId, SymbolKind, Symbols, TemplatePart,
+};
+use crate::compiler::target::{GmlVersion, VersionedBuiltins};
+use std::collections::HashSet;
+
+/// Checks that the passes left only the core of the IR the target can run:
+/// no sugar, no feature of a newer GameMaker, symbols that exist and
+/// temporaries declared once, before they are read. Errors point at the
+/// statement they are found in.
+pub fn validate(module: &Module, options: &CodegenOptions) -> Result<(), Vec<Error>> {
+    let mut validator = Validator {
+        symbols: &module.symbols,
+        target: options.target,
+        tests: options.tests,
+        temps: HashSet::new(),
+        span: None,
+        errors: Vec::new(),
+    };
+
+    validator.body(&module.body);
+
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+struct Validator<'a> {
+    symbols: &'a Symbols,
+    target: GmlVersion,
+    tests: bool,
+    /// Temporaries declared so far.
+    temps: HashSet<SymbolId>,
+    /// Where the statement being validated was written.
+    span: Option<Span>,
+    errors: Vec<Error>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, error: String) {
+        self.errors.push(Error::new(error).or_at(self.span));
+    }
+
+    fn require(&mut self, supported: bool, feature: &str) {
+        if !supported {
+            self.error(format!("{feature} is left for {}", self.target.name()));
+        }
+    }
+
+    /// Checks a symbol exists, and that a temporary has been declared when
+    /// it is read.
+    fn symbol(&mut self, id: SymbolId) {
+        match self.symbols.get(id) {
+            None => self.error(format!("Unknown symbol {id}")),
+            Some(symbol) => {
+                if matches!(symbol.kind, SymbolKind::Temp(_)) && !self.temps.contains(&id) {
+                    self.error(format!(
+                        "Temporary '{}' is read before it is set",
+                        symbol.name
+                    ));
+                }
+            }
+        }
+    }
+
+    fn declare(&mut self, id: SymbolId) {
+        match self.symbols.get(id) {
+            None => self.error(format!("Unknown symbol {id}")),
+            Some(symbol) if matches!(symbol.kind, SymbolKind::Temp(_)) => {
+                if !self.temps.insert(id) {
+                    self.error(format!("Temporary '{}' is declared twice", symbol.name));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn body(&mut self, body: &[Stmt]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let outer = self.span;
+        self.span = stmt.span.or(outer);
+        self.stmt_kind(stmt);
+        self.span = outer;
+    }
+
+    fn stmt_kind(&mut self, stmt: &Stmt) {
+        let functions = self.target.supports_functions();
+
+        match &stmt.kind {
+            StmtKind::Var(id, value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.declare(*id);
+            }
+            StmtKind::Static(id, value) => {
+                self.require(functions, "A static variable");
+                self.expr(value);
+                self.declare(*id);
+            }
+            StmtKind::GlobalVar(ids) => {
+                for id in ids {
+                    self.symbol(*id);
+                }
+            }
+            StmtKind::Macro(id, _) => self.symbol(*id),
+            StmtKind::Enum(id, members) => {
+                self.symbol(*id);
+                for value in members.iter().filter_map(|(_, value)| value.as_ref()) {
+                    self.expr(value);
+                }
+            }
+            StmtKind::Function(id, function) => {
+                self.require(functions, "A function declaration");
+                self.symbol(*id);
+                self.function(function);
+            }
+            StmtKind::Class(id, _) => {
+                self.error(format!("Class '{}' is left", self.symbols.name(*id)))
+            }
+            StmtKind::Assign(target, operator, value) => {
+                if operator == "??=" {
+                    self.require(self.target.supports_nullish(), "'??='");
+                }
+                self.expr(target);
+                self.expr(value);
+            }
+            StmtKind::Expr(value) | StmtKind::Return(Some(value)) => self.expr(value),
+            StmtKind::Throw(value) => {
+                self.require(functions, "'throw'");
+                self.expr(value);
+            }
+            StmtKind::Delete(value) => {
+                self.require(functions, "'delete'");
+                self.expr(value);
+            }
+            StmtKind::Block(body) => self.body(body),
+            StmtKind::If(condition, then, otherwise) => {
+                self.expr(condition);
+                self.body(then);
+                if let Some(otherwise) = otherwise {
+                    if !matches!(otherwise.kind, StmtKind::If(_, _, _) | StmtKind::Block(_)) {
+                        self.error(String::from("'else' is neither a block nor an 'if'"));
+                    }
+                    self.stmt(otherwise);
+                }
+            }
+            StmtKind::While(value, body)
+            | StmtKind::Repeat(value, body)
+            | StmtKind::With(value, body) => {
+                self.expr(value);
+                self.body(body);
+            }
+            StmtKind::DoUntil(body, condition) => {
+                self.body(body);
+                self.expr(condition);
+            }
+            StmtKind::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(condition) = condition {
+                    self.expr(condition);
+                }
+                self.body(body);
+                if let Some(step) = step {
+                    self.stmt(step);
+                }
+            }
+            StmtKind::Switch(value, cases) => {
+                self.expr(value);
+                for (value, body) in cases {
+                    if let Some(value) = value {
+                        self.expr(value);
+                    }
+                    self.body(body);
+                }
+            }
+            StmtKind::Try(body, catch, finally) => {
+                self.require(functions, "'try'");
+                self.body(body);
+                if let Some((id, handler)) = catch {
+                    self.declare(*id);
+                    self.body(handler);
+                }
+                if let Some(finally) = finally {
+                    self.body(finally);
+                }
+            }
+            StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue | StmtKind::Exit => {}
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        for param in &function.params {
+            self.declare(param.symbol);
+            if param.rest {
+                let name = self.symbols.name(param.symbol).to_string();
+                self.error(format!("Rest parameter '{name}' is left"));
+            }
+            if let Some(default) = &param.default {
+                self.expr(default);
+            }
+        }
+        if let Some(Some(parent)) = &function.constructor {
+            self.expr(parent);
+        }
+        self.body(&function.body);
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let functions = self.target.supports_functions();
+
+        match expr {
+            Expr::Number(_) | Expr::String(_) | Expr::Bool(_) => {}
+            Expr::Symbol(id) => self.symbol(*id),
+            Expr::Binary(left, operator, right) => {
+                if operator == "??" {
+                    self.require(self.target.supports_nullish(), "'??'");
+                }
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Unary(_, value) | Expr::Update(_, value, _) | Expr::Field(value, _) => {
+                self.expr(value)
+            }
+            Expr::Ternary(condition, if_true, if_false) => {
+                self.require(self.target.supports_ternary(), "A ternary expression");
+                self.expr(condition);
+                self.expr(if_true);
+                self.expr(if_false);
+            }
+            Expr::Index(value, _, indices) => {
+                self.expr(value);
+                self.exprs(indices);
+            }
+            Expr::Array(items) => self.exprs(items),
+            Expr::Struct(fields) => {
+                self.require(functions, "A struct literal");
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            Expr::Call(callee, args) => {
+                if let Expr::Symbol(id) = callee.as_ref() {
+                    self.builtin(*id);
+                }
+                self.expr(callee);
+                self.exprs(args);
+            }
+            Expr::New(call) => {
+                self.require(functions, "'new'");
+                self.expr(call);
+            }
+            Expr::Function(function) => {
+                self.require(functions, "A function expression");
+                self.function(function);
+            }
+            Expr::Template(parts) => {
+                self.require(self.target.supports_template_strings(), "A template string");
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(_) => {}
+                        TemplatePart::Value(Expr::Template(_), _) => {
+                            self.error(String::from("A template string is nested in another"))
+                        }
+                        TemplatePart::Value(_, Some(_)) => {
+                            self.error(String::from("A template string value has a precision"))
+                        }
+                        TemplatePart::Value(value, None) => self.expr(value),
+                    }
+                }
+            }
+            Expr::Location => {
+                if !self.tests {
+                    self.error(String::from(
+                        "An assertion location is left outside of tests",
+                    ));
+                }
+            }
+            Expr::Arrow(_, _, _) => self.error(String::from("An arrow function is left")),
+            Expr::Nullish(_, _, _) => self.error(String::from("A '??' is left")),
+        }
+    }
+
+    /// Checks a called built-in exists in the target.
+    fn builtin(&mut self, id: SymbolId) {
+        let Some(symbol) = self.symbols.get(id) else {
+            return;
+        };
+        if symbol.kind != SymbolKind::External {
+            return;
+        }
+        if let Some(version) = VersionedBuiltins::introduced_in(&symbol.name) {
+            if version > self.target {
+                let name = symbol.name.clone();
+                self.error(format!(
+                    "'{name}' is called, which needs {}",
+                    version.name()
+                ));
+            }
+        }
+    }
+}
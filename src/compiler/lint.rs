@@ -0,0 +1,1183 @@
+use crate::compiler::ast::Node;
+use crate::compiler::attributes::Registry;
+use crate::compiler::checker::{always_exits, parameter_name};
+use crate::compiler::error::Span;
+use crate::compiler::parser::Positions;
+use crate::compiler::printer::{print_program, FormatOptions};
+use crate::compiler::tokenizer::{tokenize, Token, TokenType};
+use std::collections::{HashMap, HashSet};
+
+/// How a lint is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    pub fn from_name(name: &str) -> Result<LintLevel, String> {
+        match name {
+            "allow" => Ok(LintLevel::Allow),
+            "warn" => Ok(LintLevel::Warn),
+            "deny" => Ok(LintLevel::Deny),
+            _ => Err(format!(
+                "Unknown lint level '{name}', expected one of: allow, warn, deny"
+            )),
+        }
+    }
+}
+
+/// A rule of the linter.
+pub struct Lint {
+    pub name: &'static str,
+    pub code: &'static str,
+    /// Level used when neither the command line nor the manifest sets one.
+    pub level: LintLevel,
+    pub description: &'static str,
+}
+
+pub const LINTS: [Lint; 11] = [
+    Lint {
+        name: "unused-variable",
+        code: "L001",
+        level: LintLevel::Warn,
+        description: "A local variable is never read",
+    },
+    Lint {
+        name: "unused-parameter",
+        code: "L002",
+        level: LintLevel::Warn,
+        description: "A parameter is never read, start its name with _ to keep it",
+    },
+    Lint {
+        name: "unused-import",
+        code: "L003",
+        level: LintLevel::Warn,
+        description: "An imported name is never used",
+    },
+    Lint {
+        name: "shadowed-binding",
+        code: "L004",
+        level: LintLevel::Warn,
+        description: "A variable or parameter hides another one, or is declared again",
+    },
+    Lint {
+        name: "unreachable-code",
+        code: "L005",
+        level: LintLevel::Warn,
+        description: "Statements after a return, exit, break or continue",
+    },
+    Lint {
+        name: "constant-condition",
+        code: "L006",
+        level: LintLevel::Warn,
+        description: "A condition that is always true or always false",
+    },
+    Lint {
+        name: "self-assignment",
+        code: "L007",
+        level: LintLevel::Warn,
+        description: "A variable assigned to itself",
+    },
+    Lint {
+        name: "undefined-comparison",
+        code: "L008",
+        level: LintLevel::Warn,
+        description: "A comparison with undefined instead of is_undefined()",
+    },
+    Lint {
+        name: "with-non-object",
+        code: "L009",
+        level: LintLevel::Deny,
+        description: "with used on a value that can't be an instance",
+    },
+    Lint {
+        name: "deprecated-function",
+        code: "L010",
+        level: LintLevel::Warn,
+        description: "A call to a function GameMaker deprecated",
+    },
+    Lint {
+        name: "deprecated-declaration",
+        code: "L011",
+        level: LintLevel::Warn,
+        description: "A use of a declaration marked @deprecated",
+    },
+];
+
+/// Functions GameMaker deprecated, what replaces them, and whether the
+/// replacement takes the same arguments so the call can be renamed.
+const DEPRECATED: [(&str, &str, bool); 8] = [
+    ("array_length_1d", "array_length", true),
+    ("array_length_2d", "array_length", false),
+    ("array_height_2d", "array_length", true),
+    ("instance_create", "instance_create_layer", false),
+    ("sound_play", "audio_play_sound", false),
+    ("sound_loop", "audio_play_sound", false),
+    ("sound_stop", "audio_stop_sound", true),
+    ("sound_isplaying", "audio_is_playing", true),
+];
+
+/// Looks a lint up by name or by code.
+pub fn find_lint(name: &str) -> Option<&'static Lint> {
+    LINTS
+        .iter()
+        .find(|lint| lint.name == name || lint.code == name)
+}
+
+/// What a lint found, with the fix for it when there is a safe one.
+pub struct Diagnostic {
+    pub lint: &'static Lint,
+    pub level: LintLevel,
+    pub message: String,
+    /// The statement it is about.
+    pub span: Option<Span>,
+    fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn is_fixable(&self) -> bool {
+        self.fix.is_some()
+    }
+
+    /// What `--fix` does about it, with the code it writes instead when it
+    /// replaces something.
+    pub fn suggestion(&self) -> Option<(String, Option<String>)> {
+        let fix = self.fix.as_ref()?;
+        Some((fix.description.clone(), fix.code.clone()))
+    }
+
+    /// The code `--fix` replaces or removes.
+    pub fn fix_spans(&self) -> Vec<Span> {
+        self.fix
+            .iter()
+            .flat_map(|fix| fix.edits.iter().map(|(span, _)| *span))
+            .collect()
+    }
+}
+
+/// Edits of the source fixing a diagnostic.
+struct Fix {
+    description: String,
+    /// The code written instead, for a replacement.
+    code: Option<String>,
+    /// The bytes replaced and what is written instead, nothing to remove
+    /// them.
+    edits: Vec<(Span, String)>,
+}
+
+impl Fix {
+    fn remove(description: &str, spans: Vec<Span>) -> Fix {
+        Fix {
+            description: description.to_string(),
+            code: None,
+            edits: spans
+                .into_iter()
+                .map(|span| (span, String::new()))
+                .collect(),
+        }
+    }
+
+    fn replace(code: String, edits: Vec<(Span, String)>) -> Fix {
+        Fix {
+            description: format!("Replace with `{code}`"),
+            code: Some(code),
+            edits,
+        }
+    }
+}
+
+/// Lints a program parsed with its comments, `// gmpp-allow(rule)` turning
+/// a lint off for the statement it is written before or after. `level`
+/// gives the level of every lint, those allowed being skipped. Diagnostics
+/// point at the statements `positions` knows the place of, and fixes edit
+/// the source at the offsets of `tokens`, those the program was parsed from.
+pub fn lint(
+    program: &Node,
+    positions: &Positions,
+    tokens: &[Token],
+    level: &dyn Fn(&Lint) -> LintLevel,
+) -> Vec<Diagnostic> {
+    let registry = Registry::collect(program);
+    let mut linter = Linter {
+        level,
+        positions,
+        tokens,
+        span: None,
+        deprecated: registry
+            .marked("deprecated")
+            .into_iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    registry.deprecation(name).unwrap_or_default().to_string(),
+                )
+            })
+            .collect(),
+        scopes: vec![Vec::new()],
+        allowed: Vec::new(),
+        blocks: Vec::new(),
+        entered: 0,
+        imports: Vec::new(),
+        claimed: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+
+    linter.visit(program);
+    let module = linter.scopes.pop().unwrap_or_default();
+    linter.unused_imports(module);
+
+    linter.diagnostics
+}
+
+/// Applies the fixes of the diagnostics to the source they were found in,
+/// returning the fixed source and how many problems it fixes. Everything
+/// else is kept as written. Statements removed alone on their lines take
+/// the lines with them, and edits inside removed code are dropped.
+pub fn apply_fixes(source: &str, diagnostics: &[Diagnostic]) -> (String, usize) {
+    let mut edits: Vec<(Span, &str)> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.fix.as_ref())
+        .flat_map(|fix| &fix.edits)
+        .map(|(span, text)| match text.is_empty() {
+            true => (whole_lines(source, *span), ""),
+            false => (*span, text.as_str()),
+        })
+        .collect();
+
+    // Removals first, the longest first, so what they cover is dropped.
+    edits.sort_by_key(|(span, text)| match text.is_empty() {
+        true => (span.start, 0, usize::MAX - span.end),
+        false => (span.start, 1, span.end),
+    });
+    let mut kept: Vec<(Span, &str)> = Vec::new();
+    for (span, text) in edits {
+        if kept.last().is_none_or(|(last, _)| span.start >= last.end) {
+            kept.push((span, text));
+        }
+    }
+
+    let mut code = source.to_string();
+    for (span, text) in kept.into_iter().rev() {
+        code.replace_range(span.start..span.end, text);
+    }
+    let count = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.is_fixable())
+        .count();
+    (code, count)
+}
+
+/// The span of a removal, grown to the lines it is on when nothing else is,
+/// or else to the spaces after it when it starts its line.
+fn whole_lines(source: &str, span: Span) -> Span {
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    if !source[line_start..span.start].trim().is_empty() {
+        return span;
+    }
+
+    let rest = &source[span.end..];
+    let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+    if rest[..line_end].trim().is_empty() {
+        return Span::new(line_start, span.end + line_end);
+    }
+    let spaces = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    Span::new(span.start, span.end + spaces)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BindingKind {
+    Variable,
+    Parameter,
+    /// An imported name, with the index of its import.
+    Import(usize),
+    /// Functions, enums, macros and other names no lint reports unused.
+    Global,
+}
+
+struct Binding {
+    name: String,
+    kind: BindingKind,
+    used: bool,
+    /// Lints allowed where it is declared.
+    allowed: Vec<String>,
+    /// What the value is when it can't be an instance, like `a string`.
+    non_object: Option<&'static str>,
+    /// Where it is declared.
+    span: Option<Span>,
+    /// The blocks it is declared in, outermost first.
+    blocks: Vec<usize>,
+}
+
+struct Linter<'a> {
+    level: &'a dyn Fn(&Lint) -> LintLevel,
+    positions: &'a Positions,
+    tokens: &'a [Token],
+    /// The innermost statement being linted whose position is known.
+    span: Option<Span>,
+    /// Top-level declarations marked `@deprecated`, with their message.
+    deprecated: HashMap<String, String>,
+    /// Names declared by the module and by every function being linted,
+    /// innermost last.
+    scopes: Vec<Vec<Binding>>,
+    /// Lints allowed by the comments around the statement being linted.
+    allowed: Vec<String>,
+    /// The blocks being linted, outermost first, each numbered by when it
+    /// is entered.
+    blocks: Vec<usize>,
+    entered: usize,
+    /// Imports of the module with where they are, their names and module.
+    imports: Vec<(Option<Span>, Vec<String>, String)>,
+    /// Offsets of the tokens a fix was found at, so the next problem of the
+    /// same statement is looked for after them.
+    claimed: HashSet<usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, name: &str, message: String, fix: Option<Fix>) {
+        let allowed = self.allowed.clone();
+        self.report_in(&allowed, self.span, name, message, fix);
+    }
+
+    fn report_in(
+        &mut self,
+        allowed: &[String],
+        span: Option<Span>,
+        name: &str,
+        message: String,
+        fix: Option<Fix>,
+    ) {
+        let lint = find_lint(name).expect("lints are reported by their name");
+        if allowed
+            .iter()
+            .any(|allowed| allowed == lint.name || allowed == lint.code)
+        {
+            return;
+        }
+
+        let level = (self.level)(lint);
+        if level != LintLevel::Allow {
+            self.diagnostics.push(Diagnostic {
+                lint,
+                level,
+                message,
+                span,
+                fix,
+            });
+        }
+    }
+
+    /// Lints the statements of a block, with the lints their comments allow.
+    fn body(&mut self, body: &[Box<Node>]) {
+        let mut comments: Vec<&str> = Vec::new();
+        let mut exited = false;
+        let mut reported = false;
+
+        for (i, stmt) in body.iter().enumerate() {
+            match stmt.as_ref() {
+                Node::Comment(text, false) => {
+                    comments.push(text);
+                    continue;
+                }
+                Node::Comment(_, true) | Node::BlankLine => continue,
+                _ => {}
+            }
+
+            let trailing = match body.get(i + 1).map(|next| next.as_ref()) {
+                Some(Node::Comment(text, true)) => Some(text.as_str()),
+                _ => None,
+            };
+            let outer = self.allowed.len();
+            for comment in comments.drain(..).chain(trailing) {
+                self.allowed.extend(allowed_lints(comment));
+            }
+            let outer_span = self.span;
+            if let Some(span) = self.positions.get(&(stmt.as_ref() as *const Node)) {
+                self.span = Some(*span);
+            }
+
+            if exited && !reported {
+                reported = true;
+                let spans: Option<Vec<Span>> = body[i..]
+                    .iter()
+                    .filter(|stmt| !matches!(stmt.as_ref(), Node::Comment(_, _) | Node::BlankLine))
+                    .map(|stmt| self.positions.get(&(stmt.as_ref() as *const Node)).copied())
+                    .collect();
+                let fix = spans.map(|spans| Fix::remove("Remove the unreachable code", spans));
+                self.report(
+                    "unreachable-code",
+                    String::from("This code is never run, it comes after leaving the block"),
+                    fix,
+                );
+            }
+
+            self.visit(stmt);
+            exited = exited || always_exits(stmt);
+            self.allowed.truncate(outer);
+            self.span = outer_span;
+        }
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Program(body) => self.body(body),
+            Node::Block(body) => self.block(|linter| linter.body(body)),
+            // The variables a `for` declares are only seen by its loop.
+            Node::ForStatement(..) => self.block(|linter| {
+                for child in node.children() {
+                    linter.visit(child);
+                }
+            }),
+            Node::SwitchCase(value, body) => {
+                if let Some(value) = value {
+                    self.visit(value);
+                }
+                self.body(body);
+            }
+            Node::ImportDeclaration(names, module) => {
+                let import = self.imports.len();
+                self.imports
+                    .push((self.span, names.clone(), module.clone()));
+                for name in names {
+                    self.declare(name, BindingKind::Import(import), None);
+                }
+            }
+            Node::VariableDeclaration(declaration_type, name, annotation, value) => {
+                self.visit(value);
+                // Statics are members of the constructor, read through its instances.
+                let kind = match declaration_type.as_ref() {
+                    Node::DeclarationType(TokenType::Static) => BindingKind::Global,
+                    _ => BindingKind::Variable,
+                };
+                let non_object = non_object(annotation.as_deref(), Some(value));
+                self.declare(name, kind, non_object);
+            }
+            Node::GlobalVarDeclaration(names) => {
+                for name in names {
+                    self.declare(name, BindingKind::Global, None);
+                }
+            }
+            Node::MacroDeclaration(name, _)
+            | Node::ExternFunction(name, _, _)
+            | Node::ExternVariable(_, name, _) => {
+                self.declare(name, BindingKind::Global, None);
+            }
+            Node::EnumDeclaration(name, _) => {
+                for child in node.children() {
+                    self.visit(child);
+                }
+                self.declare(name, BindingKind::Global, None);
+            }
+            Node::FunctionDeclaration(name, params, _, code) => {
+                self.declare(name, BindingKind::Global, None);
+                self.function(params, None, code);
+            }
+            Node::ConstructorDeclaration(function, parent) => match function.as_ref() {
+                Node::FunctionDeclaration(name, params, _, code) => {
+                    self.declare(name, BindingKind::Global, None);
+                    self.function(params, parent.as_deref(), code);
+                }
+                function => self.visit(function),
+            },
+            Node::ClassDeclaration(name, members) => self.class(name, members),
+            Node::FunctionExpression(params, code) | Node::ArrowFunction(params, _, code) => {
+                self.function(params, None, code);
+            }
+            Node::Identifier(name) => {
+                self.deprecated_use(name);
+                self.use_name(name);
+            }
+            Node::Assignment(target, operator, value) => {
+                if let (Node::Identifier(target), Node::Identifier(source)) =
+                    (target.as_ref(), value.as_ref())
+                {
+                    if operator == "=" && target == source {
+                        // Only a statement of its own can be removed.
+                        let fix = self
+                            .positions
+                            .get(&(node as *const Node))
+                            .map(|span| Fix::remove("Remove the statement", vec![*span]));
+                        self.report(
+                            "self-assignment",
+                            format!("'{target}' is assigned to itself"),
+                            fix,
+                        );
+                    }
+                }
+
+                self.visit(value);
+                // `x = value` only writes `x`, unlike `x += value`.
+                if !(operator == "=" && matches!(target.as_ref(), Node::Identifier(_))) {
+                    self.visit(target);
+                }
+            }
+            Node::IfStatement(condition, _, _) | Node::TernaryExpression(condition, _, _) => {
+                if let Some(value) = constant(condition) {
+                    self.report(
+                        "constant-condition",
+                        format!("This condition is always {value}"),
+                        None,
+                    );
+                }
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+            Node::WhileStatement(condition, _) => {
+                // `while (true)` is how endless loops are written.
+                if constant(condition) == Some(false) {
+                    self.report(
+                        "constant-condition",
+                        String::from("This loop never runs, its condition is always false"),
+                        None,
+                    );
+                }
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+            Node::BinaryExpression(left, operator, right) => {
+                if let Node::BinaryOperator(operator) = operator.as_ref() {
+                    self.undefined_comparison(operator, left, right);
+                }
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+            Node::WithStatement(target, _) => {
+                let described = match target.as_ref() {
+                    Node::Identifier(name) => self
+                        .lookup(name)
+                        .and_then(|binding| binding.non_object)
+                        .map(|kind| format!("'{name}', which is {kind}")),
+                    target => non_object(None, Some(target)).map(|kind| kind.to_string()),
+                };
+                if let Some(described) = described {
+                    self.report(
+                        "with-non-object",
+                        format!(
+                            "with can only be used on instances and structs, not on {described}"
+                        ),
+                        None,
+                    );
+                }
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+            Node::FunctionCall(callee, _) => {
+                if let Node::Identifier(name) = callee.as_ref() {
+                    self.deprecated_call(name);
+                }
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+            node => {
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+
+    /// Lints a function body in a scope of its own, then reports the
+    /// variables and parameters it never read.
+    fn function(&mut self, params: &[Box<Node>], parent: Option<&Node>, code: &Node) {
+        self.scopes.push(Vec::new());
+
+        for param in params {
+            if let Node::DefaultParameter(_, default) = param.as_ref() {
+                self.visit(default);
+            }
+            if let Some(name) = parameter_name(param) {
+                let non_object = non_object(parameter_annotation(param), None);
+                self.declare(name, BindingKind::Parameter, non_object);
+            }
+        }
+
+        if let Some(parent) = parent {
+            self.visit(parent);
+        }
+        self.visit(code);
+
+        let scope = self.scopes.pop().unwrap_or_default();
+        for binding in scope {
+            if binding.used || binding.name.starts_with('_') {
+                continue;
+            }
+
+            let (lint, kind) = match binding.kind {
+                BindingKind::Variable => ("unused-variable", "Variable"),
+                BindingKind::Parameter => ("unused-parameter", "Parameter"),
+                _ => continue,
+            };
+            self.report_in(
+                &binding.allowed,
+                binding.span,
+                lint,
+                format!("{kind} '{}' is never read", binding.name),
+                None,
+            );
+        }
+    }
+
+    /// Fields and methods are visible from every method of the class.
+    fn class(&mut self, name: &str, members: &[Box<Node>]) {
+        self.declare(name, BindingKind::Global, None);
+        self.scopes.push(Vec::new());
+
+        for member in members {
+            if let Node::ClassField(field, _, _) | Node::FunctionDeclaration(field, _, _, _) =
+                member.as_ref()
+            {
+                self.declare(field, BindingKind::Global, None);
+            }
+        }
+
+        for member in members {
+            match member.as_ref() {
+                Node::FunctionDeclaration(_, params, _, code) => {
+                    self.function(params, None, code);
+                }
+                Node::ConstructorDeclaration(constructor, parent) => {
+                    if let Node::FunctionDeclaration(_, params, _, code) = constructor.as_ref() {
+                        self.function(params, parent.as_deref(), code);
+                    }
+                }
+                member => self.visit(member),
+            }
+        }
+
+        self.scopes.pop();
+    }
+
+    /// Lints code in a block of its own.
+    fn block(&mut self, lint: impl FnOnce(&mut Self)) {
+        self.entered += 1;
+        self.blocks.push(self.entered);
+        lint(self);
+        self.blocks.pop();
+    }
+
+    fn declare(&mut self, name: &str, kind: BindingKind, non_object: Option<&'static str>) {
+        let is_local = matches!(kind, BindingKind::Variable | BindingKind::Parameter);
+        if is_local && self.scopes.len() > 1 {
+            let (current, outer) = self.scopes.split_last().unwrap();
+            let shadowed = outer
+                .iter()
+                .flatten()
+                .filter(|binding| binding.kind != BindingKind::Global)
+                .map(|binding| (binding, false))
+                .chain(
+                    current
+                        .iter()
+                        .filter(|binding| binding.kind == BindingKind::Parameter)
+                        .map(|binding| (binding, false)),
+                )
+                // Variables belong to the function, or to the instance for
+                // `let`, so one declared again in the same or an inner
+                // block is the same variable.
+                .chain(
+                    current
+                        .iter()
+                        .filter(|binding| {
+                            binding.kind == BindingKind::Variable
+                                && self.blocks.starts_with(&binding.blocks)
+                        })
+                        .map(|binding| (binding, true)),
+                )
+                .find(|(binding, _)| binding.name == name)
+                .map(|(binding, again)| (binding.kind, again));
+
+            match shadowed {
+                Some((_, true)) => self.report(
+                    "shadowed-binding",
+                    format!(
+                        "'{name}' is already declared in this function, \
+                         declaring it again in a block doesn't make a new variable"
+                    ),
+                    None,
+                ),
+                Some((shadowed, false)) => {
+                    let what = match shadowed {
+                        BindingKind::Parameter => "a parameter",
+                        BindingKind::Import(_) => "an import",
+                        _ => "a variable",
+                    };
+                    self.report(
+                        "shadowed-binding",
+                        format!("'{name}' hides {what} of the same name"),
+                        None,
+                    );
+                }
+                None => {}
+            }
+        }
+
+        let allowed = self.allowed.clone();
+        let span = self.span;
+        let blocks = self.blocks.clone();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding {
+                name: name.to_string(),
+                kind,
+                used: false,
+                allowed,
+                non_object,
+                span,
+                blocks,
+            });
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|binding| binding.name == name)
+    }
+
+    fn use_name(&mut self, name: &str) {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name == name);
+
+        if let Some(binding) = binding {
+            binding.used = true;
+        }
+    }
+
+    /// Reports the imported names never used, the fix removing them from
+    /// their import, or the whole import when none of its names is used.
+    fn unused_imports(&mut self, module: Vec<Binding>) {
+        let unused: Vec<&Binding> = module
+            .iter()
+            .filter(|binding| matches!(binding.kind, BindingKind::Import(_)) && !binding.used)
+            .collect();
+
+        for (i, binding) in unused.iter().enumerate() {
+            let BindingKind::Import(import) = binding.kind else {
+                continue;
+            };
+            let first_of_import = unused[..i].iter().all(|other| other.kind != binding.kind);
+
+            let fix = match &self.imports[import] {
+                (Some(span), names, module) if first_of_import => {
+                    let kept: Vec<String> = names
+                        .iter()
+                        .filter(|name| {
+                            !unused
+                                .iter()
+                                .any(|other| other.kind == binding.kind && other.name == **name)
+                        })
+                        .cloned()
+                        .collect();
+                    if kept.is_empty() {
+                        Some(Fix::remove("Remove the import", vec![*span]))
+                    } else {
+                        printed(&Node::ImportDeclaration(kept, module.clone()))
+                            .map(|code| Fix::replace(code.clone(), vec![(*span, code + ";")]))
+                    }
+                }
+                _ => None,
+            };
+
+            self.report_in(
+                &binding.allowed,
+                binding.span,
+                "unused-import",
+                format!("'{}' is imported but never used", binding.name),
+                fix,
+            );
+        }
+    }
+
+    fn undefined_comparison(&mut self, operator: &str, left: &Node, right: &Node) {
+        if operator != "==" && operator != "!=" {
+            return;
+        }
+
+        let (value, undefined_first) = match (left, right) {
+            (Node::Identifier(name), value) if name == "undefined" => (value, true),
+            (value, Node::Identifier(name)) if name == "undefined" => (value, false),
+            _ => return,
+        };
+
+        let call = Node::FunctionCall(
+            Node::Identifier(String::from("is_undefined")).to_box(),
+            Node::Arguments(vec![value.clone().to_box()]).to_box(),
+        );
+        let (replacement, written) = if operator == "==" {
+            (call, "is_undefined()")
+        } else {
+            (
+                Node::UnaryExpression(String::from("!"), call.to_box()),
+                "!is_undefined()",
+            )
+        };
+
+        let fix = printed(&replacement).and_then(|code| {
+            let edits = self.undefined_edits(operator, value, undefined_first)?;
+            Some(Fix::replace(code, edits))
+        });
+        self.report(
+            "undefined-comparison",
+            format!(
+                "Compare with undefined using {written}, which also works on arrays and structs"
+            ),
+            fix,
+        );
+    }
+
+    /// Edits turning the next comparison of `value` with `undefined` in the
+    /// statement into a call of `is_undefined`. The value is found by its
+    /// tokens, so none are made when it is written differently than printed.
+    fn undefined_edits(
+        &mut self,
+        operator: &str,
+        value: &Node,
+        undefined_first: bool,
+    ) -> Option<Vec<(Span, String)>> {
+        let mut value_tokens = tokenize(printed(value)?).ok()?;
+        value_tokens.retain(|token| token.kind != TokenType::EOF);
+        let n = value_tokens.len();
+        if n == 0 {
+            return None;
+        }
+
+        let same = |found: &[Token]| {
+            found.len() == n
+                && found
+                    .iter()
+                    .zip(&value_tokens)
+                    .all(|(a, b)| a.kind == b.kind && a.lex == b.lex)
+        };
+        let is_undefined = |token: Option<&Token>| {
+            token.is_some_and(|token| {
+                token.kind == TokenType::Identifier && token.lex == "undefined"
+            })
+        };
+        let call = if operator == "==" {
+            "is_undefined("
+        } else {
+            "!is_undefined("
+        };
+
+        let tokens = self.statement_tokens();
+        for (i, token) in tokens.iter().enumerate() {
+            if token.kind != TokenType::BinaryOperator
+                || token.lex != operator
+                || self.claimed.contains(&token.offset)
+            {
+                continue;
+            }
+
+            let edits = if undefined_first {
+                let found = tokens.get(i + 1..i + 1 + n).unwrap_or_default();
+                let undefined = i.checked_sub(1).map(|before| &tokens[before]);
+                if !is_undefined(undefined) || !same(found) {
+                    continue;
+                }
+                let end = found[n - 1].end;
+                vec![
+                    (
+                        Span::new(tokens[i - 1].offset, found[0].offset),
+                        call.to_string(),
+                    ),
+                    (Span::new(end, end), String::from(")")),
+                ]
+            } else {
+                let found = i.checked_sub(n).map_or(&[][..], |start| &tokens[start..i]);
+                if !is_undefined(tokens.get(i + 1)) || !same(found) {
+                    continue;
+                }
+                let start = found[0].offset;
+                vec![
+                    (Span::new(start, start), call.to_string()),
+                    (
+                        Span::new(found[n - 1].end, tokens[i + 1].end),
+                        String::from(")"),
+                    ),
+                ]
+            };
+            self.claimed.insert(token.offset);
+            return Some(edits);
+        }
+        None
+    }
+
+    fn deprecated_use(&mut self, name: &str) {
+        let Some(message) = self.deprecated.get(name) else {
+            return;
+        };
+        // A local of the same name hides the declaration.
+        if self.scopes[1..]
+            .iter()
+            .flatten()
+            .any(|binding| binding.name == name)
+        {
+            return;
+        }
+
+        let message = match message.as_str() {
+            "" => format!("'{name}' is deprecated"),
+            message => format!("'{name}' is deprecated: {message}"),
+        };
+        self.report("deprecated-declaration", message, None);
+    }
+
+    fn deprecated_call(&mut self, name: &str) {
+        if self.lookup(name).is_some() {
+            return;
+        }
+
+        if let Some((_, replacement, renamable)) = DEPRECATED
+            .iter()
+            .find(|(deprecated, _, _)| *deprecated == name)
+        {
+            let fix = match renamable {
+                true => self
+                    .rename_edit(name, replacement)
+                    .map(|edit| Fix::replace(replacement.to_string(), vec![edit])),
+                false => None,
+            };
+            self.report(
+                "deprecated-function",
+                format!("'{name}' is deprecated, use {replacement} instead"),
+                fix,
+            );
+        }
+    }
+
+    /// The edit renaming the next call of `name` in the statement.
+    fn rename_edit(&mut self, name: &str, replacement: &str) -> Option<(Span, String)> {
+        let tokens = self.statement_tokens();
+        let i = (0..tokens.len()).find(|&i| {
+            tokens[i].kind == TokenType::Identifier
+                && tokens[i].lex == name
+                && !self.claimed.contains(&tokens[i].offset)
+                && tokens
+                    .get(i + 1)
+                    .is_some_and(|next| next.kind == TokenType::OpenParenthesis)
+                && (i == 0 || tokens[i - 1].kind != TokenType::Dot)
+        })?;
+
+        self.claimed.insert(tokens[i].offset);
+        Some((tokens[i].span(), replacement.to_string()))
+    }
+
+    /// The tokens of the statement being linted, none when its place is
+    /// unknown.
+    fn statement_tokens(&self) -> &'a [Token] {
+        let Some(span) = self.span else {
+            return &[];
+        };
+        let start = self
+            .tokens
+            .partition_point(|token| token.offset < span.start);
+        let end = self.tokens.partition_point(|token| token.offset < span.end);
+        &self.tokens[start..end.max(start)]
+    }
+}
+
+/// A node printed as code, without the `;` ending statements.
+fn printed(node: &Node) -> Option<String> {
+    let code = print_program(
+        &Node::Program(vec![node.clone().to_box()]),
+        &FormatOptions::default(),
+    )
+    .ok()?;
+    Some(code.trim_end().trim_end_matches(';').to_string())
+}
+
+/// The lints named in a `// gmpp-allow(a, b)` comment.
+fn allowed_lints(comment: &str) -> Vec<String> {
+    let Some(start) = comment.find("gmpp-allow(") else {
+        return Vec::new();
+    };
+    let rest = &comment[start + "gmpp-allow(".len()..];
+    let list = rest.split(')').next().unwrap_or("");
+
+    list.split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn parameter_annotation(param: &Node) -> Option<&Node> {
+    match param {
+        Node::FunctionParameter(_, annotation) => annotation.as_deref(),
+        Node::OptionalParameter(param)
+        | Node::RestParameter(param)
+        | Node::DefaultParameter(param, _) => parameter_annotation(param),
+        _ => None,
+    }
+}
+
+/// What a value is, from its annotation or its literal, when it can't be an
+/// instance or a struct.
+fn non_object(annotation: Option<&Node>, value: Option<&Node>) -> Option<&'static str> {
+    if let Some(Node::TypeName(name, _)) = annotation {
+        return match name.as_str() {
+            "string" | "char" => Some("a string"),
+            "bool" => Some("a bool"),
+            "Array" => Some("an array"),
+            _ => None,
+        };
+    }
+
+    match value? {
+        Node::StringLiteral(_) | Node::TemplateString(_) => Some("a string"),
+        Node::BooleanLiteral(_) => Some("a bool"),
+        Node::ArrayLiteral(_) => Some("an array"),
+        _ => None,
+    }
+}
+
+/// The value of a condition made only of literals.
+fn constant(condition: &Node) -> Option<bool> {
+    match condition {
+        Node::BooleanLiteral(value) => Some(*value),
+        // GML treats numbers above 0.5 as true.
+        Node::NumericLiteral(value, _) => Some(*value > 0.5),
+        Node::UnaryExpression(operator, operand) if operator == "!" => {
+            constant(operand).map(|value| !value)
+        }
+        Node::BinaryExpression(left, operator, right) => {
+            let Node::BinaryOperator(operator) = operator.as_ref() else {
+                return None;
+            };
+
+            match (left.as_ref(), operator.as_str(), right.as_ref()) {
+                (Node::NumericLiteral(left, _), operator, Node::NumericLiteral(right, _)) => {
+                    match operator {
+                        "==" => Some(left == right),
+                        "!=" => Some(left != right),
+                        "<" => Some(left < right),
+                        "<=" => Some(left <= right),
+                        ">" => Some(left > right),
+                        ">=" => Some(left >= right),
+                        _ => None,
+                    }
+                }
+                (left, "&&", right) => Some(constant(left)? && constant(right)?),
+                (left, "||", right) => Some(constant(left)? || constant(right)?),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parse_with_positions;
+    use crate::compiler::tokenizer::{tokenize_lossless, Language};
+
+    fn fix(source: &str) -> (String, usize) {
+        let tokens = tokenize_lossless(source.to_string(), Language::GmlPlusPlus).unwrap();
+        let (program, positions) = parse_with_positions(tokens.clone()).unwrap();
+        let diagnostics = lint(&program, &positions, &tokens, &|lint| lint.level);
+        apply_fixes(source, &diagnostics)
+    }
+
+    #[test]
+    fn fixes_leave_the_rest_of_the_source_as_written() {
+        let source = "\
+function   foo(x) {
+    x = x; // keep
+    if (x == undefined)   { return 1; }
+    return x;
+}
+";
+        let fixed = "\
+function   foo(x) {
+    // keep
+    if (is_undefined(x))   { return 1; }
+    return x;
+}
+";
+        assert_eq!(fix(source), (String::from(fixed), 2));
+    }
+
+    #[test]
+    fn fixes_edit_the_code_they_are_about() {
+        let source = "\
+import { a, b } from \"lib\";
+import { c } from \"other\";
+
+function foo(x) {
+    if (undefined != x.y && x == undefined) {
+        return array_length_1d(array_length_1d(b));
+    }
+    return x;
+    show_debug_message(\"never\");
+}
+";
+        let fixed = "\
+import { b } from \"lib\";
+
+function foo(x) {
+    if (!is_undefined(x.y) && is_undefined(x)) {
+        return array_length(array_length(b));
+    }
+    return x;
+}
+";
+        assert_eq!(fix(source), (String::from(fixed), 7));
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        let tokens = tokenize_lossless(source.to_string(), Language::GmlPlusPlus).unwrap();
+        let (program, positions) = parse_with_positions(tokens.clone()).unwrap();
+        lint(&program, &positions, &tokens, &|lint| lint.level)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn statics_are_members_not_variables() {
+        let source = "\
+function Foo() constructor {
+    static bar = function() { return 1; };
+}
+";
+        assert_eq!(messages(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn variables_belong_to_their_function_not_their_block() {
+        let source = "\
+function f() {
+    let x = 1;
+    if (x > 0) {
+        let x = 2;
+        show_message(x);
+    }
+    for (var i = 0; i < 2; i++) {
+        show_message(i);
+    }
+    for (var i = 0; i < 2; i++) {
+        let y = i;
+    }
+    return y;
+}
+";
+        assert_eq!(
+            messages(source),
+            vec![String::from(
+                "'x' is already declared in this function, \
+                 declaring it again in a block doesn't make a new variable"
+            )]
+        );
+    }
+}
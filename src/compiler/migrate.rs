@@ -0,0 +1,755 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{check_module, class_members, infer_type, Type};
+use crate::compiler::codegen::{generate, CodegenOptions};
+use crate::compiler::error::Error;
+use crate::compiler::optimizer::OptLevel;
+use crate::compiler::parser::{parse, parse_as};
+use crate::compiler::printer::{print_program, FormatOptions};
+use crate::compiler::target::GmlVersion;
+use crate::compiler::tokenizer::{tokenize_as, Language, TokenType};
+use std::collections::{HashMap, HashSet};
+
+/// Converts a GML script to GML++: constructors become classes, `argumentN`
+/// become named parameters, `method(self, function() {})` becomes an arrow
+/// function, and the types of parameters, fields and return values are
+/// written down when they can be inferred.
+pub fn migrate(
+    source_code: String,
+    declarations: &HashMap<String, Type>,
+) -> Result<String, Vec<Error>> {
+    let mut program = tokenize_as(source_code, Language::Gml)
+        .and_then(|tokens| parse_as(tokens, Language::Gml))
+        .map_err(|error| vec![error])?;
+    check_module(&program, declarations)?;
+
+    name_arguments(&mut program);
+    bind_methods(&mut program);
+    make_classes(&mut program);
+
+    // Annotations inferred from usage may be stricter than the code calling
+    // them, so they are dropped when the program doesn't check anymore.
+    let program = [true, false]
+        .into_iter()
+        .map(|with_usage| annotate(&program, declarations, with_usage))
+        .find(|annotated| check_module(annotated, declarations).is_ok())
+        .unwrap_or(program);
+
+    let code = print_program(&program, &FormatOptions::default())
+        .map_err(|error| vec![Error::new(error)])?;
+    verify(&code, declarations)?;
+
+    Ok(code)
+}
+
+/// Compiles the migrated code again, so a migration never hands back
+/// something that doesn't build.
+fn verify(code: &str, declarations: &HashMap<String, Type>) -> Result<(), Vec<Error>> {
+    let errors = |errors: Vec<Error>| {
+        errors
+            .into_iter()
+            .map(|error| {
+                Error::new(format!(
+                    "The migrated code doesn't compile: {}",
+                    error.message
+                ))
+            })
+            .collect::<Vec<Error>>()
+    };
+
+    let program = tokenize_as(code.to_string(), Language::GmlPlusPlus)
+        .and_then(parse)
+        .map_err(|error| errors(vec![error]))?;
+    check_module(&program, declarations).map_err(errors)?;
+    generate(
+        &program,
+        &CodegenOptions::new(GmlVersion::Latest, OptLevel::O0),
+    )
+    .map_err(errors)?;
+
+    Ok(())
+}
+
+/// Index of the argument read by `argument0` or `argument[0]`.
+fn argument_index(node: &Node) -> Option<usize> {
+    match node {
+        Node::Identifier(name) => name.strip_prefix("argument")?.parse().ok(),
+        Node::IndexAccess(value, None, indices) => match (value.as_ref(), indices.as_slice()) {
+            (Node::Identifier(name), [index]) if name == "argument" => match index.as_ref() {
+                Node::NumericLiteral(index, _) if index.fract() == 0.0 && *index >= 0.0 => {
+                    Some(*index as usize)
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_function(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::FunctionDeclaration(_, _, _, _)
+            | Node::FunctionExpression(_, _)
+            | Node::ArrowFunction(_, _, _)
+            | Node::ClassDeclaration(_, _)
+    )
+}
+
+/// Collects the indices read through `argument0` and `argument[0]` by a
+/// function body, returning false when the arguments are read in a way that
+/// can't be named, like `argument[i]` or `argument_count`.
+fn collect_argument_indices(node: &Node, indices: &mut Vec<usize>) -> bool {
+    if let Some(index) = argument_index(node) {
+        indices.push(index);
+        return true;
+    }
+
+    match node {
+        Node::Identifier(name) => name != "argument" && name != "argument_count",
+        node if is_function(node) => true,
+        node => node
+            .children()
+            .into_iter()
+            .all(|child| collect_argument_indices(child, indices)),
+    }
+}
+
+fn collect_identifiers(node: &Node, names: &mut HashSet<String>) {
+    match node {
+        Node::Identifier(name) => {
+            names.insert(name.clone());
+        }
+        Node::VariableDeclaration(_, name, _, _) | Node::FunctionParameter(name, _) => {
+            names.insert(name.clone());
+        }
+        _ => {}
+    }
+
+    for child in node.children() {
+        collect_identifiers(child, names);
+    }
+}
+
+fn rename_arguments(node: &mut Node, names: &[String]) {
+    if let Some(index) = argument_index(node) {
+        *node = Node::Identifier(names[index].clone());
+        return;
+    }
+
+    if is_function(node) {
+        return;
+    }
+
+    for child in node.children_mut() {
+        rename_arguments(child, names);
+    }
+}
+
+/// The local a leading `var name = argumentN;` copies the argument into.
+fn argument_variable(stmt: &Node) -> Option<(usize, &String)> {
+    match stmt {
+        Node::VariableDeclaration(declaration_type, name, None, value)
+            if **declaration_type == Node::DeclarationType(TokenType::Var) =>
+        {
+            Some((argument_index(value)?, name))
+        }
+        _ => None,
+    }
+}
+
+/// Gives a name to every `argumentN` read by functions declaring no
+/// parameters. A function starting with `var name = argument0;` gets `name`
+/// as its parameter, the others get `arg0`, `arg1`...
+fn name_arguments(node: &mut Node) {
+    for child in node.children_mut() {
+        name_arguments(child);
+    }
+
+    let (params, code) = match node {
+        Node::FunctionDeclaration(_, params, _, code) | Node::FunctionExpression(params, code) => {
+            (params, code)
+        }
+        _ => return,
+    };
+
+    let mut indices = Vec::new();
+    if !params.is_empty() || !collect_argument_indices(code, &mut indices) {
+        return;
+    }
+
+    let count = match indices.iter().max() {
+        Some(max) => max + 1,
+        None => return,
+    };
+
+    let mut taken = HashSet::new();
+    collect_identifiers(code, &mut taken);
+
+    let mut names: Vec<Option<String>> = vec![None; count];
+    let mut copies = 0;
+
+    if let Node::Program(body) = code.as_ref() {
+        for stmt in body {
+            let (index, name) = match argument_variable(stmt) {
+                Some(copy) => copy,
+                None => break,
+            };
+
+            // The copy may be modified, so the argument itself has to be
+            // read nowhere else for them to be merged.
+            let reads = indices.iter().filter(|read| **read == index).count();
+            if reads != 1 || names[index].is_some() || names.contains(&Some(name.clone())) {
+                break;
+            }
+
+            names[index] = Some(name.clone());
+            copies += 1;
+        }
+    }
+
+    let names: Vec<String> = names
+        .into_iter()
+        .enumerate()
+        .map(|(index, name)| {
+            name.unwrap_or_else(|| {
+                let mut name = format!("arg{index}");
+                while taken.contains(&name) {
+                    name.insert(0, '_');
+                }
+                name
+            })
+        })
+        .collect();
+
+    if let Node::Program(body) = code.as_mut() {
+        body.drain(..copies);
+    }
+    rename_arguments(code, &names);
+
+    *params = names
+        .into_iter()
+        .map(|name| Node::FunctionParameter(name, None).to_box())
+        .collect();
+}
+
+/// GML binds function expressions to the `self` they're created in, so
+/// `method(self, function() {})` is just an arrow function.
+fn bind_methods(node: &mut Node) {
+    for child in node.children_mut() {
+        bind_methods(child);
+    }
+
+    let arrow = match node {
+        Node::FunctionCall(callee, args) if **callee == Node::Identifier("method".to_string()) => {
+            match args.as_ref() {
+                Node::Arguments(args)
+                    if args.len() == 2 && *args[0] == Node::Identifier("self".to_string()) =>
+                {
+                    match args[1].as_ref() {
+                        Node::FunctionExpression(params, code) => arrow_function(params, code),
+                        _ => return,
+                    }
+                }
+                _ => return,
+            }
+        }
+        _ => return,
+    };
+
+    *node = arrow;
+}
+
+/// `fn(params) => value` when the body only returns a value.
+fn arrow_function(params: &[Box<Node>], code: &Node) -> Node {
+    let body = match code {
+        Node::Program(body) => match body.as_slice() {
+            [stmt] => match stmt.as_ref() {
+                Node::ReturnStatement(value)
+                    if **value != Node::Identifier("undefined".to_string()) =>
+                {
+                    value.clone()
+                }
+                _ => code.clone().to_box(),
+            },
+            _ => code.clone().to_box(),
+        },
+        _ => code.clone().to_box(),
+    };
+
+    Node::ArrowFunction(params.to_vec(), None, body)
+}
+
+fn references_any(node: &Node, names: &HashSet<String>) -> bool {
+    match node {
+        Node::Identifier(name) => names.contains(name),
+        node if is_function(node) => false,
+        node => node
+            .children()
+            .into_iter()
+            .any(|child| references_any(child, names)),
+    }
+}
+
+/// Name of the field set by `name = value` or `self.name = value`.
+fn assigned_field(stmt: &Node) -> Option<&String> {
+    match stmt {
+        Node::Assignment(target, _, _) => match target.as_ref() {
+            Node::Identifier(name) => Some(name),
+            Node::MemberAccess(object, name)
+                if **object == Node::Identifier("self".to_string()) =>
+            {
+                Some(name)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Turns top-level constructors into classes: the fields they assign are
+/// declared, `static` functions become methods and everything else is kept
+/// in `new`.
+fn make_classes(program: &mut Node) {
+    if let Node::Program(body) = program {
+        for stmt in body.iter_mut() {
+            if let Some(class) = constructor_to_class(stmt) {
+                *stmt = class.to_box();
+            }
+        }
+    }
+}
+
+fn constructor_to_class(stmt: &Node) -> Option<Node> {
+    let (function, parent) = match stmt {
+        Node::ConstructorDeclaration(function, parent) => (function, parent),
+        _ => return None,
+    };
+    let (name, params, code) = match function.as_ref() {
+        Node::FunctionDeclaration(name, params, _, code) => (name, params, code),
+        _ => return None,
+    };
+    let body = match code.as_ref() {
+        Node::Program(body) => body,
+        _ => return None,
+    };
+
+    let mut methods = Vec::new();
+    let mut statements = Vec::new();
+
+    for stmt in body {
+        let method = match stmt.as_ref() {
+            Node::VariableDeclaration(declaration_type, method, None, value)
+                if **declaration_type == Node::DeclarationType(TokenType::Static) =>
+            {
+                match value.as_ref() {
+                    Node::FunctionExpression(params, code) => Some(Node::FunctionDeclaration(
+                        method.clone(),
+                        params.clone(),
+                        None,
+                        code.clone(),
+                    )),
+                    Node::ArrowFunction(params, return_type, code) => {
+                        let code = match code.as_ref() {
+                            Node::Program(_) => code.clone(),
+                            value => {
+                                Node::Program(vec![
+                                    Node::ReturnStatement(value.clone().to_box()).to_box()
+                                ])
+                                .to_box()
+                            }
+                        };
+                        Some(Node::FunctionDeclaration(
+                            method.clone(),
+                            params.clone(),
+                            return_type.clone(),
+                            code,
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match method {
+            Some(method) => methods.push(method),
+            None => statements.push(stmt.clone()),
+        }
+    }
+
+    // Methods end up after the body of `new`, which can't use them anymore.
+    let method_names: HashSet<String> = methods
+        .iter()
+        .filter_map(|method| method.declared_name().cloned())
+        .collect();
+    if statements
+        .iter()
+        .any(|stmt| references_any(stmt, &method_names))
+    {
+        return None;
+    }
+
+    let mut members = Vec::new();
+    for stmt in &statements {
+        if let Some(field) = assigned_field(stmt) {
+            let field = Node::ClassField(field.clone(), None, None).to_box();
+            if !members.contains(&field) {
+                members.push(field);
+            }
+        }
+    }
+
+    if !params.is_empty() || parent.is_some() || !statements.is_empty() {
+        let constructor = Node::FunctionDeclaration(
+            "new".to_string(),
+            params.clone(),
+            None,
+            Node::Program(statements).to_box(),
+        );
+        members.push(Node::ConstructorDeclaration(constructor.to_box(), parent.clone()).to_box());
+    }
+
+    members.extend(methods.into_iter().map(|method| method.to_box()));
+
+    Some(Node::ClassDeclaration(name.clone(), members))
+}
+
+/// Types worth writing down, which tell more than `any`.
+fn is_informative(kind: &Type) -> bool {
+    match kind {
+        Type::Number | Type::String | Type::Bool | Type::Named(_, _) => true,
+        Type::Array(item) => is_informative(item),
+        _ => false,
+    }
+}
+
+/// Adds return types, and with `with_usage` the types of parameters and
+/// fields guessed from how they are used. Local variables are left alone,
+/// their type is already inferred from their value.
+fn annotate(program: &Node, declarations: &HashMap<String, Type>, with_usage: bool) -> Node {
+    let mut program = program.clone();
+
+    if with_usage {
+        let scope = module_scope(&program, declarations);
+        annotate_usage(&mut program, &scope);
+    }
+
+    // Return types are inferred once the parameters are known.
+    let scope = module_scope(&program, declarations);
+    let classes = class_members(&program, declarations);
+    annotate_returns(&mut program, &scope, &classes);
+
+    program
+}
+
+/// Types visible from anywhere in the module.
+fn module_scope(program: &Node, declarations: &HashMap<String, Type>) -> HashMap<String, Type> {
+    let mut scope = declarations.clone();
+    if let Ok(types) = check_module(program, declarations) {
+        scope.extend(types);
+    }
+
+    scope
+}
+
+fn annotate_usage(program: &mut Node, scope: &HashMap<String, Type>) {
+    let body = match program {
+        Node::Program(body) => body,
+        _ => return,
+    };
+
+    for stmt in body.iter_mut() {
+        match stmt.as_mut() {
+            Node::FunctionDeclaration(_, params, _, code) => {
+                annotate_parameters(params, code, scope)
+            }
+            Node::ClassDeclaration(_, members) => {
+                let mut field_types = HashMap::new();
+
+                for member in members.iter_mut() {
+                    match member.as_mut() {
+                        Node::ConstructorDeclaration(constructor, _) => {
+                            if let Node::FunctionDeclaration(_, params, _, code) =
+                                constructor.as_mut()
+                            {
+                                annotate_parameters(params, code, scope);
+                                field_types = infer_fields(params, code, scope);
+                            }
+                        }
+                        Node::FunctionDeclaration(_, params, _, code) => {
+                            annotate_parameters(params, code, scope)
+                        }
+                        _ => {}
+                    }
+                }
+
+                for member in members.iter_mut() {
+                    if let Node::ClassField(name, annotation @ None, _) = member.as_mut() {
+                        if let Some(kind) = field_types.get(name.as_str()) {
+                            *annotation = Some(kind.to_annotation().to_box());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn annotate_returns(
+    program: &mut Node,
+    scope: &HashMap<String, Type>,
+    classes: &HashMap<String, HashMap<String, Type>>,
+) {
+    let body = match program {
+        Node::Program(body) => body,
+        _ => return,
+    };
+
+    for stmt in body.iter_mut() {
+        match stmt.as_mut() {
+            Node::FunctionDeclaration(name, _, return_type, _) => {
+                if let Some(Type::Function(_, ret)) = scope.get(name.as_str()) {
+                    annotate_return(return_type, ret);
+                }
+            }
+            Node::ClassDeclaration(name, members) => {
+                let member_types = match classes.get(name.as_str()) {
+                    Some(member_types) => member_types,
+                    None => continue,
+                };
+
+                for member in members.iter_mut() {
+                    if let Node::FunctionDeclaration(method, _, return_type, _) = member.as_mut() {
+                        if let Some(Type::Function(_, ret)) = member_types.get(method.as_str()) {
+                            annotate_return(return_type, ret);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn annotate_return(return_type: &mut Option<Box<Node>>, inferred: &Type) {
+    if return_type.is_none() && is_informative(inferred) {
+        *return_type = Some(inferred.to_annotation().to_box());
+    }
+}
+
+/// Types of the fields `new` always assigns values of the same type to.
+fn infer_fields(
+    params: &[Box<Node>],
+    code: &Node,
+    scope: &HashMap<String, Type>,
+) -> HashMap<String, Type> {
+    let mut scope = scope.clone();
+    for param in params {
+        if let Node::FunctionParameter(name, annotation) = param.as_ref() {
+            let kind = annotation
+                .as_ref()
+                .map(|annotation| Type::from_annotation(annotation))
+                .unwrap_or(Type::Untyped);
+            scope.insert(name.clone(), kind);
+        }
+    }
+
+    let mut assigned: HashMap<String, Vec<Type>> = HashMap::new();
+    collect_field_types(code, &scope, &mut assigned);
+
+    assigned
+        .into_iter()
+        .filter_map(|(field, kinds)| {
+            let first = kinds.first()?.clone();
+            let agrees = kinds.iter().all(|kind| *kind == first);
+            (agrees && is_informative(&first)).then_some((field, first))
+        })
+        .collect()
+}
+
+fn collect_field_types(
+    node: &Node,
+    scope: &HashMap<String, Type>,
+    assigned: &mut HashMap<String, Vec<Type>>,
+) {
+    if let (Some(field), Node::Assignment(_, operator, value)) = (assigned_field(node), node) {
+        let kind = if operator == "=" {
+            infer_type(value, scope)
+        } else {
+            Type::Untyped
+        };
+        assigned.entry(field.clone()).or_default().push(kind);
+    }
+
+    if is_function(node) {
+        return;
+    }
+
+    for child in node.children() {
+        collect_field_types(child, scope, assigned);
+    }
+}
+
+/// Annotates the parameters whose every use points to the same type, like
+/// being passed to a function taking a `string` or multiplied by something.
+fn annotate_parameters(params: &mut [Box<Node>], code: &Node, scope: &HashMap<String, Type>) {
+    for param in params.iter_mut() {
+        if let Node::FunctionParameter(name, annotation @ None) = param.as_mut() {
+            let mut hints = Vec::new();
+            collect_usage_hints(code, name, scope, &mut hints);
+
+            if let Some(first) = hints.first() {
+                if hints.iter().all(|hint| hint == first) && is_informative(first) {
+                    *annotation = Some(first.to_annotation().to_box());
+                }
+            }
+        }
+    }
+}
+
+fn collect_usage_hints(
+    node: &Node,
+    name: &str,
+    scope: &HashMap<String, Type>,
+    hints: &mut Vec<Type>,
+) {
+    let is_param = |node: &Node| *node == Node::Identifier(name.to_string());
+
+    match node {
+        Node::FunctionCall(callee, args) => {
+            if let (Type::Function(params, _), Node::Arguments(args)) =
+                (infer_type(callee, scope), args.as_ref())
+            {
+                for (i, arg) in args.iter().enumerate() {
+                    let param = match params.get(i).or(params.last()) {
+                        Some(Type::Optional(param)) | Some(Type::Rest(param)) => param.as_ref(),
+                        Some(param) => param,
+                        None => break,
+                    };
+                    if is_param(arg) && *param != Type::Untyped {
+                        hints.push(param.clone());
+                    }
+                }
+            }
+        }
+        Node::BinaryExpression(left, operator, right) => {
+            let operator = match operator.as_ref() {
+                Node::BinaryOperator(operator) => operator.as_str(),
+                _ => "",
+            };
+
+            for (operand, other) in [(left, right), (right, left)] {
+                if !is_param(operand) {
+                    continue;
+                }
+
+                match (operator, other.as_ref()) {
+                    ("-" | "*" | "/" | "%" | "div" | "<<" | ">>" | "&" | "|" | "^", _) => {
+                        hints.push(Type::Number)
+                    }
+                    ("+", Node::NumericLiteral(_, _)) => hints.push(Type::Number),
+                    ("+", Node::StringLiteral(_) | Node::TemplateString(_)) => {
+                        hints.push(Type::String)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Node::UnaryExpression(operator, operand) if operator != "!" && is_param(operand) => {
+            hints.push(Type::Number)
+        }
+        Node::UpdateExpression(_, target, _) if is_param(target) => hints.push(Type::Number),
+        // A nested function may declare a parameter of the same name.
+        node if is_function(node) => return,
+        _ => {}
+    }
+
+    for child in node.children() {
+        collect_usage_hints(child, name, scope, hints);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::declarations::standard_declarations;
+
+    const SCRIPT: &str = "\
+function Vec2(_x, _y) constructor {
+    x = _x;
+    y = _y;
+    static scaled = function(k) {
+        return new Vec2(x * k, y * k);
+    };
+}
+
+function setup() {
+    on_hit = method(self, function(damage) {
+        hp -= damage;
+    });
+    total = argument0 + 2;
+}
+";
+
+    #[test]
+    fn migrated_scripts_compile_back_to_gml_doing_the_same() {
+        let declarations = standard_declarations();
+        let migrated = migrate(SCRIPT.to_string(), &declarations).unwrap();
+        assert_eq!(
+            migrated,
+            "\
+class Vec2 {
+    x;
+    y;
+
+    new(_x, _y) {
+        x = _x;
+        y = _y;
+    }
+
+    scaled(k: number) -> Vec2 {
+        return new Vec2(x * k, y * k);
+    }
+}
+
+function setup(arg0: number) {
+    on_hit = fn(damage) {
+        hp -= damage;
+    };
+    total = arg0 + 2;
+}
+"
+        );
+
+        let program = tokenize_as(migrated, Language::GmlPlusPlus)
+            .and_then(parse)
+            .unwrap();
+        let gml = generate(
+            &program,
+            &CodegenOptions::new(GmlVersion::Latest, OptLevel::O0),
+        )
+        .unwrap();
+        // The constructor is the same, `argument0` is named and the method
+        // is a function expression, bound to `self` by GameMaker.
+        assert_eq!(
+            gml,
+            "\
+function Vec2(_x, _y) constructor {
+    x = _x;
+    y = _y;
+    static scaled = function(k) {
+        return new Vec2(x * k, y * k);
+    };
+}
+function setup(arg0) {
+    on_hit = function(damage) {
+        hp -= damage;
+    };
+    total = arg0 + 2;
+}
+"
+        );
+    }
+}
@@ -0,0 +1,1198 @@
+use crate::compiler::ast::Node;
+use crate::compiler::attributes::Registry;
+use crate::compiler::checker::{always_exits, parameter_name};
+use crate::compiler::tokenizer::TokenType;
+use std::collections::{HashMap, HashSet};
+
+/// How much the program is rewritten before its GML is generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptLevel {
+    /// The GML follows the source statement for statement.
+    O0,
+    /// Constants are folded and inlined, and dead code is removed.
+    /// Functions marked `@inline` are inlined.
+    O1,
+    /// Small functions are inlined too, and callbacks passed to
+    /// `array_map` and `array_foreach` become loops.
+    O2,
+}
+
+/// Folds the expressions made of literals and `const` bindings, drops the
+/// branches of `if` and `while` whose condition is known, and the code after
+/// a `return`, `break`, `continue` or `exit`. Calls to functions returning a
+/// single expression are replaced by that expression, as `level` allows.
+///
+/// Statements are moved rather than rebuilt, so the positions recorded by
+/// `parse_with_positions` stay valid for the ones that are kept.
+pub fn optimize(program: &mut Node, level: OptLevel) {
+    if level == OptLevel::O0 {
+        return;
+    }
+
+    let registry = Registry::collect(program);
+    let pure: HashSet<String> = registry
+        .marked("pure")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let inlines = inline_functions(program, &registry, &pure, level);
+
+    let mut optimizer = Optimizer {
+        level,
+        scopes: vec![HashMap::new()],
+        inlines,
+        pure,
+        inlining: Vec::new(),
+        loops: 0,
+    };
+    if let Node::Program(body) = program {
+        optimizer.body(body);
+    }
+}
+
+/// Statements stay boxed while they are moved around, so the addresses
+/// their positions are keyed by don't change.
+#[allow(clippy::vec_box)]
+type Statements = Vec<Box<Node>>;
+
+/// Built-in functions without side effects, which inlined code can call
+/// without changing when its arguments are read.
+const PURE_FUNCTIONS: [&str; 43] = [
+    "abs",
+    "sign",
+    "round",
+    "floor",
+    "ceil",
+    "frac",
+    "sqrt",
+    "sqr",
+    "power",
+    "exp",
+    "ln",
+    "log2",
+    "log10",
+    "logn",
+    "sin",
+    "cos",
+    "tan",
+    "arcsin",
+    "arccos",
+    "arctan",
+    "arctan2",
+    "dsin",
+    "dcos",
+    "dtan",
+    "degtorad",
+    "radtodeg",
+    "min",
+    "max",
+    "mean",
+    "median",
+    "clamp",
+    "lerp",
+    "point_distance",
+    "point_direction",
+    "lengthdir_x",
+    "lengthdir_y",
+    "dot_product",
+    "string_length",
+    "string_upper",
+    "string_lower",
+    "string",
+    "real",
+    "array_length",
+];
+
+/// Functions inlined without being marked `@inline` have at most this many
+/// nodes in the expression they return.
+const INLINE_SIZE: usize = 16;
+
+/// What a name in scope stands for.
+#[derive(Clone)]
+enum Binding {
+    /// A `const` bound to a literal.
+    Constant(Node),
+    /// A `var`, a `static` or a parameter, which other functions and
+    /// closures can't see.
+    Local,
+    Other,
+}
+
+/// A function whose calls can be replaced by the expression it returns.
+struct Inline {
+    params: Vec<String>,
+    body: Node,
+    /// Whether the body only calls `PURE_FUNCTIONS` and `@pure` functions, so arguments that are
+    /// variables still have the same value wherever the body reads them.
+    pure: bool,
+}
+
+struct Optimizer {
+    level: OptLevel,
+    /// Names in scope, innermost last.
+    scopes: Vec<HashMap<String, Binding>>,
+    inlines: HashMap<String, Inline>,
+    /// Functions marked `@pure`.
+    pure: HashSet<String>,
+    /// Functions being inlined, which aren't inlined again inside themselves.
+    inlining: Vec<String>,
+    /// Loops made from callbacks so far, to name their variables.
+    loops: usize,
+}
+
+impl Optimizer {
+    fn declare(&mut self, name: &str, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    /// The closest declaration of a name, with the depth of its scope.
+    fn lookup(&self, name: &str) -> Option<(usize, &Binding)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.get(name).map(|binding| (depth, binding)))
+    }
+
+    /// The literal a name stands for, unless a closer declaration hides it.
+    fn constant(&self, name: &str) -> Option<Node> {
+        match self.lookup(name) {
+            Some((_, Binding::Constant(value))) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn scoped(&mut self, visit: impl FnOnce(&mut Optimizer)) {
+        self.scopes.push(HashMap::new());
+        visit(self);
+        self.scopes.pop();
+    }
+
+    fn body(&mut self, body: &mut Statements) {
+        let mut kept = Vec::with_capacity(body.len());
+        let mut exited = false;
+
+        for mut stmt in std::mem::take(body) {
+            if exited && !is_declaration(&stmt) {
+                continue;
+            }
+
+            self.statement(&mut stmt);
+            let stmts = match self.level {
+                OptLevel::O2 => self.callback_loop(stmt),
+                _ => vec![stmt],
+            };
+            for stmt in stmts.into_iter().flat_map(fold_branch) {
+                if exited && !is_declaration(&stmt) {
+                    continue;
+                }
+                exited = exited || always_exits(&stmt);
+                kept.push(stmt);
+            }
+        }
+
+        *body = kept;
+    }
+
+    /// The body of an `if`, a loop... which is a block or a single statement.
+    fn nested(&mut self, node: &mut Node) {
+        self.scoped(|optimizer| optimizer.statement(node));
+    }
+
+    fn statement(&mut self, node: &mut Node) {
+        match node {
+            Node::Program(body) | Node::Block(body) => {
+                self.scoped(|optimizer| optimizer.body(body))
+            }
+            Node::VariableDeclaration(declaration_type, name, _, value) => {
+                self.expression(value);
+                let binding = match declaration_type.as_ref() {
+                    Node::DeclarationType(TokenType::Const) if is_literal(value) => {
+                        Binding::Constant(value.as_ref().clone())
+                    }
+                    Node::DeclarationType(TokenType::Var | TokenType::Static) => Binding::Local,
+                    _ => Binding::Other,
+                };
+                self.declare(name, binding);
+            }
+            Node::DeclarationList(declarations) => {
+                for declaration in declarations {
+                    self.statement(declaration);
+                }
+            }
+            Node::GlobalVarDeclaration(names) => {
+                for name in names {
+                    self.declare(name, Binding::Other);
+                }
+            }
+            Node::FunctionDeclaration(name, params, _, code) => {
+                self.declare(name, Binding::Other);
+                self.function(params, code);
+            }
+            // The call to the parent constructor reads the parameters, so it
+            // is left as written.
+            Node::ConstructorDeclaration(function, _)
+            | Node::ExportDeclaration(function)
+            | Node::Attributed(_, function) => self.statement(function),
+            Node::ClassDeclaration(name, members) => {
+                self.declare(name, Binding::Other);
+                self.class(members);
+            }
+            Node::EnumDeclaration(name, members) => {
+                self.declare(name, Binding::Other);
+                for value in members.iter_mut().filter_map(|(_, value)| value.as_mut()) {
+                    self.expression(value);
+                }
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                self.expression(condition);
+                self.nested(then);
+                if let Some(otherwise) = otherwise {
+                    self.nested(otherwise);
+                }
+            }
+            Node::WhileStatement(value, body)
+            | Node::RepeatStatement(value, body)
+            | Node::WithStatement(value, body) => {
+                self.expression(value);
+                self.nested(body);
+            }
+            Node::DoUntilStatement(body, condition) => {
+                self.nested(body);
+                self.expression(condition);
+            }
+            Node::ForStatement(init, condition, step, body) => self.scoped(|optimizer| {
+                if let Some(init) = init {
+                    optimizer.statement(init);
+                }
+                if let Some(condition) = condition {
+                    optimizer.expression(condition);
+                }
+                if let Some(step) = step {
+                    optimizer.statement(step);
+                }
+                optimizer.nested(body);
+            }),
+            Node::SwitchStatement(value, cases) => {
+                self.expression(value);
+                for case in cases {
+                    if let Node::SwitchCase(value, body) = case.as_mut() {
+                        if let Some(value) = value {
+                            self.expression(value);
+                        }
+                        self.body(body);
+                    }
+                }
+            }
+            Node::TryStatement(body, catch, finally) => {
+                self.nested(body);
+                if let Some((name, handler)) = catch {
+                    self.scoped(|optimizer| {
+                        optimizer.declare(name, Binding::Local);
+                        optimizer.statement(handler);
+                    });
+                }
+                if let Some(finally) = finally {
+                    self.nested(finally);
+                }
+            }
+            Node::ThrowStatement(value) | Node::DeleteStatement(value) => self.expression(value),
+            // A call whose value is dropped is kept, as GML has no statement
+            // made of an expression alone.
+            Node::FunctionCall(callee, args) => {
+                self.expression(callee);
+                self.expression(args);
+            }
+            Node::Assignment(target, _, value) => {
+                if !matches!(target.as_ref(), Node::Identifier(_)) {
+                    self.expression(target);
+                }
+                self.expression(value);
+            }
+            Node::ImportDeclaration(_, _)
+            | Node::ExternFunction(_, _, _)
+            | Node::ExternVariable(_, _, _)
+            | Node::MacroDeclaration(_, _)
+            | Node::Break
+            | Node::Continue
+            | Node::Exit
+            | Node::Comment(_, _)
+            | Node::BlankLine => {}
+            expr => self.expression(expr),
+        }
+    }
+
+    /// Fields and methods are read by name in the methods of a class, so
+    /// they hide the constants outside it.
+    fn class(&mut self, members: &mut [Box<Node>]) {
+        self.scoped(|optimizer| {
+            for member in members.iter() {
+                if let Some(name) = member.declared_name() {
+                    optimizer.declare(name, Binding::Other);
+                } else if let Node::ClassField(name, _, _) = member.as_ref() {
+                    optimizer.declare(name, Binding::Other);
+                }
+            }
+
+            for member in members.iter_mut() {
+                match member.as_mut() {
+                    Node::ClassField(_, _, Some(value)) => optimizer.expression(value),
+                    Node::FunctionDeclaration(_, params, _, code) => {
+                        optimizer.function(params, code)
+                    }
+                    Node::ConstructorDeclaration(function, _) => {
+                        if let Node::FunctionDeclaration(_, params, _, code) = function.as_mut() {
+                            optimizer.function(params, code);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// The body of a function, which is a `Program` or, for arrow functions,
+    /// an expression.
+    fn function(&mut self, params: &mut [Box<Node>], code: &mut Node) {
+        self.scoped(|optimizer| {
+            for param in params.iter_mut() {
+                if let Node::DefaultParameter(_, default) = param.as_mut() {
+                    optimizer.expression(default);
+                }
+                if let Some(name) = parameter_name(param) {
+                    optimizer.declare(name, Binding::Local);
+                }
+            }
+
+            match code {
+                Node::Program(body) => optimizer.body(body),
+                expr => optimizer.expression(expr),
+            }
+        });
+    }
+
+    fn expression(&mut self, node: &mut Node) {
+        match node {
+            Node::Identifier(name) => {
+                if let Some(value) = self.constant(name) {
+                    *node = value;
+                }
+                return;
+            }
+            Node::FunctionExpression(params, code) | Node::ArrowFunction(params, _, code) => {
+                self.function(params, code);
+                return;
+            }
+            // `x++` assigns to its operand, which has to stay a variable.
+            Node::UpdateExpression(_, _, _) => return,
+            _ => {}
+        }
+
+        for child in node.children_mut() {
+            self.expression(child);
+        }
+        fold(node);
+
+        if let Some((name, inlined)) = self.inline_call(node) {
+            *node = inlined;
+            self.inlining.push(name);
+            self.expression(node);
+            self.inlining.pop();
+        }
+    }
+
+    /// The expression a call is replaced by, when the function is inlined
+    /// and doing so can't change what the call does.
+    fn inline_call(&self, node: &Node) -> Option<(String, Node)> {
+        let Node::FunctionCall(callee, args) = node else {
+            return None;
+        };
+        let (Node::Identifier(name), Node::Arguments(args)) = (callee.as_ref(), args.as_ref())
+        else {
+            return None;
+        };
+        let inline = self.inlines.get(name)?;
+        if self.inlining.contains(name) || args.len() != inline.params.len() {
+            return None;
+        }
+        // The function only sees names declared at the top level.
+        if self.lookup(name).is_some_and(|(depth, _)| depth > 0)
+            || !self.sees_top_level(&inline.body, &inline.params)
+        {
+            return None;
+        }
+
+        for (param, arg) in inline.params.iter().zip(args) {
+            let simple = is_literal(arg) || matches!(arg.as_ref(), Node::Identifier(_));
+            let allowed = match (inline.pure, is_pure(arg, &self.pure)) {
+                (_, false) => false,
+                (false, true) => is_literal(arg),
+                (true, true) => simple || count_uses(&inline.body, param) <= 1,
+            };
+            if !allowed {
+                return None;
+            }
+        }
+
+        let substitutes: HashMap<&str, &Node> = inline
+            .params
+            .iter()
+            .map(String::as_str)
+            .zip(args.iter().map(|arg| arg.as_ref()))
+            .collect();
+        Some((name.clone(), substitute(&inline.body, &substitutes)))
+    }
+
+    /// Whether every name `body` reads, besides `params`, means here what it
+    /// means at the top level of the module.
+    fn sees_top_level(&self, body: &Node, params: &[String]) -> bool {
+        free_names(body, params)
+            .iter()
+            .all(|name| match self.lookup(name) {
+                Some((0, Binding::Local)) => false,
+                Some((depth, _)) => depth == 0,
+                None => true,
+            })
+    }
+
+    /// Whether a callback can read every name it reads, besides its
+    /// parameters, from the loop replacing it. Closures don't see the local
+    /// variables around them.
+    fn sees_callback_names(&self, body: &Node, params: &[String]) -> bool {
+        free_names(body, params)
+            .iter()
+            .all(|name| !matches!(self.lookup(name), Some((_, Binding::Local))))
+    }
+
+    /// Turns `array_foreach(items, callback)` and `x = array_map(items,
+    /// callback)` into a loop running the body of the callback, when it
+    /// doesn't need to be a function. Other statements are returned as is.
+    fn callback_loop(&mut self, mut stmt: Box<Node>) -> Statements {
+        let Some((builtin, items, params, body)) = self.callback_call(&stmt) else {
+            return vec![stmt];
+        };
+
+        let id = self.loops;
+        self.loops += 1;
+        let items_name = format!("__gmpp_items{id}");
+        let count_name = format!("__gmpp_count{id}");
+        let index_name = format!("__gmpp_i{id}");
+
+        let item = Node::IndexAccess(identifier(&items_name), None, vec![identifier(&index_name)]);
+        let index = Node::Identifier(index_name.clone());
+        let mut substitutes: HashMap<&str, &Node> = HashMap::new();
+        substitutes.insert(&params[0], &item);
+        if let Some(param) = params.get(1) {
+            substitutes.insert(param, &index);
+        }
+
+        let mut stmts = vec![local(
+            &count_name,
+            call("array_length", identifier(&items_name)),
+        )];
+        let loop_body = match (builtin, body) {
+            (Builtin::Foreach, body) => match substitute(&body, &substitutes) {
+                Node::Program(body) => body,
+                expr => vec![expr.to_box()],
+            },
+            (Builtin::Map(target), expr) => {
+                let create = call("array_create", identifier(&count_name)).to_box();
+                let name = match target {
+                    MapTarget::Declaration(declaration_type, name, annotation) => {
+                        let declaration = Node::VariableDeclaration(
+                            declaration_type,
+                            name.clone(),
+                            annotation,
+                            create,
+                        );
+                        stmts.push(declaration);
+                        name
+                    }
+                    MapTarget::Assignment(name) => {
+                        stmts.push(Node::Assignment(
+                            identifier(&name),
+                            String::from("="),
+                            create,
+                        ));
+                        name
+                    }
+                };
+                let element =
+                    Node::IndexAccess(identifier(&name), None, vec![identifier(&index_name)]);
+                let value = substitute(&expr, &substitutes);
+                vec![Node::Assignment(element.to_box(), String::from("="), value.to_box()).to_box()]
+            }
+        };
+
+        let condition = Node::BinaryExpression(
+            identifier(&index_name),
+            Node::BinaryOperator(String::from("<")).to_box(),
+            identifier(&count_name),
+        );
+        let step = Node::UpdateExpression(String::from("++"), identifier(&index_name), false);
+        stmts.push(Node::ForStatement(
+            Some(local(&index_name, number(0.0)).to_box()),
+            Some(condition.to_box()),
+            Some(step.to_box()),
+            Node::Block(loop_body).to_box(),
+        ));
+
+        // The first statement takes the place, and the position, of the call.
+        *stmt = local(&items_name, items);
+        let mut loop_stmts = vec![stmt];
+        loop_stmts.extend(stmts.into_iter().map(Node::to_box));
+        loop_stmts
+    }
+
+    /// The built-in, array, callback parameters and callback body of a
+    /// statement `callback_loop` can rewrite.
+    fn callback_call(&self, stmt: &Node) -> Option<(Builtin, Node, Vec<String>, Node)> {
+        let (builtin, call) = match stmt {
+            Node::FunctionCall(callee, _)
+                if **callee == Node::Identifier("array_foreach".into()) =>
+            {
+                (Builtin::Foreach, stmt)
+            }
+            // A `static` is only set on the first call, which a loop can't be.
+            Node::VariableDeclaration(declaration_type, _, _, _)
+                if **declaration_type == Node::DeclarationType(TokenType::Static) =>
+            {
+                return None
+            }
+            Node::VariableDeclaration(declaration_type, name, annotation, value) => (
+                Builtin::Map(MapTarget::Declaration(
+                    declaration_type.clone(),
+                    name.clone(),
+                    annotation.clone(),
+                )),
+                value.as_ref(),
+            ),
+            Node::Assignment(target, operator, value) if operator == "=" => match target.as_ref() {
+                Node::Identifier(name) => (
+                    Builtin::Map(MapTarget::Assignment(name.clone())),
+                    value.as_ref(),
+                ),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let Node::FunctionCall(callee, args) = call else {
+            return None;
+        };
+        let (Node::Identifier(function), Node::Arguments(args)) = (callee.as_ref(), args.as_ref())
+        else {
+            return None;
+        };
+        let expected = match builtin {
+            Builtin::Foreach => "array_foreach",
+            Builtin::Map(_) => "array_map",
+        };
+        if function != expected || self.lookup(function).is_some() {
+            return None;
+        }
+        let [items, callback] = args.as_slice() else {
+            return None;
+        };
+
+        let (params, code) = match callback.as_ref() {
+            Node::FunctionExpression(params, code) | Node::ArrowFunction(params, _, code) => {
+                (params, code.as_ref())
+            }
+            _ => return None,
+        };
+        let params = params
+            .iter()
+            .map(|param| match param.as_ref() {
+                Node::FunctionParameter(name, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>()?;
+        if params.is_empty() || params.len() > 2 {
+            return None;
+        }
+
+        let body = match (&builtin, code) {
+            (Builtin::Foreach, Node::Program(body)) => {
+                if !body.iter().all(|stmt| can_loop(stmt, false)) {
+                    return None;
+                }
+                code.clone()
+            }
+            (Builtin::Foreach, expr @ Node::FunctionCall(_, _)) => expr.clone(),
+            (Builtin::Map(_), Node::Program(_)) => returned_expression(code)?.clone(),
+            (Builtin::Map(_), Node::FunctionExpression(_, _) | Node::ArrowFunction(_, _, _)) => {
+                return None
+            }
+            (Builtin::Map(_), expr) => expr.clone(),
+            _ => return None,
+        };
+
+        let target = match &builtin {
+            Builtin::Map(MapTarget::Declaration(_, name, _) | MapTarget::Assignment(name)) => {
+                Some(name.as_str())
+            }
+            Builtin::Foreach => None,
+        };
+        let reads_target = target.is_some_and(|target| count_uses(&body, target) > 0);
+        let writes_params = params.iter().any(|param| assigns(&body, param));
+        if !can_loop(&body, false)
+            || reads_target
+            || writes_params
+            || !self.sees_callback_names(&body, &params)
+        {
+            return None;
+        }
+
+        Some((builtin, items.as_ref().clone(), params, body))
+    }
+}
+
+enum Builtin {
+    Foreach,
+    Map(MapTarget),
+}
+
+/// Where the array `array_map` returns goes.
+enum MapTarget {
+    Declaration(Box<Node>, String, Option<Box<Node>>),
+    Assignment(String),
+}
+
+/// The top-level functions of a program that can be inlined at `level`.
+fn inline_functions(
+    program: &Node,
+    registry: &Registry,
+    pure: &HashSet<String>,
+    level: OptLevel,
+) -> HashMap<String, Inline> {
+    let mut inlines = HashMap::new();
+    let Node::Program(body) = program else {
+        return inlines;
+    };
+
+    for stmt in body {
+        let Node::FunctionDeclaration(name, params, _, code) = stmt.declaration() else {
+            continue;
+        };
+        let forced = registry.has(name, "inline");
+        if registry.has(name, "noinline") || (level != OptLevel::O2 && !forced) {
+            continue;
+        }
+
+        let Some(params) = params
+            .iter()
+            .map(|param| match param.as_ref() {
+                Node::FunctionParameter(name, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>()
+        else {
+            continue;
+        };
+        let Some(expr) = returned_expression(code) else {
+            continue;
+        };
+
+        let recursive = count_uses(expr, name) > 0;
+        let small = forced || count_nodes(expr) <= INLINE_SIZE;
+        if recursive || !small || !can_inline(expr) {
+            continue;
+        }
+
+        inlines.insert(
+            name.clone(),
+            Inline {
+                params,
+                body: expr.clone(),
+                pure: is_pure(expr, pure),
+            },
+        );
+    }
+
+    inlines
+}
+
+/// The expression a function body made of a single `return` returns.
+fn returned_expression(code: &Node) -> Option<&Node> {
+    let Node::Program(body) = code else {
+        return None;
+    };
+    let mut stmts = body
+        .iter()
+        .filter(|stmt| !matches!(stmt.as_ref(), Node::Comment(_, _) | Node::BlankLine));
+    match (stmts.next().map(|stmt| stmt.as_ref()), stmts.next()) {
+        (Some(Node::ReturnStatement(expr)), None) => Some(expr),
+        _ => None,
+    }
+}
+
+/// Whether an expression still means the same once moved into another
+/// function: it declares no function, changes no variable and doesn't read
+/// `argument`.
+fn can_inline(node: &Node) -> bool {
+    match node {
+        Node::FunctionExpression(_, _)
+        | Node::ArrowFunction(_, _, _)
+        | Node::ConstructorDeclaration(_, _)
+        | Node::Assignment(_, _, _)
+        | Node::UpdateExpression(_, _, _) => false,
+        Node::Identifier(name) => !name.starts_with("argument"),
+        node => node.children().into_iter().all(can_inline),
+    }
+}
+
+/// Whether the body of a callback can run as the body of a loop: it declares
+/// no function or local variable, doesn't return, doesn't read `argument`,
+/// and only uses `break` and `continue` inside its own loops.
+fn can_loop(node: &Node, in_loop: bool) -> bool {
+    match node {
+        Node::FunctionExpression(_, _)
+        | Node::ArrowFunction(_, _, _)
+        | Node::FunctionDeclaration(_, _, _, _)
+        | Node::ConstructorDeclaration(_, _)
+        | Node::ClassDeclaration(_, _)
+        | Node::ReturnStatement(_)
+        | Node::Exit => false,
+        Node::VariableDeclaration(declaration_type, _, _, _) => {
+            !matches!(
+                declaration_type.as_ref(),
+                Node::DeclarationType(TokenType::Var | TokenType::Static)
+            ) && node
+                .children()
+                .into_iter()
+                .all(|child| can_loop(child, in_loop))
+        }
+        Node::Break | Node::Continue => in_loop,
+        Node::Identifier(name) => !name.starts_with("argument"),
+        Node::WhileStatement(_, _)
+        | Node::DoUntilStatement(_, _)
+        | Node::RepeatStatement(_, _)
+        | Node::ForStatement(_, _, _, _)
+        | Node::WithStatement(_, _)
+        | Node::SwitchStatement(_, _) => node
+            .children()
+            .into_iter()
+            .all(|child| can_loop(child, true)),
+        node => node
+            .children()
+            .into_iter()
+            .all(|child| can_loop(child, in_loop)),
+    }
+}
+
+/// Whether evaluating an expression has no effect besides its value.
+fn is_pure(node: &Node, pure: &HashSet<String>) -> bool {
+    match node {
+        Node::FunctionCall(callee, args) => {
+            matches!(callee.as_ref(), Node::Identifier(name) if PURE_FUNCTIONS.contains(&name.as_str()) || pure.contains(name))
+                && is_pure(args, pure)
+        }
+        Node::NewExpression(_)
+        | Node::Assignment(_, _, _)
+        | Node::UpdateExpression(_, _, _)
+        | Node::FunctionExpression(_, _)
+        | Node::ArrowFunction(_, _, _) => false,
+        node => node
+            .children()
+            .into_iter()
+            .all(|child| is_pure(child, pure)),
+    }
+}
+
+fn count_nodes(node: &Node) -> usize {
+    1 + node.children().into_iter().map(count_nodes).sum::<usize>()
+}
+
+/// How many times an expression reads a name.
+fn count_uses(node: &Node, name: &str) -> usize {
+    match node {
+        Node::Identifier(used) => usize::from(used == name),
+        node => node
+            .children()
+            .into_iter()
+            .map(|child| count_uses(child, name))
+            .sum(),
+    }
+}
+
+/// Whether some code assigns to a variable.
+fn assigns(node: &Node, name: &str) -> bool {
+    match node {
+        Node::Assignment(target, _, _) | Node::UpdateExpression(_, target, _) if matches!(target.as_ref(), Node::Identifier(target) if target == name) => {
+            true
+        }
+        node => node
+            .children()
+            .into_iter()
+            .any(|child| assigns(child, name)),
+    }
+}
+
+/// The names an expression reads, besides `params`.
+fn free_names(node: &Node, params: &[String]) -> Vec<String> {
+    let mut names = Vec::new();
+    collect_names(node, params, &mut names);
+    names
+}
+
+fn collect_names(node: &Node, params: &[String], names: &mut Vec<String>) {
+    match node {
+        Node::Identifier(name) if !params.contains(name) => names.push(name.clone()),
+        node => {
+            for child in node.children() {
+                collect_names(child, params, names);
+            }
+        }
+    }
+}
+
+/// A copy of an expression with the names in `substitutes` replaced.
+fn substitute(node: &Node, substitutes: &HashMap<&str, &Node>) -> Node {
+    if let Node::Identifier(name) = node {
+        if let Some(value) = substitutes.get(name.as_str()) {
+            return (*value).clone();
+        }
+    }
+
+    let mut copy = node.clone();
+    for (child, original) in copy.children_mut().into_iter().zip(node.children()) {
+        *child = substitute(original, substitutes);
+    }
+    copy
+}
+
+fn identifier(name: &str) -> Box<Node> {
+    Node::Identifier(name.to_string()).to_box()
+}
+
+/// A call with a single argument.
+fn call(name: &str, arg: Box<Node>) -> Node {
+    Node::FunctionCall(identifier(name), Node::Arguments(vec![arg]).to_box())
+}
+
+/// `var name = value;`
+fn local(name: &str, value: Node) -> Node {
+    Node::VariableDeclaration(
+        Node::DeclarationType(TokenType::Var).to_box(),
+        name.to_string(),
+        None,
+        value.to_box(),
+    )
+}
+
+/// Declarations GameMaker makes before running any code, which stay even
+/// after a `return`.
+fn is_declaration(node: &Node) -> bool {
+    match node {
+        Node::VariableDeclaration(declaration_type, _, _, _) => {
+            **declaration_type == Node::DeclarationType(TokenType::Static)
+        }
+        Node::FunctionDeclaration(_, _, _, _)
+        | Node::Attributed(_, _)
+        | Node::ConstructorDeclaration(_, _)
+        | Node::ClassDeclaration(_, _)
+        | Node::EnumDeclaration(_, _)
+        | Node::MacroDeclaration(_, _)
+        | Node::GlobalVarDeclaration(_)
+        | Node::ExportDeclaration(_)
+        | Node::ImportDeclaration(_, _)
+        | Node::ExternFunction(_, _, _)
+        | Node::ExternVariable(_, _, _) => true,
+        _ => false,
+    }
+}
+
+fn is_literal(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::NumericLiteral(_, _) | Node::StringLiteral(_) | Node::BooleanLiteral(_)
+    )
+}
+
+/// Whether a condition made of a literal holds. GML treats numbers above 0.5
+/// as true.
+fn truthiness(condition: &Node) -> Option<bool> {
+    match condition {
+        Node::BooleanLiteral(value) => Some(*value),
+        Node::NumericLiteral(value, _) => Some(*value > 0.5),
+        _ => None,
+    }
+}
+
+/// The statements left of an `if` or a `while` whose condition is known.
+fn fold_branch(stmt: Box<Node>) -> Statements {
+    let taken = match stmt.as_ref() {
+        Node::IfStatement(condition, _, _) => truthiness(condition),
+        Node::WhileStatement(condition, _) if truthiness(condition) == Some(false) => Some(false),
+        _ => None,
+    };
+    let Some(taken) = taken else {
+        return vec![stmt];
+    };
+
+    match (taken, *stmt) {
+        (true, Node::IfStatement(_, then, _)) => statements(then),
+        (false, Node::IfStatement(_, _, Some(otherwise))) => statements(otherwise),
+        _ => Vec::new(),
+    }
+}
+
+/// The statements of a block, keeping their boxes, or a single statement.
+fn statements(body: Box<Node>) -> Statements {
+    if !matches!(body.as_ref(), Node::Block(_)) {
+        return vec![body];
+    }
+
+    match *body {
+        Node::Block(statements) => statements,
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces an operation on literals with its result.
+fn fold(node: &mut Node) {
+    let folded = match node {
+        Node::UnaryExpression(operator, operand) => match (operator.as_str(), operand.as_ref()) {
+            ("-", Node::NumericLiteral(value, _)) => Some(number(-value)),
+            ("!", Node::BooleanLiteral(value)) => Some(Node::BooleanLiteral(!value)),
+            _ => None,
+        },
+        Node::BinaryExpression(left, operator, right) => match operator.as_ref() {
+            Node::BinaryOperator(operator) => fold_binary(left, operator, right),
+            _ => None,
+        },
+        Node::TernaryExpression(condition, if_true, if_false) => match truthiness(condition) {
+            Some(true) => Some(std::mem::replace(if_true.as_mut(), Node::Ignore)),
+            Some(false) => Some(std::mem::replace(if_false.as_mut(), Node::Ignore)),
+            None => None,
+        },
+        _ => None,
+    };
+
+    if let Some(folded) = folded {
+        *node = folded;
+    }
+}
+
+fn fold_binary(left: &Node, operator: &str, right: &Node) -> Option<Node> {
+    match (left, operator, right) {
+        (Node::NumericLiteral(left, _), _, Node::NumericLiteral(right, _)) => {
+            fold_numbers(*left, operator, *right)
+        }
+        (Node::StringLiteral(left), "+", Node::StringLiteral(right)) => {
+            Some(Node::StringLiteral(format!("{left}{right}")))
+        }
+        // Strings are kept as written, so two escapes of the same character
+        // would compare as different.
+        (Node::StringLiteral(left), "==" | "!=", Node::StringLiteral(right))
+            if !left.contains('\\') && !right.contains('\\') =>
+        {
+            Some(Node::BooleanLiteral((left == right) == (operator == "==")))
+        }
+        (Node::BooleanLiteral(left), _, Node::BooleanLiteral(right)) => {
+            let value = match operator {
+                "&&" => *left && *right,
+                "||" => *left || *right,
+                "^^" | "!=" => left != right,
+                "==" => left == right,
+                _ => return None,
+            };
+            Some(Node::BooleanLiteral(value))
+        }
+        // GML stops evaluating `&&` and `||` once the result is known.
+        (Node::BooleanLiteral(false), "&&", _) => Some(Node::BooleanLiteral(false)),
+        (Node::BooleanLiteral(true), "||", _) => Some(Node::BooleanLiteral(true)),
+        _ => None,
+    }
+}
+
+fn fold_numbers(left: f64, operator: &str, right: f64) -> Option<Node> {
+    // Comparisons of reals go through GameMaker's epsilon, which a game can
+    // change, so only whole numbers are compared.
+    let whole = left.fract() == 0.0 && right.fract() == 0.0;
+    let comparison = match operator {
+        "==" => Some(left == right),
+        "!=" => Some(left != right),
+        "<" => Some(left < right),
+        "<=" => Some(left <= right),
+        ">" => Some(left > right),
+        ">=" => Some(left >= right),
+        _ => None,
+    };
+    if let Some(value) = comparison {
+        return whole.then_some(Node::BooleanLiteral(value));
+    }
+
+    let integers = whole && left.abs() < 2f64.powi(53) && right.abs() < 2f64.powi(53);
+    let value = match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        // Dividing by zero is an error at runtime, which is left to happen.
+        "/" if right != 0.0 => left / right,
+        "%" if right != 0.0 => left % right,
+        "div" if right != 0.0 => (left / right).trunc(),
+        "&" if integers => ((left as i64) & (right as i64)) as f64,
+        "|" if integers => ((left as i64) | (right as i64)) as f64,
+        "^" if integers => ((left as i64) ^ (right as i64)) as f64,
+        _ => return None,
+    };
+
+    value.is_finite().then(|| number(value))
+}
+
+fn number(value: f64) -> Node {
+    Node::NumericLiteral(value, value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::codegen::{generate, CodegenOptions};
+    use crate::compiler::parser::parse;
+    use crate::compiler::target::GmlVersion;
+    use crate::compiler::tokenizer::tokenize;
+
+    /// The GML of some code optimized at `level`.
+    fn optimized(code: &str, level: OptLevel) -> String {
+        let mut program = tokenize(code.to_string()).and_then(parse).unwrap();
+        optimize(&mut program, level);
+        generate(
+            &program,
+            &CodegenOptions::new(GmlVersion::Latest, OptLevel::O0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(optimized("let a = 2 + 4*5;", OptLevel::O1), "a = 22;\n");
+        assert_eq!(
+            optimized("let a = 2 + 4*5;", OptLevel::O0),
+            "a = 2 + 4 * 5;\n"
+        );
+        assert_eq!(
+            optimized(
+                "let s = \"a\" + \"b\";\nlet b = true && !false;",
+                OptLevel::O1
+            ),
+            "s = \"ab\";\nb = true;\n"
+        );
+    }
+
+    #[test]
+    fn inlines_constants() {
+        let code = "\
+const TILE = 16;
+function width(tiles: number) -> number {
+    return tiles * TILE + TILE / 2;
+}";
+        assert_eq!(
+            optimized(code, OptLevel::O1),
+            "TILE = 16;\nfunction width(tiles) {\n    return tiles * 16 + 8;\n}\n"
+        );
+    }
+
+    #[test]
+    fn removes_branches_known_not_to_run() {
+        let code = "\
+const DEBUG = false;
+if (DEBUG) {
+    show_debug_message(\"debug\");
+}
+if (false) {
+    a = 1;
+} else {
+    a = 2;
+}
+while (false) {
+    a = 3;
+}";
+        assert_eq!(optimized(code, OptLevel::O1), "DEBUG = false;\na = 2;\n");
+    }
+
+    #[test]
+    fn removes_code_after_return() {
+        let code = "\
+function f() -> number {
+    return 1;
+    show_debug_message(\"never\");
+}";
+        assert_eq!(
+            optimized(code, OptLevel::O1),
+            "function f() {\n    return 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_declarations_after_return() {
+        let code = "\
+function f() -> number {
+    return g();
+    function g() -> number {
+        return 2;
+    }
+}";
+        let gml = optimized(code, OptLevel::O1);
+        assert!(gml.contains("function g()"), "{gml}");
+    }
+
+    #[test]
+    fn inlines_small_functions_at_o2() {
+        let code = "\
+function twice(n: number) -> number {
+    return n * 2;
+}
+let a = twice(b);";
+        let gml = optimized(code, OptLevel::O2);
+        assert!(gml.ends_with("a = b * 2;\n"), "{gml}");
+        let gml = optimized(code, OptLevel::O1);
+        assert!(gml.ends_with("a = twice(b);\n"), "{gml}");
+    }
+
+    #[test]
+    fn keeps_calls_of_noinline_functions() {
+        let code = "\
+@noinline
+function twice(n: number) -> number {
+    return n * 2;
+}
+let a = twice(b);";
+        let gml = optimized(code, OptLevel::O2);
+        assert!(gml.ends_with("a = twice(b);\n"), "{gml}");
+    }
+
+    #[test]
+    fn keeps_calls_of_recursive_functions() {
+        let code = "\
+@inline
+function count(n: number) -> number {
+    return n <= 0 ? 0 : 1 + count(n - 1);
+}
+let a = count(3);";
+        let gml = optimized(code, OptLevel::O2);
+        assert!(gml.ends_with("a = count(3);\n"), "{gml}");
+    }
+
+    #[test]
+    fn keeps_arguments_with_effects_in_place() {
+        // Inlining would call next() twice, or call it after reading x.
+        let code = "\
+function twice(n: number) -> number {
+    return n + n;
+}
+function after(n: number) -> number {
+    return x + n;
+}
+let a = twice(next());
+let b = after(next());
+let c = twice(3);";
+        let gml = optimized(code, OptLevel::O2);
+        assert!(gml.contains("a = twice(next());\n"), "{gml}");
+        assert!(gml.contains("b = after(next());\n"), "{gml}");
+        assert!(gml.ends_with("c = 6;\n"), "{gml}");
+    }
+}
@@ -1,81 +1,357 @@
 use crate::compiler::ast::Node;
-use crate::compiler::tokenizer::{Token, TokenType};
+use crate::compiler::error::{Error, Span};
+use crate::compiler::tokenizer::{
+    take_string_content, tokenize, Language, Token, TokenType, Trivia,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 
 struct ParseMessage(usize, Node);
 
-pub fn parse(mut tokens: Vec<Token>) -> Node {
-    let mut statements: Vec<Node> = Vec::new();
+/// Consumed tokens, name, parameters and return type of a function signature.
+struct SignatureMessage(usize, String, Vec<Node>, Option<Box<Node>>);
 
-    while tokens.first().unwrap().kind != TokenType::EOF {
-        let parse_message = parse_statement(&tokens);
-        let ParseMessage(delete_amount, result) = parse_message.unwrap();
+/// Consumed tokens, body, call to the parent constructor and whether the
+/// function is a constructor.
+struct BodyMessage(usize, Node, Option<Box<Node>>, bool);
 
-        tokens.drain(0..delete_amount);
+/// The source of every statement, by the address of its node. A boxed node
+/// keeps its address as long as it isn't cloned or dropped.
+pub type Positions = HashMap<*const Node, Span>;
+
+thread_local! {
+    /// Filled by `statement_at` while `parse_with_positions` runs.
+    static POSITIONS: RefCell<Option<Positions>> = const { RefCell::new(None) };
+    /// The language being parsed, set by `parse_as` while it runs.
+    static LANGUAGE: Cell<Language> = const { Cell::new(Language::GmlPlusPlus) };
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Node, Error> {
+    parse_as(tokens, Language::GmlPlusPlus)
+}
+
+/// Parses tokens read as `language`. Plain GML doesn't need the `;` after a
+/// statement that GML++ requires.
+pub fn parse_as(tokens: Vec<Token>, language: Language) -> Result<Node, Error> {
+    let outer = LANGUAGE.with(|current| current.replace(language));
+    let program = parse_body(&tokens);
+    LANGUAGE.with(|current| current.set(outer));
+
+    program
+}
+
+/// Parses like `parse`, also returning the span of every statement, class
+/// member and switch case statement.
+pub fn parse_with_positions(tokens: Vec<Token>) -> Result<(Node, Positions), Error> {
+    parse_with_positions_as(tokens, Language::GmlPlusPlus)
+}
+
+/// Parses like `parse_as`, also returning the positions of the statements.
+pub fn parse_with_positions_as(
+    tokens: Vec<Token>,
+    language: Language,
+) -> Result<(Node, Positions), Error> {
+    POSITIONS.with(|positions| positions.replace(Some(Positions::new())));
+    let program = parse_as(tokens, language);
+    let positions = POSITIONS.with(|positions| positions.take());
+
+    program.map(|program| (program, positions.unwrap_or_default()))
+}
+
+/// Parses a single expression, which can end with `;`, including those a
+/// statement can't start with like `1 + 2`.
+pub fn parse_expression_only(tokens: Vec<Token>) -> Result<Node, Error> {
+    let ParseMessage(consumed, expr) = parse_expression(&tokens)?;
+
+    match tokens.get(skip_semilicon(&tokens, consumed)) {
+        Some(token) if token.kind != TokenType::EOF => Err(Error::at(
+            format!("Unexpected '{}' after the expression", token.lex),
+            token.span(),
+        )),
+        _ => Ok(expr),
+    }
+}
+
+/// Boxes a statement read from `tokens`, remembering where it is.
+fn statement_at(statement: Node, tokens: &[Token]) -> Box<Node> {
+    let statement = statement.to_box();
+    if let (Some(first), Some(last)) = (tokens.first(), tokens.last()) {
+        POSITIONS.with(|positions| {
+            if let Some(positions) = positions.borrow_mut().as_mut() {
+                positions.insert(
+                    statement.as_ref() as *const Node,
+                    first.span().to(last.span()),
+                );
+            }
+        });
+    }
+    statement
+}
+
+/// An error about `token`, or about the end of the code when there is none.
+fn error_at(message: String, token: Option<&Token>) -> Error {
+    match token {
+        Some(token) => Error::at(message, token.span()),
+        None => Error::new(message),
+    }
+}
+
+/// How an error names the token it stopped at.
+fn found(token: Option<&Token>) -> String {
+    match token {
+        Some(token) if token.kind != TokenType::EOF => format!("'{}'", token.lex),
+        _ => String::from("the end of the code"),
+    }
+}
+
+/// Parses statements until the end of `tokens` or an EOF token. Comments
+/// kept on the tokens become statements of their own.
+fn parse_body(tokens: &[Token]) -> Result<Node, Error> {
+    let mut statements: Vec<Box<Node>> = Vec::new();
+    let mut consumed = 0;
+
+    while let Some(token) = tokens.get(consumed) {
+        statements.extend(leading_trivia(token));
+        if token.kind == TokenType::EOF {
+            break;
+        }
+
+        let ParseMessage(delete_amount, result) = parse_statement(&tokens[consumed..])?;
 
         if result != Node::Ignore {
-            statements.push(result);
+            statements.push(statement_at(
+                result,
+                &tokens[consumed..consumed + delete_amount],
+            ));
         }
+        consumed += delete_amount;
+        statements.extend(trailing_trivia(&tokens[consumed - 1]));
     }
 
-    let boxxer = |stmt: &Node| Box::new(stmt.clone());
+    Ok(Node::Program(statements))
+}
+
+fn leading_trivia(token: &Token) -> impl Iterator<Item = Box<Node>> + '_ {
+    token.leading.iter().map(|trivia| match trivia {
+        Trivia::Comment(text) => Node::Comment(text.clone(), false).to_box(),
+        Trivia::BlankLine => Node::BlankLine.to_box(),
+    })
+}
+
+fn trailing_trivia(token: &Token) -> Option<Box<Node>> {
+    let text = token.trailing.as_ref()?;
+    Some(Node::Comment(text.clone(), true).to_box())
+}
+
+/// Parses the statements between the `{` at the start of `tokens` and its
+/// closing `}`, keeping the comments right after `{` and before `}`.
+/// Returns the index of the `}`.
+fn parse_braced_body(tokens: &[Token]) -> Result<(usize, Node), Error> {
+    let close_index = find_closing(tokens, TokenType::OpenCurly, TokenType::CloseCurly)?;
+
+    let mut statements = Vec::new();
+    statements.extend(trailing_trivia(&tokens[0]));
+    if let Node::Program(body) = parse_body(&tokens[1..close_index])? {
+        statements.extend(body);
+    }
+    statements.extend(leading_trivia(&tokens[close_index]));
 
-    let boxed_statements: Vec<Box<Node>> = statements.iter().map(boxxer).collect();
+    Ok((close_index, Node::Program(statements)))
+}
 
-    Node::Program(boxed_statements)
+/// Parses a statement, pointing the errors that don't say where they are at
+/// its first token.
+fn parse_statement(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let first_token = tokens.first().unwrap();
+    read_statement(tokens).map_err(|error| error.or_at(Some(first_token.span())))
 }
 
-fn parse_statement(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
+fn read_statement(tokens: &[Token]) -> Result<ParseMessage, Error> {
     let first_token = tokens.first().unwrap();
     match first_token.kind {
-        TokenType::Let | TokenType::Const => parse_variable_declaration(tokens),
-        TokenType::Function => parse_function_declaration(tokens),
+        TokenType::Let | TokenType::Const | TokenType::Var | TokenType::Static => {
+            parse_variable_declaration(tokens)
+        }
+        TokenType::GlobalVar => parse_globalvar(tokens),
+        TokenType::Function
+            if tokens
+                .get(1)
+                .is_some_and(|tk| tk.kind == TokenType::Identifier) =>
+        {
+            parse_function_declaration(tokens)
+        }
         TokenType::Semilicon => Ok(ParseMessage(1, Node::Ignore)),
-        TokenType::Identifier => parse_expression(tokens),
         TokenType::Return => parse_return(tokens),
-        _ => Err(format!(
-            "Unexpected token while parsing statement: '{:?}' | '{}'",
-            first_token.kind, first_token.lex
+        TokenType::Throw => parse_throw(tokens),
+        TokenType::Delete => parse_delete(tokens),
+        TokenType::Import => parse_import(tokens),
+        TokenType::Export => parse_export(tokens),
+        TokenType::At => parse_attributed(tokens),
+        TokenType::Extern => parse_extern(tokens),
+        TokenType::Macro => parse_macro(first_token),
+        TokenType::Enum => parse_enum(tokens),
+        TokenType::Class => parse_class(tokens),
+        TokenType::OpenCurly => parse_block(tokens),
+        TokenType::If => parse_if(tokens),
+        TokenType::While | TokenType::Repeat | TokenType::With => parse_loop(tokens),
+        TokenType::Do => parse_do_until(tokens),
+        TokenType::For => parse_for(tokens),
+        TokenType::Switch => parse_switch(tokens),
+        TokenType::Try => parse_try(tokens),
+        TokenType::Break | TokenType::Continue | TokenType::Exit => {
+            let node = match first_token.kind {
+                TokenType::Break => Node::Break,
+                TokenType::Continue => Node::Continue,
+                _ => Node::Exit,
+            };
+            Ok(ParseMessage(end_statement(tokens, 1)?, node))
+        }
+        TokenType::Identifier
+        | TokenType::OpenParenthesis
+        | TokenType::UpdateOperator
+        | TokenType::Function
+        | TokenType::New => {
+            let ParseMessage(consumed, statement) = parse_expression_statement(tokens)?;
+            Ok(ParseMessage(end_statement(tokens, consumed)?, statement))
+        }
+        _ => Err(Error::at(
+            format!("A statement can't start with '{}'", first_token.lex),
+            first_token.span(),
         )),
     }
 }
 
-fn parse_expression(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    if tokens.len() == 1 {
-        return parse_primary(&tokens[0]);
+/// A `;` may follow declarations ending with `}`, so one is consumed when
+/// present.
+fn skip_semilicon(tokens: &[Token], index: usize) -> usize {
+    match tokens.get(index) {
+        Some(tk) if tk.kind == TokenType::Semilicon => index + 1,
+        _ => index,
     }
+}
 
-    if tokens.first().unwrap().kind == TokenType::Identifier
-        && tokens.get(1).unwrap().kind == TokenType::OpenParenthesis
-    {
-        let mut close_index = Option::<usize>::None;
-        let mut parenthesis_seen = 0;
-
-        for (i, tk) in tokens.iter().enumerate() {
-            if tk.kind == TokenType::OpenParenthesis {
-                parenthesis_seen += 1;
-            } else if tk.kind == TokenType::CloseParenthesis {
-                parenthesis_seen -= 1;
-                if parenthesis_seen == 0 {
-                    close_index = Some(i);
-                }
-            }
-        }
+/// Returns the index after the `;` that must end a statement at `index`,
+/// so two statements can't run together. It can be left out before the `}`
+/// closing a block, and anywhere in plain GML, where a statement ends where
+/// its expression does.
+fn end_statement(tokens: &[Token], index: usize) -> Result<usize, Error> {
+    match tokens.get(index) {
+        Some(tk) if tk.kind == TokenType::Semilicon => Ok(index + 1),
+        None => Ok(index),
+        _ if LANGUAGE.with(Cell::get) == Language::Gml => Ok(index),
+        // Pointing at the end of the statement rather than at whatever
+        // comes next, maybe lines below.
+        Some(tk) if tk.kind == TokenType::EOF => Err(error_at(
+            String::from("Expected ';' at the end of the statement"),
+            tokens.get(index.wrapping_sub(1)),
+        )),
+        Some(tk) => Err(Error::at(
+            format!(
+                "Expected ';' at the end of the statement, found '{}'",
+                tk.lex
+            ),
+            tk.span(),
+        )),
+    }
+}
+
+fn expect_token<'a>(
+    tokens: &'a [Token],
+    index: usize,
+    kind: TokenType,
+    context: &str,
+) -> Result<&'a Token, Error> {
+    match tokens.get(index) {
+        Some(tk) if tk.kind == kind => Ok(tk),
+        tk => Err(error_at(
+            format!(
+                "Expected {} in {context}, found {}",
+                expected(&kind),
+                found(tk)
+            ),
+            tk,
+        )),
+    }
+}
+
+/// How errors name the token they expected.
+fn expected(kind: &TokenType) -> &'static str {
+    match kind {
+        TokenType::OpenParenthesis => "'('",
+        TokenType::CloseParenthesis => "')'",
+        TokenType::OpenCurly => "'{'",
+        TokenType::CloseCurly => "'}'",
+        TokenType::StringLiteral => "a string",
+        TokenType::Colon => "':'",
+        TokenType::Semilicon => "';'",
+        TokenType::Until => "'until'",
+        TokenType::Identifier => "a name",
+        _ => "something else",
+    }
+}
 
-        if close_index == None {
-            return Err("Couldn't find enough close parenthesis".to_string());
+/// A function call, an update like `i++`, or an assignment such as `a = b`
+/// or `list[| 0] += 1`, without the `;` after it.
+fn parse_expression_statement(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, target) = parse_expression(tokens)?;
+
+    let operator = match tokens.get(consumed) {
+        Some(tk) if tk.kind == TokenType::Equals || tk.kind == TokenType::AssignmentOperator => {
+            tk.lex.clone()
         }
+        _ => return Ok(ParseMessage(consumed, target)),
+    };
 
-        return parse_function_call(&tokens[0..=close_index.unwrap()].to_vec());
+    if !matches!(
+        target,
+        Node::Identifier(_) | Node::MemberAccess(_, _) | Node::IndexAccess(_, _, _)
+    ) {
+        return Err(Error::at(
+            "Only variables, fields and indexes can be assigned to",
+            tokens[0].span().to(tokens[consumed - 1].span()),
+        ));
+    }
+
+    let ParseMessage(value_len, value) = parse_expression(&tokens[consumed + 1..])?;
+    let assignment = Node::Assignment(target.to_box(), operator, value.to_box());
+
+    Ok(ParseMessage(consumed + 1 + value_len, assignment))
+}
+
+fn parse_expression(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    if tokens.is_empty() {
+        return Err(Error::new("Expected an expression, found nothing"));
+    }
+
+    parse_ternary(tokens)
+}
+
+/// Parses the condition of an `if`, `while`... where GML also accepts `=`
+/// as a comparison.
+fn parse_condition(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, left) = parse_expression(tokens)?;
+
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Equals)
+    {
+        let ParseMessage(right_len, right) = parse_expression(&tokens[consumed + 1..])?;
+        let operator = Node::BinaryOperator("==".to_string());
+        let comparison = parse_binary_expr(&left, &operator, &right)?.1;
+
+        return Ok(ParseMessage(consumed + 1 + right_len, comparison));
     }
 
-    parse_additive(tokens)
+    Ok(ParseMessage(consumed, left))
 }
 
-fn parse_binary_expr(left: &Node, operator: &Node, right: &Node) -> Result<ParseMessage, String> {
+fn parse_binary_expr(left: &Node, operator: &Node, right: &Node) -> Result<ParseMessage, Error> {
     use crate::enum_utils::enum_weak_equals;
 
     if !enum_weak_equals(operator, &Node::BinaryOperator("+".to_string())) {
-        return Err(format!("Given operator node isn't valid: {:?}", operator));
+        return Err(Error::new(format!(
+            "Given operator node isn't valid: {operator:?}"
+        )));
     }
 
     let binary_expr = Node::BinaryExpression(
@@ -87,217 +363,1883 @@ fn parse_binary_expr(left: &Node, operator: &Node, right: &Node) -> Result<Parse
     Ok(ParseMessage(3, binary_expr))
 }
 
-fn parse_primary(token: &Token) -> Result<ParseMessage, String> {
+fn parse_primary(token: &Token) -> Result<ParseMessage, Error> {
     let expr = match token.kind {
-        TokenType::NumericListeral => Node::NumericLiteral(token.lex.parse().unwrap()),
+        TokenType::NumericListeral => {
+            let value =
+                parse_number(&token.lex).map_err(|error| error.or_at(Some(token.span())))?;
+            Node::NumericLiteral(value, token.lex.clone())
+        }
+        TokenType::StringLiteral => Node::StringLiteral(token.lex.clone()),
+        TokenType::TemplateString => {
+            parse_template_string(&token.lex).map_err(|error| error.or_at(Some(token.span())))?
+        }
+        TokenType::BooleanLiteral => Node::BooleanLiteral(token.lex == "true"),
         TokenType::Identifier => Node::Identifier(token.lex.clone()),
-        TokenType::BinaryOperator => Node::BinaryOperator(token.lex.clone()),
-        _ => Node::Panic,
+        _ => {
+            return Err(error_at(
+                format!("Expected an expression, found {}", found(Some(token))),
+                Some(token),
+            ))
+        }
     };
 
-    if expr == Node::Panic {
-        return Err(format!(
-            "Invalid token while parsing a primary: {:?}",
-            token
-        ));
-    }
-
     Ok(ParseMessage(1, expr))
 }
 
-fn parse_additive(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    let mut left = parse_primary(tokens.first().unwrap()).unwrap().1;
-    let mut consumed = 1;
+/// Reads decimal literals as well as `0xFF` and `$FF` hexadecimal ones.
+fn parse_number(lex: &str) -> Result<f64, Error> {
+    let hex_digits = lex.strip_prefix("0x").or(lex.strip_prefix('$'));
 
-    while tokens
+    let value = match hex_digits {
+        Some(digits) => i64::from_str_radix(digits, 16)
+            .map(|value| value as f64)
+            .ok(),
+        None => lex.parse().ok(),
+    };
+
+    value.ok_or(Error::new(format!("Invalid number literal: {lex}")))
+}
+
+fn parse_ternary(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, condition) = parse_nullish(tokens)?;
+
+    if !tokens
         .get(consumed)
-        .is_some_and(|tk| tk.kind == TokenType::BinaryOperator)
+        .is_some_and(|tk| tk.kind == TokenType::QuestionMark)
     {
-        let operator = parse_primary(&tokens[consumed]).unwrap().1;
-        let dealing_with_highcalc = tokens
-            .get(consumed + 2)
-            .is_some_and(|tk| tk.lex == "*" || tk.lex == "/");
-        let right = if !dealing_with_highcalc {
-            parse_primary(&tokens[consumed + 1]).unwrap()
-        } else {
-            parse_highcalc(&tokens[consumed + 1..].to_vec()).unwrap()
-        };
+        return Ok(ParseMessage(consumed, condition));
+    }
 
-        left = parse_binary_expr(&left, &operator, &right.1).unwrap().1;
+    let ParseMessage(true_len, if_true) = parse_ternary(&tokens[consumed + 1..])?;
+    let colon_index = consumed + 1 + true_len;
+    expect_token(
+        tokens,
+        colon_index,
+        TokenType::Colon,
+        "a ternary expression",
+    )?;
+    let ParseMessage(false_len, if_false) = parse_ternary(&tokens[colon_index + 1..])?;
 
-        consumed += 1 + right.0;
-        println!("{consumed}");
+    let ternary = Node::TernaryExpression(condition.to_box(), if_true.to_box(), if_false.to_box());
 
-        println!("{:?}", left);
+    Ok(ParseMessage(colon_index + 1 + false_len, ternary))
+}
+
+/// Parses a left-associative chain of the given binary operators, each side
+/// being parsed by `next`.
+fn parse_binary_chain(
+    tokens: &[Token],
+    operators: &[&str],
+    next: fn(&[Token]) -> Result<ParseMessage, Error>,
+) -> Result<ParseMessage, Error> {
+    let ParseMessage(mut consumed, mut left) = next(tokens)?;
+
+    while tokens.get(consumed).is_some_and(|tk| {
+        tk.kind == TokenType::BinaryOperator && operators.contains(&tk.lex.as_str())
+    }) {
+        let operator = Node::BinaryOperator(tokens[consumed].lex.clone());
+        let right = next(&tokens[consumed + 1..])?;
+
+        left = parse_binary_expr(&left, &operator, &right.1)?.1;
+
+        consumed += 1 + right.0;
     }
 
     Ok(ParseMessage(consumed, left))
 }
 
-fn parse_highcalc(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    let mut left = parse_primary(&tokens[0]).unwrap().1;
-    let mut consumed = 1;
+fn parse_nullish(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["??"], parse_logical_or)
+}
 
-    while tokens
-        .get(consumed)
-        .is_some_and(|tk| tk.lex == "*" || tk.lex == "/")
-    {
-        let operator = parse_primary(&tokens[consumed]).unwrap().1;
-        let right = parse_primary(&tokens[consumed + 1]).unwrap().1;
-        left = parse_binary_expr(&left, &operator, &right).unwrap().1;
+fn parse_logical_or(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["||"], parse_logical_xor)
+}
 
-        consumed += 2;
+fn parse_logical_xor(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["^^"], parse_logical_and)
+}
+
+fn parse_logical_and(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["&&"], parse_comparison)
+}
+
+fn parse_comparison(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["==", "!=", "<", "<=", ">", ">="], parse_bitwise)
+}
+
+fn parse_bitwise(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["|", "&", "^"], parse_shift)
+}
+
+/// `>>` is tokenized as two `>` (see `MulticharTokens`), so it is put back
+/// together here.
+fn parse_shift(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(mut consumed, mut left) = parse_additive(tokens)?;
+
+    loop {
+        let operator_len = match (tokens.get(consumed), tokens.get(consumed + 1)) {
+            (Some(tk), _) if tk.lex == "<<" => 1,
+            (Some(first), Some(second)) if first.lex == ">" && second.lex == ">" => 2,
+            _ => break,
+        };
+        let operator = if operator_len == 1 { "<<" } else { ">>" };
+
+        let right = parse_additive(&tokens[consumed + operator_len..])?;
+        left = parse_binary_expr(&left, &Node::BinaryOperator(operator.to_string()), &right.1)?.1;
+
+        consumed += operator_len + right.0;
     }
 
     Ok(ParseMessage(consumed, left))
 }
 
-fn parse_variable_declaration(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    let declaration_type = Node::DeclarationType(tokens[0].clone().kind);
-    let var_name = tokens[1].clone().lex;
+fn parse_additive(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["+", "-"], parse_highcalc)
+}
 
-    if tokens[2].kind != TokenType::Equals {
-        return Err(format!(
-            "Unexpected token while parsing variable declaration: ({:?})",
-            tokens[1]
-        ));
+fn parse_highcalc(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    parse_binary_chain(tokens, &["*", "/", "%", "div"], parse_unary)
+}
+
+fn parse_unary(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let first_token = match tokens.first() {
+        Some(tk) => tk,
+        None => return Err(Error::new("Expected an expression, found nothing")),
+    };
+
+    let is_unary = first_token.kind == TokenType::UnaryOperator
+        || (first_token.kind == TokenType::BinaryOperator
+            && (first_token.lex == "-" || first_token.lex == "+"));
+
+    if is_unary {
+        let ParseMessage(consumed, operand) = parse_unary(&tokens[1..])?;
+        let expr = Node::UnaryExpression(first_token.lex.clone(), operand.to_box());
+
+        return Ok(ParseMessage(consumed + 1, expr));
     }
 
-    let semilicon_index = tokens[0..tokens.len()]
-        .iter()
-        .position(|tk| tk.kind == TokenType::Semilicon)
-        .unwrap();
+    if first_token.kind == TokenType::UpdateOperator {
+        let ParseMessage(consumed, target) = parse_unary(&tokens[1..])?;
+        let expr = Node::UpdateExpression(first_token.lex.clone(), target.to_box(), true);
+
+        return Ok(ParseMessage(consumed + 1, expr));
+    }
+
+    parse_call(tokens)
+}
+
+/// Parses an operand followed by any number of calls, `.member` accesses,
+/// `[index]` accesses and `++`/`--`.
+fn parse_call(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(mut consumed, mut callee) = parse_operand(tokens)?;
 
-    let expr_msg = parse_expression(&tokens[3..semilicon_index].to_vec()).unwrap();
+    while let Some(tk) = tokens.get(consumed) {
+        match tk.kind {
+            TokenType::OpenParenthesis => {
+                let close_index = find_closing_parenthesis(&tokens[consumed..])? + consumed;
 
-    let delete_amount = expr_msg.0;
-    let expr_node = expr_msg.1;
+                let arguments = parse_arguments(&tokens[consumed + 1..close_index])?;
+                callee = Node::FunctionCall(callee.to_box(), arguments.to_box());
 
-    let declaration =
-        Node::VariableDeclaration(declaration_type.to_box(), var_name, expr_node.to_box());
+                consumed = close_index + 1;
+            }
+            TokenType::Dot => {
+                let member = expect_token(
+                    tokens,
+                    consumed + 1,
+                    TokenType::Identifier,
+                    "a member access",
+                )?;
+                callee = Node::MemberAccess(callee.to_box(), member.lex.clone());
+
+                consumed += 2;
+            }
+            TokenType::OpenBracket | TokenType::OpenAccessor => {
+                let close_index = find_closing_bracket(&tokens[consumed..])? + consumed;
+                let accessor = tk.lex.strip_prefix('[').filter(|symbol| !symbol.is_empty());
+
+                let indices = match parse_arguments(&tokens[consumed + 1..close_index])? {
+                    Node::Arguments(indices) if !indices.is_empty() => indices,
+                    _ => {
+                        return Err(Error::at(
+                            "Expected an index between '[' and ']'",
+                            tk.span().to(tokens[close_index].span()),
+                        ))
+                    }
+                };
+
+                callee = Node::IndexAccess(callee.to_box(), accessor.map(String::from), indices);
+                consumed = close_index + 1;
+            }
+            TokenType::UpdateOperator => {
+                callee = Node::UpdateExpression(tk.lex.clone(), callee.to_box(), false);
+                consumed += 1;
+            }
+            _ => break,
+        }
+    }
 
-    Ok(ParseMessage(delete_amount + 4, declaration))
+    Ok(ParseMessage(consumed, callee))
 }
 
-fn parse_function_declaration(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    let function_name = tokens.get(1).unwrap().lex.to_owned();
+fn parse_operand(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let first_token = match tokens.first() {
+        Some(tk) => tk,
+        None => return Err(Error::new("Expected an expression, found nothing")),
+    };
 
-    let arguments_range = 3..tokens
-        .iter()
-        .position(|tk| tk.kind == TokenType::CloseParenthesis)
-        .unwrap();
+    match first_token.kind {
+        TokenType::OpenParenthesis => {}
+        TokenType::OpenBracket => return parse_array_literal(tokens),
+        TokenType::OpenCurly => return parse_struct_literal(tokens),
+        TokenType::Function => return parse_function_expression(tokens),
+        TokenType::Fn => return parse_arrow_function(tokens),
+        TokenType::New => {
+            let ParseMessage(consumed, call) = parse_call(&tokens[1..])?;
+            if !matches!(call, Node::FunctionCall(_, _)) {
+                return Err(Error::at(
+                    "Expected a constructor call after 'new'",
+                    first_token.span().to(tokens[consumed].span()),
+                ));
+            }
 
-    let params = parse_function_parameters(&tokens[arguments_range].to_vec()).unwrap();
-    let (consumed_params_tokens, params) = params;
+            return Ok(ParseMessage(
+                consumed + 1,
+                Node::NewExpression(call.to_box()),
+            ));
+        }
+        _ => return parse_primary(first_token),
+    }
 
-    let code_range = (consumed_params_tokens + 5)
-        ..tokens
-            .iter()
-            .position(|tk| tk.kind == TokenType::CloseCurly)
-            .unwrap();
+    // Nothing can be assigned inside parenthesis, so a `=` there is a
+    // comparison, as in GML.
+    let close_index = find_closing_parenthesis(tokens)?;
+    let ParseMessage(consumed, expr) = parse_condition(&tokens[1..close_index])?;
 
-    let mut function_tokens = tokens[code_range].to_vec();
-    function_tokens.push(Token::new(TokenType::EOF, "EOF".to_string()));
+    if consumed != close_index - 1 {
+        let token = &tokens[consumed + 1];
+        return Err(Error::at(
+            format!("Unexpected '{}' inside parenthesis", token.lex),
+            token.span(),
+        ));
+    }
 
-    let code_token_count = function_tokens.len();
-    let function_code = parse(function_tokens).to_box();
+    Ok(ParseMessage(close_index + 1, expr))
+}
 
-    let declaration_node = Node::FunctionDeclaration(
-        function_name,
-        params.iter().map(|node| node.to_box()).collect(),
-        function_code,
-    );
+fn parse_array_literal(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let close_index = find_closing_bracket(tokens)?;
 
-    Ok(ParseMessage(
-        consumed_params_tokens + code_token_count + 6 - 1,
-        declaration_node,
-    ))
+    let items = match parse_arguments(&tokens[1..close_index])? {
+        Node::Arguments(items) => items,
+        _ => Vec::new(),
+    };
+
+    Ok(ParseMessage(close_index + 1, Node::ArrayLiteral(items)))
+}
+
+/// `{ name: value, other: value }`
+fn parse_struct_literal(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let close_index = find_closing(tokens, TokenType::OpenCurly, TokenType::CloseCurly)?;
+    let mut fields = Vec::new();
+
+    for field in split_top_level(&tokens[1..close_index]) {
+        let name = match field.first() {
+            Some(tk) if tk.kind == TokenType::Identifier || tk.kind == TokenType::StringLiteral => {
+                tk.lex.clone()
+            }
+            tk => {
+                return Err(error_at(
+                    format!(
+                        "Expected a field name in a struct literal, found {}",
+                        found(tk)
+                    ),
+                    tk.or(tokens.get(close_index)),
+                ))
+            }
+        };
+
+        expect_token(field, 1, TokenType::Colon, "a struct literal")?;
+        let ParseMessage(consumed, value) = parse_expression(&field[2..])?;
+
+        if consumed != field.len() - 2 {
+            let token = &field[2 + consumed];
+            return Err(Error::at(
+                format!("Unexpected '{}' in the value of '{name}'", token.lex),
+                token.span(),
+            ));
+        }
+
+        fields.push((name, value.to_box()));
+    }
+
+    Ok(ParseMessage(close_index + 1, Node::StructLiteral(fields)))
 }
 
-fn parse_function_parameters(tokens: &Vec<Token>) -> Result<(usize, Vec<Node>), String> {
-    if tokens.len() == 0 {
-        return Ok((0, Vec::<Node>::new()));
+/// `function(params) { ... }`, possibly named and possibly a constructor.
+fn parse_function_expression(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let SignatureMessage(signature_len, name, params, _) = parse_function_signature(&tokens[1..])?;
+    let signature_len = signature_len + 1;
+    let BodyMessage(body_len, code, parent, is_constructor) =
+        parse_function_body(&tokens[signature_len..], &name)?;
+
+    let params = params.iter().map(|param| param.clone().to_box()).collect();
+    let mut function = Node::FunctionExpression(params, code.to_box());
+
+    if is_constructor {
+        function = Node::ConstructorDeclaration(function.to_box(), parent);
     }
 
-    let mut consumed: usize = 0;
-    let mut params: Vec<Node> = Vec::new();
+    Ok(ParseMessage(signature_len + body_len, function))
+}
 
-    loop {
-        let name = tokens[consumed].lex.to_owned();
+/// `fn(params) -> type => expr` or `fn(params) -> type { ... }`
+fn parse_arrow_function(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let SignatureMessage(signature_len, _, params, return_type) =
+        parse_function_signature(&tokens[1..])?;
+    let consumed = signature_len + 1;
+    let params = params.iter().map(|param| param.clone().to_box()).collect();
+
+    let (body_len, code) = match tokens.get(consumed).map(|tk| &tk.kind) {
+        Some(TokenType::FatArrow) => {
+            let ParseMessage(expr_len, expr) = parse_expression(&tokens[consumed + 1..])?;
+            (expr_len + 1, expr)
+        }
+        Some(TokenType::OpenCurly) => {
+            let (close_index, code) = parse_braced_body(&tokens[consumed..])?;
+            (close_index + 1, code)
+        }
+        tk => {
+            let token = tokens.get(consumed);
+            return Err(error_at(
+                format!(
+                    "Expected '=>' or '{{' after the parameters of an arrow function, found {}",
+                    found(token)
+                ),
+                tk.and(token),
+            ));
+        }
+    };
+
+    Ok(ParseMessage(
+        consumed + body_len,
+        Node::ArrowFunction(params, return_type, code.to_box()),
+    ))
+}
 
-        params.push(Node::FunctionParameter(name));
+/// Index of the `]` closing the `[` or accessor at the start of `tokens`.
+fn find_closing_bracket(tokens: &[Token]) -> Result<usize, Error> {
+    let mut depth = 0;
 
-        if consumed >= tokens.len() - 1 {
-            consumed += 1;
-            break;
+    for (i, tk) in tokens.iter().enumerate() {
+        match tk.kind {
+            TokenType::OpenBracket | TokenType::OpenAccessor => depth += 1,
+            TokenType::CloseBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            _ => {}
         }
-        consumed += 2;
     }
 
-    Ok((consumed, params))
+    Err(error_at(
+        String::from("Couldn't find the closing ']'"),
+        tokens.first(),
+    ))
 }
 
-fn parse_function_call(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    if tokens.len() < 3 {
-        return Err(format!(
-            "Function call should be at least 3 tokens long, given one is: {} tokens long",
-            tokens.len()
-        ));
+/// Splits `tokens` on the commas that aren't nested in brackets, dropping an
+/// empty trailing element so `[1, 2,]` is allowed.
+fn split_top_level(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, tk) in tokens.iter().enumerate() {
+        match tk.kind {
+            TokenType::OpenCurly
+            | TokenType::OpenParenthesis
+            | TokenType::OpenBracket
+            | TokenType::OpenAccessor => depth += 1,
+            TokenType::CloseCurly | TokenType::CloseParenthesis | TokenType::CloseBracket => {
+                depth -= 1
+            }
+            TokenType::Comma if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
     }
 
-    let function_name = tokens.first().unwrap().lex.to_owned();
-    let arguments_range = 2..(tokens.len() - 1);
+    parts
+}
 
-    let arguments_tokens = tokens[arguments_range].to_vec();
-    let arguments = parse_arguments(&arguments_tokens).unwrap().to_box();
+fn find_closing_parenthesis(tokens: &[Token]) -> Result<usize, Error> {
+    find_closing(
+        tokens,
+        TokenType::OpenParenthesis,
+        TokenType::CloseParenthesis,
+    )
+    .map_err(|error| Error {
+        message: String::from("Couldn't find the closing ')'"),
+        ..error
+    })
+}
 
-    let identifier_node = Node::Identifier(function_name).to_box();
+/// Index of the token closing the `open` token at the start of `tokens`.
+fn find_closing(tokens: &[Token], open: TokenType, close: TokenType) -> Result<usize, Error> {
+    let mut depth = 0;
 
-    Ok(ParseMessage(
-        tokens.len(),
-        Node::FunctionCall(identifier_node, arguments),
+    for (i, tk) in tokens.iter().enumerate() {
+        if tk.kind == open {
+            depth += 1;
+        } else if tk.kind == close {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(i);
+            }
+        }
+    }
+
+    let close = match close {
+        TokenType::CloseCurly => "'}'",
+        TokenType::CloseParenthesis => "')'",
+        _ => "']'",
+    };
+    Err(error_at(
+        format!("Couldn't find the closing {close}"),
+        tokens.first(),
     ))
 }
 
-fn parse_arguments(tokens: &Vec<Token>) -> Result<Node, String> {
-    if tokens.len() == 0 {
-        return Ok(Node::Arguments(Vec::<Box<Node>>::new()));
+/// Splits the raw content of a `$"..."` token into literal text and embedded
+/// expressions. `{{` and `}}` stand for literal braces, and `{value:.2}` asks
+/// for `value` to be formatted with two decimal places.
+fn parse_template_string(content: &str) -> Result<Node, Error> {
+    let mut parts: Vec<Box<Node>> = Vec::new();
+    let mut literal = String::new();
+    let mut src = content.to_string();
+
+    while !src.is_empty() {
+        let char = src.remove(0);
+
+        if (char == '{' || char == '}') && src.starts_with(char) {
+            src.remove(0);
+            literal.push(char);
+            continue;
+        }
+
+        if char == '}' {
+            return Err(Error::new(format!(
+                "Unmatched '}}' in template string: \"{content}\""
+            )));
+        }
+
+        if char != '{' {
+            literal.push(char);
+            if char == '\\' && !src.is_empty() {
+                literal.push(src.remove(0));
+            }
+            continue;
+        }
+
+        if !literal.is_empty() {
+            parts.push(Node::StringLiteral(literal.clone()).to_box());
+            literal.clear();
+        }
+
+        let expr_source = take_template_expression(&mut src).ok_or(Error::new(format!(
+            "Unclosed '{{' in template string: \"{content}\""
+        )))?;
+        parts.push(parse_template_expression(&expr_source)?.to_box());
     }
-    println!("hi bro, {:?}", tokens);
-    let mut parenthesis_state = 0;
-    let mut arguments = Vec::<Box<Node>>::new();
-    let mut index = 0;
 
-    for (i, tk) in tokens.iter().enumerate() {
-        parenthesis_state += match tk.kind {
-            TokenType::OpenCurly | TokenType::OpenParenthesis => 1,
-            TokenType::CloseCurly | TokenType::CloseParenthesis => -1,
-            _ => 0,
-        };
+    if !literal.is_empty() {
+        parts.push(Node::StringLiteral(literal).to_box());
+    }
+
+    Ok(Node::TemplateString(parts))
+}
 
-        if parenthesis_state == 0 && (tk.kind == TokenType::Comma || i == tokens.len() - 1) {
-            println!("{} {}", index, i);
-            let expr_end = i + if i == tokens.len() - 1 { 1 } else { 0 };
+fn take_template_expression(src: &mut String) -> Option<String> {
+    let mut expr = String::new();
+    let mut depth = 0;
+
+    while !src.is_empty() {
+        let char = src.remove(0);
+
+        match char {
+            '}' if depth == 0 => return Some(expr),
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' | '$' if char == '"' || src.starts_with('"') => {
+                let is_template = char == '$';
+                expr.push(char);
+                if is_template {
+                    expr.push(src.remove(0));
+                }
+                expr.push_str(&take_string_content(src, is_template).ok()?);
+                expr.push('"');
+                continue;
+            }
+            _ => {}
+        }
+
+        expr.push(char);
+    }
 
-            let expr_msg = parse_expression(&tokens[index..expr_end].to_vec()).unwrap();
-            let expr = expr_msg.1;
+    None
+}
 
-            index = i + 1;
+/// Parses the expression of a `{...}` in a template string. Its tokens are
+/// counted from the start of the expression rather than of the file, so
+/// errors are left to point at the whole template string.
+fn parse_template_expression(source: &str) -> Result<Node, Error> {
+    read_template_expression(source).map_err(|error| Error {
+        span: None,
+        ..error
+    })
+}
 
-            arguments.push(expr.to_box());
+fn read_template_expression(source: &str) -> Result<Node, Error> {
+    let (expr_source, precision) = match find_format_specifier(source) {
+        Some(colon) => {
+            let specifier = source[colon + 1..].trim();
+            let precision = specifier
+                .strip_prefix('.')
+                .and_then(|digits| digits.parse::<usize>().ok())
+                .ok_or(Error::new(format!(
+                    "Invalid format specifier '{specifier}' in template string, expected something like '.2'"
+                )))?;
+
+            (&source[..colon], Some(precision))
         }
+        None => (source, None),
+    };
+
+    let mut tokens = tokenize(expr_source.to_string())?;
+    tokens.pop();
+
+    if tokens.is_empty() {
+        return Err(Error::new("Empty expression inside template string"));
     }
 
-    Ok(Node::Arguments(arguments))
+    let ParseMessage(consumed, expr) = parse_expression(&tokens)?;
+
+    if consumed != tokens.len() {
+        return Err(Error::new(format!(
+            "Unexpected '{}' inside template string expression '{}'",
+            tokens[consumed].lex,
+            expr_source.trim()
+        )));
+    }
+
+    Ok(Node::TemplateExpression(expr.to_box(), precision))
 }
 
-fn parse_return(tokens: &Vec<Token>) -> Result<ParseMessage, String> {
-    let expr_msg = parse_expression(&tokens[1..].to_vec()).unwrap();
-    let consumed = expr_msg.0;
-    let expr = expr_msg.1;
+/// Finds the position of the `:` introducing a format specifier, ignoring
+/// `::` paths and anything nested in brackets or strings.
+fn find_format_specifier(source: &str) -> Option<usize> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let char = chars[i];
+
+        if in_string {
+            if char == '\\' {
+                i += 1;
+            } else if char == '"' {
+                in_string = false;
+            }
+        } else {
+            match char {
+                '"' => in_string = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ':' if depth == 0 => {
+                    let is_path = chars.get(i + 1) == Some(&':') || (i > 0 && chars[i - 1] == ':');
+                    if !is_path {
+                        return Some(source.char_indices().nth(i).unwrap().0);
+                    }
+                }
+                _ => {}
+            }
+        }
 
-    Ok(ParseMessage(
-        consumed + 1,
-        Node::ReturnStatement(expr.to_box()),
-    ))
+        i += 1;
+    }
+
+    None
+}
+
+/// `let`, `const`, `var` and `static` declarations. `var` may declare several
+/// variables at once and leave them without a value.
+fn parse_variable_declaration(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let declaration_kind = tokens[0].kind.clone();
+    let mut declarations: Vec<Box<Node>> = Vec::new();
+    let mut consumed = 1;
+
+    loop {
+        let var_name = match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Identifier => tk.lex.clone(),
+            tk => {
+                return Err(error_at(
+                    format!("Expected a variable name, found {}", found(tk)),
+                    tk,
+                ))
+            }
+        };
+        consumed += 1;
+
+        let mut annotation = None;
+        if tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::Colon)
+        {
+            let ParseMessage(type_len, var_type) = parse_type(&tokens[consumed + 1..])?;
+            annotation = Some(var_type.to_box());
+            consumed += 1 + type_len;
+        }
+
+        let expr_node = match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Equals => {
+                let ParseMessage(expr_len, expr) = parse_expression(&tokens[consumed + 1..])?;
+                consumed += 1 + expr_len;
+                expr
+            }
+            // `var a;` holds undefined until something is assigned to it.
+            _ if declaration_kind == TokenType::Var => Node::Identifier("undefined".to_string()),
+            tk => {
+                return Err(error_at(
+                    format!("Expected '=' after '{var_name}', found {}", found(tk)),
+                    tk,
+                ))
+            }
+        };
+
+        declarations.push(
+            Node::VariableDeclaration(
+                Node::DeclarationType(declaration_kind.clone()).to_box(),
+                var_name,
+                annotation,
+                expr_node.to_box(),
+            )
+            .to_box(),
+        );
+
+        match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Comma && declaration_kind == TokenType::Var => {
+                consumed += 1
+            }
+            _ => break,
+        }
+    }
+
+    let declaration = if declarations.len() == 1 {
+        *declarations.remove(0)
+    } else {
+        Node::DeclarationList(declarations)
+    };
+
+    Ok(ParseMessage(end_statement(tokens, consumed)?, declaration))
+}
+
+/// `globalvar name, other;`
+fn parse_globalvar(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let mut names = Vec::new();
+    let mut consumed = 1;
+
+    loop {
+        names.push(
+            expect_token(tokens, consumed, TokenType::Identifier, "globalvar")?
+                .lex
+                .clone(),
+        );
+        consumed += 1;
+
+        match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Comma => consumed += 1,
+            _ => break,
+        }
+    }
+
+    Ok(ParseMessage(
+        end_statement(tokens, consumed)?,
+        Node::GlobalVarDeclaration(names),
+    ))
+}
+
+/// `#macro NAME value`, where the value is kept as written since macros are
+/// replaced textually by GameMaker.
+fn parse_macro(token: &Token) -> Result<ParseMessage, Error> {
+    let (name, value) = token
+        .lex
+        .split_once(char::is_whitespace)
+        .map(|(name, value)| (name, value.trim()))
+        .unwrap_or((token.lex.as_str(), ""));
+
+    if name.is_empty() {
+        return Err(Error::at("Expected a name after '#macro'", token.span()));
+    }
+
+    Ok(ParseMessage(
+        1,
+        Node::MacroDeclaration(name.to_string(), value.to_string()),
+    ))
+}
+
+/// `enum Name { First, Second = 5 }`
+fn parse_enum(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let name = expect_token(tokens, 1, TokenType::Identifier, "enum")?
+        .lex
+        .clone();
+    expect_token(tokens, 2, TokenType::OpenCurly, "enum")?;
+
+    let close_index = find_closing(&tokens[2..], TokenType::OpenCurly, TokenType::CloseCurly)? + 2;
+    let mut members = Vec::new();
+
+    for member in split_top_level(&tokens[3..close_index]) {
+        let member_name = expect_token(member, 0, TokenType::Identifier, "enum")?
+            .lex
+            .clone();
+
+        let value = match member.get(1) {
+            Some(tk) if tk.kind == TokenType::Equals => {
+                let ParseMessage(consumed, value) = parse_expression(&member[2..])?;
+                if consumed != member.len() - 2 {
+                    let token = &member[2 + consumed];
+                    return Err(Error::at(
+                        format!(
+                            "Unexpected '{}' in the value of '{name}.{member_name}'",
+                            token.lex
+                        ),
+                        token.span(),
+                    ));
+                }
+                Some(value.to_box())
+            }
+            None => None,
+            tk => {
+                return Err(error_at(
+                    format!("Unexpected {} in enum '{name}'", found(tk)),
+                    tk,
+                ))
+            }
+        };
+
+        members.push((member_name, value));
+    }
+
+    Ok(ParseMessage(
+        skip_semilicon(tokens, close_index + 1),
+        Node::EnumDeclaration(name, members),
+    ))
+}
+
+/// `class Name { field: type = value; new(params) { ... } method() { ... } }`
+/// where `new` is the constructor and may call a parent with `: Parent(args)`.
+fn parse_class(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let name = expect_token(tokens, 1, TokenType::Identifier, "a class")?
+        .lex
+        .clone();
+    expect_token(tokens, 2, TokenType::OpenCurly, "a class")?;
+    let close_index = find_closing(&tokens[2..], TokenType::OpenCurly, TokenType::CloseCurly)? + 2;
+
+    let mut members = Vec::new();
+    let mut consumed = 3;
+
+    members.extend(trailing_trivia(&tokens[2]));
+
+    while consumed < close_index {
+        let member_tokens = &tokens[consumed..close_index];
+        members.extend(leading_trivia(&member_tokens[0]));
+
+        if member_tokens[0].kind == TokenType::Semilicon {
+            consumed += 1;
+            continue;
+        }
+
+        let ParseMessage(member_len, member) = match member_tokens.get(1).map(|tk| &tk.kind) {
+            Some(TokenType::OpenParenthesis) => parse_class_method(member_tokens, &name)?,
+            _ => parse_class_field(member_tokens)?,
+        };
+
+        members.push(statement_at(member, &member_tokens[..member_len]));
+        consumed += member_len;
+        members.extend(trailing_trivia(&tokens[consumed - 1]));
+    }
+
+    members.extend(leading_trivia(&tokens[close_index]));
+
+    Ok(ParseMessage(
+        skip_semilicon(tokens, close_index + 1),
+        Node::ClassDeclaration(name, members),
+    ))
+}
+
+fn parse_class_method(tokens: &[Token], class_name: &str) -> Result<ParseMessage, Error> {
+    let SignatureMessage(signature_len, name, params, return_type) =
+        parse_function_signature(tokens)?;
+    let params = params.iter().map(|param| param.clone().to_box()).collect();
+    let mut consumed = signature_len;
+
+    if name != "new" {
+        let BodyMessage(body_len, code, _, is_constructor) =
+            parse_function_body(&tokens[consumed..], &name)?;
+        if is_constructor {
+            return Err(Error::at(
+                format!("The constructor of '{class_name}' is 'new', '{name}' can't be one"),
+                tokens[0].span(),
+            ));
+        }
+
+        let method = Node::FunctionDeclaration(name, params, return_type, code.to_box());
+        return Ok(ParseMessage(consumed + body_len, method));
+    }
+
+    let mut parent = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Colon)
+    {
+        let ParseMessage(call_len, call) = parse_call(&tokens[consumed + 1..])?;
+        if !matches!(call, Node::FunctionCall(_, _)) {
+            return Err(Error::at(
+                format!("Expected a call to the parent class of '{class_name}'"),
+                tokens[consumed + 1]
+                    .span()
+                    .to(tokens[consumed + call_len].span()),
+            ));
+        }
+        parent = Some(call.to_box());
+        consumed += 1 + call_len;
+    }
+
+    let BodyMessage(body_len, code, _, _) = parse_function_body(&tokens[consumed..], &name)?;
+    let constructor = Node::FunctionDeclaration(name, params, return_type, code.to_box());
+
+    Ok(ParseMessage(
+        consumed + body_len,
+        Node::ConstructorDeclaration(constructor.to_box(), parent),
+    ))
+}
+
+/// `name: type = value;` where both the type and the value are optional.
+fn parse_class_field(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let name = expect_token(tokens, 0, TokenType::Identifier, "a class member")?
+        .lex
+        .clone();
+    let mut consumed = 1;
+
+    let mut annotation = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Colon)
+    {
+        let ParseMessage(type_len, field_type) = parse_type(&tokens[consumed + 1..])?;
+        annotation = Some(field_type.to_box());
+        consumed += 1 + type_len;
+    }
+
+    let mut value = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Equals)
+    {
+        let ParseMessage(value_len, expr) = parse_expression(&tokens[consumed + 1..])?;
+        value = Some(expr.to_box());
+        consumed += 1 + value_len;
+    }
+
+    expect_token(tokens, consumed, TokenType::Semilicon, "a class field")?;
+
+    Ok(ParseMessage(
+        consumed + 1,
+        Node::ClassField(name, annotation, value),
+    ))
+}
+
+fn parse_block(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let (close_index, body) = parse_braced_body(tokens)?;
+    let body = match body {
+        Node::Program(body) => body,
+        _ => Vec::new(),
+    };
+
+    Ok(ParseMessage(close_index + 1, Node::Block(body)))
+}
+
+/// The body of an `if`, a loop... which is a block or a single statement.
+fn parse_statement_body(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    if tokens.is_empty() || tokens[0].kind == TokenType::EOF {
+        return Err(error_at(
+            String::from("Expected a statement, found nothing"),
+            tokens.first(),
+        ));
+    }
+
+    match parse_statement(tokens)? {
+        ParseMessage(consumed, Node::Ignore) => Ok(ParseMessage(consumed, Node::Block(Vec::new()))),
+        message => Ok(message),
+    }
+}
+
+fn parse_if(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(condition_len, condition) = parse_condition(&tokens[1..])?;
+    let mut consumed = 1 + condition_len;
+
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Then)
+    {
+        consumed += 1;
+    }
+
+    let ParseMessage(then_len, then) = parse_statement_body(&tokens[consumed..])?;
+    consumed += then_len;
+
+    let mut otherwise = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Else)
+    {
+        let ParseMessage(else_len, else_body) = parse_statement_body(&tokens[consumed + 1..])?;
+        otherwise = Some(else_body.to_box());
+        consumed += 1 + else_len;
+    }
+
+    Ok(ParseMessage(
+        consumed,
+        Node::IfStatement(condition.to_box(), then.to_box(), otherwise),
+    ))
+}
+
+/// `while`, `repeat` and `with`, which are all a value followed by a body.
+fn parse_loop(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(value_len, value) = parse_condition(&tokens[1..])?;
+    let ParseMessage(body_len, body) = parse_statement_body(&tokens[1 + value_len..])?;
+
+    let statement = match tokens[0].kind {
+        TokenType::While => Node::WhileStatement(value.to_box(), body.to_box()),
+        TokenType::Repeat => Node::RepeatStatement(value.to_box(), body.to_box()),
+        _ => Node::WithStatement(value.to_box(), body.to_box()),
+    };
+
+    Ok(ParseMessage(1 + value_len + body_len, statement))
+}
+
+fn parse_do_until(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(body_len, body) = parse_statement_body(&tokens[1..])?;
+    let until_index = 1 + body_len;
+
+    expect_token(tokens, until_index, TokenType::Until, "do")?;
+    let ParseMessage(condition_len, condition) = parse_condition(&tokens[until_index + 1..])?;
+
+    Ok(ParseMessage(
+        end_statement(tokens, until_index + 1 + condition_len)?,
+        Node::DoUntilStatement(body.to_box(), condition.to_box()),
+    ))
+}
+
+/// `for (init; condition; step) body`, where every part can be left empty.
+fn parse_for(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    expect_token(tokens, 1, TokenType::OpenParenthesis, "for")?;
+    let close_index = find_closing_parenthesis(&tokens[1..])? + 1;
+    let header = &tokens[2..close_index];
+
+    // The init is a statement, so it ends with its own `;`.
+    let (mut consumed, init) = match header.first() {
+        Some(tk) if tk.kind == TokenType::Semilicon => (1, None),
+        Some(_) => {
+            let ParseMessage(init_len, init) = parse_statement(header)?;
+            (init_len, Some(init.to_box()))
+        }
+        None => {
+            return Err(Error::at(
+                "Expected 'for (init; condition; step)'",
+                tokens[1].span().to(tokens[close_index].span()),
+            ))
+        }
+    };
+
+    let mut condition = None;
+    if header
+        .get(consumed)
+        .is_some_and(|tk| tk.kind != TokenType::Semilicon)
+    {
+        let ParseMessage(condition_len, value) = parse_condition(&header[consumed..])?;
+        condition = Some(value.to_box());
+        consumed += condition_len;
+    }
+    expect_token(header, consumed, TokenType::Semilicon, "a for loop header")?;
+    consumed += 1;
+
+    let mut step = None;
+    if consumed < header.len() {
+        let ParseMessage(step_len, value) = parse_expression_statement(&header[consumed..])?;
+        if consumed + step_len != header.len() {
+            let token = &header[consumed + step_len];
+            return Err(Error::at(
+                format!("Unexpected '{}' in a for loop header", token.lex),
+                token.span(),
+            ));
+        }
+        step = Some(value.to_box());
+    }
+
+    let ParseMessage(body_len, body) = parse_statement_body(&tokens[close_index + 1..])?;
+
+    Ok(ParseMessage(
+        close_index + 1 + body_len,
+        Node::ForStatement(init, condition, step, body.to_box()),
+    ))
+}
+
+/// `try { ... }` followed by `catch (name) { ... }`, `finally { ... }` or
+/// both.
+fn parse_try(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    expect_token(tokens, 1, TokenType::OpenCurly, "try")?;
+    let ParseMessage(body_len, body) = parse_block(&tokens[1..])?;
+    let mut consumed = 1 + body_len;
+
+    let mut catch = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Catch)
+    {
+        expect_token(tokens, consumed + 1, TokenType::OpenParenthesis, "catch")?;
+        let name = expect_token(tokens, consumed + 2, TokenType::Identifier, "catch")?
+            .lex
+            .clone();
+        expect_token(tokens, consumed + 3, TokenType::CloseParenthesis, "catch")?;
+        expect_token(tokens, consumed + 4, TokenType::OpenCurly, "catch")?;
+        let ParseMessage(handler_len, handler) = parse_block(&tokens[consumed + 4..])?;
+
+        catch = Some((name, handler.to_box()));
+        consumed += 4 + handler_len;
+    }
+
+    let mut finally = None;
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Finally)
+    {
+        expect_token(tokens, consumed + 1, TokenType::OpenCurly, "finally")?;
+        let ParseMessage(finally_len, block) = parse_block(&tokens[consumed + 1..])?;
+
+        finally = Some(block.to_box());
+        consumed += 1 + finally_len;
+    }
+
+    if catch.is_none() && finally.is_none() {
+        return Err(error_at(
+            format!(
+                "Expected 'catch' or 'finally' after the body of 'try', found {}",
+                found(tokens.get(consumed))
+            ),
+            tokens.get(consumed),
+        ));
+    }
+
+    Ok(ParseMessage(
+        consumed,
+        Node::TryStatement(body.to_box(), catch, finally),
+    ))
+}
+
+fn parse_switch(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(value_len, value) = parse_condition(&tokens[1..])?;
+    let open_index = 1 + value_len;
+
+    expect_token(tokens, open_index, TokenType::OpenCurly, "switch")?;
+    let close_index = find_closing(
+        &tokens[open_index..],
+        TokenType::OpenCurly,
+        TokenType::CloseCurly,
+    )? + open_index;
+
+    let body = &tokens[open_index + 1..close_index];
+    let mut cases: Vec<Box<Node>> = Vec::new();
+    let mut consumed = 0;
+
+    // Comments before a label end the previous case, those before the first
+    // one start it.
+    let mut leading = Vec::new();
+    leading.extend(trailing_trivia(&tokens[open_index]));
+
+    while consumed < body.len() {
+        match cases.last_mut().map(|case| case.as_mut()) {
+            Some(Node::SwitchCase(_, statements)) => {
+                statements.extend(leading_trivia(&body[consumed]))
+            }
+            _ => leading.extend(leading_trivia(&body[consumed])),
+        }
+
+        let case_value = match body[consumed].kind {
+            TokenType::Case => {
+                let ParseMessage(case_len, case_value) = parse_expression(&body[consumed + 1..])?;
+                consumed += 1 + case_len;
+                Some(case_value.to_box())
+            }
+            TokenType::Default => {
+                consumed += 1;
+                None
+            }
+            _ => {
+                return Err(Error::at(
+                    format!(
+                        "Expected 'case' or 'default' in a switch, found '{}'",
+                        body[consumed].lex
+                    ),
+                    body[consumed].span(),
+                ))
+            }
+        };
+
+        expect_token(body, consumed, TokenType::Colon, "switch case")?;
+        consumed += 1;
+
+        let mut statements = std::mem::take(&mut leading);
+        statements.extend(trailing_trivia(&body[consumed - 1]));
+
+        while body
+            .get(consumed)
+            .is_some_and(|tk| tk.kind != TokenType::Case && tk.kind != TokenType::Default)
+        {
+            statements.extend(leading_trivia(&body[consumed]));
+            let ParseMessage(stmt_len, stmt) = parse_statement(&body[consumed..])?;
+
+            if stmt != Node::Ignore {
+                statements.push(statement_at(stmt, &body[consumed..consumed + stmt_len]));
+            }
+            consumed += stmt_len;
+            statements.extend(trailing_trivia(&body[consumed - 1]));
+        }
+
+        cases.push(Node::SwitchCase(case_value, statements).to_box());
+    }
+
+    if let Some(Node::SwitchCase(_, statements)) = cases.last_mut().map(|case| case.as_mut()) {
+        statements.extend(leading_trivia(&tokens[close_index]));
+    }
+
+    Ok(ParseMessage(
+        close_index + 1,
+        Node::SwitchStatement(value.to_box(), cases),
+    ))
+}
+
+/// Parses `name(params) -> return_type`, the part shared by function
+/// declarations, extern functions and class methods, starting at the name.
+/// Returns the index right after it. Function expressions may leave the name
+/// out, in which case it is empty, and class constructors are named `new`.
+fn parse_function_signature(tokens: &[Token]) -> Result<SignatureMessage, Error> {
+    let function_name = match tokens.first() {
+        Some(tk) if tk.kind == TokenType::Identifier || tk.kind == TokenType::New => {
+            tk.lex.to_owned()
+        }
+        Some(tk) if tk.kind == TokenType::OpenParenthesis => String::new(),
+        tk => {
+            return Err(error_at(
+                format!("Expected a function name, found {}", found(tk)),
+                tk,
+            ))
+        }
+    };
+    let open_index = if function_name.is_empty() { 0 } else { 1 };
+
+    if tokens.get(open_index).map(|tk| &tk.kind) != Some(&TokenType::OpenParenthesis) {
+        let token = tokens.get(open_index);
+        return Err(error_at(
+            format!(
+                "Expected '(' after the name of '{function_name}', found {}",
+                found(token)
+            ),
+            token,
+        ));
+    }
+
+    let close_index = find_closing_parenthesis(&tokens[open_index..])? + open_index;
+    let params = parse_function_parameters(&tokens[open_index + 1..close_index])?;
+
+    let mut consumed = close_index + 1;
+    let mut return_type = None;
+
+    if tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Arrow)
+    {
+        let ParseMessage(type_len, annotation) = parse_type(&tokens[consumed + 1..])?;
+        return_type = Some(annotation.to_box());
+        consumed += 1 + type_len;
+    }
+
+    Ok(SignatureMessage(
+        consumed,
+        function_name,
+        params,
+        return_type,
+    ))
+}
+
+/// Parses what follows a signature: an optional `: Parent(args)` and
+/// `constructor`, then the body between braces.
+fn parse_function_body(tokens: &[Token], function_name: &str) -> Result<BodyMessage, Error> {
+    let mut consumed = 0;
+    let mut parent = None;
+
+    if tokens.first().is_some_and(|tk| tk.kind == TokenType::Colon) {
+        let ParseMessage(call_len, call) = parse_call(&tokens[1..])?;
+        if !matches!(call, Node::FunctionCall(_, _)) {
+            return Err(Error::at(
+                format!("Expected a call to the parent constructor of '{function_name}'"),
+                tokens[1].span().to(tokens[call_len].span()),
+            ));
+        }
+        parent = Some(call.to_box());
+        consumed += 1 + call_len;
+    }
+
+    let is_constructor = tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::Constructor);
+    if is_constructor {
+        consumed += 1;
+    } else if parent.is_some() {
+        return Err(error_at(
+            format!(
+                "'{function_name}' inherits from another constructor, so it must be a constructor too"
+            ),
+            tokens.get(consumed),
+        ));
+    }
+
+    if tokens.get(consumed).map(|tk| &tk.kind) != Some(&TokenType::OpenCurly) {
+        let token = tokens.get(consumed);
+        return Err(error_at(
+            format!(
+                "Expected '{{' to open the body of '{function_name}', found {}",
+                found(token)
+            ),
+            token,
+        ));
+    }
+
+    let (close_index, function_code) = parse_braced_body(&tokens[consumed..])?;
+    let close_index = close_index + consumed;
+
+    Ok(BodyMessage(
+        close_index + 1,
+        function_code,
+        parent,
+        is_constructor,
+    ))
+}
+
+fn parse_function_declaration(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let SignatureMessage(signature_len, function_name, params, return_type) =
+        parse_function_signature(&tokens[1..])?;
+    let signature_len = signature_len + 1;
+    let BodyMessage(body_len, function_code, parent, is_constructor) =
+        parse_function_body(&tokens[signature_len..], &function_name)?;
+
+    let mut declaration_node = Node::FunctionDeclaration(
+        function_name,
+        params.iter().map(|node| node.clone().to_box()).collect(),
+        return_type,
+        function_code.to_box(),
+    );
+
+    if is_constructor {
+        declaration_node = Node::ConstructorDeclaration(declaration_node.to_box(), parent);
+    }
+
+    Ok(ParseMessage(
+        skip_semilicon(tokens, signature_len + body_len),
+        declaration_node,
+    ))
+}
+
+fn parse_function_parameters(tokens: &[Token]) -> Result<Vec<Node>, Error> {
+    let mut consumed: usize = 0;
+    let mut params: Vec<Node> = Vec::new();
+
+    while consumed < tokens.len() {
+        let is_rest = tokens[consumed].kind == TokenType::Ellipsis;
+        if is_rest {
+            consumed += 1;
+        }
+
+        let name = match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Identifier => tk.lex.to_owned(),
+            tk => {
+                return Err(error_at(
+                    format!("Expected a parameter name, found {}", found(tk)),
+                    tk,
+                ))
+            }
+        };
+        consumed += 1;
+
+        let is_optional = tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::QuestionMark);
+        if is_optional {
+            consumed += 1;
+        }
+
+        let mut annotation = None;
+        if tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::Colon)
+        {
+            let ParseMessage(type_len, param_type) = parse_type(&tokens[consumed + 1..])?;
+            annotation = Some(param_type.to_box());
+            consumed += 1 + type_len;
+        }
+
+        let param = Node::FunctionParameter(name, annotation);
+        let param = if is_rest {
+            Node::RestParameter(param.to_box())
+        } else if is_optional {
+            Node::OptionalParameter(param.to_box())
+        } else {
+            param
+        };
+
+        if tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::Equals)
+        {
+            let ParseMessage(default_len, default) = parse_expression(&tokens[consumed + 1..])?;
+            params.push(Node::DefaultParameter(param.to_box(), default.to_box()));
+            consumed += 1 + default_len;
+        } else {
+            params.push(param);
+        }
+
+        match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Comma => consumed += 1,
+            None => break,
+            tk => {
+                return Err(error_at(
+                    format!("Unexpected {} in parameter list", found(tk)),
+                    tk,
+                ))
+            }
+        }
+    }
+
+    Ok(params)
+}
+
+/// Parses a type annotation: `number`, `DsMap<K, V>`, `T[]` or
+/// `Fn(number)<bool>`.
+fn parse_type(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let name = match tokens.first() {
+        Some(tk) if tk.kind == TokenType::Identifier => tk.lex.clone(),
+        tk => {
+            return Err(error_at(
+                format!("Expected a type, found {}", found(tk)),
+                tk,
+            ))
+        }
+    };
+    let mut consumed = 1;
+
+    let mut annotation = if name == "Fn" {
+        let mut params = Vec::new();
+
+        if tokens
+            .get(1)
+            .is_some_and(|tk| tk.kind == TokenType::OpenParenthesis)
+        {
+            let close_index = find_closing_parenthesis(&tokens[1..])? + 1;
+            params = parse_type_list(&tokens[2..close_index])?;
+            consumed = close_index + 1;
+        }
+
+        let mut return_type = Node::TypeName("void".to_string(), Vec::new());
+        if tokens.get(consumed).is_some_and(|tk| tk.lex == "<") {
+            let (args_len, mut args) = parse_type_arguments(&tokens[consumed..])?;
+            if args.len() != 1 {
+                return Err(Error::at(
+                    format!("Fn types have a single return type, found {}", args.len()),
+                    tokens[consumed]
+                        .span()
+                        .to(tokens[consumed + args_len - 1].span()),
+                ));
+            }
+            return_type = args.remove(0);
+            consumed += args_len;
+        }
+
+        Node::FunctionType(
+            params.iter().map(|param| param.clone().to_box()).collect(),
+            return_type.to_box(),
+        )
+    } else {
+        let mut args = Vec::new();
+
+        if tokens.get(consumed).is_some_and(|tk| tk.lex == "<") {
+            let (args_len, type_args) = parse_type_arguments(&tokens[consumed..])?;
+            args = type_args;
+            consumed += args_len;
+        }
+
+        Node::TypeName(name, args.iter().map(|arg| arg.clone().to_box()).collect())
+    };
+
+    while tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::OpenBracket)
+        && tokens
+            .get(consumed + 1)
+            .is_some_and(|tk| tk.kind == TokenType::CloseBracket)
+    {
+        annotation = Node::TypeName("Array".to_string(), vec![annotation.to_box()]);
+        consumed += 2;
+    }
+
+    Ok(ParseMessage(consumed, annotation))
+}
+
+/// Parses `<A, B>` and returns how many tokens it took.
+fn parse_type_arguments(tokens: &[Token]) -> Result<(usize, Vec<Node>), Error> {
+    let mut depth = 0;
+
+    for (i, tk) in tokens.iter().enumerate() {
+        match tk.lex.as_str() {
+            "<" => depth += 1,
+            ">" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((i + 1, parse_type_list(&tokens[1..i])?));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(error_at(
+        String::from("Couldn't find the closing '>' of the type arguments"),
+        tokens.first(),
+    ))
+}
+
+fn parse_type_list(tokens: &[Token]) -> Result<Vec<Node>, Error> {
+    let mut types = Vec::new();
+    let mut consumed = 0;
+
+    while consumed < tokens.len() {
+        let ParseMessage(type_len, annotation) = parse_type(&tokens[consumed..])?;
+        types.push(annotation);
+        consumed += type_len;
+
+        match tokens.get(consumed) {
+            Some(tk) if tk.kind == TokenType::Comma => consumed += 1,
+            None => break,
+            tk => {
+                return Err(error_at(
+                    format!("Unexpected {} in type list", found(tk)),
+                    tk,
+                ))
+            }
+        }
+    }
+
+    Ok(types)
+}
+
+/// `extern function name(params) -> type;` and `extern let/const name: type;`
+/// declare things that exist at runtime without generating any code.
+fn parse_extern(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let (consumed, declaration) = match tokens.get(1).map(|tk| &tk.kind) {
+        Some(TokenType::Function) => {
+            let SignatureMessage(signature_len, name, params, return_type) =
+                parse_function_signature(&tokens[2..])?;
+            if name.is_empty() {
+                return Err(Error::at("extern functions need a name", tokens[1].span()));
+            }
+            let params = params.iter().map(|param| param.clone().to_box()).collect();
+
+            (
+                signature_len + 2,
+                Node::ExternFunction(name, params, return_type),
+            )
+        }
+        Some(TokenType::Let) | Some(TokenType::Const) => {
+            let declaration_type = Node::DeclarationType(tokens[1].kind.clone());
+            let name = match tokens.get(2) {
+                Some(tk) if tk.kind == TokenType::Identifier => tk.lex.clone(),
+                tk => {
+                    return Err(error_at(
+                        format!("Expected a variable name, found {}", found(tk)),
+                        tk,
+                    ))
+                }
+            };
+
+            if tokens.get(3).map(|tk| &tk.kind) != Some(&TokenType::Colon) {
+                return Err(Error::at(
+                    format!("extern variable '{name}' needs a type annotation"),
+                    tokens[2].span(),
+                ));
+            }
+
+            let ParseMessage(type_len, annotation) = parse_type(&tokens[4..])?;
+
+            (
+                4 + type_len,
+                Node::ExternVariable(declaration_type.to_box(), name, annotation.to_box()),
+            )
+        }
+        _ => {
+            return Err(error_at(
+                format!(
+                    "Expected a function or variable after 'extern', found {}",
+                    found(tokens.get(1))
+                ),
+                tokens.get(1),
+            ))
+        }
+    };
+
+    Ok(ParseMessage(end_statement(tokens, consumed)?, declaration))
+}
+
+fn parse_arguments(tokens: &[Token]) -> Result<Node, Error> {
+    let mut arguments = Vec::<Box<Node>>::new();
+
+    for argument in split_top_level(tokens) {
+        let expr_msg = parse_expression(argument)?;
+        if expr_msg.0 != argument.len() {
+            let token = &argument[expr_msg.0];
+            return Err(Error::at(
+                format!("Unexpected '{}' in the arguments", token.lex),
+                token.span(),
+            ));
+        }
+
+        arguments.push(expr_msg.1.to_box());
+    }
+
+    Ok(Node::Arguments(arguments))
+}
+
+fn parse_return(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let has_value = tokens.get(1).is_some_and(|tk| {
+        !matches!(
+            tk.kind,
+            TokenType::Semilicon | TokenType::CloseCurly | TokenType::EOF
+        )
+    });
+
+    if !has_value {
+        let undefined = Node::Identifier("undefined".to_string());
+        return Ok(ParseMessage(
+            end_statement(tokens, 1)?,
+            Node::ReturnStatement(undefined.to_box()),
+        ));
+    }
+
+    let expr_msg = parse_expression(&tokens[1..])?;
+    let consumed = expr_msg.0;
+    let expr = expr_msg.1;
+
+    Ok(ParseMessage(
+        end_statement(tokens, consumed + 1)?,
+        Node::ReturnStatement(expr.to_box()),
+    ))
+}
+
+fn parse_throw(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, value) = parse_expression(&tokens[1..])?;
+
+    Ok(ParseMessage(
+        end_statement(tokens, consumed + 1)?,
+        Node::ThrowStatement(value.to_box()),
+    ))
+}
+
+/// `delete value;`, where the value must be something that can be assigned.
+fn parse_delete(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, value) = parse_expression(&tokens[1..])?;
+
+    if !matches!(
+        value,
+        Node::Identifier(_) | Node::MemberAccess(_, _) | Node::IndexAccess(_, _, _)
+    ) {
+        return Err(Error::at(
+            "Only variables, fields and indexes can be deleted",
+            tokens[1].span().to(tokens[consumed].span()),
+        ));
+    }
+
+    Ok(ParseMessage(
+        end_statement(tokens, consumed + 1)?,
+        Node::DeleteStatement(value.to_box()),
+    ))
+}
+
+fn parse_import(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let expect = |index: usize, kind: TokenType| expect_token(tokens, index, kind, "an import");
+
+    expect(1, TokenType::OpenCurly)?;
+
+    let mut names: Vec<String> = Vec::new();
+    let mut consumed = 2;
+
+    loop {
+        names.push(expect(consumed, TokenType::Identifier)?.lex.clone());
+        consumed += 1;
+
+        if tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::Comma)
+        {
+            consumed += 1;
+        } else {
+            break;
+        }
+    }
+
+    expect(consumed, TokenType::CloseCurly)?;
+    // `from` is only a keyword here, so it stays usable as a name elsewhere.
+    if expect(consumed + 1, TokenType::Identifier)?.lex != "from" {
+        let token = &tokens[consumed + 1];
+        return Err(Error::at(
+            format!("Expected 'from' in an import, found '{}'", token.lex),
+            token.span(),
+        ));
+    }
+    let module = expect(consumed + 2, TokenType::StringLiteral)?.lex.clone();
+    expect(consumed + 3, TokenType::Semilicon)?;
+
+    Ok(ParseMessage(
+        consumed + 4,
+        Node::ImportDeclaration(names, module),
+    ))
+}
+
+fn parse_export(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let ParseMessage(consumed, declaration) = match tokens.get(1).map(|tk| &tk.kind) {
+        Some(TokenType::Let)
+        | Some(TokenType::Const)
+        | Some(TokenType::Function)
+        | Some(TokenType::Enum)
+        | Some(TokenType::Macro)
+        | Some(TokenType::Class) => parse_statement(&tokens[1..])?,
+        _ => {
+            return Err(error_at(
+                format!(
+                    "Only declarations can be exported, found {}",
+                    found(tokens.get(1))
+                ),
+                tokens.get(1),
+            ))
+        }
+    };
+
+    Ok(ParseMessage(
+        consumed + 1,
+        Node::ExportDeclaration(declaration.to_box()),
+    ))
+}
+
+/// `@name` or `@name(args)`, any number of times before a declaration. Which
+/// attributes exist and where they go is checked by `attributes::validate`.
+fn parse_attributed(tokens: &[Token]) -> Result<ParseMessage, Error> {
+    let mut attributes: Vec<Box<Node>> = Vec::new();
+    let mut consumed = 0;
+
+    while tokens
+        .get(consumed)
+        .is_some_and(|tk| tk.kind == TokenType::At)
+    {
+        let name = match tokens.get(consumed + 1) {
+            Some(tk) if tk.kind == TokenType::Identifier => tk.lex.clone(),
+            tk => {
+                return Err(error_at(
+                    format!(
+                        "Expected the name of an attribute after '@', found {}",
+                        found(tk)
+                    ),
+                    tk,
+                ))
+            }
+        };
+        consumed += 2;
+
+        let mut args = Vec::new();
+        if tokens
+            .get(consumed)
+            .is_some_and(|tk| tk.kind == TokenType::OpenParenthesis)
+        {
+            let close_index = consumed + find_closing_parenthesis(&tokens[consumed..])?;
+            if let Node::Arguments(arguments) = parse_arguments(&tokens[consumed + 1..close_index])?
+            {
+                args = arguments;
+            }
+            consumed = close_index + 1;
+        }
+
+        attributes.push(Node::Attribute(name, args).to_box());
+    }
+
+    if consumed >= tokens.len() {
+        return Err(error_at(
+            String::from("Expected a declaration after the attributes"),
+            tokens.last(),
+        ));
+    }
+    let ParseMessage(declaration_len, declaration) = parse_statement(&tokens[consumed..])?;
+    if declaration.declared_name().is_none() {
+        let token = &tokens[consumed];
+        return Err(Error::at(
+            format!(
+                "Attributes can only be written before a declaration, found '{}'",
+                token.lex
+            ),
+            token.span(),
+        ));
+    }
+
+    Ok(ParseMessage(
+        consumed + declaration_len,
+        Node::Attributed(attributes, declaration.to_box()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tokenizer::{tokenize_as, Language};
+
+    fn parse_code(code: &str, language: Language) -> Result<Vec<Node>, Error> {
+        match tokenize_as(code.to_string(), language)
+            .and_then(|tokens| parse_as(tokens, language))?
+        {
+            Node::Program(body) => Ok(body.into_iter().map(|stmt| *stmt).collect()),
+            program => panic!("Expected a program, found {program:?}"),
+        }
+    }
+
+    #[test]
+    fn statements_end_with_a_semicolon() {
+        let error = parse_code("let y = 2\nfoo()", Language::GmlPlusPlus).unwrap_err();
+        assert_eq!(
+            error,
+            Error::at(
+                "Expected ';' at the end of the statement, found 'foo'",
+                Span::new(10, 13)
+            )
+        );
+        assert!(parse_code("x = 1", Language::GmlPlusPlus).is_err());
+        assert!(parse_code("function f() {} let a = 1;", Language::GmlPlusPlus).is_ok());
+        assert!(parse_code("function f() { return 1 }", Language::GmlPlusPlus).is_ok());
+    }
+
+    #[test]
+    fn gml_semicolons_are_optional() {
+        let body = parse_code(
+            "function f(a, b) {\n    if a == b then a = 1 else b = 2\n    return \"bob\"\n}\nx = f(1, 2) y = 3",
+            Language::Gml,
+        )
+        .unwrap();
+        assert_eq!(body.len(), 3);
+        let Node::FunctionDeclaration(_, _, _, code) = &body[0] else {
+            panic!("Expected a function, found {:?}", body[0]);
+        };
+        let Node::Program(stmts) = code.as_ref() else {
+            panic!("Expected a body, found {code:?}");
+        };
+        assert!(matches!(
+            stmts[0].as_ref(),
+            Node::IfStatement(_, _, Some(otherwise))
+                if matches!(otherwise.as_ref(), Node::Assignment(_, _, _))
+        ));
+        assert!(matches!(stmts[1].as_ref(), Node::ReturnStatement(_)));
+    }
+
+    #[test]
+    fn delete_takes_a_variable() {
+        let body = parse_code("delete s;\ndelete list[0]", Language::Gml).unwrap();
+        assert!(matches!(
+            body.as_slice(),
+            [Node::DeleteStatement(_), Node::DeleteStatement(_)]
+        ));
+
+        let error = parse_code("delete f();", Language::GmlPlusPlus).unwrap_err();
+        assert_eq!(
+            error,
+            Error::at(
+                "Only variables, fields and indexes can be deleted",
+                Span::new(7, 10)
+            )
+        );
+    }
+
+    #[test]
+    fn operators_are_not_operands() {
+        let error = parse_code("let x = 1 < >;", Language::GmlPlusPlus).unwrap_err();
+        assert_eq!(
+            error,
+            Error::at("Expected an expression, found '>'", Span::new(12, 13))
+        );
+        assert!(parse_code("let x = 1 +;", Language::GmlPlusPlus).is_err());
+    }
+
+    #[test]
+    fn try_catch_finally_and_throw() {
+        let body = parse_code(
+            "try { x = 1; } catch (e) { throw e; } finally { y = 2; }",
+            Language::Gml,
+        )
+        .unwrap();
+        let [stmt] = body.as_slice() else {
+            panic!("Expected one statement, found {body:?}");
+        };
+        let Node::TryStatement(_, Some((name, handler)), Some(_)) = stmt else {
+            panic!("Expected a try statement, found {stmt:?}");
+        };
+        assert_eq!(name, "e");
+        assert!(matches!(
+            handler.as_ref(),
+            Node::Block(stmts) if matches!(stmts[0].as_ref(), Node::ThrowStatement(_))
+        ));
+
+        assert!(parse_code("try { x = 1; }", Language::Gml).is_err());
+    }
+
+    #[test]
+    fn then_and_the_old_not_equal() {
+        let body = parse_code(
+            "if i == 10 then show_debug_message(\"ten\");\nvar b = i <> j;",
+            Language::Gml,
+        )
+        .unwrap();
+        assert_eq!(body.len(), 2);
+        assert!(matches!(&body[0], Node::IfStatement(_, _, None)));
+
+        let Node::VariableDeclaration(_, _, _, value) = &body[1] else {
+            panic!("Expected a variable, found {:?}", body[1]);
+        };
+        let Node::BinaryExpression(_, operator, _) = value.as_ref() else {
+            panic!("Expected a comparison, found {value:?}");
+        };
+        assert!(matches!(operator.as_ref(), Node::BinaryOperator(op) if op == "!="));
+    }
 }
@@ -0,0 +1,793 @@
+use crate::compiler::ast::Node;
+use crate::compiler::codegen::operator_precedence;
+use crate::compiler::tokenizer::TokenType;
+use crate::front_end::get_indent;
+use std::cell::Cell;
+
+/// Layout of printed GML++ source.
+pub struct FormatOptions {
+    pub indent_width: usize,
+    /// Argument lists going past this column are split one argument per line.
+    pub max_width: usize,
+}
+
+impl FormatOptions {
+    pub fn new(indent_width: usize, max_width: usize) -> FormatOptions {
+        FormatOptions {
+            indent_width,
+            max_width,
+        }
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions::new(4, 100)
+    }
+}
+
+/// Prints a program back as GML++ source, with annotations, classes and
+/// arrow functions kept as they are instead of being lowered.
+pub fn print_program(program: &Node, options: &FormatOptions) -> Result<String, String> {
+    let body = match program {
+        Node::Program(body) => body,
+        _ => return Err(format!("Expected a program, found: {:?}", program)),
+    };
+
+    let printer = Printer {
+        options,
+        overflowing: Cell::new(false),
+        call_depth: Cell::new(0),
+    };
+    let lines = printer.print_lines(body, 0, Printer::print_statement)?;
+    if lines.is_empty() {
+        return Ok(String::new());
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+fn is_spaced(node: &Node) -> bool {
+    match node {
+        Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+            is_spaced(declaration)
+        }
+        Node::FunctionDeclaration(_, _, _, _)
+        | Node::ConstructorDeclaration(_, _)
+        | Node::ClassDeclaration(_, _)
+        | Node::EnumDeclaration(_, _) => true,
+        _ => false,
+    }
+}
+
+struct Printer<'a> {
+    options: &'a FormatOptions,
+    /// Set while reprinting a statement whose first line was too wide.
+    overflowing: Cell<bool>,
+    /// Calls being printed around the current expression.
+    call_depth: Cell<usize>,
+}
+
+impl Printer<'_> {
+    fn indent(&self, level: usize) -> String {
+        get_indent(self.options.indent_width, level)
+    }
+
+    /// Prints a list of statements or class members, attaching trailing
+    /// comments to the line before them and keeping at most one blank line.
+    fn print_lines(
+        &self,
+        nodes: &[Box<Node>],
+        indent: usize,
+        print_node: fn(&Self, &Node, usize) -> Result<String, String>,
+    ) -> Result<Vec<String>, String> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut previous: Option<&Node> = None;
+        let overflowing = self.overflowing.replace(false);
+        let call_depth = self.call_depth.replace(0);
+
+        for (i, node) in nodes.iter().enumerate() {
+            match node.as_ref() {
+                Node::Comment(text, true) if !lines.is_empty() => {
+                    let last = lines.len() - 1;
+                    lines[last].push(' ');
+                    lines[last].push_str(text);
+                    continue;
+                }
+                Node::BlankLine => {
+                    if lines.last().is_some_and(|line| !line.is_empty()) {
+                        lines.push(String::new());
+                    }
+                    previous = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            // Functions, classes and enums are kept apart from their neighbours,
+            // along with the comments right before them.
+            let next = nodes[i..]
+                .iter()
+                .find(|node| !matches!(node.as_ref(), Node::Comment(_, _) | Node::BlankLine))
+                .unwrap_or(node);
+            if previous.is_some_and(|previous| is_spaced(previous) || is_spaced(next)) {
+                lines.push(String::new());
+            }
+
+            // Calls don't know what is printed before them on the line, so the
+            // outermost one is split when the statement turns out too wide.
+            let mut code = print_node(self, node, indent)?;
+            if code
+                .lines()
+                .next()
+                .is_some_and(|line| line.len() > self.options.max_width)
+            {
+                self.overflowing.set(true);
+                code = print_node(self, node, indent)?;
+                self.overflowing.set(false);
+            }
+            lines.push(code);
+            previous = match node.as_ref() {
+                Node::Comment(_, _) => None,
+                node => Some(node),
+            };
+        }
+
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+
+        self.overflowing.set(overflowing);
+        self.call_depth.set(call_depth);
+
+        Ok(lines)
+    }
+
+    fn print_statement(&self, node: &Node, indent: usize) -> Result<String, String> {
+        let indent_space = self.indent(indent);
+
+        let code = match node {
+            Node::VariableDeclaration(_, _, _, _) => {
+                format!(
+                    "{indent_space}{};",
+                    self.print_declaration(node, true, indent)?
+                )
+            }
+            Node::DeclarationList(declarations) => {
+                let mut items = Vec::new();
+                for (i, declaration) in declarations.iter().enumerate() {
+                    items.push(self.print_declaration(declaration, i == 0, indent)?);
+                }
+                format!("{indent_space}{};", items.join(", "))
+            }
+            Node::GlobalVarDeclaration(names) => {
+                return Err(format!(
+                    "'globalvar {}' has no GML++ equivalent, use 'global.' instead",
+                    names.join(", ")
+                ))
+            }
+            Node::MacroDeclaration(name, value) => format!("{indent_space}#macro {name} {value}"),
+            Node::EnumDeclaration(name, members) => {
+                let member_indent = self.indent(indent + 1);
+                let mut lines = Vec::new();
+
+                for (member, value) in members {
+                    lines.push(match value {
+                        Some(value) => format!(
+                            "{member_indent}{member} = {}",
+                            self.print_expression(value, indent + 1)?
+                        ),
+                        None => format!("{member_indent}{member}"),
+                    });
+                }
+
+                format!(
+                    "{indent_space}enum {name} {{\n{}\n{indent_space}}}",
+                    lines.join(",\n")
+                )
+            }
+            Node::FunctionDeclaration(name, params, return_type, code) => format!(
+                "{indent_space}function {}",
+                self.print_function(name, params, return_type, code, "", indent)?
+            ),
+            Node::ConstructorDeclaration(_, _) => {
+                format!("{indent_space}{}", self.print_expression(node, indent)?)
+            }
+            Node::ClassDeclaration(name, members) => self.print_class(name, members, indent)?,
+            Node::ReturnStatement(expr) if **expr == Node::Identifier("undefined".to_string()) => {
+                format!("{indent_space}return;")
+            }
+            Node::ReturnStatement(expr) => {
+                format!(
+                    "{indent_space}return {};",
+                    self.print_expression(expr, indent)?
+                )
+            }
+            Node::ImportDeclaration(names, module) => format!(
+                "{indent_space}import {{ {} }} from \"{module}\";",
+                names.join(", ")
+            ),
+            Node::ExportDeclaration(declaration) => {
+                let declaration = self.print_statement(declaration, indent)?;
+                format!("{indent_space}export {}", declaration.trim_start())
+            }
+            Node::Attributed(attributes, declaration) => {
+                let mut lines = Vec::new();
+                for attribute in attributes {
+                    match attribute.as_ref() {
+                        Node::Attribute(name, args) if args.is_empty() => {
+                            lines.push(format!("{indent_space}@{name}"));
+                        }
+                        Node::Attribute(name, args) => {
+                            let args = args
+                                .iter()
+                                .map(|arg| self.print_expression(arg, indent))
+                                .collect::<Result<Vec<String>, String>>()?;
+                            lines.push(format!("{indent_space}@{name}({})", args.join(", ")));
+                        }
+                        _ => {}
+                    }
+                }
+                lines.push(self.print_statement(declaration, indent)?);
+                lines.join("\n")
+            }
+            Node::ExternFunction(name, params, return_type) => format!(
+                "{indent_space}extern function {name}({}){};",
+                self.print_parameters(params, indent)?,
+                print_return_type(return_type)
+            ),
+            Node::ExternVariable(declaration_type, name, annotation) => format!(
+                "{indent_space}extern {} {name}: {};",
+                declaration_keyword(declaration_type),
+                annotation.display_type()
+            ),
+            Node::Assignment(target, operator, value) => format!(
+                "{indent_space}{} {operator} {};",
+                self.print_expression(target, indent)?,
+                self.print_expression(value, indent)?
+            ),
+            Node::Block(_) => format!("{indent_space}{}", self.print_body(node, indent)?),
+            Node::IfStatement(condition, then, otherwise) => {
+                let mut code = format!(
+                    "{indent_space}if ({}) {}",
+                    self.print_expression(condition, indent)?,
+                    self.print_body(then, indent)?
+                );
+
+                match otherwise.as_deref() {
+                    Some(otherwise @ Node::IfStatement(_, _, _)) => {
+                        let else_if = self.print_statement(otherwise, indent)?;
+                        code.push_str(&format!(" else {}", else_if.trim_start()));
+                    }
+                    Some(otherwise) => {
+                        code.push_str(&format!(" else {}", self.print_body(otherwise, indent)?));
+                    }
+                    None => {}
+                }
+
+                code
+            }
+            Node::WhileStatement(value, body)
+            | Node::RepeatStatement(value, body)
+            | Node::WithStatement(value, body) => {
+                let keyword = match node {
+                    Node::WhileStatement(_, _) => "while",
+                    Node::RepeatStatement(_, _) => "repeat",
+                    _ => "with",
+                };
+
+                format!(
+                    "{indent_space}{keyword} ({}) {}",
+                    self.print_expression(value, indent)?,
+                    self.print_body(body, indent)?
+                )
+            }
+            Node::DoUntilStatement(body, condition) => format!(
+                "{indent_space}do {} until ({});",
+                self.print_body(body, indent)?,
+                self.print_expression(condition, indent)?
+            ),
+            Node::ForStatement(init, condition, step, body) => {
+                let mut header = Vec::new();
+
+                for (part, is_condition) in [(init, false), (condition, true), (step, false)] {
+                    header.push(match part {
+                        Some(part) if is_condition => self.print_expression(part, indent)?,
+                        Some(part) => self
+                            .print_statement(part, 0)?
+                            .trim_end_matches(';')
+                            .to_string(),
+                        None => String::new(),
+                    });
+                }
+
+                format!(
+                    "{indent_space}for ({}) {}",
+                    header.join("; ").trim_end(),
+                    self.print_body(body, indent)?
+                )
+            }
+            Node::SwitchStatement(value, cases) => {
+                let case_indent = self.indent(indent + 1);
+                let mut code = format!(
+                    "{indent_space}switch ({}) {{\n",
+                    self.print_expression(value, indent)?
+                );
+
+                for case in cases {
+                    if let Node::SwitchCase(value, body) = case.as_ref() {
+                        match value {
+                            Some(value) => code.push_str(&format!(
+                                "{case_indent}case {}:\n",
+                                self.print_expression(value, indent + 1)?
+                            )),
+                            None => code.push_str(&format!("{case_indent}default:\n")),
+                        }
+
+                        for line in self.print_lines(body, indent + 2, Self::print_statement)? {
+                            code.push_str(&line);
+                            code.push('\n');
+                        }
+                    }
+                }
+
+                code.push_str(&format!("{indent_space}}}"));
+                code
+            }
+            Node::TryStatement(body, catch, finally) => {
+                let mut code = format!("{indent_space}try {}", self.print_body(body, indent)?);
+
+                if let Some((name, handler)) = catch {
+                    code.push_str(&format!(
+                        " catch ({name}) {}",
+                        self.print_body(handler, indent)?
+                    ));
+                }
+                if let Some(finally) = finally {
+                    code.push_str(&format!(" finally {}", self.print_body(finally, indent)?));
+                }
+
+                code
+            }
+            Node::ThrowStatement(value) => format!(
+                "{indent_space}throw {};",
+                self.print_expression(value, indent)?
+            ),
+            Node::DeleteStatement(value) => format!(
+                "{indent_space}delete {};",
+                self.print_expression(value, indent)?
+            ),
+            Node::Comment(text, _) => format!("{indent_space}{text}"),
+            Node::BlankLine => String::new(),
+            Node::Break => format!("{indent_space}break;"),
+            Node::Continue => format!("{indent_space}continue;"),
+            Node::Exit => format!("{indent_space}exit;"),
+            expr => format!("{indent_space}{};", self.print_expression(expr, indent)?),
+        };
+
+        Ok(code)
+    }
+
+    /// `let name: type = value`, without the keyword for the rest of a list.
+    fn print_declaration(
+        &self,
+        node: &Node,
+        with_keyword: bool,
+        indent: usize,
+    ) -> Result<String, String> {
+        let (declaration_type, name, annotation, expr) = match node {
+            Node::VariableDeclaration(declaration_type, name, annotation, expr) => {
+                (declaration_type, name, annotation, expr)
+            }
+            _ => return Err(format!("Invalid variable declaration: {:?}", node)),
+        };
+
+        let keyword = declaration_keyword(declaration_type);
+        let mut code = if with_keyword {
+            format!("{keyword} {name}")
+        } else {
+            name.clone()
+        };
+
+        if let Some(annotation) = annotation {
+            code.push_str(&format!(": {}", annotation.display_type()));
+        }
+
+        let is_undefined = **expr == Node::Identifier("undefined".to_string());
+        if !(keyword == "var" && is_undefined && annotation.is_none()) {
+            code.push_str(&format!(" = {}", self.print_expression(expr, indent)?));
+        }
+
+        Ok(code)
+    }
+
+    /// Fields first, then `new` and the methods, separated by blank lines.
+    fn print_class(
+        &self,
+        name: &str,
+        members: &[Box<Node>],
+        indent: usize,
+    ) -> Result<String, String> {
+        let indent_space = self.indent(indent);
+        let lines = self.print_lines(members, indent + 1, Self::print_member)?;
+
+        if lines.is_empty() {
+            return Ok(format!("{indent_space}class {name} {{}}"));
+        }
+
+        Ok(format!(
+            "{indent_space}class {name} {{\n{}\n{indent_space}}}",
+            lines.join("\n")
+        ))
+    }
+
+    fn print_member(&self, member: &Node, indent: usize) -> Result<String, String> {
+        let indent_space = self.indent(indent);
+
+        match member {
+            Node::ClassField(field, annotation, value) => {
+                let mut code = format!("{indent_space}{field}");
+                if let Some(annotation) = annotation {
+                    code.push_str(&format!(": {}", annotation.display_type()));
+                }
+                if let Some(value) = value {
+                    code.push_str(&format!(" = {}", self.print_expression(value, indent)?));
+                }
+                code.push(';');
+                Ok(code)
+            }
+            Node::ConstructorDeclaration(constructor, parent) => match constructor.as_ref() {
+                Node::FunctionDeclaration(_, params, _, code) => {
+                    let suffix = match parent {
+                        Some(parent) => format!(" : {}", self.print_expression(parent, indent)?),
+                        None => String::new(),
+                    };
+                    Ok(format!(
+                        "{indent_space}{}",
+                        self.print_function("new", params, &None, code, &suffix, indent)?
+                    ))
+                }
+                _ => Err(format!("Invalid constructor: {:?}", constructor)),
+            },
+            Node::FunctionDeclaration(method, params, return_type, code) => Ok(format!(
+                "{indent_space}{}",
+                self.print_function(method, params, return_type, code, "", indent)?
+            )),
+            Node::Comment(text, _) => Ok(format!("{indent_space}{text}")),
+            _ => Err(format!("Invalid class member: {:?}", member)),
+        }
+    }
+
+    /// `name(params) -> type suffix { body }`, the name being empty for function
+    /// expressions.
+    fn print_function(
+        &self,
+        name: &str,
+        params: &[Box<Node>],
+        return_type: &Option<Box<Node>>,
+        code: &Node,
+        suffix: &str,
+        indent: usize,
+    ) -> Result<String, String> {
+        Ok(format!(
+            "{name}({}){}{suffix} {}",
+            self.print_parameters(params, indent)?,
+            print_return_type(return_type),
+            self.print_body(code, indent)?
+        ))
+    }
+
+    fn print_parameters(&self, params: &[Box<Node>], indent: usize) -> Result<String, String> {
+        let mut codes = Vec::new();
+        for param in params {
+            codes.push(self.print_parameter(param, indent)?);
+        }
+
+        Ok(codes.join(", "))
+    }
+
+    fn print_parameter(&self, param: &Node, indent: usize) -> Result<String, String> {
+        let code = match param {
+            Node::FunctionParameter(name, Some(annotation)) => {
+                format!("{name}: {}", annotation.display_type())
+            }
+            Node::FunctionParameter(name, None) => name.clone(),
+            Node::OptionalParameter(param) => match param.as_ref() {
+                Node::FunctionParameter(name, Some(annotation)) => {
+                    format!("{name}?: {}", annotation.display_type())
+                }
+                Node::FunctionParameter(name, None) => format!("{name}?"),
+                _ => return Err(format!("Invalid optional parameter: {:?}", param)),
+            },
+            Node::RestParameter(param) => format!("...{}", self.print_parameter(param, indent)?),
+            Node::DefaultParameter(param, default) => format!(
+                "{} = {}",
+                self.print_parameter(param, indent)?,
+                self.print_expression(default, indent)?
+            ),
+            _ => return Err(format!("Invalid function parameter: {:?}", param)),
+        };
+
+        Ok(code)
+    }
+
+    /// Prints the body of a function, an `if` or a loop between braces, with the
+    /// closing brace at `indent`.
+    fn print_body(&self, body: &Node, indent: usize) -> Result<String, String> {
+        let statements = match body {
+            Node::Block(statements) | Node::Program(statements) => statements.clone(),
+            stmt => vec![stmt.clone().to_box()],
+        };
+
+        let lines = self.print_lines(&statements, indent + 1, Self::print_statement)?;
+        if lines.is_empty() {
+            return Ok("{}".to_string());
+        }
+
+        Ok(format!(
+            "{{\n{}\n{}}}",
+            lines.join("\n"),
+            self.indent(indent)
+        ))
+    }
+
+    /// Prints an expression, where `indent` is the indentation of the line it
+    /// starts on, used by the bodies of function expressions.
+    fn print_expression(&self, node: &Node, indent: usize) -> Result<String, String> {
+        let code = match node {
+            Node::NumericLiteral(_, raw) => raw.clone(),
+            Node::StringLiteral(txt) => format!("\"{txt}\""),
+            Node::BooleanLiteral(value) => value.to_string(),
+            Node::Identifier(id) | Node::BinaryOperator(id) => id.clone(),
+            Node::TemplateString(parts) => self.print_template(parts, indent)?,
+            Node::BinaryExpression(left, operator, right) => {
+                let op = self.print_expression(operator, indent)?;
+                format!(
+                    "{} {op} {}",
+                    self.print_operand(left, &op, false, indent)?,
+                    self.print_operand(right, &op, true, indent)?
+                )
+            }
+            Node::UnaryExpression(operator, operand) => {
+                format!("{operator}{}", self.print_postfix_operand(operand, indent)?)
+            }
+            Node::UpdateExpression(operator, target, true) => {
+                format!("{operator}{}", self.print_postfix_operand(target, indent)?)
+            }
+            Node::UpdateExpression(operator, target, false) => {
+                format!("{}{operator}", self.print_postfix_operand(target, indent)?)
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => format!(
+                "{} ? {} : {}",
+                self.print_operand(condition, "?", false, indent)?,
+                self.print_operand(if_true, "?", false, indent)?,
+                self.print_operand(if_false, "?", false, indent)?
+            ),
+            Node::MemberAccess(object, member) => {
+                format!("{}.{member}", self.print_postfix_operand(object, indent)?)
+            }
+            Node::IndexAccess(value, accessor, indices) => {
+                let mut index_codes = Vec::new();
+                for index in indices {
+                    index_codes.push(self.print_expression(index, indent)?);
+                }
+
+                let accessor = match accessor {
+                    Some(accessor) => format!("{accessor} "),
+                    None => String::new(),
+                };
+
+                format!(
+                    "{}[{accessor}{}]",
+                    self.print_postfix_operand(value, indent)?,
+                    index_codes.join(", ")
+                )
+            }
+            Node::ArrayLiteral(items) => {
+                let mut item_codes = Vec::new();
+                for item in items {
+                    item_codes.push(self.print_expression(item, indent)?);
+                }
+                format!("[{}]", item_codes.join(", "))
+            }
+            Node::StructLiteral(fields) if fields.is_empty() => "{}".to_string(),
+            Node::StructLiteral(fields) => {
+                let mut field_codes = Vec::new();
+                for (name, value) in fields {
+                    field_codes.push(format!(
+                        "{name}: {}",
+                        self.print_expression(value, indent + 1)?
+                    ));
+                }
+
+                // Fields holding functions get a line each.
+                if field_codes.iter().any(|code| code.contains('\n')) {
+                    let field_indent = self.indent(indent + 1);
+                    format!(
+                        "{{\n{field_indent}{}\n{}}}",
+                        field_codes.join(&format!(",\n{field_indent}")),
+                        self.indent(indent)
+                    )
+                } else {
+                    format!("{{ {} }}", field_codes.join(", "))
+                }
+            }
+            Node::FunctionExpression(params, code) => {
+                format!(
+                    "function{}",
+                    self.print_function("", params, &None, code, "", indent)?
+                )
+            }
+            Node::ArrowFunction(params, return_type, code) => match code.as_ref() {
+                Node::Program(_) => format!(
+                    "fn{}",
+                    self.print_function("", params, return_type, code, "", indent)?
+                ),
+                expr => format!(
+                    "fn({}){} => {}",
+                    self.print_parameters(params, indent)?,
+                    print_return_type(return_type),
+                    self.print_expression(expr, indent)?
+                ),
+            },
+            Node::ConstructorDeclaration(function, parent) => {
+                let suffix = match parent {
+                    Some(parent) => {
+                        format!(" : {} constructor", self.print_expression(parent, indent)?)
+                    }
+                    None => " constructor".to_string(),
+                };
+
+                match function.as_ref() {
+                    Node::FunctionDeclaration(name, params, return_type, code) => format!(
+                        "function {}",
+                        self.print_function(name, params, return_type, code, &suffix, indent)?
+                    ),
+                    Node::FunctionExpression(params, code) => format!(
+                        "function{}",
+                        self.print_function("", params, &None, code, &suffix, indent)?
+                    ),
+                    _ => return Err(format!("Invalid constructor: {:?}", function)),
+                }
+            }
+            Node::NewExpression(call) => format!("new {}", self.print_expression(call, indent)?),
+            Node::FunctionCall(callee, args) => {
+                let call_depth = self.call_depth.get();
+                self.call_depth.set(call_depth + 1);
+                let mut arg_codes = Vec::new();
+                if let Node::Arguments(args) = args.as_ref() {
+                    for arg in args {
+                        arg_codes.push(self.print_expression(arg, indent)?);
+                    }
+                }
+
+                let callee = self.print_postfix_operand(callee, indent)?;
+                self.call_depth.set(call_depth);
+                let one_line = format!("{callee}({})", arg_codes.join(", "));
+                let width = self.indent(indent).len() + one_line.lines().next().unwrap_or("").len();
+
+                // A trailing callback or struct is kept hugging the parentheses.
+                let hugged = match args.as_ref() {
+                    Node::Arguments(args) => args.last().is_some_and(|arg| {
+                        matches!(
+                            arg.as_ref(),
+                            Node::FunctionExpression(_, _)
+                                | Node::ArrowFunction(_, _, _)
+                                | Node::StructLiteral(_)
+                        )
+                    }),
+                    _ => true,
+                };
+
+                let too_wide =
+                    width > self.options.max_width || (call_depth == 0 && self.overflowing.get());
+                if !too_wide || arg_codes.is_empty() || hugged {
+                    one_line
+                } else {
+                    let inner = self.indent(indent + 1);
+                    let mut lines = Vec::new();
+                    self.call_depth.set(call_depth + 1);
+                    if let Node::Arguments(args) = args.as_ref() {
+                        for arg in args {
+                            lines.push(format!(
+                                "{inner}{}",
+                                self.print_expression(arg, indent + 1)?
+                            ));
+                        }
+                    }
+                    self.call_depth.set(call_depth);
+                    format!("{callee}(\n{}\n{})", lines.join(",\n"), self.indent(indent))
+                }
+            }
+            _ => return Err(format!("Can't print GML++ for node: {:?}", node)),
+        };
+
+        Ok(code)
+    }
+
+    fn print_template(&self, parts: &[Box<Node>], indent: usize) -> Result<String, String> {
+        let mut code = String::from("$\"");
+
+        for part in parts {
+            match part.as_ref() {
+                Node::StringLiteral(txt) => {
+                    code.push_str(&txt.replace('{', "{{").replace('}', "}}"))
+                }
+                Node::TemplateExpression(expr, precision) => {
+                    code.push('{');
+                    code.push_str(&self.print_expression(expr, indent)?);
+                    if let Some(precision) = precision {
+                        code.push_str(&format!(":.{precision}"));
+                    }
+                    code.push('}');
+                }
+                _ => return Err(format!("Invalid template string part: {:?}", part)),
+            }
+        }
+
+        code.push('"');
+        Ok(code)
+    }
+
+    /// Operand of a binary or ternary operator, in parenthesis when it binds less
+    /// tightly than `parent_op`.
+    fn print_operand(
+        &self,
+        node: &Node,
+        parent_op: &str,
+        is_right: bool,
+        indent: usize,
+    ) -> Result<String, String> {
+        let code = self.print_expression(node, indent)?;
+
+        let needs_parenthesis = match node {
+            Node::BinaryExpression(_, operator, _) => {
+                let precedence = operator_precedence(&self.print_expression(operator, indent)?);
+                let parent_precedence = operator_precedence(parent_op);
+                let is_associative =
+                    ["+", "*", "&&", "||", "^^", "&", "|", "^", "??"].contains(&parent_op);
+
+                precedence < parent_precedence
+                    || (is_right && precedence == parent_precedence && !is_associative)
+            }
+            Node::TernaryExpression(_, _, _) | Node::ArrowFunction(_, _, _) => true,
+            _ => false,
+        };
+
+        if needs_parenthesis {
+            Ok(format!("({code})"))
+        } else {
+            Ok(code)
+        }
+    }
+
+    fn print_postfix_operand(&self, node: &Node, indent: usize) -> Result<String, String> {
+        let code = self.print_expression(node, indent)?;
+
+        match node {
+            Node::BinaryExpression(_, _, _)
+            | Node::TernaryExpression(_, _, _)
+            | Node::UnaryExpression(_, _)
+            | Node::FunctionExpression(_, _)
+            | Node::ArrowFunction(_, _, _)
+            | Node::NewExpression(_) => Ok(format!("({code})")),
+            _ => Ok(code),
+        }
+    }
+}
+
+fn declaration_keyword(declaration_type: &Node) -> &'static str {
+    match declaration_type {
+        Node::DeclarationType(TokenType::Const) => "const",
+        Node::DeclarationType(TokenType::Var) => "var",
+        Node::DeclarationType(TokenType::Static) => "static",
+        _ => "let",
+    }
+}
+
+fn print_return_type(return_type: &Option<Box<Node>>) -> String {
+    match return_type {
+        Some(return_type) => format!(" -> {}", return_type.display_type()),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,313 @@
+use crate::lsp::json::Json;
+use std::path::{Path, PathBuf};
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The source line and column, counted from 0, every line of a module's GML
+/// comes from.
+pub type LineTable = Vec<Option<(usize, usize)>>;
+
+/// A version 3 source map from a generated `.gml` file back to the sources
+/// it was compiled from, with one mapping per generated line.
+#[derive(Debug, Default, PartialEq)]
+pub struct SourceMap {
+    /// Name of the generated file.
+    pub file: String,
+    /// Paths of the sources, relative to the map.
+    pub sources: Vec<String>,
+    /// For every generated line, the source, line and column it comes from.
+    pub lines: Vec<Option<(usize, usize, usize)>>,
+}
+
+impl SourceMap {
+    pub fn new(file: &str) -> SourceMap {
+        SourceMap {
+            file: file.to_string(),
+            ..SourceMap::default()
+        }
+    }
+
+    /// Maps the lines of a module whose GML starts at generated line
+    /// `first_line`, counted from 0.
+    pub fn add(&mut self, source: &str, first_line: usize, table: &LineTable) {
+        let index = match self.sources.iter().position(|known| known == source) {
+            Some(index) => index,
+            None => {
+                self.sources.push(source.to_string());
+                self.sources.len() - 1
+            }
+        };
+
+        if self.lines.len() < first_line + table.len() {
+            self.lines.resize(first_line + table.len(), None);
+        }
+        for (i, position) in table.iter().enumerate() {
+            self.lines[first_line + i] = position.map(|(line, column)| (index, line, column));
+        }
+    }
+
+    /// The source and line, counted from 1, of a generated line counted
+    /// from 1.
+    pub fn original(&self, line: usize) -> Option<(&str, usize)> {
+        let (source, line, _) = (*self.lines.get(line.checked_sub(1)?)?)?;
+        Some((self.sources.get(source)?, line + 1))
+    }
+
+    pub fn to_json(&self) -> String {
+        // Every field of a segment is relative to the same field in the
+        // segment before, except the generated column, which is always 0.
+        let mut previous = (0, 0, 0);
+        let mut mappings = String::new();
+
+        for (i, position) in self.lines.iter().enumerate() {
+            if i > 0 {
+                mappings.push(';');
+            }
+            if let Some((source, line, column)) = *position {
+                encode_vlq(0, &mut mappings);
+                encode_vlq(source as i64 - previous.0 as i64, &mut mappings);
+                encode_vlq(line as i64 - previous.1 as i64, &mut mappings);
+                encode_vlq(column as i64 - previous.2 as i64, &mut mappings);
+                previous = (source, line, column);
+            }
+        }
+
+        let sources = self.sources.iter().map(|source| Json::string(source));
+        let map = Json::object(vec![
+            ("version", Json::number(3)),
+            ("file", Json::string(&self.file)),
+            ("sources", Json::Array(sources.collect())),
+            ("names", Json::Array(Vec::new())),
+            ("mappings", Json::String(mappings)),
+        ]);
+        format!("{map}\n")
+    }
+
+    /// Reads a map written by `to_json`, or by another tool, keeping the
+    /// first segment of every line.
+    pub fn parse(text: &str) -> Result<SourceMap, String> {
+        let json = Json::parse(text)?;
+        if json.get("version").as_usize() != Some(3) {
+            return Err(String::from("Only version 3 source maps are supported"));
+        }
+
+        let sources = json
+            .get("sources")
+            .as_array()
+            .iter()
+            .map(|source| source.as_str().unwrap_or_default().to_string())
+            .collect();
+        let mappings = json
+            .get("mappings")
+            .as_str()
+            .ok_or("The source map has no mappings")?;
+
+        let mut fields = [0i64; 5];
+        let mut lines = Vec::new();
+        for line in mappings.split(';') {
+            // The generated column starts again from 0 on every line.
+            fields[0] = 0;
+            let mut first = None;
+
+            for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+                let values = decode_vlq(segment)?;
+                for (field, value) in fields.iter_mut().zip(&values) {
+                    *field += value;
+                }
+                if first.is_none() && values.len() >= 4 {
+                    first = Some((
+                        fields[1].max(0) as usize,
+                        fields[2].max(0) as usize,
+                        fields[3].max(0) as usize,
+                    ));
+                }
+            }
+            lines.push(first);
+        }
+
+        Ok(SourceMap {
+            file: json.get("file").as_str().unwrap_or_default().to_string(),
+            sources,
+            lines,
+        })
+    }
+}
+
+/// Writes a number as base64 digits of 5 bits, least significant first,
+/// with the sign in the lowest bit.
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut rest = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>, String> {
+    let mut values = Vec::new();
+    let mut value = 0;
+    let mut shift = 0;
+
+    for char in segment.bytes() {
+        let digit = BASE64
+            .iter()
+            .position(|known| *known == char)
+            .ok_or(format!(
+                "Invalid character '{}' in the mappings",
+                char as char
+            ))? as i64;
+
+        value |= (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            continue;
+        }
+
+        values.push(if value & 1 == 1 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        value = 0;
+        shift = 0;
+    }
+
+    Ok(values)
+}
+
+/// The map of a `.gml` file generated from a single source.
+pub fn module_map(output: &Path, source: &Path, lines: &LineTable) -> SourceMap {
+    let file = output.file_name().unwrap_or_default().to_string_lossy();
+    let mut map = SourceMap::new(&file);
+    map.add(&relative_path(&output_folder(output), source), 0, lines);
+    map
+}
+
+/// Writes the source map of `output` next to it, unless it is already there.
+pub fn write_map(output: &Path, map: &SourceMap) -> Result<(), String> {
+    let path = map_path(output);
+    let json = map.to_json();
+    if std::fs::read_to_string(&path).is_ok_and(|old| old == json) {
+        return Ok(());
+    }
+    std::fs::write(&path, json).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Where the source map of a generated file goes, next to it.
+pub fn map_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".map");
+    PathBuf::from(path)
+}
+
+/// The absolute folder of a file that may not exist yet, which paths in its
+/// source map are relative to.
+pub fn output_folder(output: &Path) -> PathBuf {
+    let folder = match output.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    std::fs::canonicalize(&folder).unwrap_or(folder)
+}
+
+/// The path of `target` relative to the folder `from`, with `/` between
+/// folders as source maps want.
+pub fn relative_path(from: &Path, target: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let target: Vec<_> = target.components().collect();
+    let common = from.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let parts: Vec<String> = std::iter::repeat_n(String::from(".."), from.len() - common)
+        .chain(
+            target[common..]
+                .iter()
+                .map(|part| part.as_os_str().to_string_lossy().to_string()),
+        )
+        .collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vlq(values: &[i64]) -> String {
+        let mut out = String::new();
+        for value in values {
+            encode_vlq(*value, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn vlq_round_trip() {
+        assert_eq!(vlq(&[0, 1, -1, 15, 16, 123, -2048]), "ACDegB2HhgE");
+        assert_eq!(
+            decode_vlq("ACDegB2HhgE"),
+            Ok(vec![0, 1, -1, 15, 16, 123, -2048])
+        );
+        assert_eq!(
+            decode_vlq("A!"),
+            Err(String::from("Invalid character '!' in the mappings"))
+        );
+    }
+
+    #[test]
+    fn mappings_are_relative_to_the_segment_before() {
+        let mut map = SourceMap::new("main.gml");
+        map.add("main.gmpp", 0, &vec![Some((0, 0)), None, Some((2, 4))]);
+        map.add("lib.gmpp", 3, &vec![Some((1, 0))]);
+
+        let json = map.to_json();
+        assert_eq!(
+            json,
+            "{\"version\":3,\"file\":\"main.gml\",\"sources\":[\"main.gmpp\",\"lib.gmpp\"],\"names\":[],\"mappings\":\"AAAA;;AAEI;ACDJ\"}\n"
+        );
+        assert_eq!(SourceMap::parse(&json), Ok(map));
+    }
+
+    #[test]
+    fn reads_the_first_segment_of_other_maps() {
+        // Several segments per line, with names, as other tools write them.
+        let map = SourceMap::parse(
+            r#"{"version":3,"file":"a.gml","sources":["a.gmpp"],"names":["x"],"mappings":"AAAA,IAAIA;AACA;;EAAE"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            map.lines,
+            [Some((0, 0, 0)), Some((0, 1, 4)), None, Some((0, 1, 6))]
+        );
+        assert_eq!(map.original(2), Some(("a.gmpp", 2)));
+        assert_eq!(map.original(3), None);
+        assert_eq!(map.original(0), None);
+
+        assert_eq!(
+            SourceMap::parse(r#"{"version":2,"mappings":""}"#),
+            Err(String::from("Only version 3 source maps are supported"))
+        );
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(Path::new("/game/build"), Path::new("/game/src/player.gmpp")),
+            "../src/player.gmpp"
+        );
+        assert_eq!(
+            relative_path(Path::new("/game"), Path::new("/game/player.gmpp")),
+            "player.gmpp"
+        );
+    }
+}
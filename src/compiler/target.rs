@@ -0,0 +1,166 @@
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum GmlVersion {
+    Gms22,
+    Gms23,
+    Lts2023,
+    Latest,
+}
+
+impl GmlVersion {
+    pub fn from_name(name: &str) -> Result<GmlVersion, String> {
+        match name.to_lowercase().as_str() {
+            "2.2" | "gms2.2" | "legacy" => Ok(GmlVersion::Gms22),
+            "2.3" | "gms2.3" => Ok(GmlVersion::Gms23),
+            "lts" | "2023-lts" | "lts2023" => Ok(GmlVersion::Lts2023),
+            "latest" => Ok(GmlVersion::Latest),
+            _ => Err(format!(
+                "Unknown GameMaker target '{name}', expected one of: 2.2, 2.3, 2023-lts, latest"
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GmlVersion::Gms22 => "GameMaker Studio 2.2",
+            GmlVersion::Gms23 => "GameMaker Studio 2.3",
+            GmlVersion::Lts2023 => "GameMaker 2023 LTS",
+            GmlVersion::Latest => "the latest GameMaker",
+        }
+    }
+
+    /// `function` declarations, structs, `method()` and `static`.
+    pub fn supports_functions(&self) -> bool {
+        *self >= GmlVersion::Gms23
+    }
+
+    /// `cond ? a : b`
+    pub fn supports_ternary(&self) -> bool {
+        *self >= GmlVersion::Gms23
+    }
+
+    /// `$"..."`
+    pub fn supports_template_strings(&self) -> bool {
+        *self >= GmlVersion::Lts2023
+    }
+
+    /// `a ?? b`
+    pub fn supports_nullish(&self) -> bool {
+        *self >= GmlVersion::Lts2023
+    }
+
+    /// `array_map`, `array_filter`, `array_reduce`...
+    pub fn supports_array_functions(&self) -> bool {
+        *self >= GmlVersion::Latest
+    }
+}
+
+/// Built-in functions that only exist starting from some runtime version.
+/// Those that can be written in plain GML have a polyfill named
+/// `__gmpp_<name>` that gets emitted when the target lacks them.
+pub struct VersionedBuiltins {}
+impl VersionedBuiltins {
+    pub fn get() -> Vec<(&'static str, GmlVersion)> {
+        vec![
+            ("method", GmlVersion::Gms23),
+            ("is_struct", GmlVersion::Gms23),
+            ("is_method", GmlVersion::Gms23),
+            ("variable_struct_get", GmlVersion::Gms23),
+            ("variable_struct_set", GmlVersion::Gms23),
+            ("array_map", GmlVersion::Latest),
+            ("array_filter", GmlVersion::Latest),
+            ("array_reduce", GmlVersion::Latest),
+            ("array_foreach", GmlVersion::Latest),
+            ("array_find_index", GmlVersion::Latest),
+            ("array_any", GmlVersion::Latest),
+            ("array_all", GmlVersion::Latest),
+        ]
+    }
+
+    pub fn introduced_in(name: &str) -> Option<GmlVersion> {
+        VersionedBuiltins::get()
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, version)| *version)
+    }
+
+    /// Polyfills need `function` declarations and function values, so they
+    /// can only be emitted for 2.3 and newer.
+    pub fn polyfill(name: &str) -> Option<&'static str> {
+        let code = match name {
+            "array_map" => {
+                "function __gmpp_array_map(array, func) {
+    var result = array_create(array_length(array));
+    for (var i = 0; i < array_length(array); i++) {
+        result[i] = func(array[i], i);
+    }
+    return result;
+}"
+            }
+            "array_filter" => {
+                "function __gmpp_array_filter(array, func) {
+    var result = [];
+    for (var i = 0; i < array_length(array); i++) {
+        if (func(array[i], i)) {
+            array_push(result, array[i]);
+        }
+    }
+    return result;
+}"
+            }
+            "array_reduce" => {
+                "function __gmpp_array_reduce(array, func, init) {
+    var start = 0;
+    var value = init;
+    if (is_undefined(value)) {
+        value = array[0];
+        start = 1;
+    }
+    for (var i = start; i < array_length(array); i++) {
+        value = func(value, array[i], i);
+    }
+    return value;
+}"
+            }
+            "array_foreach" => {
+                "function __gmpp_array_foreach(array, func) {
+    for (var i = 0; i < array_length(array); i++) {
+        func(array[i], i);
+    }
+}"
+            }
+            "array_find_index" => {
+                "function __gmpp_array_find_index(array, func) {
+    for (var i = 0; i < array_length(array); i++) {
+        if (func(array[i], i)) {
+            return i;
+        }
+    }
+    return -1;
+}"
+            }
+            "array_any" => {
+                "function __gmpp_array_any(array, func) {
+    for (var i = 0; i < array_length(array); i++) {
+        if (func(array[i], i)) {
+            return true;
+        }
+    }
+    return false;
+}"
+            }
+            "array_all" => {
+                "function __gmpp_array_all(array, func) {
+    for (var i = 0; i < array_length(array); i++) {
+        if (!func(array[i], i)) {
+            return false;
+        }
+    }
+    return true;
+}"
+            }
+            _ => return None,
+        };
+
+        Some(code)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::compiler::ast::Node;
+use crate::compiler::attributes::{has_attribute, Registry};
+use crate::compiler::error::Error;
+use crate::compiler::parser::Positions;
+use crate::compiler::source_map::LineTable;
+use std::collections::HashSet;
+
+/// The assertions `@test` functions can call, with how many arguments they
+/// take, optional ones included.
+pub const ASSERTIONS: [(&str, usize); 3] = [("assert", 2), ("assert_eq", 2), ("assert_ne", 2)];
+
+/// Written by the GML of an assertion where its source location goes, until
+/// `fill_locations` knows which line it is on.
+pub const LOCATION: char = '\u{2}';
+
+/// GML of the assertions, which throw a struct with the message and where the
+/// assertion is written.
+const HELPERS: &str = "\
+function __gmpp_assert(condition, message, location) {
+    if (!condition) {
+        __gmpp_fail(is_undefined(message) ? \"assertion failed\" : message, location);
+    }
+}
+
+function __gmpp_assert_eq(actual, expected, location) {
+    if (!__gmpp_equals(actual, expected)) {
+        __gmpp_fail(\"expected \" + string(expected) + \", got \" + string(actual), location);
+    }
+}
+
+function __gmpp_assert_ne(actual, unexpected, location) {
+    if (__gmpp_equals(actual, unexpected)) {
+        __gmpp_fail(\"expected anything but \" + string(unexpected), location);
+    }
+}
+
+function __gmpp_equals(a, b) {
+    if (is_array(a) && is_array(b)) {
+        return array_equals(a, b);
+    }
+    if (is_numeric(a) && is_numeric(b)) {
+        return a == b;
+    }
+    return typeof(a) == typeof(b) && a == b;
+}
+
+function __gmpp_fail(message, location) {
+    throw { message: is_undefined(location) ? message : message + \" at \" + location };
+}
+";
+
+/// How many arguments an assertion takes, if `name` is one.
+pub fn assertion_arity(name: &str) -> Option<usize> {
+    ASSERTIONS
+        .iter()
+        .find(|(assertion, _)| *assertion == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// Names of the `@test` functions of a module, in the order they are written.
+pub fn test_functions(program: &Node) -> Vec<String> {
+    Registry::collect(program)
+        .marked("test")
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Checks that `@test` functions take no parameters, and that assertions are
+/// only called inside them, as they are left out of builds. A module
+/// declaring a function named like an assertion, or importing one from a
+/// GML script in `gml_functions`, calls its own.
+pub fn validate(
+    program: &Node,
+    positions: &Positions,
+    gml_functions: &HashSet<String>,
+) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let Node::Program(body) = program else {
+        return errors;
+    };
+
+    let declared: Vec<&String> = body
+        .iter()
+        .filter_map(|stmt| stmt.declared_name())
+        .collect();
+    let assertions: Vec<&str> = ASSERTIONS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| {
+            !declared.iter().any(|declared| declared == name) && !gml_functions.contains(*name)
+        })
+        .collect();
+
+    for stmt in body {
+        let span = positions.get(&(stmt.as_ref() as *const Node)).copied();
+        if has_attribute(stmt, "test") {
+            if let Node::FunctionDeclaration(name, params, _, _) = stmt.declaration() {
+                if !params.is_empty() {
+                    errors.push(
+                        Error::new(format!("Test '{name}' can't take parameters")).or_at(span),
+                    );
+                }
+            }
+            continue;
+        }
+
+        if let Some(assertion) = called_assertion(stmt, &assertions) {
+            errors.push(
+                Error::new(format!(
+                    "'{assertion}' can only be called in @test functions"
+                ))
+                .or_at(span),
+            );
+        }
+    }
+
+    errors
+}
+
+/// The first assertion called under a node.
+fn called_assertion<'a>(node: &Node, assertions: &[&'a str]) -> Option<&'a str> {
+    if let Node::FunctionCall(callee, _) = node {
+        if let Node::Identifier(name) = callee.as_ref() {
+            if let Some(assertion) = assertions.iter().find(|assertion| *assertion == name) {
+                return Some(assertion);
+            }
+        }
+    }
+
+    node.children()
+        .into_iter()
+        .find_map(|child| called_assertion(child, assertions))
+}
+
+/// Replaces the `LOCATION` of every assertion with the file, line and column
+/// of the statement it is in, as `"path:line:column"`.
+pub fn fill_locations(gml: &str, path: &str, lines: &LineTable) -> String {
+    let path = path.replace('\\', "/");
+
+    gml.split_inclusive('\n')
+        .enumerate()
+        .map(|(i, code)| {
+            if !code.contains(LOCATION) {
+                return code.to_string();
+            }
+            let location = match lines.get(i).copied().flatten() {
+                Some((line, column)) => format!("\"{path}:{}:{}\"", line + 1, column + 1),
+                None => String::from("undefined"),
+            };
+            code.replace(LOCATION, &location)
+        })
+        .collect()
+}
+
+/// GML running the given tests with `gmpp_run_tests()`, which prints the
+/// result of each one and returns how many failed.
+pub fn runner(tests: &[String]) -> String {
+    let list: Vec<String> = tests
+        .iter()
+        .map(|test| format!("        [\"{test}\", {test}]"))
+        .collect();
+
+    format!(
+        "{HELPERS}
+function gmpp_run_tests() {{
+    var tests = [
+{}
+    ];
+    var failed = 0;
+    for (var i = 0; i < array_length(tests); i++) {{
+        try {{
+            tests[i][1]();
+            show_debug_message(\"ok \" + tests[i][0]);
+        }} catch (error) {{
+            failed++;
+            var message = is_struct(error) && variable_struct_exists(error, \"message\") ? error.message : string(error);
+            show_debug_message(\"FAILED \" + tests[i][0] + \": \" + message);
+        }}
+    }}
+    show_debug_message(string(array_length(tests) - failed) + \" passed, \" + string(failed) + \" failed\");
+    return failed;
+}}
+",
+        list.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parse_with_positions_as;
+    use crate::compiler::tokenizer::{tokenize_as, Language};
+    use crate::interp::host::StandardHost;
+    use crate::interp::Interpreter;
+    use std::rc::Rc;
+
+    /// Runs GML with the interpreter, returning what it printed.
+    fn run_gml(code: &str) -> String {
+        let (program, positions) = tokenize_as(code.to_string(), Language::Gml)
+            .and_then(|tokens| parse_with_positions_as(tokens, Language::Gml))
+            .unwrap();
+        let mut interpreter = Interpreter::new(StandardHost::new());
+        interpreter
+            .load(Rc::new(program), &positions, code, "tests.gml")
+            .unwrap();
+        interpreter.host.output
+    }
+
+    #[test]
+    fn assertions_get_the_line_of_their_statement() {
+        let gml = format!(
+            "function f() {{\n    __gmpp_assert(false, undefined, {LOCATION});\n    x = 1;\n}}\n"
+        );
+        let lines = vec![Some((4, 0)), Some((5, 4)), Some((6, 4)), None];
+        assert_eq!(
+            fill_locations(&gml, "tests\\damage.gmpp", &lines),
+            "function f() {\n    __gmpp_assert(false, undefined, \"tests/damage.gmpp:6:5\");\n    x = 1;\n}\n"
+        );
+        assert_eq!(
+            fill_locations(&gml, "damage.gmpp", &Vec::new()),
+            "function f() {\n    __gmpp_assert(false, undefined, undefined);\n    x = 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn the_runner_reports_every_test() {
+        let tests = "\
+function adds() {
+    __gmpp_assert_eq(1 + 1, 2, \"a.gmpp:2:5\");
+    __gmpp_assert_ne([1, 2], [2, 1], \"a.gmpp:3:5\");
+}
+function compares() {
+    __gmpp_assert_eq(\"2\", 2, \"a.gmpp:6:5\");
+}
+function asserts() {
+    __gmpp_assert(false, undefined, undefined);
+}
+function throws() {
+    throw \"broken\";
+}
+";
+        let names = ["adds", "compares", "asserts", "throws"].map(String::from);
+        let code = format!(
+            "{tests}{}show_debug_message(gmpp_run_tests());\n",
+            runner(&names)
+        );
+        assert_eq!(
+            run_gml(&code),
+            "\
+ok adds
+FAILED compares: expected 2, got 2 at a.gmpp:6:5
+FAILED asserts: assertion failed
+FAILED throws: broken
+1 passed, 3 failed
+3
+"
+        );
+    }
+}
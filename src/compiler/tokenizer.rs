@@ -1,14 +1,112 @@
+use crate::compiler::error::{Error, Span};
 use crate::string_utils::{char_at, is_alphabetic, is_numeric};
 use std::collections::HashMap;
 
+/// Which flavour of the language a source file is written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    GmlPlusPlus,
+    /// Plain GML, as written for the GameMaker IDE.
+    Gml,
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Result<Language, String> {
+        match name.to_lowercase().as_str() {
+            "gmpp" | "gml++" => Ok(Language::GmlPlusPlus),
+            "gml" => Ok(Language::Gml),
+            _ => Err(format!(
+                "Unknown language '{name}', expected one of: gmpp, gml"
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::GmlPlusPlus => "gmpp",
+            Language::Gml => "gml",
+        }
+    }
+
+    /// Picks the language from the extension of a source file.
+    pub fn from_path(path: &std::path::Path) -> Language {
+        match path.extension() {
+            Some(ext) if ext == "gml" => Language::Gml,
+            _ => Language::GmlPlusPlus,
+        }
+    }
+}
+
 pub struct Keywords {}
 impl Keywords {
-    pub fn get<'a>() -> HashMap<&'a str, TokenType> {
+    pub fn get<'a>(language: Language) -> HashMap<&'a str, TokenType> {
         let mut map = HashMap::new();
-        map.insert("let", TokenType::Let);
-        map.insert("const", TokenType::Const);
+        map.insert("var", TokenType::Var);
+        map.insert("static", TokenType::Static);
         map.insert("function", TokenType::Function);
+        map.insert("constructor", TokenType::Constructor);
         map.insert("return", TokenType::Return);
+        map.insert("if", TokenType::If);
+        map.insert("else", TokenType::Else);
+        map.insert("while", TokenType::While);
+        map.insert("do", TokenType::Do);
+        map.insert("until", TokenType::Until);
+        map.insert("for", TokenType::For);
+        map.insert("repeat", TokenType::Repeat);
+        map.insert("with", TokenType::With);
+        map.insert("switch", TokenType::Switch);
+        map.insert("case", TokenType::Case);
+        map.insert("default", TokenType::Default);
+        map.insert("break", TokenType::Break);
+        map.insert("continue", TokenType::Continue);
+        map.insert("exit", TokenType::Exit);
+        map.insert("enum", TokenType::Enum);
+        map.insert("new", TokenType::New);
+        map.insert("try", TokenType::Try);
+        map.insert("catch", TokenType::Catch);
+        map.insert("finally", TokenType::Finally);
+        map.insert("throw", TokenType::Throw);
+        map.insert("delete", TokenType::Delete);
+        map.insert("true", TokenType::BooleanLiteral);
+        map.insert("false", TokenType::BooleanLiteral);
+
+        match language {
+            Language::GmlPlusPlus => {
+                map.insert("let", TokenType::Let);
+                map.insert("const", TokenType::Const);
+                map.insert("import", TokenType::Import);
+                map.insert("export", TokenType::Export);
+                map.insert("extern", TokenType::Extern);
+                map.insert("fn", TokenType::Fn);
+                map.insert("class", TokenType::Class);
+            }
+            Language::Gml => {
+                map.insert("globalvar", TokenType::GlobalVar);
+                map.insert("then", TokenType::Then);
+            }
+        }
+
+        map
+    }
+}
+
+/// Words that stand for a symbol, like `and` for `&&`. They are tokenized as
+/// the symbol so the rest of the compiler only has to handle one spelling.
+pub struct WordTokens {}
+impl WordTokens {
+    pub fn get<'a>(language: Language) -> HashMap<&'a str, (TokenType, &'a str)> {
+        let mut map = HashMap::new();
+        map.insert("and", (TokenType::BinaryOperator, "&&"));
+        map.insert("or", (TokenType::BinaryOperator, "||"));
+        map.insert("xor", (TokenType::BinaryOperator, "^^"));
+        map.insert("not", (TokenType::UnaryOperator, "!"));
+        map.insert("mod", (TokenType::BinaryOperator, "%"));
+        map.insert("div", (TokenType::BinaryOperator, "div"));
+
+        if language == Language::Gml {
+            map.insert("begin", (TokenType::OpenCurly, "{"));
+            map.insert("end", (TokenType::CloseCurly, "}"));
+        }
 
         map
     }
@@ -30,6 +128,62 @@ impl SinglecharTokens {
         map.insert('{', TokenType::OpenCurly);
         map.insert('}', TokenType::CloseCurly);
         map.insert(',', TokenType::Comma);
+        map.insert(':', TokenType::Colon);
+        map.insert('<', TokenType::BinaryOperator);
+        map.insert('>', TokenType::BinaryOperator);
+        map.insert('[', TokenType::OpenBracket);
+        map.insert(']', TokenType::CloseBracket);
+        map.insert('?', TokenType::QuestionMark);
+        map.insert('.', TokenType::Dot);
+        map.insert('%', TokenType::BinaryOperator);
+        map.insert('&', TokenType::BinaryOperator);
+        map.insert('|', TokenType::BinaryOperator);
+        map.insert('^', TokenType::BinaryOperator);
+        map.insert('!', TokenType::UnaryOperator);
+        map.insert('~', TokenType::UnaryOperator);
+        map.insert('@', TokenType::At);
+
+        map
+    }
+}
+
+pub struct MulticharTokens {}
+impl MulticharTokens {
+    pub fn get<'a>() -> HashMap<&'a str, TokenType> {
+        let mut map = HashMap::new();
+
+        map.insert("??", TokenType::BinaryOperator);
+        map.insert("==", TokenType::BinaryOperator);
+        map.insert("!=", TokenType::BinaryOperator);
+        map.insert("<=", TokenType::BinaryOperator);
+        map.insert(">=", TokenType::BinaryOperator);
+        map.insert("&&", TokenType::BinaryOperator);
+        map.insert("||", TokenType::BinaryOperator);
+        map.insert("^^", TokenType::BinaryOperator);
+        map.insert("<<", TokenType::BinaryOperator);
+        // The old spelling of `!=`, read as it by `read_tokens`.
+        map.insert("<>", TokenType::BinaryOperator);
+        // `>>` is read as two `>` so nested type arguments like
+        // `DsMap<string, DsList<number>>` still close properly.
+        map.insert("+=", TokenType::AssignmentOperator);
+        map.insert("-=", TokenType::AssignmentOperator);
+        map.insert("*=", TokenType::AssignmentOperator);
+        map.insert("/=", TokenType::AssignmentOperator);
+        map.insert("%=", TokenType::AssignmentOperator);
+        map.insert("&=", TokenType::AssignmentOperator);
+        map.insert("|=", TokenType::AssignmentOperator);
+        map.insert("^=", TokenType::AssignmentOperator);
+        map.insert("??=", TokenType::AssignmentOperator);
+        map.insert("++", TokenType::UpdateOperator);
+        map.insert("--", TokenType::UpdateOperator);
+        // Accessors: `list[| i]`, `map[? key]`, `grid[# x, y]`, `array[@ i]`.
+        map.insert("[|", TokenType::OpenAccessor);
+        map.insert("[?", TokenType::OpenAccessor);
+        map.insert("[#", TokenType::OpenAccessor);
+        map.insert("[@", TokenType::OpenAccessor);
+        map.insert("->", TokenType::Arrow);
+        map.insert("=>", TokenType::FatArrow);
+        map.insert("...", TokenType::Ellipsis);
 
         map
     }
@@ -46,34 +200,114 @@ impl SkippableCharacters {
 pub enum TokenType {
     Equals,
     BinaryOperator,
+    UnaryOperator,
+    AssignmentOperator,
+    UpdateOperator,
     Semilicon,
     OpenParenthesis,
     CloseParenthesis,
 
     Identifier,
     NumericListeral,
+    StringLiteral,
+    TemplateString,
+    BooleanLiteral,
+    /// `#macro NAME value`, with everything after `#macro` as the lexeme.
+    Macro,
     Let,
     Const,
+    Var,
+    GlobalVar,
+    Static,
     Function,
+    Fn,
+    Class,
+    Constructor,
     Return,
+    Import,
+    Export,
+    Extern,
+    If,
+    /// The optional `then` of `if x then ...` in plain GML.
+    Then,
+    Else,
+    While,
+    Do,
+    Until,
+    For,
+    Repeat,
+    With,
+    Switch,
+    Case,
+    Default,
+    Break,
+    Continue,
+    Exit,
+    Enum,
+    New,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Delete,
 
     Comma,
+    Colon,
+    Arrow,
+    FatArrow,
+    Dot,
+    Ellipsis,
+    QuestionMark,
+    /// `@`, which starts an attribute.
+    At,
 
     OpenCurly,
     CloseCurly,
+    OpenBracket,
+    CloseBracket,
+    OpenAccessor,
 
     EOF,
 }
 
+/// What sits between tokens and only matters to the formatter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trivia {
+    /// A comment on its own line, `//` or `/* */` included.
+    Comment(String),
+    /// One or more empty lines.
+    BlankLine,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenType,
     pub lex: String,
+    /// Comments and blank lines before the token, kept by `tokenize_lossless`.
+    pub leading: Vec<Trivia>,
+    /// A comment after the token on the same line.
+    pub trailing: Option<String>,
+    /// Where the token starts in the source, in bytes.
+    pub offset: usize,
+    /// Where it ends, just past its last byte.
+    pub end: usize,
 }
 
 impl Token {
     pub fn new(kind: TokenType, lex: String) -> Token {
-        Token { kind, lex }
+        Token {
+            kind,
+            lex,
+            leading: Vec::new(),
+            trailing: None,
+            offset: 0,
+            end: 0,
+        }
+    }
+
+    /// The source the token was read from.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.end)
     }
 
     pub fn to_box(&self) -> Box<Self> {
@@ -86,22 +320,144 @@ use crate::box_utils::Boxable;
 impl Boxable for Token {}
 impl Boxable for TokenType {}
 
-pub fn tokenize(mut src: String) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
+pub fn tokenize(src: String) -> Result<Vec<Token>, Error> {
+    tokenize_as(src, Language::GmlPlusPlus)
+}
+
+pub fn tokenize_as(src: String, language: Language) -> Result<Vec<Token>, Error> {
+    read_tokens(src, language, false)
+}
+
+/// Tokenizes while keeping comments and blank lines on the tokens, so the
+/// source can be printed back without losing them.
+pub fn tokenize_lossless(src: String, language: Language) -> Result<Vec<Token>, Error> {
+    read_tokens(src, language, true)
+}
+
+/// Tokens read so far, and the trivia waiting for the next one.
+struct TokenList {
+    tokens: Vec<Token>,
+    keep_trivia: bool,
+    pending: Vec<Trivia>,
+    newlines: usize,
+    /// Offset of the token being read.
+    start: usize,
+    /// Length of the whole source.
+    source_len: usize,
+}
+
+impl TokenList {
+    /// Adds a token read from the source up to what is left in `rest`.
+    fn push(&mut self, mut token: Token, rest: &str) {
+        if self.keep_trivia {
+            self.blank_line();
+            token.leading = std::mem::take(&mut self.pending);
+        }
+
+        token.offset = self.start;
+        token.end = self.source_len - rest.len();
+        self.newlines = 0;
+        self.tokens.push(token);
+    }
+
+    fn newline(&mut self) {
+        self.newlines += 1;
+    }
+
+    fn comment(&mut self, text: String) {
+        if !self.keep_trivia {
+            return;
+        }
 
-    println!("{}", src);
+        let same_line = self.newlines == 0 && self.pending.is_empty();
+        match self.tokens.last_mut() {
+            Some(last) if same_line && last.trailing.is_none() => last.trailing = Some(text),
+            _ => {
+                self.blank_line();
+                self.pending.push(Trivia::Comment(text));
+            }
+        }
 
-    while src.chars().count() != 0 {
+        self.newlines = 0;
+    }
+
+    /// Two line breaks in a row leave an empty line, unless nothing came
+    /// before them.
+    fn blank_line(&mut self) {
+        if self.newlines >= 2 && (!self.tokens.is_empty() || !self.pending.is_empty()) {
+            self.pending.push(Trivia::BlankLine);
+        }
+    }
+}
+
+fn read_tokens(src: String, language: Language, keep_trivia: bool) -> Result<Vec<Token>, Error> {
+    let mut tokens = TokenList {
+        tokens: Vec::new(),
+        keep_trivia,
+        pending: Vec::new(),
+        newlines: 0,
+        start: 0,
+        source_len: src.len(),
+    };
+
+    // Mistakes are reported where the token they stopped at starts.
+    read_into(&mut tokens, src, language)
+        .map_err(|message| Error::at(message, Span::new(tokens.start, tokens.start + 1)))?;
+    Ok(tokens.tokens)
+}
+
+fn read_into(tokens: &mut TokenList, mut src: String, language: Language) -> Result<(), String> {
+    let source_len = tokens.source_len;
+
+    while !src.is_empty() {
+        tokens.start = source_len - src.len();
         let char = char_at(&src, 0);
         let char_as_string = String::from(char);
 
-        if is_alphabetic(&char_as_string) || char == '_' {
+        if char == '"' || (char == '$' && src.chars().nth(1) == Some('"')) {
+            let is_template = char == '$';
+            src.drain(0..if is_template { 2 } else { 1 });
+
+            let content = take_string_content(&mut src, is_template)?;
+            let kind = if is_template {
+                TokenType::TemplateString
+            } else {
+                TokenType::StringLiteral
+            };
+
+            tokens.push(Token::new(kind, content), &src);
+        } else if src.starts_with("@\"") || src.starts_with("@'") {
+            // Verbatim strings have no escapes, so they are stored escaped like
+            // any other string literal.
+            let quote = char_at(&src, 1);
+            src.drain(0..2);
+
+            let end = src
+                .find(quote)
+                .ok_or("Unterminated verbatim string literal")?;
+            let content: String = src.drain(0..end).collect();
+            src.remove(0);
+
+            let escaped = content
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            tokens.push(Token::new(TokenType::StringLiteral, escaped), &src);
+        } else if src.starts_with("#macro") {
+            let line = take_line(&mut src);
+            let definition = line["#macro".len()..].trim().to_string();
+
+            tokens.push(Token::new(TokenType::Macro, definition), &src);
+        } else if src.starts_with("#region") || src.starts_with("#endregion") {
+            let line = take_line(&mut src);
+            tokens.comment(line.trim_end().to_string());
+        } else if is_alphabetic(&char_as_string) || char == '_' {
             let mut identifier = String::new();
 
             loop {
                 identifier.push(src.remove(0));
 
-                if src.len() == 0 {
+                if src.is_empty() {
                     break;
                 }
 
@@ -115,47 +471,230 @@ pub fn tokenize(mut src: String) -> Vec<Token> {
                 }
             }
 
-            let keywords = Keywords::get();
-            let is_keyword = keywords.contains_key(identifier.as_str());
-            let token_type: TokenType = if is_keyword {
-                keywords.get(identifier.as_str()).cloned().unwrap()
+            let keywords = Keywords::get(language);
+            let word_tokens = WordTokens::get(language);
+
+            let token = if let Some(kind) = keywords.get(identifier.as_str()) {
+                Token::new(kind.clone(), identifier)
+            } else if let Some((kind, lex)) = word_tokens.get(identifier.as_str()) {
+                Token::new(kind.clone(), lex.to_string())
             } else {
-                TokenType::Identifier
+                Token::new(TokenType::Identifier, identifier)
             };
 
-            tokens.push(Token::new(token_type, identifier));
+            tokens.push(token, &src);
+        } else if src.starts_with("0x")
+            || (char == '$' && src.chars().nth(1).is_some_and(|c| c.is_ascii_hexdigit()))
+        {
+            let prefix_len = if char == '$' { 1 } else { 2 };
+            let digits: String = src[prefix_len..]
+                .chars()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .collect();
+            let literal: String = src.drain(0..prefix_len + digits.len()).collect();
+
+            tokens.push(Token::new(TokenType::NumericListeral, literal), &src);
         } else if is_numeric(&char_as_string) {
             let mut literal = String::new();
+            let mut has_dot = false;
 
             loop {
                 literal.push(src.remove(0));
 
-                if src.len() == 0 {
+                if src.is_empty() {
                     break;
                 }
 
                 let next_char = String::from(char_at(&src, 0));
+                let is_decimal_dot = next_char == "."
+                    && !has_dot
+                    && src.chars().nth(1).is_some_and(|c| c.is_ascii_digit());
 
-                if !is_numeric(&next_char) {
+                if is_decimal_dot {
+                    has_dot = true;
+                } else if !is_numeric(&next_char) {
                     break;
                 }
             }
 
-            tokens.push(Token::new(TokenType::NumericListeral, literal));
+            tokens.push(Token::new(TokenType::NumericListeral, literal), &src);
+        } else if src.starts_with("//") {
+            let line_end = src.find('\n').unwrap_or(src.len());
+            let comment: String = src.drain(0..line_end).collect();
+            tokens.comment(comment.trim_end().to_string());
+        } else if src.starts_with("/*") {
+            let comment_end = src
+                .find("*/")
+                .map(|index| index + 2)
+                .ok_or("Unterminated block comment")?;
+            tokens.comment(src.drain(0..comment_end).collect());
+        } else if src.starts_with("[$") && src.chars().nth(2) != Some('"') {
+            // `struct[$ key]`, told apart from an array holding a template string.
+            let lex = src.drain(0..2).collect();
+            tokens.push(Token::new(TokenType::OpenAccessor, lex), &src);
+        } else if let Some((lex_len, kind)) = [3, 2].iter().find_map(|len| {
+            src.get(0..*len)
+                .and_then(|lex| MulticharTokens::get().get(lex).cloned())
+                .map(|kind| (*len, kind))
+        }) {
+            let lex: String = src.drain(0..lex_len).collect();
+            let lex = if lex == "<>" { String::from("!=") } else { lex };
+
+            tokens.push(Token::new(kind, lex), &src);
         } else if SinglecharTokens::get().contains_key(&char) {
             let kind = SinglecharTokens::get().get(&char).cloned().unwrap();
 
-            tokens.push(Token::new(kind, char_as_string));
-
             src.remove(0);
+
+            tokens.push(Token::new(kind, char_as_string), &src);
         } else if SkippableCharacters::get().contains(&char) {
+            if char == '\n' {
+                tokens.newline();
+            }
             src.remove(0);
+        } else if char == '\'' {
+            return Err(String::from(
+                "Strings are written between double quotes, or as @'verbatim' strings",
+            ));
         } else {
-            panic!("Strange character: {:?}", char);
+            return Err(format!("Unexpected character {char:?}"));
         }
     }
 
-    tokens.push(Token::new(TokenType::EOF, "EOF".to_string()));
+    tokens.start = source_len;
+    tokens.push(Token::new(TokenType::EOF, "EOF".to_string()), &src);
+
+    Ok(())
+}
 
-    tokens
+/// Removes the rest of the current line from `src`, following `\` line
+/// continuations like GameMaker does for macros.
+fn take_line(src: &mut String) -> String {
+    let mut line = String::new();
+
+    loop {
+        let line_end = src.find('\n').unwrap_or(src.len());
+        let part: String = src.drain(0..line_end).collect();
+        let part = part.trim_end_matches('\r');
+
+        match part.strip_suffix('\\') {
+            Some(continued) if !src.is_empty() => {
+                line.push_str(continued);
+                line.push('\n');
+                src.remove(0);
+            }
+            _ => {
+                line.push_str(part);
+                return line;
+            }
+        }
+    }
+}
+
+/// Consumes the body of a string literal whose opening quote has already been
+/// removed from `src`, returning its raw content (escape sequences untouched).
+///
+/// For template strings, quotes that appear inside `{...}` belong to the
+/// embedded expression, so nested strings and templates are read as a whole.
+pub fn take_string_content(src: &mut String, is_template: bool) -> Result<String, String> {
+    let mut content = String::new();
+    let mut depth = 0;
+
+    loop {
+        if src.is_empty() {
+            let start: String = content
+                .lines()
+                .next()
+                .unwrap_or("")
+                .chars()
+                .take(20)
+                .collect();
+            return Err(format!("Unterminated string literal: {:?}", start));
+        }
+
+        let char = src.remove(0);
+
+        match char {
+            '\\' => {
+                content.push(char);
+                if !src.is_empty() {
+                    content.push(src.remove(0));
+                }
+            }
+            '"' if depth == 0 => break,
+            '"' | '$' if depth > 0 => {
+                let nested_template = char == '$' && src.starts_with('"');
+                content.push(char);
+                if char == '$' && !nested_template {
+                    continue;
+                }
+                if nested_template {
+                    content.push(src.remove(0));
+                }
+                content.push_str(&take_string_content(src, nested_template)?);
+                content.push('"');
+            }
+            '{' if is_template => {
+                if depth == 0 && src.starts_with('{') {
+                    content.push(char);
+                    content.push(src.remove(0));
+                } else {
+                    depth += 1;
+                    content.push(char);
+                }
+            }
+            '}' if is_template && depth > 0 => {
+                depth -= 1;
+                content.push(char);
+            }
+            _ => content.push(char),
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_strings_and_comments_are_errors() {
+        assert_eq!(
+            tokenize(String::from("let a = \"abc;")).unwrap_err(),
+            Error::at("Unterminated string literal: \"abc;\"", Span::new(8, 9))
+        );
+        assert!(tokenize(String::from("let a = $\"{b\"")).is_err());
+        assert!(tokenize(String::from("let a = @'abc")).is_err());
+        assert!(tokenize(String::from("let a = 1; /* open")).is_err());
+    }
+
+    #[test]
+    fn single_quoted_strings_are_errors() {
+        assert!(tokenize_as(String::from("var s = 'abc';"), Language::Gml).is_err());
+        assert!(tokenize(String::from("let a = 1 ` 2;")).is_err());
+    }
+
+    #[test]
+    fn tokens_know_where_they_are() {
+        let tokens = tokenize(String::from("a = \"é\";\nb += $FF;")).unwrap();
+        let spans: Vec<(usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.offset, token.end))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0, 1),
+                (2, 3),
+                (4, 8),
+                (8, 9),
+                (10, 11),
+                (12, 14),
+                (15, 18),
+                (18, 19),
+                (19, 19)
+            ]
+        );
+    }
 }
@@ -1,31 +1,955 @@
+use crate::compiler::error::{Error, Span};
+use crate::compiler::lint::{find_lint, Diagnostic, Lint, LintLevel, LINTS};
+use crate::compiler::optimizer::OptLevel;
+use crate::compiler::printer::FormatOptions;
+use crate::compiler::target::GmlVersion;
+use crate::compiler::tokenizer::Language;
+use crate::lsp::json::Json;
+use crate::manifest::Manifest;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+
+/// The program ran without finding anything wrong.
+pub const EXIT_SUCCESS: i32 = 0;
+/// The code has errors, a lint set to deny fired, or `fmt --check` found
+/// unformatted files.
+pub const EXIT_FAILURE: i32 = 1;
+/// The command line is invalid.
+pub const EXIT_USAGE: i32 = 2;
+/// A file couldn't be read or written.
+pub const EXIT_IO: i32 = 3;
+
+pub const HELP: &str = "\
+gmpp, the GML++ compiler
+
+Usage: gmpp <command> [options] [path]
+
+Commands:
+  build     Compile a .gmpp file, or every source of a .yyp project
+  check     Report errors without writing anything
+  fmt       Reformat .gmpp files, a single one or every one under a folder
+  lint      Report likely mistakes in a file, a folder or a .yyp project
+  watch     Build a .yyp project again every time one of its sources changes
+  test      Run the @test functions of a file or a folder, or write a GML script running them with -o
+  run       Run a file with the interpreter, printing what it shows with show_debug_message
+  repl      Run code as it is typed, printing the value and type of expressions
+  tokens    Print the tokens of a file
+  ast       Print the syntax tree of a file
+  init      Create a gmpp.toml and a main.gmpp in a folder
+  migrate   Convert a .gml script to GML++
+  trace     Point the frames of a GameMaker crash log at the .gmpp lines they come from
+  help      Print this help
+
+The path can be left out, or be -, to read a single file from stdin. A folder
+is built following its gmpp.toml. `gmpp <path>` is short for `gmpp build <path>`.
+
+Settings are read from the nearest gmpp.toml, and the options below override them.
+
+Options:
+  -o, --output <file>            Write the output to a file, - for stdout
+      --target <version>         GameMaker version to compile for: 2.2, 2.3, 2023-lts, latest
+      --emit <tokens|ast|ir|gml> What `build` prints, GML by default
+      --error-format <format>    How errors are printed: human, json, short or sarif
+      --color[=<when>]           Color errors: auto, always or never
+      --lang <gmpp|gml>          Language of the file, taken from its extension by default
+      --declarations <file>      Load a .d.gmpp file, can be given several times
+  -O0, -O1                       Leave the code as written, or fold constants and remove dead code (the default)
+  -O2                            Also inline small functions, and callbacks of array_map and array_foreach
+      --strict                   Report the checks of strict mode as errors
+      --timings                  Print how long each build step took
+      --check                    With `fmt`, only report unformatted files
+      --fix                      With `lint`, apply the fixes that are safe
+      --allow <lint>             Turn a lint off, can be given several times
+      --warn <lint>              Report a lint as a warning
+      --deny <lint>              Report a lint as an error
+      --project <path>           With `trace`, the project or folder to find source maps in
+      --indent-width <n>         Spaces per indent level for `fmt`
+      --max-width <n>            Line width `fmt` wraps at
+  -h, --help                     Print this help
+  -V, --version                  Print the version
+
+Exit codes: 0 on success, 1 when the code has errors, 2 for an invalid command line,
+3 when a file can't be read or written.
+";
+
+/// What to do, given before the path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Build,
+    /// Reports errors without writing any output.
+    Check,
+    /// Reformats `.gmpp` files, only reporting unformatted ones with `check`.
+    Fmt {
+        check: bool,
+    },
+    /// Reports lints, applying their fixes with `fix`.
+    Lint {
+        fix: bool,
+    },
+    /// Builds a project again every time one of its sources changes.
+    Watch,
+    /// Runs the `@test` functions, or generates a GML script running them.
+    Test,
+    /// Runs a script with the interpreter, without GameMaker.
+    Run,
+    /// Runs code as it is typed, loading a file first when given one.
+    Repl,
+    Tokens,
+    Ast,
+    /// Creates a `gmpp.toml` and a first script.
+    Init,
+    /// Converts a `.gml` script to `.gmpp`.
+    Migrate,
+    /// Rewrites the frames of a GameMaker stack trace to `.gmpp` lines.
+    Trace,
+    Help,
+    Version,
+}
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "build" => Some(Command::Build),
+            "check" => Some(Command::Check),
+            "fmt" => Some(Command::Fmt { check: false }),
+            "lint" => Some(Command::Lint { fix: false }),
+            "watch" => Some(Command::Watch),
+            "test" => Some(Command::Test),
+            "run" => Some(Command::Run),
+            "repl" => Some(Command::Repl),
+            "tokens" => Some(Command::Tokens),
+            "ast" => Some(Command::Ast),
+            "init" => Some(Command::Init),
+            "migrate" => Some(Command::Migrate),
+            "trace" => Some(Command::Trace),
+            "help" => Some(Command::Help),
+            _ => None,
+        }
+    }
+}
+
+/// What `build` prints for a single file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    /// The intermediate representation, see `compiler::ir`.
+    Ir,
+    Gml,
+}
+
+impl Emit {
+    pub fn from_name(name: &str) -> Result<Emit, String> {
+        match name {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "ir" => Ok(Emit::Ir),
+            "gml" => Ok(Emit::Gml),
+            _ => Err(format!(
+                "Unknown output '{name}', expected one of: tokens, ast, ir, gml"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// `error: message`, with the file on its own line.
+    Human,
+    /// One JSON object per error.
+    Json,
+    /// `file: error: message`, one line per error.
+    Short,
+    /// A SARIF 2.1.0 log of the whole run, printed when it ends.
+    Sarif,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Result<ErrorFormat, String> {
+        match name {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            "short" => Ok(ErrorFormat::Short),
+            "sarif" => Ok(ErrorFormat::Sarif),
+            _ => Err(format!(
+                "Unknown error format '{name}', expected one of: human, json, short, sarif"
+            )),
+        }
+    }
+}
+
 pub struct Config {
+    pub command: Command,
+    /// File or folder to work on, `-` for stdin.
     pub path: String,
+    /// `--strict`, turning strict mode on for every file.
     pub strict: bool,
+    pub target: GmlVersion,
+    /// `-O0`, `-O1` or `-O2`, the last one given winning.
+    pub opt_level: OptLevel,
+    /// Extra `.d.gmpp` files describing extensions.
+    pub declarations: Vec<String>,
+    /// Language of the source file, taken from its extension when not given.
+    pub language: Option<Language>,
+    pub format: FormatOptions,
+    /// Print how long each build step took.
+    pub timings: bool,
+    /// Where the output goes, stdout when not given or `-`.
+    pub output: Option<String>,
+    pub emit: Emit,
+    /// Levels given with `--allow`, `--warn` and `--deny`, later ones winning.
+    pub lints: Vec<(String, LintLevel)>,
+    pub error_format: ErrorFormat,
+    /// Whether errors are printed in color.
+    pub color: bool,
+    /// `--project`, where `trace` looks for source maps.
+    pub project: Option<String>,
+    /// The `gmpp.toml` found above the path, whose settings the flags override.
+    pub manifest: Option<Manifest>,
+    /// Results of the SARIF log, printed by `flush_diagnostics`.
+    sarif_results: RefCell<Vec<Json>>,
 }
 
 impl Config {
-    pub fn new(path: String, strict: bool) -> Config {
-        Config { path, strict }
+    pub fn reads_stdin(&self) -> bool {
+        self.path == "-"
+    }
+
+    /// Whether strict mode applies to `file`, from `--strict` or the manifest.
+    pub fn strict_for(&self, file: &std::path::Path) -> bool {
+        (self.strict_files())(file)
+    }
+
+    /// The level of a lint in `file`: from the command line, else from the
+    /// manifest, else its default.
+    pub fn lint_level(&self, file: &std::path::Path, lint: &Lint) -> LintLevel {
+        self.lints
+            .iter()
+            .rfind(|(name, _)| name == lint.name)
+            .map(|(_, level)| *level)
+            .or_else(|| {
+                self.manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.lint_level(file, lint.name))
+            })
+            .unwrap_or(lint.level)
+    }
+
+    /// `strict_for` owning what it needs, for a build database.
+    pub fn strict_files(&self) -> impl Fn(&std::path::Path) -> bool + 'static {
+        let strict = self.strict;
+        let manifest = self.manifest.clone();
+        move |file| {
+            strict
+                || manifest
+                    .as_ref()
+                    .is_some_and(|manifest| manifest.strict_for(file))
+        }
+    }
+}
+
+/// Options followed by a value, as `--target 2.3` or `--target=2.3`.
+const VALUE_OPTIONS: [&str; 14] = [
+    "-o",
+    "--output",
+    "--target",
+    "--emit",
+    "--error-format",
+    "--lang",
+    "--declarations",
+    "--indent-width",
+    "--max-width",
+    "--color",
+    "--allow",
+    "--warn",
+    "--deny",
+    "--project",
+];
+
+pub fn parse_arguments(args: &[String]) -> Result<Config, String> {
+    let mut command = None;
+    let mut path = None;
+    let mut flags: Vec<&str> = Vec::new();
+    let mut options: Vec<(&str, String)> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with('-') => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        if name == "--color" && inline_value.is_none() {
+            options.push(("--color", String::from("always")));
+        } else if VALUE_OPTIONS.contains(&name) {
+            let value = match inline_value {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .cloned()
+                    .ok_or(format!("{name} needs a value"))?,
+            };
+            options.push((name, value));
+        } else if name.starts_with('-') && name != "-" {
+            if inline_value.is_some() {
+                return Err(format!("{name} doesn't take a value"));
+            }
+            flags.push(name);
+        } else if command.is_none() && path.is_none() && Command::from_name(name).is_some() {
+            command = Command::from_name(name);
+        } else if path.is_none() {
+            path = Some(arg.clone());
+        } else {
+            return Err(format!("Unexpected argument '{arg}'"));
+        }
+    }
+
+    for flag in &flags {
+        if ![
+            "--strict",
+            "--timings",
+            "--check",
+            "--fix",
+            "-O0",
+            "-O1",
+            "-O2",
+            "-h",
+            "--help",
+            "-V",
+            "--version",
+        ]
+        .contains(flag)
+        {
+            return Err(format!("Unknown option '{flag}'"));
+        }
+    }
+
+    let has_flag = |flag: &str| flags.contains(&flag);
+    let option = |name: &str| {
+        options
+            .iter()
+            .rfind(|(option, _)| *option == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let command = if has_flag("-h") || has_flag("--help") {
+        Command::Help
+    } else if has_flag("-V") || has_flag("--version") {
+        Command::Version
+    } else {
+        match command.unwrap_or(Command::Build) {
+            Command::Fmt { .. } => Command::Fmt {
+                check: has_flag("--check"),
+            },
+            Command::Lint { .. } => Command::Lint {
+                fix: has_flag("--fix"),
+            },
+            command => command,
+        }
+    };
+
+    let path = match (path, command) {
+        (Some(path), _) => path,
+        (None, Command::Init | Command::Repl) => String::from("."),
+        (None, Command::Help | Command::Version) => String::new(),
+        (None, Command::Watch) => return Err(String::from("watch needs a .yyp project")),
+        (None, _) if std::io::stdin().is_terminal() => {
+            return Err(String::from(
+                "No file given, pass a path or pipe code to stdin",
+            ))
+        }
+        (None, _) => String::from("-"),
+    };
+
+    let manifest = match command {
+        Command::Init | Command::Help | Command::Version => None,
+        _ => Manifest::find(&manifest_start(&path)).map_err(|errors| errors.join("\nerror: "))?,
+    };
+
+    let target = match option("--target") {
+        Some(name) => GmlVersion::from_name(&name)?,
+        None => manifest
+            .as_ref()
+            .and_then(|manifest| manifest.target)
+            .unwrap_or(GmlVersion::Latest),
+    };
+
+    let mut declarations: Vec<String> = manifest
+        .as_ref()
+        .map(|manifest| manifest.declarations.clone())
+        .unwrap_or_default();
+    declarations.extend(
+        options
+            .iter()
+            .filter(|(name, _)| *name == "--declarations")
+            .map(|(_, file)| file.clone()),
+    );
+
+    let mut lints = Vec::new();
+    for (option, name) in &options {
+        let level = match *option {
+            "--allow" => LintLevel::Allow,
+            "--warn" => LintLevel::Warn,
+            "--deny" => LintLevel::Deny,
+            _ => continue,
+        };
+        let lint = find_lint(name).ok_or(format!("Unknown lint '{name}'"))?;
+        lints.push((lint.name.to_string(), level));
+    }
+
+    let opt_level = match flags.iter().rfind(|flag| flag.starts_with("-O")) {
+        Some(&"-O0") => OptLevel::O0,
+        Some(&"-O2") => OptLevel::O2,
+        _ => OptLevel::O1,
+    };
+
+    let language = match option("--lang") {
+        Some(name) => Some(Language::from_name(&name)?),
+        None => None,
+    };
+
+    let defaults = FormatOptions::default();
+    let format = FormatOptions::new(
+        read_width(option("--indent-width"), "--indent-width")?
+            .or(manifest.as_ref().and_then(|manifest| manifest.indent_width))
+            .unwrap_or(defaults.indent_width),
+        read_width(option("--max-width"), "--max-width")?
+            .or(manifest.as_ref().and_then(|manifest| manifest.max_width))
+            .unwrap_or(defaults.max_width),
+    );
+
+    let emit = match (command, option("--emit")) {
+        (Command::Tokens, _) => Emit::Tokens,
+        (Command::Ast, _) => Emit::Ast,
+        (_, Some(name)) => Emit::from_name(&name)?,
+        (_, None) => Emit::Gml,
+    };
+
+    let error_format = match option("--error-format") {
+        Some(name) => ErrorFormat::from_name(&name)?,
+        None => ErrorFormat::Human,
+    };
+
+    let color = match option("--color").as_deref() {
+        Some("always") => true,
+        Some("never") => false,
+        Some("auto") | None => {
+            std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+        }
+        Some(when) => {
+            return Err(format!(
+                "Unknown color choice '{when}', expected one of: auto, always, never"
+            ))
+        }
+    };
+
+    Ok(Config {
+        command,
+        path,
+        strict: has_flag("--strict"),
+        target,
+        opt_level,
+        declarations,
+        language,
+        format,
+        timings: has_flag("--timings"),
+        output: option("--output").or_else(|| option("-o")),
+        emit,
+        lints,
+        error_format,
+        color,
+        project: option("--project"),
+        manifest,
+        sarif_results: RefCell::new(Vec::new()),
+    })
+}
+
+fn read_width(value: Option<String>, flag: &str) -> Result<Option<usize>, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{flag} needs a number, found '{value}'")),
+        None => Ok(None),
+    }
+}
+
+/// Where to start looking for a `gmpp.toml`: the folder of the path, or the
+/// working directory for stdin.
+fn manifest_start(path: &str) -> std::path::PathBuf {
+    let path = std::path::Path::new(path);
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+
+    match path.parent() {
+        Some(parent) if path != std::path::Path::new("-") && !parent.as_os_str().is_empty() => {
+            parent.to_path_buf()
+        }
+        _ => std::path::PathBuf::from("."),
+    }
+}
+
+/// Reads the file given on the command line, or stdin for `-`.
+pub fn get_source_code(config: &Config) -> Result<String, String> {
+    if config.reads_stdin() {
+        let mut source_code = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source_code)
+            .map_err(|err| format!("stdin: {err}"))?;
+        return Ok(source_code);
+    }
+
+    std::fs::read_to_string(&config.path).map_err(|err| format!("{}: {err}", config.path))
+}
+
+/// Writes the output to `--output`, or stdout when it isn't given.
+pub fn write_output(config: &Config, output: &str) -> Result<(), String> {
+    match config.output.as_deref() {
+        Some(path) if path != "-" => {
+            std::fs::write(path, output).map_err(|err| format!("{path}: {err}"))
+        }
+        _ => {
+            print!("{output}");
+            Ok(())
+        }
     }
 }
-pub fn parse_arguments() -> Result<Config, String> {
-    let args: Vec<String> = std::env::args().collect();
 
-    if args.len() < 2 {
-        return Err(String::from("not enought arguments"));
+/// A diagnostic ready to print: an error or warning from the compiler, or a
+/// lint with its code.
+pub struct Report {
+    pub file: Option<String>,
+    /// The code it is about, in bytes of the file.
+    pub span: Option<Span>,
+    pub message: String,
+    pub lint: Option<&'static Lint>,
+    /// What `lint --fix` does about it, with the code it writes instead and
+    /// the code it edits.
+    pub fix: Option<(String, Option<String>, Vec<Span>)>,
+}
+
+impl Report {
+    pub fn new(error: &Error) -> Report {
+        Report {
+            file: error.file.as_deref().map(display_path),
+            span: error.span,
+            message: error.message.clone(),
+            lint: None,
+            fix: None,
+        }
     }
 
-    let path = String::from(args[1].as_str());
+    /// A lint found in `file`, which is `None` for code read from stdin.
+    pub fn lint(file: Option<&Path>, diagnostic: &Diagnostic) -> Report {
+        Report {
+            file: file.map(|file| display_path(&file.display().to_string())),
+            span: diagnostic.span,
+            message: diagnostic.message.clone(),
+            lint: Some(diagnostic.lint),
+            fix: diagnostic
+                .suggestion()
+                .map(|(message, code)| (message, code, diagnostic.fix_spans())),
+        }
+    }
 
-    let strict_command = String::from("--strict");
+    /// The message with the lint it comes from, as the text formats show it.
+    fn text(&self) -> String {
+        match self.lint {
+            Some(lint) => {
+                let fixable = if self.fix.is_some() {
+                    " (fixable with --fix)"
+                } else {
+                    ""
+                };
+                format!("{} [{} {}]{fixable}", self.message, lint.code, lint.name)
+            }
+            None => self.message.clone(),
+        }
+    }
+}
 
-    Ok(Config::new(path, args.contains(&strict_command)))
+/// A file as reports show it: relative to the working directory when it is
+/// in it, whether the compiler or the linter found the problem.
+fn display_path(file: &str) -> String {
+    let path = Path::new(file);
+    let relative = match std::env::current_dir().and_then(std::fs::canonicalize) {
+        Ok(cwd) if path.is_absolute() => path.strip_prefix(&cwd).ok(),
+        _ => path.strip_prefix(".").ok(),
+    };
+    match relative {
+        Some(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+        _ => file.to_string(),
+    }
 }
 
-pub fn get_source_code(config: &Config) -> Result<String, std::io::Error> {
-    std::fs::read_to_string(&config.path)
+/// Prints errors in the format asked for with `--error-format`.
+pub fn print_errors(config: &Config, errors: &[Error]) {
+    let reports: Vec<Report> = errors.iter().map(Report::new).collect();
+    print_reports(config, "error", &reports);
+}
+
+/// Prints warnings like `print_errors`.
+pub fn print_warnings(config: &Config, warnings: &[Error]) {
+    let reports: Vec<Report> = warnings.iter().map(Report::new).collect();
+    print_reports(config, "warning", &reports);
+}
+
+/// Prints errors or warnings, as `severity` says.
+pub fn print_reports(config: &Config, severity: &str, reports: &[Report]) {
+    let color = if severity == "error" { "1;31" } else { "1;33" };
+    let mut sources = Sources::default();
+
+    for report in reports {
+        let label = paint(config, color, severity);
+        let lines = sources.lines(report);
+        let location = location(report, lines);
+
+        match config.error_format {
+            ErrorFormat::Human => {
+                eprintln!("{label}: {}", report.text());
+                if let Some(location) = location {
+                    eprintln!("  {} {location}", paint(config, "1;34", "-->"));
+                }
+            }
+            ErrorFormat::Short => match location {
+                Some(location) => eprintln!("{location}: {label}: {}", report.text()),
+                None => eprintln!("{label}: {}", report.text()),
+            },
+            ErrorFormat::Json => {
+                let fix_lines = match &report.fix {
+                    Some((_, _, spans)) => spans
+                        .iter()
+                        .map(|span| sources.span_lines(report.file.as_ref(), *span))
+                        .collect::<Option<Vec<Lines>>>()
+                        .unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                println!("{}", json_report(severity, report, lines, &fix_lines));
+            }
+            ErrorFormat::Sarif => {
+                let result = sarif_result(severity, report, lines);
+                config.sarif_results.borrow_mut().push(result);
+            }
+        }
+    }
+
+    if severity == "error" && config.error_format == ErrorFormat::Human && reports.len() > 1 {
+        eprintln!(
+            "{}: found {} errors",
+            paint(config, "1;31", "error"),
+            reports.len()
+        );
+    }
+}
+
+/// Prints the SARIF log of what was reported since the last call, when
+/// asked for with `--error-format=sarif`.
+pub fn flush_diagnostics(config: &Config) {
+    if config.error_format != ErrorFormat::Sarif {
+        return;
+    }
+
+    let rules = LINTS
+        .iter()
+        .map(|lint| {
+            Json::object(vec![
+                ("id", Json::string(lint.code)),
+                ("name", Json::string(lint.name)),
+                (
+                    "shortDescription",
+                    Json::object(vec![("text", Json::string(lint.description))]),
+                ),
+                (
+                    "defaultConfiguration",
+                    Json::object(vec![("level", Json::string(sarif_level(lint.level)))]),
+                ),
+            ])
+        })
+        .collect();
+    let driver = Json::object(vec![
+        ("name", Json::string("gmpp")),
+        ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+        ("rules", Json::Array(rules)),
+    ]);
+    let results = config.sarif_results.take();
+
+    let log = Json::object(vec![
+        ("version", Json::string("2.1.0")),
+        (
+            "$schema",
+            Json::string("https://json.schemastore.org/sarif-2.1.0.json"),
+        ),
+        (
+            "runs",
+            Json::Array(vec![Json::object(vec![
+                ("tool", Json::object(vec![("driver", driver)])),
+                ("columnKind", Json::string("unicodeCodePoints")),
+                ("results", Json::Array(results)),
+            ])]),
+        ),
+    ]);
+    println!("{log}");
+}
+
+fn sarif_level(level: LintLevel) -> &'static str {
+    match level {
+        LintLevel::Allow => "none",
+        LintLevel::Warn => "warning",
+        LintLevel::Deny => "error",
+    }
+}
+
+/// First and last line and column of the code a report is about, counted
+/// from 1, the end column being just past the code.
+type Lines = (usize, usize, usize, usize);
+
+/// The files reports point to, read once to find the lines of their spans.
+#[derive(Default)]
+struct Sources {
+    texts: HashMap<String, Option<String>>,
+}
+
+impl Sources {
+    fn lines(&mut self, report: &Report) -> Option<Lines> {
+        self.span_lines(report.file.as_ref(), report.span?)
+    }
+
+    fn span_lines(&mut self, file: Option<&String>, span: Span) -> Option<Lines> {
+        let file = file?;
+        let text = self
+            .texts
+            .entry(file.clone())
+            .or_insert_with(|| std::fs::read_to_string(file).ok())
+            .as_ref()?;
+        Some(span.lines(text))
+    }
+}
+
+/// `file:line:column` of a report when the code it is about is known, as
+/// editors link it, or else its file.
+fn location(report: &Report, lines: Option<Lines>) -> Option<String> {
+    let file = report.file.as_ref()?;
+    Some(match lines {
+        Some((line, column, _, _)) => format!("{file}:{line}:{column}"),
+        None => file.clone(),
+    })
+}
+
+/// One line of `--error-format=json`, `fix_lines` being where each edit of
+/// its fix is. The fields stay the same between versions, new ones only
+/// being added.
+fn json_report(severity: &str, report: &Report, lines: Option<Lines>, fix_lines: &[Lines]) -> Json {
+    let mut fields = vec![
+        ("severity", Json::string(severity)),
+        (
+            "code",
+            report
+                .lint
+                .map_or(Json::Null, |lint| Json::string(lint.code)),
+        ),
+        (
+            "rule",
+            report
+                .lint
+                .map_or(Json::Null, |lint| Json::string(lint.name)),
+        ),
+        ("message", Json::string(&report.message)),
+    ];
+    // Code read from stdin has no file.
+    fields.push((
+        "file",
+        report.file.as_deref().map_or(Json::Null, Json::string),
+    ));
+
+    let span = |file: &String, lines: Lines| {
+        let (line_start, column_start, line_end, column_end) = lines;
+        Json::object(vec![
+            ("file", Json::string(file)),
+            ("line_start", Json::number(line_start)),
+            ("column_start", Json::number(column_start)),
+            ("line_end", Json::number(line_end)),
+            ("column_end", Json::number(column_end)),
+        ])
+    };
+    let spans = report
+        .file
+        .as_ref()
+        .zip(lines)
+        .map(|(file, lines)| span(file, lines));
+    fields.push(("spans", Json::Array(spans.into_iter().collect())));
+
+    let fixes = report.fix.as_ref().map(|(message, replacement, _)| {
+        let spans = report
+            .file
+            .iter()
+            .flat_map(|file| fix_lines.iter().map(|lines| span(file, *lines)))
+            .collect();
+        Json::object(vec![
+            ("message", Json::string(message)),
+            (
+                "replacement",
+                replacement.as_deref().map_or(Json::Null, Json::string),
+            ),
+            ("spans", Json::Array(spans)),
+        ])
+    });
+    fields.push(("fixes", Json::Array(fixes.into_iter().collect())));
+
+    Json::object(fields)
+}
+
+/// A file as SARIF wants it, a URI relative to where gmpp runs, or a `file:`
+/// one for absolute paths.
+fn artifact_location(file: &str) -> Json {
+    let uri = file.replace('\\', "/");
+    let uri = if Path::new(file).is_absolute() {
+        format!(
+            "file://{}",
+            if uri.starts_with('/') {
+                uri
+            } else {
+                format!("/{uri}")
+            }
+        )
+    } else {
+        uri
+    };
+    Json::object(vec![("uri", Json::String(uri))])
+}
+
+fn sarif_result(severity: &str, report: &Report, lines: Option<Lines>) -> Json {
+    let mut fields = Vec::new();
+    if let Some(lint) = report.lint {
+        fields.push(("ruleId", Json::string(lint.code)));
+        let index = LINTS.iter().position(|rule| rule.code == lint.code);
+        fields.push(("ruleIndex", Json::number(index.unwrap_or(0))));
+    }
+    fields.push(("level", Json::string(severity)));
+    fields.push((
+        "message",
+        Json::object(vec![("text", Json::string(&report.message))]),
+    ));
+
+    let location = report.file.as_ref().map(|file| {
+        let mut location = vec![("artifactLocation", artifact_location(file))];
+        if let Some((line_start, column_start, line_end, column_end)) = lines {
+            let region = Json::object(vec![
+                ("startLine", Json::number(line_start)),
+                ("startColumn", Json::number(column_start)),
+                ("endLine", Json::number(line_end)),
+                ("endColumn", Json::number(column_end)),
+            ]);
+            location.push(("region", region));
+        }
+        Json::object(location)
+    });
+    let locations = location
+        .map(|location| Json::object(vec![("physicalLocation", location)]))
+        .into_iter()
+        .collect();
+    fields.push(("locations", Json::Array(locations)));
+
+    Json::object(fields)
+}
+
+fn paint(config: &Config, style: &str, text: &str) -> String {
+    if config.color {
+        format!("\x1b[{style}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
 }
 
 pub fn get_indent(size: usize, indent: usize) -> String {
     " ".repeat(size * indent)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report::new(
+            &Error::at("Unknown variable 'speed'", Span::new(10, 15))
+                .in_file(Path::new("src/player.gmpp")),
+        )
+    }
+
+    fn lint_report() -> Report {
+        Report {
+            file: Some(String::from("src/player.gmpp")),
+            span: Some(Span::new(0, 17)),
+            message: String::from("'x' is assigned to itself"),
+            lint: find_lint("self-assignment"),
+            fix: Some((
+                String::from("Remove the statement"),
+                None,
+                vec![Span::new(0, 17)],
+            )),
+        }
+    }
+
+    #[test]
+    fn reports_point_at_their_line_and_column() {
+        assert_eq!(
+            location(&report(), Some((2, 3, 2, 8))),
+            Some(String::from("src/player.gmpp:2:3"))
+        );
+        assert_eq!(
+            location(&report(), None),
+            Some(String::from("src/player.gmpp"))
+        );
+        assert_eq!(location(&Report::new(&Error::new("No file")), None), None);
+    }
+
+    #[test]
+    fn json_reports() {
+        assert_eq!(
+            json_report("error", &report(), Some((2, 3, 2, 8)), &[]).to_string(),
+            r#"{"severity":"error","code":null,"rule":null,"message":"Unknown variable 'speed'","file":"src/player.gmpp","spans":[{"file":"src/player.gmpp","line_start":2,"column_start":3,"line_end":2,"column_end":8}],"fixes":[]}"#
+        );
+        assert_eq!(
+            json_report(
+                "warning",
+                &lint_report(),
+                Some((1, 1, 1, 18)),
+                &[(1, 1, 1, 18)]
+            )
+            .to_string(),
+            r#"{"severity":"warning","code":"L007","rule":"self-assignment","message":"'x' is assigned to itself","file":"src/player.gmpp","spans":[{"file":"src/player.gmpp","line_start":1,"column_start":1,"line_end":1,"column_end":18}],"fixes":[{"message":"Remove the statement","replacement":null,"spans":[{"file":"src/player.gmpp","line_start":1,"column_start":1,"line_end":1,"column_end":18}]}]}"#
+        );
+        // Code read from stdin.
+        let stdin = Report::new(&Error::at("Unknown variable 'speed'", Span::new(10, 15)));
+        assert_eq!(
+            json_report("error", &stdin, None, &[]).to_string(),
+            r#"{"severity":"error","code":null,"rule":null,"message":"Unknown variable 'speed'","file":null,"spans":[],"fixes":[]}"#
+        );
+    }
+
+    #[test]
+    fn report_paths_are_relative_to_the_working_directory() {
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        let absolute = cwd.join("src").join("player.gmpp");
+        assert_eq!(
+            display_path(&absolute.display().to_string()),
+            Path::new("src").join("player.gmpp").display().to_string()
+        );
+        assert_eq!(display_path("./src/player.gmpp"), "src/player.gmpp");
+        assert_eq!(display_path("src/player.gmpp"), "src/player.gmpp");
+    }
+
+    #[test]
+    fn sarif_results() {
+        assert_eq!(
+            sarif_result("error", &report(), Some((2, 3, 2, 8))).to_string(),
+            r#"{"level":"error","message":{"text":"Unknown variable 'speed'"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/player.gmpp"},"region":{"startLine":2,"startColumn":3,"endLine":2,"endColumn":8}}}]}"#
+        );
+        assert_eq!(
+            sarif_result("warning", &lint_report(), None).to_string(),
+            r#"{"ruleId":"L007","ruleIndex":6,"level":"warning","message":{"text":"'x' is assigned to itself"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/player.gmpp"}}}]}"#
+        );
+    }
+}
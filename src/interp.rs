@@ -0,0 +1,1615 @@
+pub mod host;
+pub mod value;
+
+use crate::compiler::ast::Node;
+use crate::compiler::parser::{parse, Positions};
+use crate::compiler::tokenizer::{tokenize, TokenType};
+use crate::interp::host::Host;
+use crate::interp::value::{equals, format_real, Array, Callable, Function, Struct, Value, HOST};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// How deep calls can nest before the script is stopped.
+const MAX_DEPTH: usize = 256;
+
+/// An error stopping the script, and the statement it happened in.
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    /// `path:line:column` of the statement, when it was parsed with positions.
+    pub location: Option<Rc<str>>,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{} at {location}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Why statements stopped running before their end.
+enum Flow {
+    Break,
+    Continue,
+    Return(Value),
+    Exit,
+    Error(RuntimeError),
+    /// A value thrown by `throw`, and where it was.
+    Throw(Value, Option<Rc<str>>),
+}
+
+type Exec<T> = Result<T, Flow>;
+
+/// A call being run, or the top level of the scripts.
+struct Frame {
+    /// Arrays made during the call belong to it, see `ArrayData::owner`.
+    id: usize,
+    locals: HashMap<String, Value>,
+    this: Struct,
+    other: Struct,
+    args: Vec<Value>,
+    /// The function running, whose static variables can be used.
+    function: Option<Rc<Node>>,
+    /// The statement the call was made from, where errors point again once
+    /// it returns.
+    caller: Option<Rc<str>>,
+}
+
+/// Runs GML++ and GML programs by walking their syntax tree, with the
+/// built-in functions of a `Host`.
+///
+/// Scripts share one global struct, the `self` of top-level code, and their
+/// functions, enums and macros are declared before any of them runs. The
+/// programs are kept as long as the interpreter, so nodes are told apart by
+/// their address, and function values hold a copy of their function.
+pub struct Interpreter<H: Host> {
+    pub host: H,
+    global: Struct,
+    /// Variables declared with `globalvar`, which always live in `global`.
+    globalvars: HashSet<String>,
+    functions: HashMap<String, Rc<Node>>,
+    enums: HashMap<String, Vec<(String, Value)>>,
+    /// The value of each macro parsed as an expression, `None` when it isn't one.
+    macros: HashMap<String, Option<Rc<Node>>>,
+    statics: HashMap<*const Node, Struct>,
+    /// The programs loaded and the expressions evaluated, for the addresses
+    /// of their nodes to stay theirs.
+    programs: Vec<Rc<Node>>,
+    /// The copy of each function, constructor and class function values are
+    /// made from, by the address of the node it copies.
+    scripts: HashMap<*const Node, Rc<Node>>,
+    frames: Vec<Frame>,
+    next_frame: usize,
+    locations: HashMap<*const Node, Rc<str>>,
+    location: Option<Rc<str>>,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl<H: Host> Interpreter<H> {
+    pub fn new(host: H) -> Interpreter<H> {
+        let global = Struct::new();
+        let top = Frame {
+            id: HOST + 1,
+            locals: HashMap::new(),
+            this: global.clone(),
+            other: global.clone(),
+            args: Vec::new(),
+            function: None,
+            caller: None,
+        };
+
+        Interpreter {
+            host,
+            global,
+            globalvars: HashSet::new(),
+            functions: HashMap::new(),
+            enums: HashMap::new(),
+            macros: HashMap::new(),
+            statics: HashMap::new(),
+            programs: Vec::new(),
+            scripts: HashMap::new(),
+            frames: vec![top],
+            next_frame: HOST + 2,
+            locations: HashMap::new(),
+            location: None,
+            steps: 0,
+            step_limit: None,
+        }
+    }
+
+    /// Stops scripts running more statements than `limit`, so a loop that
+    /// never ends fails instead of hanging.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = Some(limit);
+    }
+
+    pub fn global(&self) -> &Struct {
+        &self.global
+    }
+
+    /// Declares the functions, enums and macros of a program, then runs its
+    /// top-level statements. Errors point at `path` when positions are given.
+    pub fn load(
+        &mut self,
+        program: Rc<Node>,
+        positions: &Positions,
+        source: &str,
+        path: &str,
+    ) -> Result<(), RuntimeError> {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        for (node, span) in positions {
+            let line = line_starts.partition_point(|start| *start <= span.start) - 1;
+            let column = source
+                .get(line_starts[line]..span.start)
+                .map_or(0, |text| text.chars().count());
+            let location = format!("{path}:{}:{}", line + 1, column + 1);
+            self.locations.insert(*node, Rc::from(location));
+        }
+
+        self.programs.push(program.clone());
+        let Node::Program(body) = program.as_ref() else {
+            return Ok(());
+        };
+        self.declare(body).map_err(into_error)?;
+        for stmt in body {
+            match self.execute(stmt) {
+                Ok(()) => {}
+                Err(flow @ (Flow::Error(_) | Flow::Throw(_, _))) => return Err(into_error(flow)),
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls a function by name, the way a test runner calls a test.
+    pub fn call_global(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.location = None;
+        let function = self.lookup(name).map_err(into_error)?;
+        self.call_value(&function, None, args).map_err(into_error)
+    }
+
+    /// The value of an expression in the top-level scope.
+    pub fn evaluate(&mut self, expr: Rc<Node>) -> Result<Value, RuntimeError> {
+        self.location = None;
+        self.programs.push(expr.clone());
+        self.eval(&expr).map_err(into_error)
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames
+            .last()
+            .expect("The top-level frame is never popped")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("The top-level frame is never popped")
+    }
+
+    fn error(&self, message: String) -> Flow {
+        Flow::Error(RuntimeError {
+            message,
+            location: self.location.clone(),
+        })
+    }
+
+    fn tick(&mut self) -> Exec<()> {
+        self.steps += 1;
+        match self.step_limit {
+            Some(limit) if self.steps > limit => Err(self.error(format!(
+                "Stopped after running {limit} statements, is there a loop that never ends?"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn declare(&mut self, body: &[Box<Node>]) -> Exec<()> {
+        for stmt in body {
+            match stmt.declaration() {
+                declaration @ (Node::FunctionDeclaration(name, _, _, _)
+                | Node::ClassDeclaration(name, _)) => {
+                    let script = self.script(declaration);
+                    self.functions.insert(name.clone(), script);
+                }
+                declaration @ Node::ConstructorDeclaration(function, _) => {
+                    if let Node::FunctionDeclaration(name, _, _, _) = function.as_ref() {
+                        let script = self.script(declaration);
+                        self.functions.insert(name.clone(), script);
+                    }
+                }
+                Node::EnumDeclaration(name, members) => {
+                    let mut next = 0.0;
+                    self.enums.insert(name.clone(), Vec::new());
+                    for (member, value) in members {
+                        if let Some(value) = value {
+                            let value = self.eval(value)?;
+                            next = self.real(&value)?;
+                        }
+                        if let Some(members) = self.enums.get_mut(name) {
+                            members.push((member.clone(), Value::Real(next)));
+                        }
+                        next += 1.0;
+                    }
+                }
+                Node::MacroDeclaration(name, text) => {
+                    // Parsed as the value of a variable, since statements
+                    // can't start with every expression.
+                    let value = match tokenize(format!("var value = {text};")).and_then(parse) {
+                        Ok(Node::Program(mut body)) if body.len() == 1 => match *body.remove(0) {
+                            Node::VariableDeclaration(_, _, _, value) => Some(Rc::new(*value)),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    self.programs.extend(value.clone());
+                    self.macros.insert(name.clone(), value);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, node: &Node) -> Exec<()> {
+        if let Some(location) = self.locations.get(&(node as *const Node)) {
+            self.location = Some(location.clone());
+        }
+        self.tick()?;
+
+        match node {
+            Node::VariableDeclaration(declaration_type, name, _, value) => {
+                match declaration_type.as_ref() {
+                    Node::DeclarationType(TokenType::Var) => {
+                        let value = self.eval(value)?;
+                        self.frame_mut().locals.insert(name.clone(), value);
+                    }
+                    Node::DeclarationType(TokenType::Static) => match self.frame().function.clone()
+                    {
+                        Some(function) => {
+                            let statics = self.statics_of(&function);
+                            if !statics.has(name) {
+                                let value = self.eval(value)?;
+                                statics.set(name, value);
+                            }
+                        }
+                        None => {
+                            let value = self.eval(value)?;
+                            self.frame_mut().locals.insert(name.clone(), value);
+                        }
+                    },
+                    _ => {
+                        let value = self.eval(value)?;
+                        self.set_variable(name, value);
+                    }
+                }
+            }
+            Node::DeclarationList(declarations) | Node::Block(declarations) => {
+                for declaration in declarations {
+                    self.execute(declaration)?;
+                }
+            }
+            Node::GlobalVarDeclaration(names) => {
+                self.globalvars.extend(names.iter().cloned());
+            }
+            Node::FunctionDeclaration(name, _, _, _) | Node::ClassDeclaration(name, _) => {
+                self.declare_nested(name, node);
+            }
+            Node::ConstructorDeclaration(function, _) => {
+                if let Node::FunctionDeclaration(name, _, _, _) = function.as_ref() {
+                    self.declare_nested(name, node);
+                }
+            }
+            Node::ExportDeclaration(declaration) | Node::Attributed(_, declaration) => {
+                self.execute(declaration)?;
+            }
+            Node::Assignment(target, operator, value) => {
+                self.assign_with(target, operator, value)?;
+            }
+            Node::ReturnStatement(value) => {
+                let value = self.eval(value)?;
+                return Err(Flow::Return(value));
+            }
+            Node::IfStatement(condition, then, otherwise) => {
+                if self.condition(condition)? {
+                    self.execute(then)?;
+                } else if let Some(otherwise) = otherwise {
+                    self.execute(otherwise)?;
+                }
+            }
+            Node::WhileStatement(condition, body) => {
+                while self.condition(condition)? {
+                    if self.run_loop_body(body)? {
+                        break;
+                    }
+                }
+            }
+            Node::DoUntilStatement(body, condition) => loop {
+                if self.run_loop_body(body)? || self.condition(condition)? {
+                    break;
+                }
+            },
+            Node::RepeatStatement(count, body) => {
+                let count = self.eval(count)?;
+                let count = self.real(&count)?;
+                let mut i = 0.0;
+                while i < count.round() {
+                    if self.run_loop_body(body)? {
+                        break;
+                    }
+                    i += 1.0;
+                }
+            }
+            Node::ForStatement(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.execute(init)?;
+                }
+                loop {
+                    if let Some(condition) = condition {
+                        if !self.condition(condition)? {
+                            break;
+                        }
+                    }
+                    if self.run_loop_body(body)? {
+                        break;
+                    }
+                    if let Some(step) = step {
+                        self.execute(step)?;
+                    }
+                }
+            }
+            Node::WithStatement(value, body) => {
+                // Only structs can be run with, there are no instances
+                // outside of the game.
+                if let Value::Struct(instance) = self.eval(value)? {
+                    let frame = self.frame_mut();
+                    let outer_other = std::mem::replace(&mut frame.other, frame.this.clone());
+                    let outer_this = std::mem::replace(&mut frame.this, instance);
+                    let result = self.run_loop_body(body);
+                    let frame = self.frame_mut();
+                    frame.this = outer_this;
+                    frame.other = outer_other;
+                    result?;
+                }
+            }
+            Node::SwitchStatement(value, cases) => self.switch(value, cases)?,
+            Node::TryStatement(body, catch, finally) => {
+                let mut result = self.execute(body);
+                if let Some((name, handler)) = catch {
+                    let exception = match &result {
+                        Err(Flow::Throw(value, _)) => Some(value.clone()),
+                        Err(Flow::Error(error)) => Some(exception(error)),
+                        _ => None,
+                    };
+                    if let Some(exception) = exception {
+                        self.frame_mut().locals.insert(name.clone(), exception);
+                        result = self.execute(handler);
+                    }
+                }
+                // What `finally` does wins over how the rest stopped.
+                if let Some(finally) = finally {
+                    self.execute(finally)?;
+                }
+                result?;
+            }
+            Node::ThrowStatement(value) => {
+                let value = self.eval(value)?;
+                return Err(Flow::Throw(value, self.location.clone()));
+            }
+            Node::DeleteStatement(target) => self.assign(target, Value::Undefined)?,
+            Node::Break => return Err(Flow::Break),
+            Node::Continue => return Err(Flow::Continue),
+            Node::Exit => return Err(Flow::Exit),
+            Node::EnumDeclaration(_, _)
+            | Node::MacroDeclaration(_, _)
+            | Node::ImportDeclaration(_, _)
+            | Node::ExternFunction(_, _, _)
+            | Node::ExternVariable(_, _, _)
+            | Node::Comment(_, _)
+            | Node::BlankLine => {}
+            expr => {
+                self.eval(expr)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A function declared inside another one becomes a method of `self`,
+    /// those of the top level are already declared.
+    fn declare_nested(&mut self, name: &str, node: &Node) {
+        if self.frames.len() > 1 {
+            let this = self.frame().this.clone();
+            let script = self.script(node);
+            this.set(
+                name,
+                Value::Function(Function::script(script, Some(this.clone()))),
+            );
+        }
+    }
+
+    /// Runs the body of a loop, returning whether it hit `break`.
+    fn run_loop_body(&mut self, body: &Node) -> Exec<bool> {
+        self.tick()?;
+        match self.execute(body) {
+            Ok(()) | Err(Flow::Continue) => Ok(false),
+            Err(Flow::Break) => Ok(true),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    fn switch(&mut self, value: &Node, cases: &[Box<Node>]) -> Exec<()> {
+        let value = self.eval(value)?;
+        let mut start = None;
+        for (i, case) in cases.iter().enumerate() {
+            if let Node::SwitchCase(Some(case_value), _) = case.as_ref() {
+                if equals(&value, &self.eval(case_value)?) {
+                    start = Some(i);
+                    break;
+                }
+            }
+        }
+        let start = start.or_else(|| {
+            cases
+                .iter()
+                .position(|case| matches!(case.as_ref(), Node::SwitchCase(None, _)))
+        });
+
+        // Cases fall through to the next ones until a `break`.
+        for case in cases.iter().skip(start.unwrap_or(cases.len())) {
+            if let Node::SwitchCase(_, body) = case.as_ref() {
+                for stmt in body {
+                    match self.execute(stmt) {
+                        Err(Flow::Break) => return Ok(()),
+                        result => result?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self, node: &Node) -> Exec<bool> {
+        let value = self.eval(node)?;
+        self.truthy(&value)
+    }
+
+    fn truthy(&self, value: &Value) -> Exec<bool> {
+        value
+            .is_truthy()
+            .ok_or_else(|| self.error(format!("Expected a condition, got a {}", value.type_name())))
+    }
+
+    fn real(&self, value: &Value) -> Exec<f64> {
+        value
+            .as_real()
+            .ok_or_else(|| self.error(format!("Expected a number, got a {}", value.type_name())))
+    }
+
+    fn statics_of(&mut self, function: &Rc<Node>) -> Struct {
+        self.statics
+            .entry(Rc::as_ptr(function))
+            .or_default()
+            .clone()
+    }
+
+    /// The copy of a function node its values hold, made once so they are
+    /// all the same function, with the same static variables.
+    fn script(&mut self, node: &Node) -> Rc<Node> {
+        if let Some(script) = self.scripts.get(&(node as *const Node)) {
+            return script.clone();
+        }
+
+        let script = Rc::new(node.clone());
+        self.relocate(node, &script);
+        self.scripts.insert(node, script.clone());
+        script
+    }
+
+    /// Gives the statements of a copy the locations of those it copies.
+    fn relocate(&mut self, original: &Node, copy: &Node) {
+        if let Some(location) = self.locations.get(&(original as *const Node)) {
+            self.locations.insert(copy, location.clone());
+        }
+        for (original, copy) in original.children().into_iter().zip(copy.children()) {
+            self.relocate(original, copy);
+        }
+    }
+
+    fn eval(&mut self, node: &Node) -> Exec<Value> {
+        let value = match node {
+            Node::NumericLiteral(value, _) => Value::Real(*value),
+            Node::StringLiteral(text) => Value::string(&unescape(text)),
+            Node::BooleanLiteral(value) => Value::Bool(*value),
+            Node::TemplateString(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part.as_ref() {
+                        Node::StringLiteral(part) => text.push_str(&unescape(part)),
+                        Node::TemplateExpression(expr, Some(precision)) => {
+                            let value = self.eval(expr)?;
+                            text.push_str(&format!("{:.*}", *precision, self.real(&value)?));
+                        }
+                        Node::TemplateExpression(expr, None) => {
+                            let value = self.eval(expr)?;
+                            text.push_str(&self.stringify(value)?);
+                        }
+                        _ => {}
+                    }
+                }
+                Value::string(&text)
+            }
+            Node::Identifier(name) => self.lookup(name)?,
+            Node::BinaryExpression(left, operator, right) => {
+                let Node::BinaryOperator(operator) = operator.as_ref() else {
+                    return Err(self.error(format!("Invalid operator: {:?}", operator)));
+                };
+                self.binary_expression(left, operator, right)?
+            }
+            Node::UnaryExpression(operator, operand) => {
+                let value = self.eval(operand)?;
+                match operator.as_str() {
+                    "!" => Value::Bool(!self.truthy(&value)?),
+                    "-" => Value::Real(-self.real(&value)?),
+                    "~" => Value::Real(!(self.real(&value)? as i64) as f64),
+                    _ => value,
+                }
+            }
+            Node::UpdateExpression(operator, target, prefix) => {
+                let old = self.eval(target)?;
+                let old = self.real(&old)?;
+                let new = if operator == "++" {
+                    old + 1.0
+                } else {
+                    old - 1.0
+                };
+                self.assign(target, Value::Real(new))?;
+                Value::Real(if *prefix { new } else { old })
+            }
+            Node::TernaryExpression(condition, if_true, if_false) => {
+                if self.condition(condition)? {
+                    self.eval(if_true)?
+                } else {
+                    self.eval(if_false)?
+                }
+            }
+            Node::MemberAccess(object, member) => self.member(object, member)?,
+            Node::IndexAccess(value, accessor, indices) => {
+                let container = self.eval(value)?;
+                let mut keys = Vec::new();
+                for index in indices {
+                    keys.push(self.eval(index)?);
+                }
+                self.index(container, accessor.as_deref(), keys)?
+            }
+            Node::ArrayLiteral(items) => {
+                let mut values = Vec::new();
+                for item in items {
+                    values.push(self.eval(item)?);
+                }
+                Value::Array(Array::new(values, self.frame().id))
+            }
+            Node::StructLiteral(fields) => {
+                let instance = Struct::new();
+                for (name, value) in fields {
+                    // Functions written in a struct literal are its methods.
+                    let value = match value.as_ref() {
+                        Node::FunctionExpression(_, _) | Node::ArrowFunction(_, _, _) => {
+                            let script = self.script(value);
+                            Value::Function(Function::script(script, Some(instance.clone())))
+                        }
+                        _ => self.eval(value)?,
+                    };
+                    instance.set(name, value);
+                }
+                Value::Struct(instance)
+            }
+            Node::FunctionExpression(_, _) | Node::ArrowFunction(_, _, _) => {
+                let script = self.script(node);
+                Value::Function(Function::script(script, Some(self.frame().this.clone())))
+            }
+            Node::ConstructorDeclaration(_, _) => {
+                Value::Function(Function::script(self.script(node), None))
+            }
+            Node::NewExpression(call) => {
+                let Node::FunctionCall(callee, args) = call.as_ref() else {
+                    return Err(self.error(String::from("Expected a call after 'new'")));
+                };
+                let constructor = self.eval(callee)?;
+                let args = self.arguments(args)?;
+                self.construct(&constructor, args)?
+            }
+            Node::FunctionCall(callee, args) => self.call(callee, args)?,
+            Node::Assignment(target, operator, value) => {
+                self.assign_with(target, operator, value)?;
+                Value::Undefined
+            }
+            _ => return Err(self.error(format!("Can't evaluate {:?}", node))),
+        };
+
+        Ok(value)
+    }
+
+    fn lookup(&mut self, name: &str) -> Exec<Value> {
+        match name {
+            "undefined" => return Ok(Value::Undefined),
+            "self" | "id" => return Ok(Value::Struct(self.frame().this.clone())),
+            "other" => return Ok(Value::Struct(self.frame().other.clone())),
+            "global" => return Ok(Value::Struct(self.global.clone())),
+            "argument_count" => return Ok(Value::Real(self.frame().args.len() as f64)),
+            "argument" => {
+                let args = self.frame().args.clone();
+                return Ok(Value::Array(Array::new(args, self.frame().id)));
+            }
+            _ => {}
+        }
+        if let Some(index) = name
+            .strip_prefix("argument")
+            .and_then(|index| index.parse::<usize>().ok())
+        {
+            return Ok(self
+                .frame()
+                .args
+                .get(index)
+                .cloned()
+                .unwrap_or(Value::Undefined));
+        }
+
+        if let Some(value) = self.variable(name) {
+            return Ok(value);
+        }
+        if let Some(function) = self.functions.get(name) {
+            return Ok(Value::Function(Function::script(function.clone(), None)));
+        }
+        if let Some(value) = self.macros.get(name).cloned() {
+            return match value {
+                Some(value) => self.eval(&value),
+                None => Err(self.error(format!("Macro '{name}' isn't an expression"))),
+            };
+        }
+        if let Some(value) = self.host.constant(name) {
+            return Ok(value);
+        }
+        if is_intrinsic(name) || self.host.has_function(name) {
+            return Ok(Value::Function(Function::builtin(name)));
+        }
+
+        Err(self.error(format!("'{name}' isn't set before being read")))
+    }
+
+    /// A variable in scope: a local, a static of the running function, a
+    /// variable of `self` or a global one.
+    fn variable(&mut self, name: &str) -> Option<Value> {
+        if self.globalvars.contains(name) {
+            return self.global.get(name);
+        }
+        if let Some(value) = self.frame().locals.get(name) {
+            return Some(value.clone());
+        }
+        if let Some(function) = &self.frame().function {
+            if let Some(value) = self
+                .statics
+                .get(&Rc::as_ptr(function))
+                .and_then(|statics| statics.get(name))
+            {
+                return Some(value);
+            }
+        }
+        self.frame()
+            .this
+            .lookup(name)
+            .or_else(|| self.global.get(name))
+    }
+
+    /// Writes a variable the way GameMaker does: to the local or static one if
+    /// there is one, to `self` otherwise.
+    fn set_variable(&mut self, name: &str, value: Value) {
+        if self.globalvars.contains(name) {
+            self.global.set(name, value);
+            return;
+        }
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("The top-level frame is never popped");
+        if let Some(local) = frame.locals.get_mut(name) {
+            *local = value;
+            return;
+        }
+        if let Some(statics) = frame
+            .function
+            .as_ref()
+            .and_then(|function| self.statics.get(&Rc::as_ptr(function)))
+        {
+            if statics.has(name) {
+                statics.set(name, value);
+                return;
+            }
+        }
+        frame.this.set(name, value);
+    }
+
+    fn member(&mut self, object: &Node, member: &str) -> Exec<Value> {
+        if let Node::Identifier(name) = object {
+            if self.enums.contains_key(name) && self.variable(name).is_none() {
+                return self.enums[name]
+                    .iter()
+                    .find(|(known, _)| known == member)
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| self.error(format!("Enum '{name}' has no member '{member}'")));
+            }
+        }
+
+        match self.members_of(object)? {
+            Ok(instance) => instance
+                .lookup(member)
+                .ok_or_else(|| self.error(format!("'{member}' isn't set on the struct"))),
+            Err(value) => {
+                Err(self.error(format!("Can't read '{member}' of a {}", value.type_name())))
+            }
+        }
+    }
+
+    /// The struct whose variables `object.name` is about: a struct itself, or
+    /// the static variables of a function, like `Counter.made`.
+    fn members_of(&mut self, object: &Node) -> Exec<Result<Struct, Value>> {
+        Ok(match self.eval(object)? {
+            Value::Struct(instance) => Ok(instance),
+            Value::Function(Function {
+                callable: Callable::Script(node),
+                ..
+            }) => Ok(self.statics_of(&node)),
+            value => Err(value),
+        })
+    }
+
+    fn index(&mut self, container: Value, accessor: Option<&str>, keys: Vec<Value>) -> Exec<Value> {
+        let function = match accessor {
+            Some("|") => "ds_list_find_value",
+            Some("?") => "ds_map_find_value",
+            Some("#") => "ds_grid_get",
+            _ => {
+                let mut value = container;
+                for key in keys {
+                    let Value::Array(array) = &value else {
+                        return Err(self.error(format!("Can't index a {}", value.type_name())));
+                    };
+                    let index = self.array_index(&key)?;
+                    value = array.get(index).ok_or_else(|| {
+                        self.error(format!(
+                            "Index {index} is out of range for an array of length {}",
+                            array.len()
+                        ))
+                    })?;
+                }
+                return Ok(value);
+            }
+        };
+
+        let mut args = vec![container];
+        args.extend(keys);
+        self.call_builtin(function, args)
+    }
+
+    fn array_index(&self, key: &Value) -> Exec<usize> {
+        match self.real(key)? {
+            index if index >= 0.0 => Ok(index as usize),
+            index => Err(self.error(format!(
+                "Array index {} can't be negative",
+                format_real(index)
+            ))),
+        }
+    }
+
+    fn binary_expression(&mut self, left: &Node, operator: &str, right: &Node) -> Exec<Value> {
+        match operator {
+            "&&" => {
+                let value = self.condition(left)? && self.condition(right)?;
+                Ok(Value::Bool(value))
+            }
+            "||" => {
+                let value = self.condition(left)? || self.condition(right)?;
+                Ok(Value::Bool(value))
+            }
+            "??" => match self.eval(left)? {
+                Value::Undefined => self.eval(right),
+                value => Ok(value),
+            },
+            _ => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(operator, left, right)
+            }
+        }
+    }
+
+    fn binary(&self, operator: &str, left: Value, right: Value) -> Exec<Value> {
+        match (operator, &left, &right) {
+            ("+", Value::String(a), Value::String(b)) => {
+                return Ok(Value::string(&format!("{a}{b}")))
+            }
+            ("==", _, _) => return Ok(Value::Bool(equals(&left, &right))),
+            ("!=", _, _) => return Ok(Value::Bool(!equals(&left, &right))),
+            ("<" | "<=" | ">" | ">=", Value::String(a), Value::String(b)) => {
+                return Ok(Value::Bool(compare_with(operator, a.cmp(b))));
+            }
+            _ => {}
+        }
+
+        let (Some(a), Some(b)) = (left.as_real(), right.as_real()) else {
+            return Err(self.error(format!(
+                "Can't use '{operator}' on a {} and a {}",
+                left.type_name(),
+                right.type_name()
+            )));
+        };
+        let value = match operator {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" | "%" | "div" if b == 0.0 => {
+                return Err(self.error(String::from("Division by zero")));
+            }
+            "/" => a / b,
+            "%" => a % b,
+            "div" => (a / b).trunc(),
+            "<" | "<=" | ">" | ">=" => {
+                return Ok(Value::Bool(compare_with(operator, a.total_cmp(&b))));
+            }
+            "^^" => return Ok(Value::Bool((a > 0.5) != (b > 0.5))),
+            "&" => ((a as i64) & (b as i64)) as f64,
+            "|" => ((a as i64) | (b as i64)) as f64,
+            "^" => ((a as i64) ^ (b as i64)) as f64,
+            "<<" => ((a as i64) << (b as i64 & 63)) as f64,
+            ">>" => ((a as i64) >> (b as i64 & 63)) as f64,
+            _ => return Err(self.error(format!("Unknown operator '{operator}'"))),
+        };
+        Ok(Value::Real(value))
+    }
+
+    fn assign_with(&mut self, target: &Node, operator: &str, value: &Node) -> Exec<()> {
+        let value = match operator {
+            "=" => self.eval(value)?,
+            "??=" => match self.read_target(target)? {
+                Value::Undefined => self.eval(value)?,
+                _ => return Ok(()),
+            },
+            _ => {
+                let current = self.eval(target)?;
+                let value = self.eval(value)?;
+                self.binary(operator.trim_end_matches('='), current, value)?
+            }
+        };
+        self.assign(target, value)
+    }
+
+    /// The value of an assignment target, undefined when it isn't set yet.
+    fn read_target(&mut self, target: &Node) -> Exec<Value> {
+        match target {
+            Node::Identifier(name) => Ok(self.variable(name).unwrap_or(Value::Undefined)),
+            Node::MemberAccess(object, member) => match self.members_of(object)? {
+                Ok(instance) => Ok(instance.lookup(member).unwrap_or(Value::Undefined)),
+                Err(_) => Ok(Value::Undefined),
+            },
+            _ => self.eval(target),
+        }
+    }
+
+    fn assign(&mut self, target: &Node, value: Value) -> Exec<()> {
+        match target {
+            Node::Identifier(name) => {
+                self.set_variable(name, value);
+                Ok(())
+            }
+            Node::MemberAccess(object, member) => match self.members_of(object)? {
+                Ok(instance) => {
+                    instance.set(member, value);
+                    Ok(())
+                }
+                Err(object) => {
+                    Err(self.error(format!("Can't set '{member}' on a {}", object.type_name())))
+                }
+            },
+            Node::IndexAccess(container, accessor, indices) => {
+                let mut keys = Vec::new();
+                for index in indices {
+                    keys.push(self.eval(index)?);
+                }
+                let function = match accessor.as_deref() {
+                    Some("|") => "ds_list_set",
+                    Some("?") => "ds_map_set",
+                    Some("#") => "ds_grid_set",
+                    accessor => {
+                        let mut array = self.array_to_write(container, accessor == Some("@"))?;
+                        let last = keys.pop().unwrap_or(Value::Real(0.0));
+                        // `a[i, j]` is an array in an array.
+                        for key in keys {
+                            let index = self.array_index(&key)?;
+                            array = match array.get(index) {
+                                Some(Value::Array(inner)) => inner,
+                                _ => {
+                                    let inner = Array::new(Vec::new(), self.frame().id);
+                                    array.set(index, Value::Array(inner.clone()));
+                                    inner
+                                }
+                            };
+                        }
+                        let index = self.array_index(&last)?;
+                        array.set(index, value);
+                        return Ok(());
+                    }
+                };
+                let mut args = vec![self.eval(container)?];
+                args.extend(keys);
+                args.push(value);
+                self.call_builtin(function, args).map(|_| ())
+            }
+            _ => Err(self.error(format!("Can't assign to {:?}", target))),
+        }
+    }
+
+    /// The array behind an assignment target, copied first when another call
+    /// made it, unless it is written with `[@`, and made when the target
+    /// isn't an array yet.
+    fn array_to_write(&mut self, target: &Node, in_place: bool) -> Exec<Array> {
+        let frame = self.frame().id;
+        match self.read_target(target)? {
+            Value::Array(array) => {
+                let owner = array.0.borrow().owner;
+                if owner == HOST {
+                    array.0.borrow_mut().owner = frame;
+                }
+                if in_place || owner == HOST || owner == frame {
+                    return Ok(array);
+                }
+                let copy = Array::new(array.items(), frame);
+                self.assign(target, Value::Array(copy.clone()))?;
+                Ok(copy)
+            }
+            _ => {
+                let array = Array::new(Vec::new(), frame);
+                self.assign(target, Value::Array(array.clone()))?;
+                Ok(array)
+            }
+        }
+    }
+
+    fn arguments(&mut self, args: &Node) -> Exec<Vec<Value>> {
+        let mut values = Vec::new();
+        if let Node::Arguments(args) = args {
+            for arg in args {
+                values.push(self.eval(arg)?);
+            }
+        }
+        Ok(values)
+    }
+
+    fn call(&mut self, callee: &Node, args: &Node) -> Exec<Value> {
+        match callee {
+            Node::Identifier(name) => {
+                let args = self.arguments(args)?;
+                let local = self.frame().locals.get(name).cloned();
+                let method = || self.frame().this.lookup(name);
+                if let Some(Value::Function(function)) = local.or_else(method) {
+                    return self.call_value(&Value::Function(function), None, args);
+                }
+                if let Some(function) = self.functions.get(name.as_str()).cloned() {
+                    return self.call_value(
+                        &Value::Function(Function::script(function, None)),
+                        None,
+                        args,
+                    );
+                }
+                if is_intrinsic(name) || self.host.has_function(name) {
+                    return self.call_builtin(name, args);
+                }
+                let function = self.lookup(name)?;
+                self.call_value(&function, None, args)
+            }
+            Node::MemberAccess(object, member) => {
+                let instance = match self.members_of(object)? {
+                    Ok(instance) => instance,
+                    Err(value) => {
+                        return Err(
+                            self.error(format!("Can't call '{member}' on a {}", value.type_name()))
+                        )
+                    }
+                };
+                let function = instance
+                    .lookup(member)
+                    .ok_or_else(|| self.error(format!("'{member}' isn't set on the struct")))?;
+                let args = self.arguments(args)?;
+                self.call_value(&function, Some(instance), args)
+            }
+            callee => {
+                let function = self.eval(callee)?;
+                let args = self.arguments(args)?;
+                self.call_value(&function, None, args)
+            }
+        }
+    }
+
+    /// Calls a function value, with `this` as `self` unless it is bound to a
+    /// struct, and the current `self` when neither is given.
+    fn call_value(
+        &mut self,
+        function: &Value,
+        this: Option<Struct>,
+        args: Vec<Value>,
+    ) -> Exec<Value> {
+        let Value::Function(function) = function else {
+            return Err(self.error(format!("A {} can't be called", function.type_name())));
+        };
+        match &function.callable {
+            Callable::Builtin(name) => self.call_builtin(name, args),
+            Callable::Script(node) => {
+                let this = function
+                    .bound
+                    .clone()
+                    .or(this)
+                    .unwrap_or_else(|| self.frame().this.clone());
+                self.call_script(node, this, args)
+            }
+        }
+    }
+
+    fn call_script(&mut self, node: &Rc<Node>, this: Struct, args: Vec<Value>) -> Exec<Value> {
+        let (params, body) = match node.as_ref() {
+            Node::FunctionDeclaration(_, params, _, body)
+            | Node::FunctionExpression(params, body)
+            | Node::ArrowFunction(params, _, body) => (params, body),
+            _ => {
+                let name = Function::script(node.clone(), None).name().to_string();
+                return Err(self.error(format!("'{name}' is a constructor, create it with new")));
+            }
+        };
+
+        self.push_frame(node.clone(), this, args)?;
+        let result = self.run_function(params, body);
+        self.pop_frame(result)
+    }
+
+    fn run_function(&mut self, params: &[Box<Node>], body: &Node) -> Exec<Value> {
+        self.bind_parameters(params)?;
+        match body {
+            Node::Program(stmts) => {
+                for stmt in stmts {
+                    self.execute(stmt)?;
+                }
+                Ok(Value::Undefined)
+            }
+            expr => self.eval(expr),
+        }
+    }
+
+    fn push_frame(&mut self, function: Rc<Node>, this: Struct, args: Vec<Value>) -> Exec<()> {
+        if self.frames.len() > MAX_DEPTH {
+            return Err(self.error(format!(
+                "Stack overflow, calls nested more than {MAX_DEPTH} times"
+            )));
+        }
+        let frame = Frame {
+            id: self.next_frame,
+            locals: HashMap::new(),
+            other: self.frame().this.clone(),
+            this,
+            args,
+            function: Some(function),
+            caller: self.location.clone(),
+        };
+        self.next_frame += 1;
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    /// Leaves a call, turning how its statements stopped into its result.
+    fn pop_frame(&mut self, result: Exec<Value>) -> Exec<Value> {
+        if let Some(frame) = self.frames.pop() {
+            self.location = frame.caller;
+        }
+        match result {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Exit | Flow::Break | Flow::Continue) => Ok(Value::Undefined),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn bind_parameters(&mut self, params: &[Box<Node>]) -> Exec<()> {
+        for (i, param) in params.iter().enumerate() {
+            let arg = self.frame().args.get(i).cloned();
+            let (name, value) = match param.as_ref() {
+                Node::FunctionParameter(name, _) => (name, arg.unwrap_or(Value::Undefined)),
+                Node::OptionalParameter(param) => {
+                    (parameter_name(param), arg.unwrap_or(Value::Undefined))
+                }
+                Node::DefaultParameter(param, default) => {
+                    let value = match arg {
+                        Some(Value::Undefined) | None => self.eval(default)?,
+                        Some(value) => value,
+                    };
+                    (parameter_name(param), value)
+                }
+                Node::RestParameter(param) => {
+                    let rest = self.frame().args.iter().skip(i).cloned().collect();
+                    (
+                        parameter_name(param),
+                        Value::Array(Array::new(rest, self.frame().id)),
+                    )
+                }
+                _ => continue,
+            };
+            self.frame_mut().locals.insert(name.clone(), value);
+        }
+        Ok(())
+    }
+
+    fn construct(&mut self, constructor: &Value, args: Vec<Value>) -> Exec<Value> {
+        let node = match constructor {
+            Value::Function(Function {
+                callable: Callable::Script(node),
+                ..
+            }) if matches!(
+                node.as_ref(),
+                Node::ConstructorDeclaration(_, _) | Node::ClassDeclaration(_, _)
+            ) =>
+            {
+                node.clone()
+            }
+            Value::Function(function) => {
+                return Err(self.error(format!("'{}' isn't a constructor", function.name())));
+            }
+            value => return Err(self.error(format!("Can't use new on a {}", value.type_name()))),
+        };
+
+        let instance = Struct::new();
+        {
+            let mut data = instance.0.borrow_mut();
+            data.constructor = Some(Function::script(node.clone(), None).name().to_string());
+            data.statics = Some(self.statics_of(&node));
+        }
+        self.run_constructor(&node, &instance, args)?;
+        Ok(Value::Struct(instance))
+    }
+
+    /// Runs a constructor on an instance, after the constructor it inherits
+    /// from. Methods of a class are its static variables, set before its
+    /// fields and the body of `new`.
+    fn run_constructor(
+        &mut self,
+        node: &Rc<Node>,
+        instance: &Struct,
+        args: Vec<Value>,
+    ) -> Exec<()> {
+        let (params, body, parent, members): (&[Box<Node>], Option<&Node>, _, &[Box<Node>]) =
+            match node.as_ref() {
+                Node::ConstructorDeclaration(function, parent) => match function.as_ref() {
+                    Node::FunctionDeclaration(_, params, _, body)
+                    | Node::FunctionExpression(params, body) => {
+                        (params, Some(body.as_ref()), parent.as_deref(), &[])
+                    }
+                    _ => return Err(self.error(format!("Invalid constructor: {:?}", function))),
+                },
+                Node::ClassDeclaration(_, members) => {
+                    let new = members.iter().find_map(|member| match member.as_ref() {
+                        Node::ConstructorDeclaration(function, parent) => match function.as_ref() {
+                            Node::FunctionDeclaration(_, params, _, body) => {
+                                Some((params, body, parent))
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                    match new {
+                        Some((params, body, parent)) => (
+                            params.as_slice(),
+                            Some(body.as_ref()),
+                            parent.as_deref(),
+                            members.as_slice(),
+                        ),
+                        None => (&[][..], None, None, members.as_slice()),
+                    }
+                }
+                _ => return Err(self.error(String::from("Expected a constructor"))),
+            };
+
+        self.push_frame(node.clone(), instance.clone(), args)?;
+        let result = self.constructor_body(node, params, body, parent, members, instance);
+        self.pop_frame(result.map(|()| Value::Undefined))
+            .map(|_| ())
+    }
+
+    fn constructor_body(
+        &mut self,
+        node: &Rc<Node>,
+        params: &[Box<Node>],
+        body: Option<&Node>,
+        parent: Option<&Node>,
+        members: &[Box<Node>],
+        instance: &Struct,
+    ) -> Exec<()> {
+        self.bind_parameters(params)?;
+
+        if let Some(Node::FunctionCall(parent, args)) = parent {
+            let parent = match self.eval(parent)? {
+                Value::Function(Function {
+                    callable: Callable::Script(parent),
+                    ..
+                }) => parent,
+                value => {
+                    return Err(self.error(format!("Can't inherit from a {}", value.type_name())));
+                }
+            };
+            let statics = self.statics_of(node);
+            if statics.0.borrow().statics.is_none() {
+                statics.0.borrow_mut().statics = Some(self.statics_of(&parent));
+            }
+            let args = self.arguments(args)?;
+            self.run_constructor(&parent, instance, args)?;
+        }
+
+        let statics = self.statics_of(node);
+        for member in members {
+            match member.as_ref() {
+                Node::FunctionDeclaration(name, _, _, _) if !statics.has(name) => {
+                    let script = self.script(member);
+                    statics.set(name, Value::Function(Function::script(script, None)));
+                }
+                Node::ClassField(name, _, Some(value)) => {
+                    if let Some(location) = self.locations.get(&(member.as_ref() as *const Node)) {
+                        self.location = Some(location.clone());
+                    }
+                    let value = self.eval(value)?;
+                    instance.set(name, value);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(Node::Program(stmts)) = body {
+            for stmt in stmts {
+                self.execute(stmt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls a function of the interpreter or of the host.
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>) -> Exec<Value> {
+        if let Some(result) = self.call_intrinsic(name, &args) {
+            return result;
+        }
+        match self.host.call(name, &args) {
+            Some(result) => result.map_err(|message| self.error(message)),
+            None => Err(self.error(format!("Function '{name}' doesn't exist"))),
+        }
+    }
+
+    /// Functions that call back into the script, or need its state.
+    fn call_intrinsic(&mut self, name: &str, args: &[Value]) -> Option<Exec<Value>> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        let result = match name {
+            "assert" => match self.truthy(&arg(0)) {
+                Ok(true) => Ok(Value::Undefined),
+                Ok(false) => Err(self.error(match arg(1) {
+                    Value::Undefined => String::from("assertion failed"),
+                    message => message.to_gml_string(),
+                })),
+                Err(error) => Err(error),
+            },
+            "assert_eq" if !same(&arg(0), &arg(1)) => Err(self.error(format!(
+                "expected {}, got {}",
+                arg(1).to_gml_string(),
+                arg(0).to_gml_string()
+            ))),
+            "assert_ne" if same(&arg(0), &arg(1)) => {
+                Err(self.error(format!("expected anything but {}", arg(1).to_gml_string())))
+            }
+            "assert_eq" | "assert_ne" => Ok(Value::Undefined),
+            "string" if args.len() == 1 => self.stringify(arg(0)).map(|text| Value::string(&text)),
+            "variable_global_get" => Ok(self
+                .global
+                .get(&arg(0).to_gml_string())
+                .unwrap_or(Value::Undefined)),
+            "variable_global_set" => {
+                self.global.set(&arg(0).to_gml_string(), arg(1));
+                Ok(Value::Undefined)
+            }
+            "array_sort" if matches!(arg(1), Value::Function(_)) => self.sort(args),
+            "array_map" | "array_filter" | "array_reduce" | "array_foreach"
+            | "array_find_index" | "array_any" | "array_all" => self.array_callback(name, args),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// `string()`, calling the `toString` method of structs that have one.
+    fn stringify(&mut self, value: Value) -> Exec<String> {
+        if let Value::Struct(instance) = &value {
+            if let Some(method @ Value::Function(_)) = instance.lookup("toString") {
+                let text = self.call_value(&method, Some(instance.clone()), Vec::new())?;
+                return Ok(text.to_gml_string());
+            }
+        }
+        Ok(value.to_gml_string())
+    }
+
+    fn array_argument(&self, args: &[Value], name: &str) -> Exec<Array> {
+        match args.first() {
+            Some(Value::Array(array)) => Ok(array.clone()),
+            value => Err(self.error(format!(
+                "{name}() expects an array as argument 1, got {}",
+                value.map_or("undefined", Value::type_name)
+            ))),
+        }
+    }
+
+    fn sort(&mut self, args: &[Value]) -> Exec<Value> {
+        let array = self.array_argument(args, "array_sort")?;
+        let mut items = array.items();
+        let mut error = None;
+        items.sort_by(|a, b| {
+            if error.is_some() {
+                return std::cmp::Ordering::Equal;
+            }
+            let result = self
+                .call_value(&args[1], None, vec![a.clone(), b.clone()])
+                .and_then(|order| self.real(&order));
+            match result {
+                Ok(order) => order.total_cmp(&0.0),
+                Err(flow) => {
+                    error = Some(flow);
+                    std::cmp::Ordering::Equal
+                }
+            }
+        });
+        if let Some(error) = error {
+            return Err(error);
+        }
+        array.0.borrow_mut().items = items;
+        Ok(Value::Undefined)
+    }
+
+    fn array_callback(&mut self, name: &str, args: &[Value]) -> Exec<Value> {
+        let array = self.array_argument(args, name)?;
+        let function = args.get(1).cloned().unwrap_or(Value::Undefined);
+        let items = array.items();
+
+        if name == "array_reduce" {
+            let (mut accumulator, start) = match args.get(2) {
+                Some(initial) => (initial.clone(), 0),
+                None => (items.first().cloned().unwrap_or(Value::Undefined), 1),
+            };
+            for (i, item) in items.into_iter().enumerate().skip(start) {
+                accumulator = self.call_value(
+                    &function,
+                    None,
+                    vec![accumulator, item, Value::Real(i as f64)],
+                )?;
+            }
+            return Ok(accumulator);
+        }
+
+        let mut results = Vec::new();
+        for (i, item) in items.into_iter().enumerate() {
+            let result =
+                self.call_value(&function, None, vec![item.clone(), Value::Real(i as f64)])?;
+            match name {
+                "array_map" => results.push(result),
+                "array_filter" if self.truthy(&result)? => results.push(item),
+                "array_find_index" if self.truthy(&result)? => return Ok(Value::Real(i as f64)),
+                "array_any" if self.truthy(&result)? => return Ok(Value::Bool(true)),
+                "array_all" if !self.truthy(&result)? => return Ok(Value::Bool(false)),
+                _ => {}
+            }
+        }
+
+        Ok(match name {
+            "array_map" | "array_filter" => Value::Array(Array::new(results, self.frame().id)),
+            "array_find_index" => Value::Real(-1.0),
+            "array_any" => Value::Bool(false),
+            "array_all" => Value::Bool(true),
+            _ => Value::Undefined,
+        })
+    }
+}
+
+fn into_error(flow: Flow) -> RuntimeError {
+    match flow {
+        Flow::Error(error) => error,
+        Flow::Throw(value, location) => RuntimeError {
+            message: format!("Unhandled exception {}", value.repr()),
+            location,
+        },
+        _ => RuntimeError {
+            message: String::from("break, continue or return outside of a function"),
+            location: None,
+        },
+    }
+}
+
+/// The struct `catch` receives for a runtime error, with the fields
+/// GameMaker gives it.
+fn exception(error: &RuntimeError) -> Value {
+    let long_message = error.to_string();
+    let script = error
+        .location
+        .as_deref()
+        .and_then(|location| location.rsplitn(3, ':').nth(2))
+        .unwrap_or("");
+
+    Value::Struct(Struct::with_fields(vec![
+        (String::from("message"), Value::string(&error.message)),
+        (String::from("longMessage"), Value::string(&long_message)),
+        (String::from("script"), Value::string(script)),
+        (
+            String::from("stacktrace"),
+            Value::Array(Array::new(Vec::new(), HOST)),
+        ),
+    ]))
+}
+
+/// Functions the interpreter runs itself rather than the host.
+fn is_intrinsic(name: &str) -> bool {
+    matches!(
+        name,
+        "assert"
+            | "assert_eq"
+            | "assert_ne"
+            | "variable_global_get"
+            | "variable_global_set"
+            | "array_map"
+            | "array_filter"
+            | "array_reduce"
+            | "array_foreach"
+            | "array_find_index"
+            | "array_any"
+            | "array_all"
+    )
+}
+
+fn parameter_name(param: &Node) -> &String {
+    match param {
+        Node::FunctionParameter(name, _) => name,
+        Node::OptionalParameter(param)
+        | Node::DefaultParameter(param, _)
+        | Node::RestParameter(param) => parameter_name(param),
+        _ => panic!("Invalid function parameter: {:?}", param),
+    }
+}
+
+fn compare_with(operator: &str, order: std::cmp::Ordering) -> bool {
+    match operator {
+        "<" => order.is_lt(),
+        "<=" => order.is_le(),
+        ">" => order.is_gt(),
+        _ => order.is_ge(),
+    }
+}
+
+/// What the assertions compare with: arrays by their items, everything else
+/// like `==`.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            let (a, b) = (a.items(), b.items());
+            a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| same(a, b))
+        }
+        _ => equals(a, b),
+    }
+}
+
+/// The text of a string literal, which is kept with its escapes as written.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            result.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('b') => result.push('\u{8}'),
+            Some('f') => result.push('\u{c}'),
+            Some('v') => result.push('\u{b}'),
+            Some('a') => result.push('\u{7}'),
+            Some('0') => result.push('\0'),
+            Some(kind @ ('x' | 'u')) => {
+                let length = if kind == 'x' { 2 } else { 4 };
+                let digits: String = chars.clone().take(length).collect();
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(decoded) if digits.len() == length => {
+                        result.push(decoded);
+                        chars.nth(length - 1);
+                    }
+                    _ => result.push(kind),
+                }
+            }
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::parse_with_positions;
+    use crate::interp::host::StandardHost;
+
+    fn load(interpreter: &mut Interpreter<StandardHost>, code: &str) -> Result<(), RuntimeError> {
+        let (program, positions) = tokenize(code.to_string())
+            .and_then(parse_with_positions)
+            .unwrap();
+        interpreter.load(Rc::new(program), &positions, code, "test.gmpp")
+    }
+
+    fn output(code: &str) -> String {
+        let mut interpreter = Interpreter::new(StandardHost::new());
+        load(&mut interpreter, code).unwrap();
+        interpreter.host.output
+    }
+
+    #[test]
+    fn runs_are_deterministic() {
+        let code = "\
+randomize();
+show_debug_message(random(10));
+show_debug_message(irandom_range(1, 100));
+show_debug_message(choose(\"a\", \"b\", \"c\", \"d\"));
+random_set_seed(7);
+let first = irandom(1000);
+random_set_seed(7);
+show_debug_message(first == irandom(1000));
+let s = { zebra: 1, apple: 2, mango: 3 };
+s.banana = 4;
+show_debug_message(struct_get_names(s));
+show_debug_message(current_time);
+";
+        let first = output(code);
+        assert_eq!(first, output(code));
+        assert!(first.contains("true\n[ \"zebra\",\"apple\",\"mango\",\"banana\" ]\n"));
+    }
+
+    #[test]
+    fn errors_after_a_call_point_at_the_caller() {
+        let code = "\
+function apply(x) {
+    return x * 2;
+}
+
+assert_eq(apply(20), 100);
+";
+        let mut interpreter = Interpreter::new(StandardHost::new());
+        let error = load(&mut interpreter, code).unwrap_err();
+        assert_eq!(error.location.as_deref(), Some("test.gmpp:5:1"));
+    }
+
+    #[test]
+    fn functions_outlive_the_program_declaring_them() {
+        let mut interpreter = Interpreter::new(StandardHost::new());
+        load(
+            &mut interpreter,
+            "function counter() { static n = 0; n += 1; return n; }
+             let make = function() { return function() { return counter(); }; };",
+        )
+        .unwrap();
+        load(
+            &mut interpreter,
+            "let f = make(); f(); show_debug_message(f() + counter());",
+        )
+        .unwrap();
+        assert_eq!(interpreter.host.output, "5\n");
+    }
+}
@@ -0,0 +1,1054 @@
+use crate::compiler::checker::Type;
+use crate::compiler::declarations::standard_declarations;
+use crate::interp::value::{equals, format_real, Array, Function, Struct, Value, HOST};
+use crate::lsp::json::Json;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// The built-in functions and constants a script can use. The interpreter
+/// handles the language itself and the functions taking callbacks, and asks
+/// the host for everything else.
+pub trait Host {
+    /// Calls a built-in function, `None` when the host doesn't have it.
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>>;
+
+    fn has_function(&self, name: &str) -> bool;
+
+    /// The value of a built-in constant or variable.
+    fn constant(&self, name: &str) -> Option<Value>;
+}
+
+/// GameMaker's runtime without the game: strings, math, arrays, structs,
+/// JSON and data structures work, drawing, audio, input and the rest of the
+/// declared functions do nothing and return an empty value of their type.
+///
+/// Runs are deterministic: the random generator starts from the same seed,
+/// which `randomize` keeps, and time doesn't pass.
+pub struct StandardHost {
+    /// Everything printed by `show_debug_message`, one message per line.
+    pub output: String,
+    /// Also print the messages to stdout as they come.
+    pub echo: bool,
+    random_state: u64,
+    lists: Vec<Option<Vec<Value>>>,
+    maps: Vec<Option<Vec<(Value, Value)>>>,
+    stacks: Vec<Option<Vec<Value>>>,
+    queues: Vec<Option<VecDeque<Value>>>,
+    priorities: Vec<Option<Vec<(Value, f64)>>>,
+    grids: Vec<Option<Grid>>,
+    /// Types of the declared functions and variables, for the stubs.
+    declarations: HashMap<String, Type>,
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Value>,
+}
+
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+const CONSTANTS: [(&str, f64); 48] = [
+    ("pi", std::f64::consts::PI),
+    ("infinity", f64::INFINITY),
+    ("NaN", f64::NAN),
+    ("noone", -4.0),
+    ("all", -3.0),
+    ("c_white", 16777215.0),
+    ("c_black", 0.0),
+    ("c_red", 255.0),
+    ("c_green", 32768.0),
+    ("c_blue", 16711680.0),
+    ("c_yellow", 65535.0),
+    ("c_orange", 4235519.0),
+    ("c_purple", 8388736.0),
+    ("c_gray", 8421504.0),
+    ("c_aqua", 16776960.0),
+    ("c_fuchsia", 16711935.0),
+    ("fa_left", 0.0),
+    ("fa_center", 1.0),
+    ("fa_right", 2.0),
+    ("fa_top", 0.0),
+    ("fa_middle", 1.0),
+    ("fa_bottom", 2.0),
+    ("vk_left", 37.0),
+    ("vk_right", 39.0),
+    ("vk_up", 38.0),
+    ("vk_down", 40.0),
+    ("vk_space", 32.0),
+    ("vk_enter", 13.0),
+    ("vk_escape", 27.0),
+    ("vk_shift", 16.0),
+    ("vk_control", 17.0),
+    ("vk_anykey", 1.0),
+    ("mb_left", 1.0),
+    ("mb_right", 2.0),
+    ("mb_middle", 3.0),
+    ("ev_create", 0.0),
+    ("ev_destroy", 1.0),
+    ("ev_step", 3.0),
+    ("ev_draw", 8.0),
+    ("buffer_fixed", 0.0),
+    ("buffer_grow", 1.0),
+    ("buffer_u8", 1.0),
+    ("buffer_s32", 6.0),
+    ("buffer_f32", 8.0),
+    ("buffer_string", 11.0),
+    ("room_speed", 60.0),
+    ("fps", 60.0),
+    ("fps_real", 60.0),
+];
+
+impl StandardHost {
+    pub fn new() -> StandardHost {
+        StandardHost {
+            output: String::new(),
+            echo: false,
+            random_state: SEED,
+            lists: Vec::new(),
+            maps: Vec::new(),
+            stacks: Vec::new(),
+            queues: Vec::new(),
+            priorities: Vec::new(),
+            grids: Vec::new(),
+            declarations: standard_declarations(),
+        }
+    }
+
+    /// Adds the functions and variables of a declaration file, which do
+    /// nothing like the other stubs.
+    pub fn declare(&mut self, declarations: &HashMap<String, Type>) {
+        for (name, declared) in declarations {
+            self.declarations.insert(name.clone(), declared.clone());
+        }
+    }
+
+    fn print(&mut self, message: &str) {
+        if self.echo {
+            println!("{message}");
+        }
+        self.output.push_str(message);
+        self.output.push('\n');
+    }
+
+    /// A number in [0, 1), from a xorshift generator.
+    fn random(&mut self) -> f64 {
+        let mut state = self.random_state;
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        self.random_state = state;
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn call_native(&mut self, name: &str, args: &Args) -> Option<Result<Value, String>> {
+        self.call_output(name, args)
+            .or_else(|| call_string(name, args))
+            .or_else(|| call_type(name, args))
+            .or_else(|| self.call_math(name, args))
+            .or_else(|| call_array(name, args))
+            .or_else(|| call_struct(name, args))
+            .or_else(|| self.call_data_structure(name, args))
+    }
+
+    fn call_output(&mut self, name: &str, args: &Args) -> Option<Result<Value, String>> {
+        let result = match name {
+            "show_debug_message" | "show_message" => {
+                let message = format_values(args.values);
+                self.print(&message);
+                Ok(Value::Undefined)
+            }
+            "show_error" => Err(args.get(0).to_gml_string()),
+            "get_string" | "get_integer" => Ok(args.get(1)),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn call_math(&mut self, name: &str, args: &Args) -> Option<Result<Value, String>> {
+        let result = match name {
+            "random" => args.real(0).map(|limit| Value::Real(self.random() * limit)),
+            "random_range" => args.real(0).and_then(|low| {
+                let high = args.real(1)?;
+                Ok(Value::Real(low + self.random() * (high - low)))
+            }),
+            "irandom" => args.real(0).map(|limit| {
+                let limit = limit.floor();
+                Value::Real((self.random() * (limit + 1.0)).floor())
+            }),
+            "irandom_range" => args.real(0).and_then(|low| {
+                let (low, high) = (low.floor(), args.real(1)?.floor());
+                Ok(Value::Real(
+                    low + (self.random() * (high - low + 1.0)).floor(),
+                ))
+            }),
+            "choose" if args.values.is_empty() => Ok(Value::Undefined),
+            "choose" => {
+                let index = (self.random() * args.values.len() as f64) as usize;
+                Ok(args.get(index.min(args.values.len() - 1)))
+            }
+            "randomize" => Ok(Value::Real(SEED as f64)),
+            "random_set_seed" => args.real(0).map(|seed| {
+                self.random_state = (seed as i64 as u64) ^ SEED;
+                if self.random_state == 0 {
+                    self.random_state = SEED;
+                }
+                Value::Undefined
+            }),
+            _ => return call_arithmetic(name, args),
+        };
+        Some(result)
+    }
+
+    fn call_data_structure(&mut self, name: &str, args: &Args) -> Option<Result<Value, String>> {
+        let result =
+            match name {
+                "ds_list_create" => Ok(create(&mut self.lists, Vec::new())),
+                "ds_list_destroy" => destroy(&mut self.lists, args, "list"),
+                "ds_list_add" => find(&mut self.lists, args, "list").map(|list| {
+                    list.extend(args.values[1..].iter().cloned());
+                    Value::Undefined
+                }),
+                "ds_list_find_value" => {
+                    let index = args.real(1);
+                    find(&mut self.lists, args, "list").and_then(|list| {
+                        let index = index?;
+                        Ok(if index < 0.0 {
+                            Value::Undefined
+                        } else {
+                            list.get(index as usize)
+                                .cloned()
+                                .unwrap_or(Value::Undefined)
+                        })
+                    })
+                }
+                "ds_list_set" => {
+                    let index = args.index(1);
+                    find(&mut self.lists, args, "list").and_then(|list| {
+                        let index = index?;
+                        if list.len() <= index {
+                            list.resize(index + 1, Value::Real(0.0));
+                        }
+                        list[index] = args.get(2);
+                        Ok(Value::Undefined)
+                    })
+                }
+                "ds_list_find_index" => find(&mut self.lists, args, "list").map(|list| {
+                    let value = args.get(1);
+                    let index = list.iter().position(|item| equals(item, &value));
+                    Value::Real(index.map_or(-1.0, |index| index as f64))
+                }),
+                "ds_list_delete" => {
+                    let index = args.index(1);
+                    find(&mut self.lists, args, "list").and_then(|list| {
+                        let index = index?;
+                        if index < list.len() {
+                            list.remove(index);
+                        }
+                        Ok(Value::Undefined)
+                    })
+                }
+                "ds_list_size" => {
+                    find(&mut self.lists, args, "list").map(|list| Value::Real(list.len() as f64))
+                }
+                "ds_list_clear" => find(&mut self.lists, args, "list").map(|list| {
+                    list.clear();
+                    Value::Undefined
+                }),
+                "ds_list_empty" => {
+                    find(&mut self.lists, args, "list").map(|list| Value::Bool(list.is_empty()))
+                }
+                "ds_list_sort" => {
+                    let ascending = args.truthy(1);
+                    find(&mut self.lists, args, "list").and_then(|list| {
+                        sort(list, ascending?)?;
+                        Ok(Value::Undefined)
+                    })
+                }
+                "ds_map_create" => Ok(create(&mut self.maps, Vec::new())),
+                "ds_map_destroy" => destroy(&mut self.maps, args, "map"),
+                "ds_map_add" | "ds_map_set" => find(&mut self.maps, args, "map").map(|map| {
+                    let key = args.get(1);
+                    match map.iter_mut().find(|(known, _)| equals(known, &key)) {
+                        Some((_, value)) if name == "ds_map_set" => *value = args.get(2),
+                        Some(_) => return Value::Bool(false),
+                        None => map.push((key, args.get(2))),
+                    }
+                    Value::Bool(true)
+                }),
+                "ds_map_find_value" => find(&mut self.maps, args, "map").map(|map| {
+                    let key = args.get(1);
+                    map.iter()
+                        .find(|(known, _)| equals(known, &key))
+                        .map_or(Value::Undefined, |(_, value)| value.clone())
+                }),
+                "ds_map_exists" => find(&mut self.maps, args, "map").map(|map| {
+                    let key = args.get(1);
+                    Value::Bool(map.iter().any(|(known, _)| equals(known, &key)))
+                }),
+                "ds_map_delete" => find(&mut self.maps, args, "map").map(|map| {
+                    let key = args.get(1);
+                    map.retain(|(known, _)| !equals(known, &key));
+                    Value::Undefined
+                }),
+                "ds_map_size" => {
+                    find(&mut self.maps, args, "map").map(|map| Value::Real(map.len() as f64))
+                }
+                "ds_map_keys_to_array" => find(&mut self.maps, args, "map").map(|map| {
+                    let keys = map.iter().map(|(key, _)| key.clone()).collect();
+                    Value::Array(Array::new(keys, HOST))
+                }),
+                "ds_stack_create" => Ok(create(&mut self.stacks, Vec::new())),
+                "ds_stack_destroy" => destroy(&mut self.stacks, args, "stack"),
+                "ds_stack_push" => find(&mut self.stacks, args, "stack").map(|stack| {
+                    stack.extend(args.values[1..].iter().cloned());
+                    Value::Undefined
+                }),
+                "ds_stack_pop" => find(&mut self.stacks, args, "stack")
+                    .map(|stack| stack.pop().unwrap_or(Value::Undefined)),
+                "ds_stack_top" => find(&mut self.stacks, args, "stack")
+                    .map(|stack| stack.last().cloned().unwrap_or(Value::Undefined)),
+                "ds_stack_size" => find(&mut self.stacks, args, "stack")
+                    .map(|stack| Value::Real(stack.len() as f64)),
+                "ds_stack_empty" => {
+                    find(&mut self.stacks, args, "stack").map(|stack| Value::Bool(stack.is_empty()))
+                }
+                "ds_queue_create" => Ok(create(&mut self.queues, VecDeque::new())),
+                "ds_queue_destroy" => destroy(&mut self.queues, args, "queue"),
+                "ds_queue_enqueue" => find(&mut self.queues, args, "queue").map(|queue| {
+                    queue.extend(args.values[1..].iter().cloned());
+                    Value::Undefined
+                }),
+                "ds_queue_dequeue" => find(&mut self.queues, args, "queue")
+                    .map(|queue| queue.pop_front().unwrap_or(Value::Undefined)),
+                "ds_queue_head" => find(&mut self.queues, args, "queue")
+                    .map(|queue| queue.front().cloned().unwrap_or(Value::Undefined)),
+                "ds_queue_size" => find(&mut self.queues, args, "queue")
+                    .map(|queue| Value::Real(queue.len() as f64)),
+                "ds_queue_empty" => {
+                    find(&mut self.queues, args, "queue").map(|queue| Value::Bool(queue.is_empty()))
+                }
+                "ds_priority_create" => Ok(create(&mut self.priorities, Vec::new())),
+                "ds_priority_destroy" => destroy(&mut self.priorities, args, "priority queue"),
+                "ds_priority_add" => {
+                    let priority = args.real(2);
+                    find(&mut self.priorities, args, "priority queue").and_then(|queue| {
+                        queue.push((args.get(1), priority?));
+                        Ok(Value::Undefined)
+                    })
+                }
+                "ds_priority_delete_min" | "ds_priority_delete_max" => {
+                    find(&mut self.priorities, args, "priority queue").map(|queue| {
+                        let mut chosen: Option<usize> = None;
+                        for (i, (_, priority)) in queue.iter().enumerate() {
+                            let better = match chosen {
+                                None => true,
+                                Some(best) if name == "ds_priority_delete_min" => {
+                                    *priority < queue[best].1
+                                }
+                                Some(best) => *priority > queue[best].1,
+                            };
+                            if better {
+                                chosen = Some(i);
+                            }
+                        }
+                        chosen.map_or(Value::Undefined, |i| queue.remove(i).0)
+                    })
+                }
+                "ds_priority_size" => find(&mut self.priorities, args, "priority queue")
+                    .map(|queue| Value::Real(queue.len() as f64)),
+                "ds_grid_create" => args.index(0).and_then(|width| {
+                    let height = args.index(1)?;
+                    let cells = vec![Value::Real(0.0); width * height];
+                    Ok(create(
+                        &mut self.grids,
+                        Grid {
+                            width,
+                            height,
+                            cells,
+                        },
+                    ))
+                }),
+                "ds_grid_destroy" => destroy(&mut self.grids, args, "grid"),
+                "ds_grid_get" | "ds_grid_set" => {
+                    let cell = args.index(1).and_then(|x| Ok((x, args.index(2)?)));
+                    find(&mut self.grids, args, "grid").and_then(|grid| {
+                        let (x, y) = cell?;
+                        if x >= grid.width || y >= grid.height {
+                            return Err(format!(
+                                "Cell ({x}, {y}) is outside of the {}x{} grid",
+                                grid.width, grid.height
+                            ));
+                        }
+                        let cell = &mut grid.cells[y * grid.width + x];
+                        if name == "ds_grid_get" {
+                            return Ok(cell.clone());
+                        }
+                        *cell = args.get(3);
+                        Ok(Value::Undefined)
+                    })
+                }
+                "ds_grid_width" => {
+                    find(&mut self.grids, args, "grid").map(|grid| Value::Real(grid.width as f64))
+                }
+                "ds_grid_height" => {
+                    find(&mut self.grids, args, "grid").map(|grid| Value::Real(grid.height as f64))
+                }
+                _ => return None,
+            };
+        Some(result)
+    }
+}
+
+impl Default for StandardHost {
+    fn default() -> StandardHost {
+        StandardHost::new()
+    }
+}
+
+impl Host for StandardHost {
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let args = Args {
+            function: name,
+            values: args,
+        };
+        if let Some(result) = self.call_native(name, &args) {
+            return Some(result);
+        }
+
+        match self.declarations.get(name) {
+            Some(Type::Function(_, returned)) => Some(Ok(empty_value(returned))),
+            _ => None,
+        }
+    }
+
+    fn has_function(&self, name: &str) -> bool {
+        name == "ds_list_set" || matches!(self.declarations.get(name), Some(Type::Function(_, _)))
+    }
+
+    fn constant(&self, name: &str) -> Option<Value> {
+        if let Some((_, value)) = CONSTANTS.iter().find(|(constant, _)| *constant == name) {
+            return Some(Value::Real(*value));
+        }
+        match self.declarations.get(name) {
+            Some(Type::Function(_, _)) | None => None,
+            Some(declared) => Some(empty_value(declared)),
+        }
+    }
+}
+
+/// What a stub returns, or an unset variable holds.
+fn empty_value(declared: &Type) -> Value {
+    match declared {
+        Type::Number => Value::Real(0.0),
+        Type::Bool => Value::Bool(false),
+        Type::String => Value::string(""),
+        Type::Array(_) => Value::Array(Array::new(Vec::new(), HOST)),
+        _ => Value::Undefined,
+    }
+}
+
+/// The arguments of a built-in function, with the checks of their types.
+struct Args<'a> {
+    function: &'a str,
+    values: &'a [Value],
+}
+
+impl Args<'_> {
+    fn get(&self, index: usize) -> Value {
+        self.values.get(index).cloned().unwrap_or(Value::Undefined)
+    }
+
+    fn error(&self, index: usize, expected: &str) -> String {
+        format!(
+            "{}() expects {expected} as argument {}, got {}",
+            self.function,
+            index + 1,
+            self.get(index).type_name()
+        )
+    }
+
+    fn real(&self, index: usize) -> Result<f64, String> {
+        self.get(index)
+            .as_real()
+            .ok_or_else(|| self.error(index, "a number"))
+    }
+
+    fn index(&self, index: usize) -> Result<usize, String> {
+        match self.real(index)? {
+            value if value >= 0.0 => Ok(value as usize),
+            _ => Err(self.error(index, "a positive number")),
+        }
+    }
+
+    fn truthy(&self, index: usize) -> Result<bool, String> {
+        self.get(index)
+            .is_truthy()
+            .ok_or_else(|| self.error(index, "a bool"))
+    }
+
+    fn string(&self, index: usize) -> Result<Rc<str>, String> {
+        match self.get(index) {
+            Value::String(text) => Ok(text),
+            _ => Err(self.error(index, "a string")),
+        }
+    }
+
+    fn array(&self, index: usize) -> Result<Array, String> {
+        match self.get(index) {
+            Value::Array(array) => Ok(array),
+            _ => Err(self.error(index, "an array")),
+        }
+    }
+
+    fn instance(&self, index: usize) -> Result<Struct, String> {
+        match self.get(index) {
+            Value::Struct(instance) => Ok(instance),
+            _ => Err(self.error(index, "a struct")),
+        }
+    }
+}
+
+/// The text of `show_debug_message` and `string` given several values, where
+/// `{0}`, `{1}`... in the first one are replaced by the others.
+fn format_values(values: &[Value]) -> String {
+    let mut text = values.first().map(Value::to_gml_string).unwrap_or_default();
+    for (i, value) in values.iter().enumerate().skip(1) {
+        text = text.replace(&format!("{{{}}}", i - 1), &value.to_gml_string());
+    }
+    text
+}
+
+/// Characters of a string from a 1-based position, like GameMaker counts them.
+fn char_range(text: &str, start: f64, count: f64) -> (usize, usize) {
+    let length = text.chars().count();
+    let start = (start.max(1.0) as usize - 1).min(length);
+    let count = (count.max(0.0) as usize).min(length - start);
+    (start, count)
+}
+
+fn call_string(name: &str, args: &Args) -> Option<Result<Value, String>> {
+    let text = || args.string(0);
+    let result = match name {
+        "string" => Ok(Value::string(&format_values(args.values))),
+        "real" => match args.get(0) {
+            Value::String(text) => text
+                .trim()
+                .parse()
+                .map(Value::Real)
+                .map_err(|_| format!("Unable to convert \"{text}\" to a number")),
+            value => value
+                .as_real()
+                .map(Value::Real)
+                .ok_or_else(|| args.error(0, "a number or a string")),
+        },
+        "string_length" => text().map(|text| Value::Real(text.chars().count() as f64)),
+        "string_char_at" => text().and_then(|text| {
+            let (start, count) = char_range(&text, args.real(1)?, 1.0);
+            Ok(Value::string(
+                &text.chars().skip(start).take(count).collect::<String>(),
+            ))
+        }),
+        "string_copy" => text().and_then(|text| {
+            let (start, count) = char_range(&text, args.real(1)?, args.real(2)?);
+            Ok(Value::string(
+                &text.chars().skip(start).take(count).collect::<String>(),
+            ))
+        }),
+        "string_delete" => text().and_then(|text| {
+            let (start, count) = char_range(&text, args.real(1)?, args.real(2)?);
+            let kept: String = text
+                .chars()
+                .enumerate()
+                .filter(|(i, _)| *i < start || *i >= start + count)
+                .map(|(_, char)| char)
+                .collect();
+            Ok(Value::string(&kept))
+        }),
+        "string_insert" => args.string(0).and_then(|inserted| {
+            let text = args.string(1)?;
+            let (start, _) = char_range(&text, args.real(2)?, 0.0);
+            let mut result: String = text.chars().take(start).collect();
+            result.push_str(&inserted);
+            result.extend(text.chars().skip(start));
+            Ok(Value::string(&result))
+        }),
+        "string_pos" => args.string(0).and_then(|needle| {
+            let text = args.string(1)?;
+            let position = text
+                .find(needle.as_ref())
+                .map_or(0, |byte| text[..byte].chars().count() + 1);
+            Ok(Value::Real(position as f64))
+        }),
+        "string_count" => args.string(0).and_then(|needle| {
+            let text = args.string(1)?;
+            let count = if needle.is_empty() {
+                0
+            } else {
+                text.matches(needle.as_ref()).count()
+            };
+            Ok(Value::Real(count as f64))
+        }),
+        "string_replace" | "string_replace_all" => text().and_then(|text| {
+            let (old, new) = (args.string(1)?, args.string(2)?);
+            if old.is_empty() {
+                return Ok(Value::String(text));
+            }
+            let count = if name == "string_replace" {
+                1
+            } else {
+                usize::MAX
+            };
+            Ok(Value::string(&text.replacen(old.as_ref(), &new, count)))
+        }),
+        "string_upper" => text().map(|text| Value::string(&text.to_uppercase())),
+        "string_lower" => text().map(|text| Value::string(&text.to_lowercase())),
+        "string_repeat" => text().and_then(|text| Ok(Value::string(&text.repeat(args.index(1)?)))),
+        "string_digits" => text().map(|text| {
+            Value::string(
+                &text
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect::<String>(),
+            )
+        }),
+        "string_letters" => text().map(|text| {
+            Value::string(
+                &text
+                    .chars()
+                    .filter(char::is_ascii_alphabetic)
+                    .collect::<String>(),
+            )
+        }),
+        "string_trim" => text().map(|text| Value::string(text.trim())),
+        "string_starts_with" => {
+            text().and_then(|text| Ok(Value::Bool(text.starts_with(args.string(1)?.as_ref()))))
+        }
+        "string_ends_with" => {
+            text().and_then(|text| Ok(Value::Bool(text.ends_with(args.string(1)?.as_ref()))))
+        }
+        "string_format" => args.real(0).and_then(|value| {
+            let (total, decimals) = (args.index(1)?, args.index(2)?);
+            Ok(Value::string(&format!("{value:>total$.decimals$}")))
+        }),
+        "string_split" => text().and_then(|text| {
+            let delimiter = args.string(1)?;
+            let remove_empty = args.values.len() > 2 && args.truthy(2)?;
+            let mut parts: Vec<&str> = if delimiter.is_empty() {
+                vec![text.as_ref()]
+            } else {
+                text.split(delimiter.as_ref()).collect()
+            };
+            if remove_empty {
+                parts.retain(|part| !part.is_empty());
+            }
+            let parts = parts.into_iter().map(Value::string).collect();
+            Ok(Value::Array(Array::new(parts, HOST)))
+        }),
+        "string_join" => args.string(0).map(|delimiter| {
+            let parts: Vec<String> = args.values[1..].iter().map(Value::to_gml_string).collect();
+            Value::string(&parts.join(&delimiter))
+        }),
+        "chr" => args.real(0).map(|code| {
+            let char = char::from_u32(code as u32).unwrap_or_default();
+            Value::string(&char.to_string())
+        }),
+        "ord" => {
+            text().map(|text| Value::Real(text.chars().next().map_or(0.0, |c| c as u32 as f64)))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn call_type(name: &str, args: &Args) -> Option<Result<Value, String>> {
+    let value = args.get(0);
+    let result = match name {
+        "is_string" => Value::Bool(matches!(value, Value::String(_))),
+        "is_real" => Value::Bool(matches!(value, Value::Real(_))),
+        "is_numeric" => Value::Bool(value.as_real().is_some()),
+        "is_bool" => Value::Bool(matches!(value, Value::Bool(_))),
+        "is_array" => Value::Bool(matches!(value, Value::Array(_))),
+        "is_struct" => Value::Bool(matches!(value, Value::Struct(_))),
+        "is_method" => Value::Bool(matches!(value, Value::Function(_))),
+        "is_undefined" => Value::Bool(matches!(value, Value::Undefined)),
+        "is_ptr" => Value::Bool(false),
+        "typeof" => Value::string(value.type_name()),
+        "instanceof" => match value {
+            Value::Struct(instance) => {
+                Value::string(instance.constructor().as_deref().unwrap_or("struct"))
+            }
+            _ => Value::Undefined,
+        },
+        _ => return None,
+    };
+    Some(Ok(result))
+}
+
+fn call_arithmetic(name: &str, args: &Args) -> Option<Result<Value, String>> {
+    let unary: Option<fn(f64) -> f64> = match name {
+        "abs" => Some(f64::abs),
+        "sign" => Some(|x| if x == 0.0 { 0.0 } else { x.signum() }),
+        "round" => Some(f64::round_ties_even),
+        "floor" => Some(f64::floor),
+        "ceil" => Some(f64::ceil),
+        "frac" => Some(f64::fract),
+        "sqrt" => Some(f64::sqrt),
+        "sqr" => Some(|x| x * x),
+        "exp" => Some(f64::exp),
+        "ln" => Some(f64::ln),
+        "log2" => Some(f64::log2),
+        "log10" => Some(f64::log10),
+        "sin" => Some(f64::sin),
+        "cos" => Some(f64::cos),
+        "tan" => Some(f64::tan),
+        "arcsin" => Some(f64::asin),
+        "arccos" => Some(f64::acos),
+        "arctan" => Some(f64::atan),
+        "dsin" => Some(|x: f64| x.to_radians().sin()),
+        "dcos" => Some(|x: f64| x.to_radians().cos()),
+        "dtan" => Some(|x: f64| x.to_radians().tan()),
+        "degtorad" => Some(f64::to_radians),
+        "radtodeg" => Some(f64::to_degrees),
+        _ => None,
+    };
+    if let Some(function) = unary {
+        return Some(args.real(0).map(|x| Value::Real(function(x))));
+    }
+
+    let numbers =
+        || -> Result<Vec<f64>, String> { (0..args.values.len()).map(|i| args.real(i)).collect() };
+    let result = match name {
+        "power" => args.real(0).and_then(|x| Ok(x.powf(args.real(1)?))),
+        "arctan2" => args.real(0).and_then(|y| Ok(y.atan2(args.real(1)?))),
+        "min" => numbers().map(|numbers| numbers.into_iter().fold(f64::INFINITY, f64::min)),
+        "max" => numbers().map(|numbers| numbers.into_iter().fold(f64::NEG_INFINITY, f64::max)),
+        "mean" => numbers().map(|numbers| numbers.iter().sum::<f64>() / numbers.len() as f64),
+        // The lower of the two middle values for an even count, like GameMaker.
+        "median" => numbers().map(|mut numbers| {
+            numbers.sort_by(f64::total_cmp);
+            numbers
+                .get(numbers.len().saturating_sub(1) / 2)
+                .copied()
+                .unwrap_or(0.0)
+        }),
+        "clamp" => args
+            .real(0)
+            .and_then(|x| Ok(x.max(args.real(1)?).min(args.real(2)?))),
+        "lerp" => args.real(0).and_then(|a| {
+            let (b, amount) = (args.real(1)?, args.real(2)?);
+            Ok(a + (b - a) * amount)
+        }),
+        "point_distance" => numbers().map(|numbers| {
+            let [x1, y1, x2, y2] = point_args(&numbers);
+            (x2 - x1).hypot(y2 - y1)
+        }),
+        // The y axis points down in rooms, so angles go counterclockwise on
+        // screen.
+        "point_direction" => numbers().map(|numbers| {
+            let [x1, y1, x2, y2] = point_args(&numbers);
+            (-(y2 - y1)).atan2(x2 - x1).to_degrees().rem_euclid(360.0)
+        }),
+        "lengthdir_x" => args
+            .real(0)
+            .and_then(|length| Ok(length * args.real(1)?.to_radians().cos())),
+        "lengthdir_y" => args
+            .real(0)
+            .and_then(|length| Ok(-length * args.real(1)?.to_radians().sin())),
+        "angle_difference" => args
+            .real(0)
+            .and_then(|a| Ok((a - args.real(1)? + 540.0).rem_euclid(360.0) - 180.0)),
+        "make_color_rgb" => numbers().map(|numbers| {
+            let [red, green, blue, _] = point_args(&numbers);
+            red + green * 256.0 + blue * 65536.0
+        }),
+        "make_color_hsv" => numbers().map(|numbers| {
+            let [hue, saturation, value, _] = point_args(&numbers);
+            let [red, green, blue] = hsv_to_rgb(hue, saturation, value);
+            red + green * 256.0 + blue * 65536.0
+        }),
+        "merge_color" => numbers().map(|numbers| {
+            let [first, second, amount, _] = point_args(&numbers);
+            let (first, second) = (first as u32, second as u32);
+            (0..3)
+                .map(|channel| {
+                    let a = f64::from((first >> (channel * 8)) & 255);
+                    let b = f64::from((second >> (channel * 8)) & 255);
+                    (a + (b - a) * amount).round() * 256f64.powi(channel)
+                })
+                .sum()
+        }),
+        _ => return None,
+    };
+    Some(result.map(Value::Real))
+}
+
+/// The first four numbers, zero when left out.
+fn point_args(numbers: &[f64]) -> [f64; 4] {
+    let mut point = [0.0; 4];
+    for (slot, number) in point.iter_mut().zip(numbers) {
+        *slot = *number;
+    }
+    point
+}
+
+/// Channels of a color given hue, saturation and value from 0 to 255.
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [f64; 3] {
+    let hue = (hue / 255.0 * 360.0).rem_euclid(360.0);
+    let (saturation, value) = (saturation / 255.0, value / 255.0);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (red, green, blue) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let base = value - chroma;
+    [red, green, blue].map(|channel| ((channel + base) * 255.0).round())
+}
+
+/// Orders two values the way `array_sort` does: numbers, then strings.
+fn compare(a: &Value, b: &Value) -> Result<Ordering, String> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        _ => match (a.as_real(), b.as_real()) {
+            (Some(a), Some(b)) => Ok(a.total_cmp(&b)),
+            _ => Err(format!(
+                "Can't sort a {} and a {} together",
+                a.type_name(),
+                b.type_name()
+            )),
+        },
+    }
+}
+
+fn sort(items: &mut [Value], ascending: bool) -> Result<(), String> {
+    let mut error = None;
+    items.sort_by(|a, b| {
+        let order = compare(a, b).unwrap_or_else(|message| {
+            error = Some(message);
+            Ordering::Equal
+        });
+        if ascending {
+            order
+        } else {
+            order.reverse()
+        }
+    });
+    error.map_or(Ok(()), Err)
+}
+
+fn call_array(name: &str, args: &Args) -> Option<Result<Value, String>> {
+    let array = || args.array(0);
+    let result = match name {
+        "array_create" => args.index(0).map(|size| {
+            let value = match args.get(1) {
+                Value::Undefined if args.values.len() < 2 => Value::Real(0.0),
+                value => value,
+            };
+            Value::Array(Array::new(vec![value; size], HOST))
+        }),
+        "array_length" => array().map(|array| Value::Real(array.len() as f64)),
+        "array_push" => array().map(|array| {
+            array
+                .0
+                .borrow_mut()
+                .items
+                .extend(args.values[1..].iter().cloned());
+            Value::Undefined
+        }),
+        "array_pop" => {
+            array().map(|array| array.0.borrow_mut().items.pop().unwrap_or(Value::Undefined))
+        }
+        "array_insert" => array().and_then(|array| {
+            let index = args.index(1)?;
+            let mut data = array.0.borrow_mut();
+            if data.items.len() < index {
+                data.items.resize(index, Value::Real(0.0));
+            }
+            let tail = data.items.split_off(index);
+            data.items.extend(args.values[2..].iter().cloned());
+            data.items.extend(tail);
+            Ok(Value::Undefined)
+        }),
+        "array_delete" => array().and_then(|array| {
+            let index = args.index(1)?;
+            let count = args.index(2)?;
+            let mut data = array.0.borrow_mut();
+            let start = index.min(data.items.len());
+            let end = (index + count).min(data.items.len());
+            data.items.drain(start..end);
+            Ok(Value::Undefined)
+        }),
+        "array_resize" => array().and_then(|array| {
+            let size = args.index(1)?;
+            array.0.borrow_mut().items.resize(size, Value::Real(0.0));
+            Ok(Value::Undefined)
+        }),
+        "array_copy" => array().and_then(|destination| {
+            let destination_index = args.index(1)?;
+            let source = args.array(2)?.items();
+            let source_index = args.index(3)?.min(source.len());
+            let length = args.index(4)?.min(source.len() - source_index);
+            for (i, item) in source[source_index..source_index + length]
+                .iter()
+                .enumerate()
+            {
+                destination.set(destination_index + i, item.clone());
+            }
+            Ok(Value::Undefined)
+        }),
+        "array_sort" => array().and_then(|array| {
+            let ascending = args.truthy(1)?;
+            sort(&mut array.0.borrow_mut().items, ascending)?;
+            Ok(Value::Undefined)
+        }),
+        "array_contains" => array().map(|array| {
+            let value = args.get(1);
+            Value::Bool(array.items().iter().any(|item| equals(item, &value)))
+        }),
+        "array_get_index" => array().map(|array| {
+            let value = args.get(1);
+            let index = array.items().iter().position(|item| equals(item, &value));
+            Value::Real(index.map_or(-1.0, |index| index as f64))
+        }),
+        "array_reverse" => array().map(|array| {
+            let mut items = array.items();
+            items.reverse();
+            Value::Array(Array::new(items, HOST))
+        }),
+        "array_concat" => (0..args.values.len())
+            .map(|i| args.array(i))
+            .collect::<Result<Vec<Array>, String>>()
+            .map(|arrays| {
+                let items = arrays.iter().flat_map(Array::items).collect();
+                Value::Array(Array::new(items, HOST))
+            }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn call_struct(name: &str, args: &Args) -> Option<Result<Value, String>> {
+    let instance = || args.instance(0);
+    let result = match name {
+        "method" => match args.get(1) {
+            Value::Function(function) => Ok(Value::Function(Function {
+                callable: function.callable,
+                bound: match args.get(0) {
+                    Value::Struct(instance) => Some(instance),
+                    _ => None,
+                },
+            })),
+            _ => Err(args.error(1, "a function")),
+        },
+        "method_get_self" => match args.get(0) {
+            Value::Function(function) => Ok(function.bound.map_or(Value::Undefined, Value::Struct)),
+            _ => Err(args.error(0, "a function")),
+        },
+        "struct_exists" | "variable_struct_exists" => {
+            instance().and_then(|instance| Ok(Value::Bool(instance.has(&args.string(1)?))))
+        }
+        "struct_get" | "variable_struct_get" | "variable_instance_get" => match args.get(0) {
+            Value::Struct(instance) => args
+                .string(1)
+                .map(|name| instance.get(&name).unwrap_or(Value::Undefined)),
+            _ => Ok(Value::Undefined),
+        },
+        "struct_set" | "variable_struct_set" | "variable_instance_set" => match args.get(0) {
+            Value::Struct(instance) => args.string(1).map(|name| {
+                instance.set(&name, args.get(2));
+                Value::Undefined
+            }),
+            _ => Ok(Value::Undefined),
+        },
+        "struct_remove" => instance().and_then(|instance| {
+            instance.remove(&args.string(1)?);
+            Ok(Value::Undefined)
+        }),
+        "struct_get_names" => instance().map(|instance| {
+            let names = instance
+                .names()
+                .iter()
+                .map(|name| Value::string(name))
+                .collect();
+            Value::Array(Array::new(names, HOST))
+        }),
+        "json_stringify" => Ok(Value::string(&to_json(&args.get(0), 0).to_string())),
+        "json_parse" => args
+            .string(0)
+            .and_then(|text| Json::parse(&text))
+            .map(|json| from_json(&json)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn to_json(value: &Value, depth: usize) -> Json {
+    if depth > 64 {
+        return Json::Null;
+    }
+    match value {
+        Value::Undefined | Value::Function(_) => Json::Null,
+        Value::Real(number) if number.is_finite() => Json::Number(*number),
+        Value::Real(number) => Json::String(format_real(*number)),
+        Value::Bool(value) => Json::Bool(*value),
+        Value::String(text) => Json::string(text),
+        Value::Array(array) => Json::Array(
+            array
+                .items()
+                .iter()
+                .map(|item| to_json(item, depth + 1))
+                .collect(),
+        ),
+        Value::Struct(instance) => Json::Object(
+            instance
+                .0
+                .borrow()
+                .fields
+                .iter()
+                .filter(|(_, value)| !matches!(value, Value::Function(_)))
+                .map(|(name, value)| (name.clone(), to_json(value, depth + 1)))
+                .collect(),
+        ),
+    }
+}
+
+fn from_json(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::Undefined,
+        Json::Bool(value) => Value::Bool(*value),
+        Json::Number(number) => Value::Real(*number),
+        Json::String(text) => Value::string(text),
+        Json::Array(items) => Value::Array(Array::new(items.iter().map(from_json).collect(), HOST)),
+        Json::Object(fields) => Value::Struct(Struct::with_fields(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), from_json(value)))
+                .collect(),
+        )),
+    }
+}
+
+fn create<T>(pool: &mut Vec<Option<T>>, value: T) -> Value {
+    pool.push(Some(value));
+    Value::Real((pool.len() - 1) as f64)
+}
+
+fn find<'a, T>(pool: &'a mut [Option<T>], args: &Args, kind: &str) -> Result<&'a mut T, String> {
+    let id = args.real(0)?;
+    pool.get_mut(id as usize)
+        .filter(|_| id >= 0.0)
+        .and_then(Option::as_mut)
+        .ok_or_else(|| format!("The {kind} {} doesn't exist", format_real(id)))
+}
+
+fn destroy<T>(pool: &mut [Option<T>], args: &Args, kind: &str) -> Result<Value, String> {
+    find(pool, args, kind)?;
+    pool[args.real(0)? as usize] = None;
+    Ok(Value::Undefined)
+}
@@ -0,0 +1,358 @@
+use crate::compiler::ast::Node;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A GML value. Booleans are kept apart from reals so `typeof` can tell them
+/// apart, but they count as 1 and 0 wherever a number is expected.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Undefined,
+    Real(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Array(Array),
+    Struct(Struct),
+    Function(Function),
+}
+
+/// An array and the call that owns it. Writing to it from another call
+/// copies it first, the way GameMaker's copy on write does.
+#[derive(Clone, Debug)]
+pub struct Array(pub Rc<RefCell<ArrayData>>);
+
+#[derive(Debug)]
+pub struct ArrayData {
+    pub items: Vec<Value>,
+    /// The call that made it, or `HOST` for arrays returned by built-in
+    /// functions, which the first call writing to them takes.
+    pub owner: usize,
+}
+
+/// Owner of the arrays made by the host.
+pub const HOST: usize = 0;
+
+/// A struct, or an instance made by a constructor. Fields keep the order
+/// they were set in, so printing one always gives the same text.
+#[derive(Clone, Debug)]
+pub struct Struct(pub Rc<RefCell<StructData>>);
+
+#[derive(Debug, Default)]
+pub struct StructData {
+    pub fields: Vec<(String, Value)>,
+    /// Name of the constructor that made it, for `instanceof`.
+    pub constructor: Option<String>,
+    /// The static variables of its constructor, read through the struct,
+    /// which lead on to those of the parent constructor.
+    pub statics: Option<Struct>,
+}
+
+/// A function value, and the struct it is bound to when it is a method.
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub callable: Callable,
+    pub bound: Option<Struct>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Callable {
+    /// A function, constructor or class of the program, holding a copy of
+    /// its declaration or expression.
+    Script(Rc<Node>),
+    /// A function of the host or of the interpreter itself.
+    Builtin(Rc<str>),
+}
+
+impl Array {
+    pub fn new(items: Vec<Value>, owner: usize) -> Array {
+        Array(Rc::new(RefCell::new(ArrayData { items, owner })))
+    }
+
+    pub fn items(&self) -> Vec<Value> {
+        self.0.borrow().items.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.0.borrow().items.get(index).cloned()
+    }
+
+    /// Sets an item, filling the array with zeros up to it like GameMaker.
+    pub fn set(&self, index: usize, value: Value) {
+        let mut data = self.0.borrow_mut();
+        if data.items.len() <= index {
+            data.items.resize(index + 1, Value::Real(0.0));
+        }
+        data.items[index] = value;
+    }
+}
+
+impl Struct {
+    pub fn new() -> Struct {
+        Struct(Rc::new(RefCell::new(StructData::default())))
+    }
+
+    pub fn with_fields(fields: Vec<(String, Value)>) -> Struct {
+        let instance = Struct::new();
+        for (name, value) in fields {
+            instance.set(&name, value);
+        }
+        instance
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.0
+            .borrow()
+            .fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// A field, or else a static variable of its constructor.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.get(name) {
+            return Some(value);
+        }
+        let statics = self.0.borrow().statics.clone();
+        statics.and_then(|statics| statics.lookup(name))
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.0
+            .borrow()
+            .fields
+            .iter()
+            .any(|(field, _)| field == name)
+    }
+
+    pub fn set(&self, name: &str, value: Value) {
+        let mut data = self.0.borrow_mut();
+        match data.fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, old)) => *old = value,
+            None => data.fields.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.0
+            .borrow_mut()
+            .fields
+            .retain(|(field, _)| field != name);
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0
+            .borrow()
+            .fields
+            .iter()
+            .map(|(field, _)| field.clone())
+            .collect()
+    }
+
+    pub fn constructor(&self) -> Option<String> {
+        self.0.borrow().constructor.clone()
+    }
+
+    pub fn is(&self, other: &Struct) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Default for Struct {
+    fn default() -> Struct {
+        Struct::new()
+    }
+}
+
+impl Function {
+    pub fn builtin(name: &str) -> Function {
+        Function {
+            callable: Callable::Builtin(Rc::from(name)),
+            bound: None,
+        }
+    }
+
+    pub fn script(node: Rc<Node>, bound: Option<Struct>) -> Function {
+        Function {
+            callable: Callable::Script(node),
+            bound,
+        }
+    }
+
+    /// The name GameMaker would show, empty for function expressions.
+    pub fn name(&self) -> &str {
+        match &self.callable {
+            Callable::Builtin(name) => name,
+            Callable::Script(node) => match node.as_ref() {
+                Node::FunctionDeclaration(name, _, _, _) | Node::ClassDeclaration(name, _) => name,
+                Node::ConstructorDeclaration(function, _) => match function.as_ref() {
+                    Node::FunctionDeclaration(name, _, _, _) => name,
+                    _ => "",
+                },
+                _ => "",
+            },
+        }
+    }
+
+    pub fn is(&self, other: &Function) -> bool {
+        let same_callable = match (&self.callable, &other.callable) {
+            (Callable::Script(a), Callable::Script(b)) => Rc::ptr_eq(a, b),
+            (Callable::Builtin(a), Callable::Builtin(b)) => a == b,
+            _ => false,
+        };
+        let same_self = match (&self.bound, &other.bound) {
+            (Some(a), Some(b)) => a.is(b),
+            (None, None) => true,
+            _ => false,
+        };
+        same_callable && same_self
+    }
+}
+
+impl Value {
+    pub fn string(text: &str) -> Value {
+        Value::String(Rc::from(text))
+    }
+
+    /// What `typeof` returns.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Undefined => "undefined",
+            Value::Real(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Struct(_) => "struct",
+            Value::Function(_) => "method",
+        }
+    }
+
+    /// The number a real or a bool stands for.
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Value::Real(value) => Some(*value),
+            Value::Bool(value) => Some(f64::from(u8::from(*value))),
+            _ => None,
+        }
+    }
+
+    /// Whether a condition holds: GameMaker treats numbers above 0.5 as true.
+    pub fn is_truthy(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            Value::Real(value) => Some(*value > 0.5),
+            Value::Undefined => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The text `string()` gives.
+    pub fn to_gml_string(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, false, 0);
+        text
+    }
+
+    /// Like `to_gml_string`, with strings quoted the way they are inside
+    /// arrays, so `"1"` and `1` can be told apart.
+    pub fn repr(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, true, 0);
+        text
+    }
+
+    fn write(&self, out: &mut String, nested: bool, depth: usize) {
+        // Structs can hold themselves, which GameMaker stops printing too.
+        if depth > 16 {
+            out.push_str("...");
+            return;
+        }
+
+        match self {
+            Value::Undefined => out.push_str("undefined"),
+            Value::Real(value) => out.push_str(&format_real(*value)),
+            Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Value::String(text) if nested => {
+                out.push('"');
+                out.push_str(text);
+                out.push('"');
+            }
+            Value::String(text) => out.push_str(text),
+            Value::Array(array) => {
+                out.push_str("[ ");
+                for (i, item) in array.items().iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write(out, true, depth + 1);
+                }
+                out.push_str(" ]");
+            }
+            Value::Struct(instance) => {
+                out.push_str("{ ");
+                let fields = instance.0.borrow().fields.clone();
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(name);
+                    out.push_str(" : ");
+                    value.write(out, true, depth + 1);
+                }
+                out.push_str(" }");
+            }
+            Value::Function(function) => match function.name() {
+                "" => out.push_str("function anonymous"),
+                name => {
+                    out.push_str("function ");
+                    out.push_str(name);
+                }
+            },
+        }
+    }
+}
+
+/// Reals print as whole numbers when they are, and with two decimals
+/// otherwise, like `string()` does.
+pub fn format_real(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "inf" } else { "-inf" })
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        let text = format!("{value:.2}");
+        match text.as_str() {
+            "-0.00" => String::from("0.00"),
+            _ => text,
+        }
+    }
+}
+
+/// How far apart two reals can be and still be equal, GameMaker's default.
+pub const EPSILON: f64 = 0.00001;
+
+/// `==` in GameMaker: reals within `EPSILON` of each other are equal, arrays,
+/// structs and methods are the same one, and values of different types never
+/// are, except reals and bools.
+pub fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Undefined, Value::Undefined) => true,
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(&a.0, &b.0),
+        (Value::Struct(a), Value::Struct(b)) => a.is(b),
+        (Value::Function(a), Value::Function(b)) => a.is(b),
+        (a, b) => match (a.as_real(), b.as_real()) {
+            (Some(a), Some(b)) => a == b || (a - b).abs() <= EPSILON,
+            _ => false,
+        },
+    }
+}
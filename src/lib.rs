@@ -2,4 +2,11 @@ pub mod box_utils;
 pub mod compiler;
 pub mod enum_utils;
 pub mod front_end;
+pub mod interp;
+pub mod lsp;
+pub mod manifest;
+pub mod project;
+pub mod repl;
 pub mod string_utils;
+pub mod trace;
+pub mod watch;
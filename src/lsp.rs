@@ -0,0 +1,924 @@
+pub mod document;
+pub mod json;
+
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{parameter_name, parameter_type, Type};
+use crate::compiler::declarations::{load_declarations, STANDARD_DECLARATIONS};
+use crate::compiler::parser::parse;
+use crate::compiler::tokenizer::{tokenize, Language, TokenType};
+use document::{Definition, Document, SymbolKind, FILE_SCOPE};
+use json::Json;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INTERNAL_ERROR: f64 = -32603.0;
+
+/// A language server for GML++ and GML files, answering one JSON-RPC message
+/// at a time so it can be driven by a script as well as by an editor.
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// Types of the GameMaker API and of the project's `.d.gmpp` files.
+    declarations: HashMap<String, Type>,
+    /// The bundled extern declarations, for parameter names.
+    extern_functions: Vec<Node>,
+    shutting_down: bool,
+    pub exit_code: Option<i32>,
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        let extern_functions = match tokenize(STANDARD_DECLARATIONS.to_string()).and_then(parse) {
+            Ok(Node::Program(body)) => body.into_iter().map(|stmt| *stmt).collect(),
+            _ => Vec::new(),
+        };
+
+        Server {
+            documents: HashMap::new(),
+            declarations: load_declarations(&[]).unwrap_or_default(),
+            extern_functions,
+            shutting_down: false,
+            exit_code: None,
+        }
+    }
+
+    /// Handles a request or notification, returning the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or("").to_string();
+        let params = message.get("params");
+        let id = message.get("id").clone();
+
+        let result = catch_unwind(AssertUnwindSafe(|| self.dispatch(&method, params)));
+        let (result, mut notifications) = match result {
+            Ok(Ok((result, notifications))) => (Ok(result), notifications),
+            Ok(Err((code, error))) => (Err((code, error)), Vec::new()),
+            Err(_) => (
+                Err((
+                    INTERNAL_ERROR,
+                    format!("The server crashed handling '{method}'"),
+                )),
+                Vec::new(),
+            ),
+        };
+
+        // Notifications have no id and get no response.
+        if id != Json::Null {
+            let response = match result {
+                Ok(result) => Json::object(vec![
+                    ("jsonrpc", Json::string("2.0")),
+                    ("id", id),
+                    ("result", result),
+                ]),
+                Err((code, error)) => Json::object(vec![
+                    ("jsonrpc", Json::string("2.0")),
+                    ("id", id),
+                    (
+                        "error",
+                        Json::object(vec![
+                            ("code", Json::Number(code)),
+                            ("message", Json::String(error)),
+                        ]),
+                    ),
+                ]),
+            };
+            notifications.insert(0, response);
+        }
+
+        notifications
+    }
+
+    fn dispatch(
+        &mut self,
+        method: &str,
+        params: &Json,
+    ) -> Result<(Json, Vec<Json>), (f64, String)> {
+        let result = match method {
+            "initialize" => self.initialize(params),
+            "shutdown" => {
+                self.shutting_down = true;
+                Json::Null
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutting_down { 0 } else { 1 });
+                Json::Null
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.get("uri").as_str().unwrap_or("");
+                let text = document.get("text").as_str().unwrap_or("");
+                return Ok((Json::Null, self.open(uri, text.to_string())));
+            }
+            "textDocument/didChange" => {
+                let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+                // Only full syncs are asked for, so the last change is the whole text.
+                let text = params
+                    .get("contentChanges")
+                    .as_array()
+                    .last()
+                    .and_then(|change| change.get("text").as_str())
+                    .unwrap_or("");
+                return Ok((Json::Null, self.open(uri, text.to_string())));
+            }
+            "textDocument/didClose" => {
+                let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+                self.documents.remove(uri);
+                return Ok((Json::Null, vec![publish_diagnostics(uri, Vec::new())]));
+            }
+            "textDocument/hover" => self.with_position(params, Server::hover),
+            "textDocument/definition" => self.with_position(params, Server::definition),
+            "textDocument/references" => self.with_position(params, Server::references),
+            "textDocument/completion" => self.with_position(params, Server::completion),
+            "textDocument/signatureHelp" => self.with_position(params, Server::signature_help),
+            "textDocument/documentSymbol" => {
+                let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+                match self.documents.get(uri) {
+                    Some(document) => document_symbols(document),
+                    None => Json::Array(Vec::new()),
+                }
+            }
+            "initialized"
+            | "$/cancelRequest"
+            | "$/setTrace"
+            | "workspace/didChangeConfiguration" => Json::Null,
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method '{method}'"))),
+        };
+
+        Ok((result, Vec::new()))
+    }
+
+    fn initialize(&mut self, params: &Json) -> Json {
+        let root = params
+            .get("rootUri")
+            .as_str()
+            .map(uri_to_path)
+            .or(params.get("rootPath").as_str().map(PathBuf::from));
+
+        if let Some(root) = root {
+            let mut files: Vec<String> = std::fs::read_dir(root)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|entry| entry.path().display().to_string())
+                        .filter(|path| path.ends_with(".d.gmpp"))
+                        .collect()
+                })
+                .unwrap_or_default();
+            files.sort();
+
+            match load_declarations(&files) {
+                Ok(declarations) => self.declarations = declarations,
+                Err(errors) => {
+                    for error in errors {
+                        eprintln!("error: {error}");
+                    }
+                }
+            }
+        }
+
+        Json::object(vec![
+            (
+                "capabilities",
+                Json::object(vec![
+                    ("textDocumentSync", Json::number(1)),
+                    ("hoverProvider", Json::Bool(true)),
+                    ("definitionProvider", Json::Bool(true)),
+                    ("referencesProvider", Json::Bool(true)),
+                    ("documentSymbolProvider", Json::Bool(true)),
+                    (
+                        "completionProvider",
+                        Json::object(vec![(
+                            "triggerCharacters",
+                            Json::Array(vec![Json::string(".")]),
+                        )]),
+                    ),
+                    (
+                        "signatureHelpProvider",
+                        Json::object(vec![(
+                            "triggerCharacters",
+                            Json::Array(vec![Json::string("("), Json::string(",")]),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                "serverInfo",
+                Json::object(vec![
+                    ("name", Json::string("gmpp-lsp")),
+                    ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+                ]),
+            ),
+        ])
+    }
+
+    fn open(&mut self, uri: &str, text: String) -> Vec<Json> {
+        let language = Language::from_path(Path::new(uri));
+
+        match self.documents.get_mut(uri) {
+            Some(document) => document.update(text, language, &self.declarations),
+            None => {
+                let document = Document::new(text, language, &self.declarations);
+                self.documents.insert(uri.to_string(), document);
+            }
+        }
+
+        let document = &self.documents[uri];
+        let errors = document.errors.iter().map(|error| (error, 1));
+        let warnings = document.warnings.iter().map(|warning| (warning, 2));
+        let diagnostics = errors
+            .chain(warnings)
+            .map(|(error, severity)| {
+                Json::object(vec![
+                    ("range", document.error_range(error)),
+                    ("severity", Json::number(severity)),
+                    ("source", Json::string("gmpp")),
+                    ("message", Json::string(&error.message)),
+                ])
+            })
+            .collect();
+
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn with_position(
+        &self,
+        params: &Json,
+        handler: fn(&Server, &str, &Document, usize, &Json) -> Json,
+    ) -> Json {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+
+        match self.documents.get(uri) {
+            Some(document) => {
+                let offset = document.offset(params.get("position"));
+                handler(self, uri, document, offset, params)
+            }
+            None => Json::Null,
+        }
+    }
+
+    /// Top-level declarations of the other open files, for imported names.
+    fn find_elsewhere(&self, uri: &str, name: &str) -> Option<(&str, &Document, &Definition)> {
+        self.documents
+            .iter()
+            .filter(|(other, _)| other.as_str() != uri)
+            .find_map(|(other, document)| {
+                document
+                    .definitions
+                    .iter()
+                    .find(|definition| {
+                        definition.name == name
+                            && definition.scope == FILE_SCOPE
+                            && definition.kind != SymbolKind::Import
+                    })
+                    .map(|definition| (other.as_str(), document, definition))
+            })
+    }
+
+    fn hover(&self, uri: &str, document: &Document, offset: usize, _: &Json) -> Json {
+        let index = match document.identifier_at(offset) {
+            Some(index) => index,
+            None => return Json::Null,
+        };
+        let name = &document.tokens[index].lex;
+
+        let text = match document.resolve(index) {
+            Some(definition) if definition.kind != SymbolKind::Import => {
+                describe(definition, document.type_of(definition))
+            }
+            _ => match self.find_elsewhere(uri, name) {
+                Some((_, other, definition)) => describe(definition, other.type_of(definition)),
+                None => match self.declarations.get(name) {
+                    Some(kind) => format!("extern {name}: {}", kind.name()),
+                    None => return Json::Null,
+                },
+            },
+        };
+
+        Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![
+                    ("kind", Json::string("markdown")),
+                    ("value", Json::String(format!("```gmpp\n{text}\n```"))),
+                ]),
+            ),
+            ("range", document.token_range(index)),
+        ])
+    }
+
+    fn definition(&self, uri: &str, document: &Document, offset: usize, _: &Json) -> Json {
+        let index = match document.identifier_at(offset) {
+            Some(index) => index,
+            None => return Json::Null,
+        };
+
+        match document.resolve(index) {
+            Some(definition) if definition.kind != SymbolKind::Import => {
+                location(uri, document.name_range(definition))
+            }
+            local => match self.find_elsewhere(uri, &document.tokens[index].lex) {
+                Some((other_uri, other, definition)) => {
+                    location(other_uri, other.name_range(definition))
+                }
+                None => match local {
+                    Some(import) => location(uri, document.name_range(import)),
+                    None => Json::Null,
+                },
+            },
+        }
+    }
+
+    fn references(&self, uri: &str, document: &Document, offset: usize, params: &Json) -> Json {
+        let include_declaration = params
+            .get("context")
+            .get("includeDeclaration")
+            .as_bool()
+            .unwrap_or(true);
+
+        let index = match document.identifier_at(offset) {
+            Some(index) => index,
+            None => return Json::Null,
+        };
+        let name = document.tokens[index].lex.clone();
+
+        // Find the declaration first, then every file that can see it.
+        let (definition_uri, definition) = match document.resolve(index) {
+            Some(definition) if definition.kind != SymbolKind::Import => (uri, definition.clone()),
+            _ => match self.find_elsewhere(uri, &name) {
+                Some((other_uri, _, definition)) => (other_uri, definition.clone()),
+                None => return Json::Array(Vec::new()),
+            },
+        };
+
+        let mut uris: Vec<&String> = self.documents.keys().collect();
+        uris.sort();
+
+        let mut locations = Vec::new();
+        for other_uri in uris {
+            let other = &self.documents[other_uri];
+
+            let indices = if other_uri == definition_uri {
+                other.references(&definition)
+            } else if definition.scope == FILE_SCOPE {
+                other.unresolved(&name)
+            } else {
+                Vec::new()
+            };
+
+            for index in indices {
+                let is_declaration =
+                    other_uri == definition_uri && other.tokens[index].offset == definition.offset;
+                if include_declaration || !is_declaration {
+                    locations.push(location(other_uri, other.token_range(index)));
+                }
+            }
+        }
+
+        Json::Array(locations)
+    }
+
+    fn completion(&self, _: &str, document: &Document, offset: usize, _: &Json) -> Json {
+        // The identifier being typed, if any, is left for the editor to filter.
+        let mut before = document.token_before(offset);
+        if let Some(index) = before {
+            let token = &document.tokens[index];
+            if token.kind == TokenType::Identifier && token.offset + token.lex.len() == offset {
+                before = index.checked_sub(1);
+            }
+        }
+
+        let items = match before {
+            Some(dot) if dot >= 1 && document.tokens[dot].kind == TokenType::Dot => {
+                self.member_completions(document, dot - 1, offset)
+            }
+            _ => self.scope_completions(document, offset),
+        };
+
+        Json::object(vec![
+            ("isIncomplete", Json::Bool(false)),
+            ("items", Json::Array(items)),
+        ])
+    }
+
+    fn member_completions(&self, document: &Document, receiver: usize, offset: usize) -> Vec<Json> {
+        let container = document.container_of(receiver);
+
+        let members: Vec<Json> = document
+            .definitions
+            .iter()
+            .filter(|definition| {
+                container.is_some()
+                    && definition.container == container
+                    && definition.kind != SymbolKind::Parameter
+            })
+            .map(|definition| completion_item(definition, document.type_of(definition)))
+            .collect();
+        if !members.is_empty() {
+            return members;
+        }
+
+        // Data structures and other handles have no members, but the functions
+        // taking them as first argument are offered as if they were, and
+        // written out as a call.
+        let kind = match document
+            .resolve(receiver)
+            .and_then(|definition| document.type_of(definition))
+        {
+            Some(Type::Named(name, _)) => name,
+            _ => return Vec::new(),
+        };
+        let prefix = snake_case(&kind) + "_";
+        let receiver_token = &document.tokens[receiver];
+
+        let mut functions: Vec<(&String, &Vec<Type>)> = self
+            .declarations
+            .iter()
+            .filter_map(|(name, declared)| match declared {
+                Type::Function(params, _) => match params.first() {
+                    Some(Type::Named(first, _)) if *first == kind => Some((name, params)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        functions.sort_by_key(|(name, _)| *name);
+
+        functions
+            .into_iter()
+            .map(|(name, params)| {
+                let label = name.strip_prefix(&prefix).unwrap_or(name);
+                let rest = if params.len() > 1 { ", " } else { ")" };
+
+                Json::object(vec![
+                    ("label", Json::string(label)),
+                    ("kind", Json::number(3)),
+                    ("detail", Json::String(self.declarations[name].name())),
+                    (
+                        "filterText",
+                        Json::String(format!("{}.{label}", receiver_token.lex)),
+                    ),
+                    (
+                        "textEdit",
+                        Json::object(vec![
+                            ("range", document.range(receiver_token.offset, offset)),
+                            (
+                                "newText",
+                                Json::String(format!("{name}({}{rest}", receiver_token.lex)),
+                            ),
+                        ]),
+                    ),
+                ])
+            })
+            .collect()
+    }
+
+    fn scope_completions(&self, document: &Document, offset: usize) -> Vec<Json> {
+        let mut seen = Vec::new();
+        let mut items = Vec::new();
+
+        for definition in document.definitions.iter().rev() {
+            let visible = definition.scope.0 <= offset && offset <= definition.scope.1;
+            if visible && !seen.contains(&definition.name) {
+                seen.push(definition.name.clone());
+                items.push(completion_item(definition, document.type_of(definition)));
+            }
+        }
+
+        let mut declarations: Vec<(&String, &Type)> = self.declarations.iter().collect();
+        declarations.sort_by_key(|(name, _)| *name);
+        for (name, kind) in declarations {
+            if !seen.contains(name) {
+                let code = if matches!(kind, Type::Function(_, _)) {
+                    3
+                } else {
+                    21
+                };
+                items.push(Json::object(vec![
+                    ("label", Json::string(name)),
+                    ("kind", Json::number(code)),
+                    ("detail", Json::String(kind.name())),
+                ]));
+            }
+        }
+
+        items
+    }
+
+    fn signature_help(&self, _: &str, document: &Document, offset: usize, _: &Json) -> Json {
+        // Walk back to the parenthesis of the call the cursor is in, counting
+        // the commas on the way.
+        let mut depth = 0;
+        let mut commas = 0;
+        let mut open = None;
+        let mut index = document.token_before(offset);
+
+        while let Some(i) = index {
+            match document.tokens[i].kind {
+                TokenType::CloseParenthesis | TokenType::CloseBracket | TokenType::CloseCurly => {
+                    depth += 1
+                }
+                TokenType::OpenParenthesis if depth == 0 => {
+                    open = Some(i);
+                    break;
+                }
+                TokenType::OpenParenthesis | TokenType::OpenBracket | TokenType::OpenAccessor => {
+                    depth -= 1
+                }
+                TokenType::OpenCurly | TokenType::Semilicon if depth == 0 => break,
+                TokenType::OpenCurly => depth -= 1,
+                TokenType::Comma if depth == 0 => commas += 1,
+                _ => {}
+            }
+            index = i.checked_sub(1);
+        }
+
+        let callee = match open.and_then(|open| open.checked_sub(1)) {
+            Some(callee) if document.tokens[callee].kind == TokenType::Identifier => callee,
+            _ => return Json::Null,
+        };
+
+        let name = &document.tokens[callee].lex;
+        let signature = document
+            .program
+            .as_ref()
+            .and_then(|program| find_signature(program, name))
+            .or(self
+                .extern_functions
+                .iter()
+                .find_map(|node| find_signature(node, name)));
+
+        let (params, return_type) = match signature {
+            Some(signature) => signature,
+            None => return Json::Null,
+        };
+
+        let labels: Vec<String> = params.iter().map(|param| parameter_label(param)).collect();
+        let mut label = format!("{name}({})", labels.join(", "));
+        if let Some(return_type) = return_type {
+            label.push_str(&format!(" -> {return_type}"));
+        }
+
+        let is_rest = params
+            .last()
+            .is_some_and(|param| matches!(param.as_ref(), Node::RestParameter(_)));
+        let active = if is_rest {
+            commas.min(params.len().saturating_sub(1))
+        } else {
+            commas
+        };
+
+        Json::object(vec![
+            (
+                "signatures",
+                Json::Array(vec![Json::object(vec![
+                    ("label", Json::String(label)),
+                    (
+                        "parameters",
+                        Json::Array(
+                            labels
+                                .into_iter()
+                                .map(|label| Json::object(vec![("label", Json::String(label))]))
+                                .collect(),
+                        ),
+                    ),
+                ])]),
+            ),
+            ("activeSignature", Json::number(0)),
+            ("activeParameter", Json::number(active)),
+        ])
+    }
+}
+
+/// Reads a message framed with a `Content-Length` header. A line holding a
+/// whole JSON object is also read as a message, so sessions can be scripted.
+pub fn read_message(reader: &mut impl BufRead) -> Option<Result<Json, String>> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+
+        let line = line.trim();
+        if line.starts_with('{') {
+            return Some(Json::parse(line));
+        }
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length?];
+    if reader.read_exact(&mut body).is_err() {
+        return None;
+    }
+
+    Some(
+        String::from_utf8(body)
+            .map_err(|error| error.to_string())
+            .and_then(|body| Json::parse(&body)),
+    )
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", Json::string(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn location(uri: &str, range: Json) -> Json {
+    Json::object(vec![("uri", Json::string(uri)), ("range", range)])
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+
+    // Percent-encoded bytes, like `%20` for spaces.
+    let mut bytes = Vec::new();
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex: Vec<u8> = chars.by_ref().take(2).collect();
+            let decoded = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match decoded {
+                Some(decoded) => bytes.push(decoded),
+                None => {
+                    bytes.push(byte);
+                    bytes.extend(hex);
+                }
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn describe(definition: &Definition, kind: Option<Type>) -> String {
+    let name = match &definition.container {
+        Some(container) => format!("{container}.{}", definition.name),
+        None => definition.name.clone(),
+    };
+
+    match kind {
+        Some(kind) => format!("{} {name}: {}", definition.kind.describe(), kind.name()),
+        None => format!("{} {name}", definition.kind.describe()),
+    }
+}
+
+fn completion_item(definition: &Definition, kind: Option<Type>) -> Json {
+    let mut item = vec![
+        ("label", Json::string(&definition.name)),
+        ("kind", Json::number(definition.kind.completion_code())),
+    ];
+    if let Some(kind) = kind {
+        item.push(("detail", Json::String(kind.name())));
+    }
+    Json::object(item)
+}
+
+/// `DsList` becomes `ds_list`, the prefix of the functions taking one.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, char) in name.chars().enumerate() {
+        if char.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(char.to_lowercase());
+    }
+    snake
+}
+
+fn parameter_label(param: &Node) -> String {
+    let name = parameter_name(param).cloned().unwrap_or_default();
+
+    match parameter_type(param) {
+        Type::Optional(inner) if *inner == Type::Untyped => format!("{name}?"),
+        Type::Optional(inner) => format!("{name}?: {}", inner.name()),
+        Type::Rest(inner) if *inner == Type::Untyped => format!("...{name}"),
+        Type::Rest(inner) => format!("...{name}: {}", inner.name()),
+        Type::Untyped => name,
+        kind => format!("{name}: {}", kind.name()),
+    }
+}
+
+type Signature<'a> = (&'a [Box<Node>], Option<String>);
+
+/// Parameters and return type of the function, class or extern named `name`.
+fn find_signature<'a>(node: &'a Node, name: &str) -> Option<Signature<'a>> {
+    let return_type =
+        |annotation: &Option<Box<Node>>| annotation.as_ref().map(|node| node.display_type());
+
+    match node {
+        Node::FunctionDeclaration(function, params, annotation, _)
+        | Node::ExternFunction(function, params, annotation)
+            if function == name =>
+        {
+            return Some((params, return_type(annotation)));
+        }
+        Node::ConstructorDeclaration(function, _) => {
+            if let Node::FunctionDeclaration(function, params, _, _) = function.as_ref() {
+                if function == name {
+                    return Some((params, Some(name.to_string())));
+                }
+            }
+        }
+        Node::ClassDeclaration(class, members) if class == name => {
+            let constructor = members.iter().find_map(|member| match member.as_ref() {
+                Node::ConstructorDeclaration(function, _) => match function.as_ref() {
+                    Node::FunctionDeclaration(_, params, _, _) => Some(params.as_slice()),
+                    _ => None,
+                },
+                _ => None,
+            });
+            return Some((constructor.unwrap_or(&[]), Some(name.to_string())));
+        }
+        _ => {}
+    }
+
+    node.children()
+        .into_iter()
+        .find_map(|child| find_signature(child, name))
+}
+
+fn document_symbols(document: &Document) -> Json {
+    let symbol = |definition: &Definition, children: Vec<Json>| {
+        let mut fields = vec![
+            ("name", Json::string(&definition.name)),
+            ("kind", Json::number(definition.kind.symbol_code())),
+            ("range", document.name_range(definition)),
+            ("selectionRange", document.name_range(definition)),
+        ];
+        if let Some(kind) = document.type_of(definition) {
+            fields.push(("detail", Json::String(kind.name())));
+        }
+        if !children.is_empty() {
+            fields.push(("children", Json::Array(children)));
+        }
+        Json::object(fields)
+    };
+
+    let symbols = document
+        .definitions
+        .iter()
+        .filter(|definition| {
+            definition.scope == FILE_SCOPE && definition.kind != SymbolKind::Import
+        })
+        .map(|definition| {
+            let members = document
+                .definitions
+                .iter()
+                .filter(|member| member.container.as_ref() == Some(&definition.name))
+                .map(|member| symbol(member, Vec::new()))
+                .collect();
+            symbol(definition, members)
+        })
+        .collect();
+
+    Json::Array(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends framed messages to a server the way an editor does, returning
+    /// the messages it sent back and the server.
+    fn session(messages: &[&str]) -> (Vec<Json>, Server) {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, &Json::parse(message).unwrap()).unwrap();
+        }
+
+        let mut server = Server::new();
+        let mut output = Vec::new();
+        let mut reader = input.as_slice();
+        while let Some(message) = read_message(&mut reader) {
+            for reply in server.handle(&message.unwrap()) {
+                write_message(&mut output, &reply).unwrap();
+            }
+        }
+
+        let mut replies = Vec::new();
+        let mut reader = output.as_slice();
+        while let Some(reply) = read_message(&mut reader) {
+            replies.push(reply.unwrap());
+        }
+        (replies, server)
+    }
+
+    fn open(text: &str) -> String {
+        let document = Json::object(vec![
+            ("uri", Json::string("file:///game/a.gmpp")),
+            ("languageId", Json::string("gmpp")),
+            ("version", Json::number(1)),
+            ("text", Json::string(text)),
+        ]);
+        Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string("textDocument/didOpen")),
+            ("params", Json::object(vec![("textDocument", document)])),
+        ])
+        .to_string()
+    }
+
+    #[test]
+    fn initializes_and_exits() {
+        let (replies, server) = session(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]);
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].get("id").as_usize(), Some(1));
+        let capabilities = replies[0].get("result").get("capabilities");
+        assert_eq!(
+            capabilities.get("documentSymbolProvider").as_bool(),
+            Some(true)
+        );
+        assert_eq!(replies[1].get("id").as_usize(), Some(2));
+        assert_eq!(server.exit_code, Some(0));
+    }
+
+    #[test]
+    fn diagnostics_cover_the_code_they_are_about() {
+        let (replies, _) = session(&[&open(
+            "let a = 1;\nfunction f() {\n    let b: string = a;\n}\n",
+        )]);
+
+        let [notification] = replies.as_slice() else {
+            panic!("Expected a single notification, found {replies:?}");
+        };
+        assert_eq!(
+            notification.get("method").as_str(),
+            Some("textDocument/publishDiagnostics")
+        );
+        let diagnostics = notification.get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].get("message").as_str(),
+            Some("Can't assign a number to 'b', which is declared as string")
+        );
+        assert_eq!(
+            diagnostics[0].get("range").to_string(),
+            r#"{"start":{"line":2,"character":4},"end":{"line":2,"character":22}}"#
+        );
+    }
+
+    #[test]
+    fn symbols_are_kept_before_a_tokenizer_error() {
+        let (replies, _) = session(&[
+            &open("function greet() {}\nlet s = \"abc;\n"),
+            r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///game/a.gmpp"}}}"#,
+        ]);
+
+        let diagnostics = replies[0].get("params").get("diagnostics").as_array();
+        assert_eq!(
+            diagnostics[0].get("range").get("start").to_string(),
+            r#"{"line":1,"character":8}"#
+        );
+
+        let symbols = replies[1].get("result").as_array();
+        let names: Vec<&str> = symbols
+            .iter()
+            .filter_map(|symbol| symbol.get("name").as_str())
+            .collect();
+        assert_eq!(names, ["greet", "s"]);
+    }
+}
@@ -0,0 +1,766 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{analyze, Analysis, Strictness, Type};
+use crate::compiler::error::Error;
+use crate::compiler::parser::parse_with_positions_as;
+use crate::compiler::tokenizer::{tokenize_as, Language, Token, TokenType};
+use crate::lsp::json::Json;
+use std::collections::HashMap;
+
+/// The whole file, for symbols visible everywhere in it.
+pub const FILE_SCOPE: (usize, usize) = (0, usize::MAX);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Enum,
+    Variable,
+    Parameter,
+    Field,
+    Method,
+    EnumMember,
+    Macro,
+    /// A name brought in by `import`, defined in another file.
+    Import,
+}
+
+impl SymbolKind {
+    /// The word shown before the name when hovering it.
+    pub fn describe(&self) -> &str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Class => "class",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Field => "field",
+            SymbolKind::Method => "method",
+            SymbolKind::EnumMember => "enum member",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Import => "import",
+        }
+    }
+
+    /// LSP's `SymbolKind`.
+    pub fn symbol_code(&self) -> usize {
+        match self {
+            SymbolKind::Function => 12,
+            SymbolKind::Class => 5,
+            SymbolKind::Enum => 10,
+            SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Import => 13,
+            SymbolKind::Field => 8,
+            SymbolKind::Method => 6,
+            SymbolKind::EnumMember => 22,
+            SymbolKind::Macro => 14,
+        }
+    }
+
+    /// LSP's `CompletionItemKind`.
+    pub fn completion_code(&self) -> usize {
+        match self {
+            SymbolKind::Function => 3,
+            SymbolKind::Class => 7,
+            SymbolKind::Enum => 13,
+            SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Import => 6,
+            SymbolKind::Field => 5,
+            SymbolKind::Method => 2,
+            SymbolKind::EnumMember => 20,
+            SymbolKind::Macro => 21,
+        }
+    }
+}
+
+/// Where a name is declared. The tree has no positions, so these are found
+/// by walking the tokens.
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Offset of the name in the source.
+    pub offset: usize,
+    /// Range of the source where the name can be used without a `.` before it.
+    pub scope: (usize, usize),
+    /// The class or enum declaring it.
+    pub container: Option<String>,
+    /// Whether the checker declares it as a variable or parameter, which is how
+    /// it is matched with `Analysis::locals`.
+    pub checked: bool,
+}
+
+/// An open file, analysed again on every change.
+pub struct Document {
+    pub text: String,
+    pub tokens: Vec<Token>,
+    pub definitions: Vec<Definition>,
+    /// The tree and types of the last version that parsed, so completion
+    /// keeps working while a line is being typed.
+    pub program: Option<Node>,
+    pub analysis: Option<Analysis>,
+    pub errors: Vec<Error>,
+    /// What strict mode would reject, shown as warnings.
+    pub warnings: Vec<Error>,
+    line_starts: Vec<usize>,
+}
+
+impl Document {
+    pub fn new(text: String, language: Language, imports: &HashMap<String, Type>) -> Document {
+        let mut document = Document {
+            text: String::new(),
+            tokens: Vec::new(),
+            definitions: Vec::new(),
+            program: None,
+            analysis: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            line_starts: Vec::new(),
+        };
+        document.update(text, language, imports);
+        document
+    }
+
+    pub fn update(&mut self, text: String, language: Language, imports: &HashMap<String, Type>) {
+        self.line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.errors.clear();
+        self.warnings.clear();
+
+        let tokenized = tokenize_as(text.clone(), language);
+        self.tokens = match &tokenized {
+            Ok(tokens) => tokens.clone(),
+            // The code before the mistake still declares names, for the
+            // outline and going to definitions.
+            Err(error) => error
+                .span
+                .and_then(|span| text.get(..span.start))
+                .and_then(|before| tokenize_as(before.to_string(), language).ok())
+                .unwrap_or_default(),
+        };
+        self.text = text;
+        self.definitions = find_definitions(&self.tokens, &self.text);
+
+        if let Err(error) = tokenized {
+            self.errors.push(error);
+            return;
+        }
+
+        match parse_with_positions_as(self.tokens.clone(), language) {
+            Ok((program, positions)) => {
+                let strictness = match language {
+                    Language::Gml => Strictness::Off,
+                    Language::GmlPlusPlus => Strictness::Warn,
+                };
+                let analysis = analyze(&program, &positions, imports, strictness);
+                self.errors.extend(analysis.errors.clone());
+                self.warnings.extend(analysis.warnings.clone());
+                self.analysis = Some(analysis);
+                self.program = Some(program);
+            }
+            Err(error) => self.errors.push(error),
+        }
+    }
+
+    /// LSP position (line and UTF-16 column) of a byte offset.
+    pub fn position(&self, offset: usize) -> Json {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self
+            .text
+            .get(start..offset)
+            .map(|text| text.encode_utf16().count())
+            .unwrap_or(0);
+
+        Json::object(vec![
+            ("line", Json::number(line)),
+            ("character", Json::number(character)),
+        ])
+    }
+
+    /// Byte offset of an LSP position, clamped to the text.
+    pub fn offset(&self, position: &Json) -> usize {
+        let line = position.get("line").as_usize().unwrap_or(0);
+        let character = position.get("character").as_usize().unwrap_or(0);
+
+        let start = match self.line_starts.get(line) {
+            Some(start) => *start,
+            None => return self.text.len(),
+        };
+
+        let mut units = 0;
+        for (i, char) in self.text[start..].char_indices() {
+            if units >= character || char == '\n' {
+                return start + i;
+            }
+            units += char.len_utf16();
+        }
+        self.text.len()
+    }
+
+    pub fn range(&self, start: usize, end: usize) -> Json {
+        Json::object(vec![
+            ("start", self.position(start)),
+            ("end", self.position(end)),
+        ])
+    }
+
+    pub fn token_range(&self, index: usize) -> Json {
+        let token = &self.tokens[index];
+        self.range(token.offset, token.end)
+    }
+
+    pub fn name_range(&self, definition: &Definition) -> Json {
+        self.range(definition.offset, definition.offset + definition.name.len())
+    }
+
+    /// The identifier under or right before the cursor.
+    pub fn identifier_at(&self, offset: usize) -> Option<usize> {
+        self.tokens.iter().position(|token| {
+            token.kind == TokenType::Identifier
+                && token.offset <= offset
+                && offset <= token.offset + token.lex.len()
+        })
+    }
+
+    /// Index of the last token ending at or before `offset`.
+    pub fn token_before(&self, offset: usize) -> Option<usize> {
+        self.tokens.iter().rposition(|token| {
+            token.kind != TokenType::EOF && token.offset + token.lex.len() <= offset
+        })
+    }
+
+    /// The definition an identifier token refers to, if it is in this file.
+    pub fn resolve(&self, index: usize) -> Option<&Definition> {
+        let token = self.tokens.get(index)?;
+        if token.kind != TokenType::Identifier {
+            return None;
+        }
+
+        if let Some(definition) = self
+            .definitions
+            .iter()
+            .find(|definition| definition.offset == token.offset)
+        {
+            return Some(definition);
+        }
+
+        let is_member = index >= 2 && self.tokens[index - 1].kind == TokenType::Dot;
+        if is_member {
+            return self.resolve_member(index - 2, &token.lex);
+        }
+
+        let visible: Vec<&Definition> = self
+            .definitions
+            .iter()
+            .filter(|definition| {
+                definition.name == token.lex
+                    && definition.kind != SymbolKind::EnumMember
+                    && definition.scope.0 <= token.offset
+                    && token.offset <= definition.scope.1
+            })
+            .collect();
+
+        // The innermost scope wins, then the closest declaration before the use.
+        let narrowest = visible
+            .iter()
+            .map(|definition| definition.scope.1 - definition.scope.0)
+            .min()?;
+        let candidates: Vec<&Definition> = visible
+            .into_iter()
+            .filter(|definition| definition.scope.1 - definition.scope.0 == narrowest)
+            .collect();
+
+        candidates
+            .iter()
+            .rev()
+            .find(|definition| definition.offset <= token.offset)
+            .or(candidates.first())
+            .copied()
+    }
+
+    /// The field, method or enum member `receiver.name` refers to.
+    fn resolve_member(&self, receiver: usize, name: &str) -> Option<&Definition> {
+        let container = self.container_of(receiver);
+
+        self.definitions
+            .iter()
+            .filter(|definition| {
+                definition.name == name
+                    && matches!(
+                        definition.kind,
+                        SymbolKind::Field | SymbolKind::Method | SymbolKind::EnumMember
+                    )
+            })
+            .find(|definition| container.is_none() || definition.container == container)
+    }
+
+    /// The class or enum the value of an identifier token belongs to.
+    pub fn container_of(&self, index: usize) -> Option<String> {
+        let token = self.tokens.get(index)?;
+        if token.kind != TokenType::Identifier {
+            return None;
+        }
+
+        if token.lex == "self" {
+            return self
+                .definitions
+                .iter()
+                .rfind(|definition| {
+                    definition.kind == SymbolKind::Class
+                        && self.class_body(definition).is_some_and(|(start, end)| {
+                            start <= token.offset && token.offset <= end
+                        })
+                })
+                .map(|definition| definition.name.clone());
+        }
+
+        let definition = self.resolve(index)?;
+        if definition.kind == SymbolKind::Enum {
+            return Some(definition.name.clone());
+        }
+
+        match self.type_of(definition)? {
+            Type::Named(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn class_body(&self, class: &Definition) -> Option<(usize, usize)> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.container.as_ref() == Some(&class.name))
+            .map(|member| member.scope)
+    }
+
+    /// The type the checker found for a definition.
+    pub fn type_of(&self, definition: &Definition) -> Option<Type> {
+        let analysis = self.analysis.as_ref()?;
+
+        match definition.kind {
+            SymbolKind::Variable | SymbolKind::Parameter if definition.checked => {
+                // The n-th declaration of a name is the n-th one checked.
+                let nth = self
+                    .definitions
+                    .iter()
+                    .filter(|other| {
+                        other.checked
+                            && other.name == definition.name
+                            && other.offset < definition.offset
+                    })
+                    .count();
+
+                analysis
+                    .locals
+                    .iter()
+                    .filter(|(name, _)| *name == definition.name)
+                    .nth(nth)
+                    .map(|(_, kind)| kind.clone())
+            }
+            SymbolKind::Field | SymbolKind::Method => analysis
+                .classes
+                .get(definition.container.as_ref()?)?
+                .get(&definition.name)
+                .cloned(),
+            SymbolKind::EnumMember => Some(Type::Number),
+            _ if definition.scope == FILE_SCOPE => analysis.globals.get(&definition.name).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Every identifier token referring to `definition`, its own name included.
+    pub fn references(&self, definition: &Definition) -> Vec<usize> {
+        (0..self.tokens.len())
+            .filter(|index| {
+                self.resolve(*index)
+                    .is_some_and(|found| found.offset == definition.offset)
+            })
+            .collect()
+    }
+
+    /// Identifier tokens that aren't declared in this file, like the uses of a
+    /// symbol imported from another one.
+    pub fn unresolved(&self, name: &str) -> Vec<usize> {
+        (0..self.tokens.len())
+            .filter(|index| {
+                let token = &self.tokens[*index];
+                let is_member = *index >= 1 && self.tokens[index - 1].kind == TokenType::Dot;
+
+                token.kind == TokenType::Identifier
+                    && token.lex == name
+                    && !is_member
+                    && self
+                        .resolve(*index)
+                        .is_none_or(|definition| definition.kind == SymbolKind::Import)
+            })
+            .collect()
+    }
+
+    /// Where to underline an error, the first line when it has no span.
+    pub fn error_range(&self, error: &Error) -> Json {
+        match error.span {
+            Some(span) => self.range(span.start, span.end),
+            None => {
+                let first_line = self.text.find('\n').unwrap_or(self.text.len());
+                self.range(0, first_line)
+            }
+        }
+    }
+}
+
+/// Index of the closing bracket of every opening one. Unclosed brackets run
+/// to the end of the file.
+fn match_brackets(tokens: &[Token]) -> Vec<usize> {
+    let mut closing = vec![tokens.len().saturating_sub(1); tokens.len()];
+    let mut open = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenType::OpenParenthesis
+            | TokenType::OpenBracket
+            | TokenType::OpenAccessor
+            | TokenType::OpenCurly => open.push(i),
+            TokenType::CloseParenthesis | TokenType::CloseBracket | TokenType::CloseCurly => {
+                if let Some(start) = open.pop() {
+                    closing[start] = i;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    closing
+}
+
+fn is_opening(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenType::OpenParenthesis
+            | TokenType::OpenBracket
+            | TokenType::OpenAccessor
+            | TokenType::OpenCurly
+    )
+}
+
+fn is_closing(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenType::CloseParenthesis | TokenType::CloseBracket | TokenType::CloseCurly
+    )
+}
+
+struct DefinitionFinder<'a> {
+    tokens: &'a [Token],
+    closing: Vec<usize>,
+    definitions: Vec<Definition>,
+    /// Source ranges of function bodies and classes, which variables declared
+    /// inside them are limited to.
+    scopes: Vec<(usize, usize)>,
+}
+
+fn find_definitions(tokens: &[Token], source: &str) -> Vec<Definition> {
+    let mut finder = DefinitionFinder {
+        tokens,
+        closing: match_brackets(tokens),
+        definitions: Vec::new(),
+        scopes: Vec::new(),
+    };
+
+    for i in 0..tokens.len() {
+        finder.definitions_at(i, source);
+    }
+
+    // Functions, classes and variables are visible in the innermost function
+    // or class around them.
+    let DefinitionFinder {
+        mut definitions,
+        scopes,
+        ..
+    } = finder;
+    for definition in &mut definitions {
+        if definition.scope == FILE_SCOPE {
+            definition.scope = scopes
+                .iter()
+                .filter(|(start, end)| *start < definition.offset && definition.offset < *end)
+                .min_by_key(|(start, end)| end - start)
+                .copied()
+                .unwrap_or(FILE_SCOPE);
+        }
+    }
+
+    definitions.sort_by_key(|definition| definition.offset);
+    definitions
+}
+
+impl DefinitionFinder<'_> {
+    fn kind(&self, index: usize) -> Option<&TokenType> {
+        self.tokens.get(index).map(|token| &token.kind)
+    }
+
+    fn define(
+        &mut self,
+        index: usize,
+        kind: SymbolKind,
+        scope: (usize, usize),
+        container: Option<String>,
+    ) {
+        let checked = matches!(kind, SymbolKind::Variable | SymbolKind::Parameter)
+            && self.kind(index.wrapping_sub(2)) != Some(&TokenType::Extern);
+        self.define_name(
+            self.tokens[index].lex.clone(),
+            self.tokens[index].offset,
+            kind,
+            scope,
+            container,
+            checked,
+        );
+    }
+
+    fn define_name(
+        &mut self,
+        name: String,
+        offset: usize,
+        kind: SymbolKind,
+        scope: (usize, usize),
+        container: Option<String>,
+        checked: bool,
+    ) {
+        self.definitions.push(Definition {
+            name,
+            kind,
+            offset,
+            scope,
+            container,
+            checked,
+        });
+    }
+
+    fn is_identifier(&self, index: usize) -> bool {
+        self.kind(index) == Some(&TokenType::Identifier)
+    }
+
+    fn definitions_at(&mut self, i: usize, source: &str) {
+        let tokens = self.tokens;
+
+        match tokens[i].kind {
+            TokenType::Function | TokenType::Fn => {
+                let is_extern = i > 0 && tokens[i - 1].kind == TokenType::Extern;
+                let mut open = i + 1;
+                if self.is_identifier(open) {
+                    self.define(open, SymbolKind::Function, FILE_SCOPE, None);
+                    open += 1;
+                }
+                if self.kind(open) == Some(&TokenType::OpenParenthesis) && !is_extern {
+                    self.function(open);
+                }
+            }
+            TokenType::Class if self.is_identifier(i + 1) => {
+                let name = tokens[i + 1].lex.clone();
+                self.define(i + 1, SymbolKind::Class, FILE_SCOPE, None);
+
+                if self.kind(i + 2) == Some(&TokenType::OpenCurly) {
+                    self.class_members(i + 2, name);
+                }
+            }
+            TokenType::Enum if self.is_identifier(i + 1) => {
+                let name = tokens[i + 1].lex.clone();
+                self.define(i + 1, SymbolKind::Enum, FILE_SCOPE, None);
+
+                if self.kind(i + 2) == Some(&TokenType::OpenCurly) {
+                    let mut k = i + 3;
+                    while k < self.closing[i + 2] {
+                        let starts_member =
+                            matches!(tokens[k - 1].kind, TokenType::OpenCurly | TokenType::Comma);
+                        if starts_member && self.is_identifier(k) {
+                            self.define(k, SymbolKind::EnumMember, (0, 0), Some(name.clone()));
+                        }
+                        k += 1;
+                    }
+                }
+            }
+            TokenType::Let | TokenType::Const | TokenType::Var | TokenType::Static
+                if self.is_identifier(i + 1) =>
+            {
+                self.define(i + 1, SymbolKind::Variable, FILE_SCOPE, None);
+                self.declaration_list(i + 2, SymbolKind::Variable);
+            }
+            // `catch (e) { ... }`, where `e` is only seen by the block.
+            TokenType::Catch
+                if self.kind(i + 1) == Some(&TokenType::OpenParenthesis)
+                    && self.is_identifier(i + 2)
+                    && self.kind(i + 4) == Some(&TokenType::OpenCurly) =>
+            {
+                let end = self.closing[i + 4];
+                let scope = (tokens[i + 1].offset, tokens[end].offset + 1);
+                self.define(i + 2, SymbolKind::Variable, scope, None);
+            }
+            TokenType::GlobalVar if self.is_identifier(i + 1) => {
+                self.define(i + 1, SymbolKind::Variable, FILE_SCOPE, None);
+                self.definitions.last_mut().unwrap().checked = false;
+                self.declaration_list(i + 2, SymbolKind::Variable);
+            }
+            TokenType::Import if self.kind(i + 1) == Some(&TokenType::OpenCurly) => {
+                for k in i + 2..self.closing[i + 1] {
+                    if self.is_identifier(k) {
+                        self.define(k, SymbolKind::Import, FILE_SCOPE, None);
+                    }
+                }
+            }
+            TokenType::Macro => {
+                let name = tokens[i].lex.split_whitespace().next().unwrap_or("");
+                let offset = source
+                    .get(tokens[i].offset..)
+                    .and_then(|rest| rest.find(name))
+                    .map(|found| tokens[i].offset + found);
+
+                if let (false, Some(offset)) = (name.is_empty(), offset) {
+                    self.define_name(
+                        name.to_string(),
+                        offset,
+                        SymbolKind::Macro,
+                        FILE_SCOPE,
+                        None,
+                        false,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `let a = 1, b = 2`: the names after the first one, up to the end of
+    /// the statement.
+    fn declaration_list(&mut self, mut k: usize, kind: SymbolKind) {
+        let checked = self.definitions.last().is_some_and(|last| last.checked);
+
+        while k < self.tokens.len() {
+            let token = &self.tokens[k];
+            if is_opening(token) {
+                k = self.closing[k] + 1;
+                continue;
+            }
+
+            let ends_statement = matches!(
+                token.kind,
+                TokenType::Semilicon
+                    | TokenType::EOF
+                    | TokenType::Let
+                    | TokenType::Const
+                    | TokenType::Var
+                    | TokenType::Function
+                    | TokenType::Return
+                    | TokenType::If
+                    | TokenType::For
+                    | TokenType::While
+            );
+            if ends_statement || is_closing(token) {
+                return;
+            }
+
+            if token.kind == TokenType::Comma && self.is_identifier(k + 1) {
+                self.define(k + 1, kind, FILE_SCOPE, None);
+                self.definitions.last_mut().unwrap().checked = checked;
+            }
+            k += 1;
+        }
+    }
+
+    /// Defines the parameters of the function whose parameter list opens at
+    /// `open`, and returns the index of the token ending its body.
+    fn function(&mut self, open: usize) -> usize {
+        let close = self.closing[open];
+        let mut end = close;
+
+        // The body follows the return type, `constructor` or parent call, or
+        // the `=>` of an arrow function.
+        let mut k = close + 1;
+        while k < self.tokens.len() {
+            let token = &self.tokens[k];
+            match token.kind {
+                TokenType::OpenCurly => {
+                    end = self.closing[k];
+                    break;
+                }
+                TokenType::FatArrow if self.kind(k + 1) != Some(&TokenType::OpenCurly) => {
+                    end = self.expression_end(k + 1);
+                    break;
+                }
+                TokenType::OpenParenthesis | TokenType::OpenBracket => k = self.closing[k] + 1,
+                TokenType::Semilicon | TokenType::EOF => break,
+                _ if is_closing(token) => break,
+                _ => k += 1,
+            }
+        }
+
+        let scope = (self.tokens[open].offset, self.tokens[end].offset + 1);
+        self.scopes.push(scope);
+
+        let mut k = open + 1;
+        while k < close {
+            if is_opening(&self.tokens[k]) {
+                k = self.closing[k] + 1;
+                continue;
+            }
+
+            let starts_parameter = matches!(
+                self.tokens[k - 1].kind,
+                TokenType::OpenParenthesis | TokenType::Comma | TokenType::Ellipsis
+            );
+            if starts_parameter && self.is_identifier(k) {
+                self.define(k, SymbolKind::Parameter, scope, None);
+            }
+            k += 1;
+        }
+
+        end
+    }
+
+    /// Index of the token ending an expression body, like a `;`, a `,` or the
+    /// bracket closing the call the arrow function is passed to.
+    fn expression_end(&self, mut k: usize) -> usize {
+        while k < self.tokens.len() {
+            let token = &self.tokens[k];
+            if is_opening(token) {
+                k = self.closing[k] + 1;
+                continue;
+            }
+            if matches!(
+                token.kind,
+                TokenType::Semilicon | TokenType::Comma | TokenType::EOF
+            ) || is_closing(token)
+            {
+                return k;
+            }
+            k += 1;
+        }
+        self.tokens.len() - 1
+    }
+
+    fn class_members(&mut self, open: usize, class: String) {
+        let close = self.closing[open];
+        let scope = (self.tokens[open].offset, self.tokens[close].offset + 1);
+        self.scopes.push(scope);
+
+        let mut k = open + 1;
+        while k < close {
+            let is_name = self.is_identifier(k) || self.kind(k) == Some(&TokenType::New);
+
+            if is_name && self.kind(k + 1) == Some(&TokenType::OpenParenthesis) {
+                if self.is_identifier(k) {
+                    self.define(k, SymbolKind::Method, scope, Some(class.clone()));
+                }
+                k = self.function(k + 1) + 1;
+            } else if self.is_identifier(k) {
+                self.define(k, SymbolKind::Field, scope, Some(class.clone()));
+                while k < close && self.kind(k) != Some(&TokenType::Semilicon) {
+                    k = if is_opening(&self.tokens[k]) {
+                        self.closing[k] + 1
+                    } else {
+                        k + 1
+                    };
+                }
+            } else {
+                k += 1;
+            }
+        }
+    }
+}
@@ -1,21 +1,1086 @@
-use compiler::tokenizer::{tokenize, Token};
+use compiler::tokenizer::{tokenize_as, tokenize_lossless, Language};
 use gamemaker_plus::compiler;
 
-use front_end::{get_source_code, parse_arguments};
+use front_end::{
+    flush_diagnostics, get_source_code, parse_arguments, print_errors, print_reports,
+    print_warnings, write_output, Command, Config, Emit, Report, EXIT_FAILURE, EXIT_IO,
+    EXIT_SUCCESS, EXIT_USAGE, HELP,
+};
 use gamemaker_plus::front_end;
 
-use compiler::parser::parse;
+use compiler::ast::Node;
+use compiler::checker::{check_module_as, Strictness, Type};
+use compiler::codegen::{generate_mapped, generate_polyfills, lower_module, CodegenOptions};
+use compiler::declarations::load_declarations;
+use compiler::driver::{import_statements, join_modules, ModuleKind};
+use compiler::error::Error;
+use compiler::formatter::format_source;
+use compiler::incremental::{BuiltModule, Database};
+use compiler::lint::{apply_fixes, lint, Diagnostic, LintLevel};
+use compiler::migrate::migrate;
+use compiler::parser::{parse_as, parse_with_positions_as, Positions};
+use compiler::source_map::{map_path, output_folder, relative_path, SourceMap};
+use compiler::testing::{runner, test_functions};
+
+use gamemaker_plus::interp::host::StandardHost;
+use gamemaker_plus::interp::{Interpreter, RuntimeError};
+use gamemaker_plus::manifest::{build_sources, check_sources, find_sources, Manifest};
+use gamemaker_plus::project::{build_project, check_project, Project, CACHE_DIR};
+use gamemaker_plus::repl::Repl;
+use gamemaker_plus::trace::{trace, SourceMaps};
+use gamemaker_plus::watch::{wait_for_changes, Snapshot};
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 fn main() {
-    let config = parse_arguments().unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let config = parse_arguments(&args).unwrap_or_else(|error| {
+        eprintln!("error: {error}\n\nRun `gmpp --help` to see the commands and options.");
+        std::process::exit(EXIT_USAGE);
+    });
+
+    let exit_code = run(&config);
+    flush_diagnostics(&config);
+    std::process::exit(exit_code);
+}
+
+/// Runs the command, returning the exit code.
+fn run(config: &Config) -> i32 {
+    match config.command {
+        Command::Help => {
+            print!("{HELP}");
+            EXIT_SUCCESS
+        }
+        Command::Version => {
+            println!("gmpp {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Command::Init => init(config),
+        Command::Migrate => migrate_script(config),
+        Command::Trace => trace_log(config),
+        Command::Fmt { check } => format_files(config, check),
+        Command::Lint { fix } => lint_files(config, fix),
+        Command::Watch => watch(config),
+        Command::Test => run_tests(config),
+        Command::Run => run_script(config),
+        Command::Repl => repl(config),
+        Command::Build | Command::Check | Command::Tokens | Command::Ast => {
+            let is_folder = Path::new(&config.path).is_dir();
+            if !config.path.ends_with(".yyp") && !is_folder {
+                return compile_file(config);
+            }
+
+            if config.emit != Emit::Gml {
+                eprintln!(
+                    "error: Tokens, syntax trees and IR can only be printed for a single file"
+                );
+                return EXIT_USAGE;
+            }
+
+            match &config.manifest {
+                Some(manifest) if is_folder => build_folder(config, manifest),
+                None if is_folder => {
+                    eprintln!(
+                        "error: {} has no gmpp.toml, pass a file or a .yyp project",
+                        config.path
+                    );
+                    EXIT_USAGE
+                }
+                _ => build(config),
+            }
+        }
+    }
+}
+
+/// Compiles, or only checks, a single file and everything it imports. With
+/// `--emit`, prints its tokens, syntax tree or IR instead.
+fn compile_file(config: &Config) -> i32 {
+    let source_code = match get_source_code(config) {
+        Ok(source_code) => source_code,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+    let language = config
+        .language
+        .unwrap_or(Language::from_path(Path::new(&config.path)));
+
+    let mut source_map = None;
+    let result = match (config.command, config.emit) {
+        (Command::Check, _) => compile_gml(config, source_code).map(|_| None),
+        (_, Emit::Tokens) => tokenize_as(source_code, language)
+            .map(|tokens| {
+                let tokens = tokens
+                    .iter()
+                    .map(|token| format!("{} {:?} {:?}\n", token.offset, token.kind, token.lex))
+                    .collect();
+                Some(tokens)
+            })
+            .map_err(|error| vec![error]),
+        (_, Emit::Ast) => tokenize_as(source_code, language)
+            .and_then(|tokens| parse_as(tokens, language))
+            .map(|ast| Some(ast.program_tree(0)))
+            .map_err(|error| vec![error]),
+        (_, Emit::Ir) => compile_ir(config, source_code).map(Some),
+        (_, Emit::Gml) => compile_gml(config, source_code).map(|(gml, map)| {
+            source_map = map;
+            Some(gml)
+        }),
+    };
+
+    match result {
+        Ok(Some(output)) => match (finish_output(config, &output), source_map) {
+            (EXIT_SUCCESS, Some((path, map))) => match std::fs::write(&path, map.to_json()) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(err) => {
+                    print_errors(config, &[Error::new(err.to_string()).in_file(&path)]);
+                    EXIT_IO
+                }
+            },
+            (exit_code, _) => exit_code,
+        },
+        Ok(None) => EXIT_SUCCESS,
+        Err(errors) => {
+            print_errors(config, &errors);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Checks a file, then prints the IR it lowers to, after the passes.
+fn compile_ir(config: &Config, source_code: String) -> Result<String, Vec<Error>> {
+    let declarations = load_declarations(&config.declarations)?;
+    let options = CodegenOptions::new(config.target, config.opt_level);
+    let language = config.language.unwrap_or(if config.reads_stdin() {
+        Language::GmlPlusPlus
+    } else {
+        Language::from_path(Path::new(&config.path))
+    });
+    let (ast, positions) = tokenize_as(source_code, language)
+        .and_then(|tokens| parse_with_positions_as(tokens, language))
+        .map_err(|error| vec![error])?;
+
+    let mut imports = declarations.clone();
+    if config.reads_stdin() {
+        if let Some((_, _, span)) = import_statements(&ast, &positions).first() {
+            return Err(vec![Error::new(
+                "Imports can't be resolved in code read from stdin, pass a file instead",
+            )
+            .or_at(*span)]);
+        }
+        check_module_as(
+            &ast,
+            &positions,
+            &declarations,
+            &HashSet::new(),
+            Strictness::Off,
+        )
+        .0?;
+    } else {
+        let entries = [(PathBuf::from(&config.path), ModuleKind::Script)];
+        let search_paths = config
+            .manifest
+            .as_ref()
+            .map(|manifest| manifest.sources.clone())
+            .unwrap_or_default();
+        let mut database = Database::new();
+        database.check(&entries, &search_paths, config.language, &declarations)?;
+        imports.extend(database.exported_types());
+    }
+
+    lower_module(&ast, &options, positions, &imports).map(|module| module.to_string())
+}
+
+/// A source map and the path it goes to.
+type MapFile = (PathBuf, SourceMap);
+
+/// Checks a file and generates its GML. Code from stdin can't import anything,
+/// as there is no folder to look the modules up in. When the GML goes to a
+/// file, also returns the source map to write next to it.
+fn compile_gml(
+    config: &Config,
+    source_code: String,
+) -> Result<(String, Option<MapFile>), Vec<Error>> {
+    let declarations = load_declarations(&config.declarations)?;
+    let options = CodegenOptions::new(config.target, config.opt_level);
+
+    if config.reads_stdin() {
+        let language = config.language.unwrap_or(Language::GmlPlusPlus);
+        let (ast, positions) = tokenize_as(source_code, language)
+            .and_then(|tokens| parse_with_positions_as(tokens, language))
+            .map_err(|error| vec![error])?;
+        if let Some((_, _, span)) = import_statements(&ast, &positions).first() {
+            return Err(vec![Error::new(
+                "Imports can't be resolved in code read from stdin, pass a file instead",
+            )
+            .or_at(*span)]);
+        }
+
+        let strictness = match language {
+            Language::Gml => Strictness::Off,
+            Language::GmlPlusPlus if config.strict_for(Path::new(".")) => Strictness::Deny,
+            Language::GmlPlusPlus => Strictness::Warn,
+        };
+        let (checked, warnings) =
+            check_module_as(&ast, &positions, &declarations, &HashSet::new(), strictness);
+        print_warnings(config, &warnings);
+        checked?;
+        let (gml, polyfills, _) = generate_mapped(&ast, &options, &positions)?;
+        return Ok((generate_polyfills(&polyfills) + &gml, None));
+    }
+
+    let entries = [(PathBuf::from(&config.path), ModuleKind::Script)];
+    let search_paths = config
+        .manifest
+        .as_ref()
+        .map(|manifest| manifest.sources.clone())
+        .unwrap_or_default();
+    let mut database = Database::new();
+    database.set_strict(config.strict_files());
+    let result = if config.command == Command::Check {
+        database
+            .check(&entries, &search_paths, config.language, &declarations)
+            .map(|_| (String::new(), None))
+    } else {
+        database
+            .build(
+                &entries,
+                &search_paths,
+                config.language,
+                &declarations,
+                &options,
+            )
+            .map(|(modules, polyfills)| {
+                let joined: Vec<(&Path, &str)> = modules
+                    .iter()
+                    .map(|module| (module.path.as_path(), module.gml.as_str()))
+                    .collect();
+                let (gml, first_lines) = join_modules(&joined, polyfills);
+
+                let output = config.output.as_deref().filter(|output| *output != "-");
+                let source_map = output.map(|output| {
+                    let output = Path::new(output);
+                    let mut map =
+                        SourceMap::new(&output.file_name().unwrap_or_default().to_string_lossy());
+                    let folder = output_folder(output);
+                    for (module, first_line) in modules.iter().zip(first_lines) {
+                        map.add(
+                            &relative_path(&folder, &module.path),
+                            first_line,
+                            &module.lines,
+                        );
+                    }
+                    (map_path(output), map)
+                });
+                (gml, source_map)
+            })
+    };
+
+    if config.timings {
+        eprintln!("{}", database.timings);
+    }
+    print_warnings(config, &database.warnings);
+
+    result
+}
+
+/// Runs the `@test` functions of a file and the modules it imports, or of
+/// every source of a `gmpp.toml` folder, with the interpreter. With
+/// `--output`, generates a GML script calling them from `gmpp_run_tests()`
+/// instead, to run them in the game.
+fn run_tests(config: &Config) -> i32 {
+    if !config.target.supports_functions() {
+        eprintln!(
+            "error: Tests need try and catch, which {} doesn't have",
+            config.target.name()
+        );
+        return EXIT_USAGE;
+    }
+
+    let mut options = CodegenOptions::new(config.target, config.opt_level);
+    options.tests = true;
+    let Build {
+        modules,
+        polyfills,
+        declarations,
+    } = match build_for_interpreter(config, &options) {
+        Ok(built) => built,
+        Err(code) => return code,
+    };
+    let scripts = match parse_scripts(&modules) {
+        Ok(scripts) => scripts,
+        Err(error) => {
+            print_errors(config, &[error]);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let tests: Vec<String> = scripts
+        .iter()
+        .filter(|script| script.language == Language::GmlPlusPlus)
+        .flat_map(|script| test_functions(&script.program))
+        .collect();
+    if tests.is_empty() {
+        print_errors(
+            config,
+            &[Error::new(format!(
+                "No @test functions found in {}",
+                config.path
+            ))],
+        );
+        return EXIT_FAILURE;
+    }
+
+    if config.output.is_none() {
+        return interpret_tests(&scripts, &tests, &declarations);
+    }
+
+    let runner = runner(&tests);
+    let mut joined: Vec<(&Path, &str)> = modules
+        .iter()
+        .map(|module| (module.path.as_path(), module.gml.as_str()))
+        .collect();
+    joined.push((Path::new("gmpp_run_tests.gml"), &runner));
+    let (gml, _) = join_modules(&joined, polyfills);
+
+    finish_output(config, &gml)
+}
+
+/// How many statements a test can run before it is stopped, so one that
+/// never ends fails instead of hanging.
+const TEST_STEP_LIMIT: u64 = 10_000_000;
+
+/// Runs every test in an interpreter of its own, after the top-level code of
+/// the scripts, printing what failed with the messages it showed.
+fn interpret_tests(
+    scripts: &[Script],
+    tests: &[String],
+    declarations: &HashMap<String, Type>,
+) -> i32 {
+    let mut failed = 0;
+
+    for test in tests {
+        let mut host = StandardHost::new();
+        host.declare(declarations);
+        let mut interpreter = Interpreter::new(host);
+        interpreter.set_step_limit(TEST_STEP_LIMIT);
+
+        let result = scripts
+            .iter()
+            .try_for_each(|script| script.load(&mut interpreter))
+            .and_then(|()| interpreter.call_global(test, Vec::new()));
+        match result {
+            Ok(_) => println!("ok {test}"),
+            Err(error) => {
+                failed += 1;
+                println!("FAILED {test}: {error}");
+                for line in interpreter.host.output.lines() {
+                    println!("    {line}");
+                }
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", tests.len() - failed);
+    if failed == 0 {
+        EXIT_SUCCESS
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// Runs a file and the modules it imports with the interpreter, printing
+/// what they show with `show_debug_message`.
+fn run_script(config: &Config) -> i32 {
+    let options = CodegenOptions::new(config.target, config.opt_level);
+    let Build {
+        modules,
+        declarations,
+        ..
+    } = match build_for_interpreter(config, &options) {
+        Ok(built) => built,
+        Err(code) => return code,
+    };
+    let scripts = match parse_scripts(&modules) {
+        Ok(scripts) => scripts,
+        Err(error) => {
+            print_errors(config, &[error]);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let mut host = StandardHost::new();
+    host.declare(&declarations);
+    host.echo = true;
+    let mut interpreter = Interpreter::new(host);
+    for script in &scripts {
+        if let Err(error) = script.load(&mut interpreter) {
+            print_errors(config, &[Error::new(error.to_string())]);
+            return EXIT_FAILURE;
+        }
+    }
+
+    EXIT_SUCCESS
+}
+
+/// What `test` and `run` work on once built.
+struct Build {
+    /// Dependencies first.
+    modules: Vec<BuiltModule>,
+    polyfills: String,
+    /// The declarations the modules were checked against.
+    declarations: HashMap<String, Type>,
+}
+
+/// Starts a REPL, after running the file given if there is one.
+fn repl(config: &Config) -> i32 {
+    let declarations = match load_declarations(&config.declarations) {
+        Ok(declarations) => declarations,
+        Err(errors) => {
+            print_errors(config, &errors);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let mut repl = Repl::new(
+        declarations,
+        CodegenOptions::new(config.target, config.opt_level),
+    );
+    if !Path::new(&config.path).is_dir() {
+        repl.input(&format!(":load {}", config.path));
+    }
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    repl.run(stdin.lock(), interactive);
+
+    EXIT_SUCCESS
+}
+
+/// Checks and builds a file and the modules it imports, or every source of a
+/// `gmpp.toml` folder, or returns the exit code after printing why it failed.
+fn build_for_interpreter(config: &Config, options: &CodegenOptions) -> Result<Build, i32> {
+    if config.reads_stdin() {
+        eprintln!("error: Scripts can't be run from stdin, pass a file or a folder");
+        return Err(EXIT_USAGE);
+    }
+
+    let path = Path::new(&config.path);
+    let search_paths = config
+        .manifest
+        .as_ref()
+        .map(|manifest| manifest.sources.clone())
+        .unwrap_or_default();
+    let entries: Vec<(PathBuf, ModuleKind)> = match &config.manifest {
+        Some(manifest) if path.is_dir() => manifest
+            .source_files()
+            .into_iter()
+            .map(|path| (path, ModuleKind::Script))
+            .collect(),
+        None if path.is_dir() => {
+            eprintln!(
+                "error: {} has no gmpp.toml, pass a file instead",
+                config.path
+            );
+            return Err(EXIT_USAGE);
+        }
+        _ => vec![(path.to_path_buf(), ModuleKind::Script)],
+    };
+
+    let declarations = load_declarations(&config.declarations).map_err(|errors| {
+        print_errors(config, &errors);
+        EXIT_FAILURE
+    })?;
+
+    let mut database = Database::new();
+    database.set_strict(config.strict_files());
+    let result = database.build(
+        &entries,
+        &search_paths,
+        config.language,
+        &declarations,
+        options,
+    );
+    print_warnings(config, &database.warnings);
+    let (modules, polyfills) = result.map_err(|errors| {
+        print_errors(config, &errors);
+        EXIT_FAILURE
+    })?;
+
+    Ok(Build {
+        modules,
+        polyfills,
+        declarations,
+    })
+}
+
+/// A built module parsed again for the interpreter, which keeps the program
+/// as long as the functions it declares.
+struct Script {
+    path: String,
+    language: Language,
+    source: String,
+    program: Rc<Node>,
+    positions: Positions,
+}
+
+impl Script {
+    fn load(&self, interpreter: &mut Interpreter<StandardHost>) -> Result<(), RuntimeError> {
+        interpreter.load(
+            self.program.clone(),
+            &self.positions,
+            &self.source,
+            &self.path,
+        )
+    }
+}
+
+fn parse_scripts(modules: &[BuiltModule]) -> Result<Vec<Script>, Error> {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let mut scripts = Vec::new();
+
+    for module in modules {
+        let source = std::fs::read_to_string(&module.path)
+            .map_err(|err| Error::new(err.to_string()).in_file(&module.path))?;
+        let (program, positions) = tokenize_as(source.clone(), module.language)
+            .and_then(|tokens| parse_with_positions_as(tokens, module.language))
+            .map_err(|error| error.in_file(&module.path))?;
+        // Failures are read where the scripts run, so paths are shown from
+        // there when they can be.
+        let path = module
+            .path
+            .strip_prefix(&current_dir)
+            .unwrap_or(&module.path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        scripts.push(Script {
+            path,
+            language: module.language,
+            source,
+            program: Rc::new(program),
+            positions,
+        });
+    }
+
+    Ok(scripts)
+}
+
+fn finish_output(config: &Config, output: &str) -> i32 {
+    match write_output(config, output) {
+        Ok(()) => EXIT_SUCCESS,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            EXIT_IO
+        }
+    }
+}
+
+/// Builds, or only checks, every source of a `.yyp` project.
+fn build(config: &Config) -> i32 {
+    let project = match Project::open(&config.path) {
+        Ok(project) => project,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+    let mut database = Database::open(&project.root.join(CACHE_DIR));
+    database.set_strict(config.strict_files());
+
+    let declarations = match project_declarations(config, &project) {
+        Ok(declarations) => declarations,
+        Err(()) => return EXIT_FAILURE,
+    };
+
+    let built = if config.command == Command::Check {
+        let result = check_project(&project, &declarations, &mut database);
+        if config.timings {
+            eprintln!("{}", database.timings);
+        }
+        print_warnings(config, &database.warnings);
+        result
+            .map_err(|errors| print_errors(config, &errors))
+            .is_ok()
+    } else {
+        build_once(config, &project, &declarations, &mut database)
+    };
+
+    if built {
+        EXIT_SUCCESS
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// Builds, or only checks, the source folders of a `gmpp.toml`.
+fn build_folder(config: &Config, manifest: &Manifest) -> i32 {
+    let mut database = Database::open(&manifest.root.join(CACHE_DIR));
+    database.set_strict(config.strict_files());
+    let result = load_declarations(&config.declarations).and_then(|declarations| {
+        if config.command == Command::Check {
+            check_sources(manifest, &declarations, &mut database).map(|_| Vec::new())
+        } else {
+            let options = CodegenOptions::new(config.target, config.opt_level);
+            build_sources(manifest, &options, &declarations, &mut database)
+        }
+    });
+
+    if config.timings {
+        eprintln!("{}", database.timings);
+    }
+    print_warnings(config, &database.warnings);
+
+    match result {
+        Ok(compiled) if compiled.is_empty() && config.command == Command::Build => {
+            println!("Everything is up to date");
+            EXIT_SUCCESS
+        }
+        Ok(compiled) => {
+            for path in compiled {
+                println!("Compiled {}", path.display());
+            }
+            EXIT_SUCCESS
+        }
+        Err(errors) => {
+            print_errors(config, &errors);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Builds a project, then again whenever one of its sources changes, until
+/// interrupted. Only what changed and the scripts importing it are rebuilt.
+fn watch(config: &Config) -> i32 {
+    let project = match Project::open(&config.path) {
+        Ok(project) => project,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+    let mut database = Database::open(&project.root.join(CACHE_DIR));
+    database.set_strict(config.strict_files());
+    let mut snapshot = Snapshot::take(&project);
+    let mut declarations = project_declarations(config, &project);
+
+    loop {
+        if let Ok(declarations) = &declarations {
+            build_once(config, &project, declarations, &mut database);
+        }
+        flush_diagnostics(config);
+        println!("Watching {} for changes...", config.path);
+
+        let changes = wait_for_changes(&project, &mut snapshot);
+        for path in &changes {
+            println!("Changed {}", path.display());
+        }
+
+        let declarations_changed = changes
+            .iter()
+            .any(|path| path.to_string_lossy().ends_with(".d.gmpp"));
+        if declarations_changed || declarations.is_err() {
+            declarations = project_declarations(config, &project);
+        }
+    }
+}
+
+/// Loads the declaration files given on the command line and those at the
+/// root of the project, printing the errors they contain.
+fn project_declarations(config: &Config, project: &Project) -> Result<HashMap<String, Type>, ()> {
+    let mut declaration_files = config.declarations.clone();
+    declaration_files.extend(project.find_declarations());
+
+    load_declarations(&declaration_files).map_err(|errors| print_errors(config, &errors))
+}
+
+/// Builds the project once, printing what was compiled or the errors found.
+/// Returns whether the build succeeded.
+fn build_once(
+    config: &Config,
+    project: &Project,
+    declarations: &HashMap<String, Type>,
+    database: &mut Database,
+) -> bool {
+    let options = CodegenOptions::new(config.target, config.opt_level);
+    let result = build_project(project, &options, declarations, database);
+
+    if config.timings {
+        eprintln!("{}", database.timings);
+    }
+    print_warnings(config, &database.warnings);
+
+    match result {
+        Ok(compiled) if compiled.is_empty() => {
+            println!("Everything is up to date");
+            true
+        }
+        Ok(compiled) => {
+            for path in compiled {
+                println!("Compiled {}", path.display());
+            }
+            true
+        }
+        Err(errors) => {
+            print_errors(config, &errors);
+            false
+        }
+    }
+}
+
+/// Creates a `gmpp.toml` and a first script in the given folder.
+fn init(config: &Config) -> i32 {
+    let folder = Path::new(&config.path);
+    let manifest = folder.join("gmpp.toml");
+    if manifest.exists() {
+        print_errors(
+            config,
+            &[Error::new(format!("{} already exists", manifest.display()))],
+        );
+        return EXIT_FAILURE;
+    }
+
+    let mut files = vec![(
+        manifest,
+        "# Settings of the GML++ compiler for the files in this folder.\n\
+         target = \"latest\"\n\
+         strict = false\n\
+         sources = [\".\"]\n\
+         \n\
+         [format]\n\
+         indent_width = 4\n\
+         max_width = 100\n",
+    )];
+
+    let script = folder.join("main.gmpp");
+    if !script.exists() {
+        files.push((
+            script,
+            "function greet(name: string) -> string {\n    \
+             return \"Hello, \" + name;\n\
+             }\n\
+             \n\
+             show_debug_message(greet(\"GameMaker\"));\n",
+        ));
+    }
+
+    let result = std::fs::create_dir_all(folder)
+        .map_err(|err| format!("{}: {err}", folder.display()))
+        .and_then(|_| {
+            for (path, contents) in files {
+                std::fs::write(&path, contents)
+                    .map_err(|err| format!("{}: {err}", path.display()))?;
+                println!("Created {}", path.display());
+            }
+            Ok(())
+        });
+
+    match result {
+        Ok(()) => EXIT_SUCCESS,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            EXIT_IO
+        }
+    }
+}
+
+/// Writes the GML++ version of a `.gml` script next to it, or to `--output`.
+/// Code read from stdin goes to stdout.
+fn migrate_script(config: &Config) -> i32 {
+    let output = match config.output.as_deref() {
+        Some("-") => None,
+        Some(output) => Some(PathBuf::from(output)),
+        None if config.reads_stdin() => None,
+        None => Some(Path::new(&config.path).with_extension("gmpp")),
+    };
+
+    if let Some(output) = output.as_ref().filter(|output| output.exists()) {
+        print_errors(
+            config,
+            &[Error::new(format!("{} already exists", output.display()))],
+        );
+        return EXIT_FAILURE;
+    }
+
+    let source_code = match get_source_code(config) {
+        Ok(source_code) => source_code,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+    let result = load_declarations(&config.declarations)
+        .and_then(|declarations| migrate(source_code, &declarations));
+
+    match (result, output) {
+        (Ok(code), Some(output)) => match std::fs::write(&output, code) {
+            Ok(()) => {
+                println!("Migrated {} to {}", config.path, output.display());
+                EXIT_SUCCESS
+            }
+            Err(err) => {
+                print_errors(config, &[Error::new(err.to_string()).in_file(&output)]);
+                EXIT_IO
+            }
+        },
+        (Ok(code), None) => {
+            print!("{code}");
+            EXIT_SUCCESS
+        }
+        (Err(errors), _) => {
+            print_errors(config, &errors);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Rewrites the frames of a crash log to the `.gmpp` lines they come from,
+/// with the source maps of `--project`, or else of the `gmpp.toml` folder.
+fn trace_log(config: &Config) -> i32 {
+    let root = match (&config.project, &config.manifest) {
+        (Some(project), _) if project.ends_with(".yyp") => Path::new(project)
+            .parent()
+            .filter(|folder| !folder.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+        (Some(project), _) => PathBuf::from(project),
+        (None, Some(manifest)) => manifest.root.clone(),
+        (None, None) => PathBuf::from("."),
+    };
+
+    let maps = match SourceMaps::load(&root) {
+        Ok(maps) if maps.is_empty() => {
+            print_errors(
+                config,
+                &[Error::new(format!(
+                    "No source maps found under {}, build the project first",
+                    root.display()
+                ))],
+            );
+            return EXIT_FAILURE;
+        }
+        Ok(maps) => maps,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+
+    let log = match get_source_code(config) {
+        Ok(log) => log,
+        Err(error) => {
+            print_errors(config, &[Error::new(error)]);
+            return EXIT_IO;
+        }
+    };
+
+    let (traced, rewritten) = trace(&log, &maps);
+    if rewritten == 0 {
+        print_warnings(
+            config,
+            &[Error::new(
+                "No frame of the log comes from a file with a source map",
+            )],
+        );
+    }
+    finish_output(config, &traced)
+}
+
+/// Formats a `.gmpp` file, or every one under a directory. With `check`, files
+/// are left alone and the exit code tells whether any needs formatting. Code
+/// read from stdin is printed once formatted.
+fn format_files(config: &Config, check: bool) -> i32 {
+    if config.reads_stdin() {
+        let result = get_source_code(config).map(|source_code| {
+            let formatted = format_source(source_code.clone(), &config.format);
+            (source_code, formatted)
+        });
+
+        return match result {
+            Err(error) => {
+                print_errors(config, &[Error::new(error)]);
+                EXIT_IO
+            }
+            Ok((_, Err(error))) => {
+                print_errors(config, &[error]);
+                EXIT_FAILURE
+            }
+            Ok((source_code, Ok(code))) if check => {
+                if code == source_code {
+                    EXIT_SUCCESS
+                } else {
+                    println!("Unformatted stdin");
+                    EXIT_FAILURE
+                }
+            }
+            Ok((_, Ok(code))) => finish_output(config, &code),
+        };
+    }
+
+    let mut paths = Vec::new();
+    find_sources(Path::new(&config.path), &mut paths);
+
+    let mut exit_code = EXIT_SUCCESS;
+    for path in paths {
+        let source_code = match std::fs::read_to_string(&path) {
+            Ok(source_code) => source_code,
+            Err(err) => {
+                print_errors(config, &[Error::new(err.to_string()).in_file(&path)]);
+                exit_code = EXIT_IO;
+                continue;
+            }
+        };
+
+        match format_source(source_code.clone(), &config.format) {
+            Ok(code) if code == source_code => {}
+            Ok(_) if check => {
+                println!("Unformatted {}", path.display());
+                exit_code = exit_code.max(EXIT_FAILURE);
+            }
+            Ok(code) => match std::fs::write(&path, code) {
+                Ok(()) => println!("Formatted {}", path.display()),
+                Err(err) => {
+                    print_errors(config, &[Error::new(err.to_string()).in_file(&path)]);
+                    exit_code = EXIT_IO;
+                }
+            },
+            Err(error) => {
+                print_errors(config, &[Error::new(error.to_string()).in_file(&path)]);
+                exit_code = exit_code.max(EXIT_FAILURE);
+            }
+        }
+    }
+
+    exit_code
+}
+
+/// Lints a file, or every source of a folder or a `.yyp` project. With `fix`,
+/// the safe fixes are applied to `.gmpp` files, code read from stdin being
+/// printed once fixed.
+fn lint_files(config: &Config, fix: bool) -> i32 {
+    let path = Path::new(&config.path);
+    let paths = if config.reads_stdin() {
+        vec![path.to_path_buf()]
+    } else if config.path.ends_with(".yyp") {
+        match Project::open(&config.path).and_then(|project| project.find_sources()) {
+            Ok(sources) => sources
+                .iter()
+                .map(|source| source.path().to_path_buf())
+                .collect(),
+            Err(error) => {
+                print_errors(config, &[Error::new(error)]);
+                return EXIT_IO;
+            }
+        }
+    } else if path.is_dir() {
+        match &config.manifest {
+            Some(manifest) => manifest.source_files(),
+            None => {
+                let mut paths = Vec::new();
+                find_sources(path, &mut paths);
+                paths
+            }
+        }
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut exit_code = EXIT_SUCCESS;
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+
+    for path in paths {
+        let source_code = match get_source_code(config) {
+            Ok(source_code) if config.reads_stdin() => source_code,
+            _ => match std::fs::read_to_string(&path) {
+                Ok(source_code) => source_code,
+                Err(err) => {
+                    errors.push(Report::new(&Error::new(err.to_string()).in_file(&path)));
+                    exit_code = EXIT_IO;
+                    continue;
+                }
+            },
+        };
+
+        let (diagnostics, fixed) = match lint_source(config, &path, source_code, fix) {
+            Ok(linted) => linted,
+            Err(error) if config.reads_stdin() => {
+                errors.push(Report::new(&error));
+                exit_code = exit_code.max(EXIT_FAILURE);
+                continue;
+            }
+            Err(error) => {
+                errors.push(Report::new(&error.in_file(&path)));
+                exit_code = exit_code.max(EXIT_FAILURE);
+                continue;
+            }
+        };
+
+        match fixed {
+            Some((code, count)) if config.reads_stdin() => {
+                eprintln!("Fixed {count} problems");
+                exit_code = exit_code.max(finish_output(config, &code));
+            }
+            Some((code, count)) => match std::fs::write(&path, code) {
+                Ok(()) => println!("Fixed {count} problems in {}", path.display()),
+                Err(err) => {
+                    errors.push(Report::new(&Error::new(err.to_string()).in_file(&path)));
+                    exit_code = EXIT_IO;
+                }
+            },
+            None => {}
+        }
+
+        for diagnostic in diagnostics {
+            let file = (!config.reads_stdin()).then_some(path.as_path());
+            let report = Report::lint(file, &diagnostic);
+            if diagnostic.level == LintLevel::Deny {
+                errors.push(report);
+                exit_code = exit_code.max(EXIT_FAILURE);
+            } else {
+                warnings.push(report);
+            }
+        }
+    }
+
+    print_reports(config, "warning", &warnings);
+    print_reports(config, "error", &errors);
+    exit_code
+}
+
+/// What a lint run leaves to fix by hand, and the fixed code with its fix count.
+type Linted = (Vec<Diagnostic>, Option<(String, usize)>);
 
-    println!("Path: {}\nStrict: {}\n", config.path, config.strict);
+/// Lints a source file, returning what is left to fix by hand and, when
+/// fixes were applied, the fixed code and how many problems it fixes.
+fn lint_source(
+    config: &Config,
+    path: &Path,
+    source_code: String,
+    fix: bool,
+) -> Result<Linted, Error> {
+    let language = config.language.unwrap_or(Language::from_path(path));
+    let lint_code = |code: &str| -> Result<Vec<Diagnostic>, Error> {
+        let tokens = tokenize_lossless(code.to_string(), language)?;
+        let (program, positions) = parse_with_positions_as(tokens.clone(), language)?;
+        Ok(lint(&program, &positions, &tokens, &|lint| {
+            config.lint_level(path, lint)
+        }))
+    };
+    let diagnostics = lint_code(&source_code)?;
 
-    let source_code = get_source_code(&config).expect("Couldn't read the source code");
+    let fixable = diagnostics.iter().any(|diagnostic| diagnostic.is_fixable());
+    if !fix || !fixable {
+        return Ok((diagnostics, None));
+    }
 
-    let tokens: Vec<Token> = tokenize(source_code);
-    println!("{:?}", tokens);
-    let ast = parse(tokens);
+    // Fixes edit the source, leaving the rest of it as written. What is
+    // left is found again in the fixed source, so it points into it.
+    let (code, count) = apply_fixes(&source_code, &diagnostics);
+    let remaining = lint_code(&code)?;
 
-    ast.display_program(0);
+    Ok((remaining, Some((code, count))))
 }
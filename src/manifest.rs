@@ -0,0 +1,563 @@
+pub mod toml;
+
+use crate::compiler::checker::Type;
+use crate::compiler::codegen::CodegenOptions;
+use crate::compiler::driver::ModuleKind;
+use crate::compiler::error::Error;
+use crate::compiler::incremental::Database;
+use crate::compiler::lint::{find_lint, LintLevel};
+use crate::compiler::source_map::{module_map, write_map};
+use crate::compiler::target::GmlVersion;
+use crate::compiler::tokenizer::Language;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml::{Entry, Table, Value};
+
+pub const MANIFEST_NAME: &str = "gmpp.toml";
+const POLYFILLS_FILE: &str = "__gmpp_polyfills.gml";
+
+/// Settings for the files under a folder, replacing the ones of the manifest.
+#[derive(Clone)]
+pub struct Override {
+    pub path: PathBuf,
+    pub strict: Option<bool>,
+    pub lints: Vec<(String, LintLevel)>,
+}
+
+/// The `gmpp.toml` of a project. Paths in it are relative to its folder.
+#[derive(Clone)]
+pub struct Manifest {
+    /// Folder holding the `gmpp.toml`.
+    pub root: PathBuf,
+    /// Folders whose `.gmpp` files are built when the project folder is.
+    pub sources: Vec<PathBuf>,
+    /// Where the `.gml` files go, next to their sources when not given.
+    pub output: Option<PathBuf>,
+    pub target: Option<GmlVersion>,
+    pub strict: bool,
+    /// Declaration files loaded before anything else.
+    pub declarations: Vec<String>,
+    pub indent_width: Option<usize>,
+    pub max_width: Option<usize>,
+    pub lints: Vec<(String, LintLevel)>,
+    /// Per-folder settings, later ones winning.
+    pub overrides: Vec<Override>,
+}
+
+impl Manifest {
+    /// Looks for a `gmpp.toml` in `start` and then in every folder above it.
+    pub fn find(start: &Path) -> Result<Option<Manifest>, Vec<String>> {
+        let start = std::fs::canonicalize(start).unwrap_or(start.to_path_buf());
+
+        for folder in start.ancestors() {
+            let path = folder.join(MANIFEST_NAME);
+            if path.is_file() {
+                return Manifest::open(&path).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads a manifest, returning every invalid key with its line.
+    pub fn open(path: &Path) -> Result<Manifest, Vec<String>> {
+        let name = path.display().to_string();
+        let text = std::fs::read_to_string(path).map_err(|err| vec![format!("{name}: {err}")])?;
+        let table = toml::parse(&text).map_err(|error| vec![format!("{name}:{error}")])?;
+
+        let mut reader = Reader {
+            file: name,
+            root: std::fs::canonicalize(path.parent().unwrap_or(Path::new(".")))
+                .unwrap_or_default(),
+            errors: Vec::new(),
+        };
+        let manifest = reader.manifest(&table);
+
+        if reader.errors.is_empty() {
+            Ok(manifest)
+        } else {
+            Err(reader.errors)
+        }
+    }
+
+    /// Whether strict mode applies to `file`.
+    pub fn strict_for(&self, file: &Path) -> bool {
+        self.overrides_for(file)
+            .find_map(|folder| folder.strict)
+            .unwrap_or(self.strict)
+    }
+
+    /// The level set for a lint in `file`, if the manifest sets one.
+    pub fn lint_level(&self, file: &Path, lint: &str) -> Option<LintLevel> {
+        let overridden = self
+            .overrides_for(file)
+            .flat_map(|folder| &folder.lints)
+            .chain(&self.lints);
+
+        overridden
+            .filter(|(name, _)| name == lint)
+            .map(|(_, level)| *level)
+            .next()
+    }
+
+    /// Overrides whose folder holds `file`, the last one first.
+    fn overrides_for(&self, file: &Path) -> impl Iterator<Item = &Override> {
+        let file = std::fs::canonicalize(file).unwrap_or(file.to_path_buf());
+        self.overrides
+            .iter()
+            .rev()
+            .filter(move |folder| file.starts_with(&folder.path))
+    }
+
+    /// Every `.gmpp` file under the source folders.
+    pub fn source_files(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for source in &self.sources {
+            find_sources(source, &mut paths);
+        }
+
+        paths.retain(|path| {
+            !path.starts_with(self.root.join(crate::project::CACHE_DIR))
+                && self
+                    .output
+                    .as_ref()
+                    .is_none_or(|output| !path.starts_with(output))
+        });
+        paths
+    }
+
+    /// Where the GML of a source goes, keeping its place under its source folder.
+    pub fn output_path(&self, source: &Path) -> PathBuf {
+        let Some(output) = &self.output else {
+            return source.with_extension("gml");
+        };
+
+        let relative = self
+            .sources
+            .iter()
+            .find_map(|folder| source.strip_prefix(folder).ok())
+            .unwrap_or(source.file_name().map(Path::new).unwrap_or(source));
+        output.join(relative).with_extension("gml")
+    }
+}
+
+/// Finds `.gmpp` sources under a folder, or takes the path itself when it is
+/// a file. Declaration files are left out.
+pub fn find_sources(path: &Path, paths: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        paths.push(path.to_path_buf());
+        return;
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            find_sources(&entry, paths);
+        } else if entry
+            .extension()
+            .is_some_and(|extension| extension == "gmpp")
+            && !entry.to_string_lossy().ends_with(".d.gmpp")
+        {
+            paths.push(entry);
+        }
+    }
+}
+
+/// Compiles the `.gmpp` files under the source folders of the manifest,
+/// writing their GML to the output folder. Polyfills go to a single
+/// `__gmpp_polyfills.gml`.
+///
+/// Returns the sources whose `.gml` changed, or every error found.
+pub fn build_sources(
+    manifest: &Manifest,
+    options: &CodegenOptions,
+    declarations: &HashMap<String, Type>,
+    database: &mut Database,
+) -> Result<Vec<PathBuf>, Vec<Error>> {
+    let entries = source_entries(manifest);
+    let (outputs, polyfills) =
+        database.build(&entries, &manifest.sources, None, declarations, options)?;
+
+    let mut compiled = Vec::new();
+    let mut errors = Vec::new();
+
+    for module in outputs {
+        if module.language == Language::Gml {
+            continue;
+        }
+
+        let output = manifest.output_path(&module.path);
+        let written = write_if_changed(&output, &module.gml).and_then(|written| {
+            write_map(&output, &module_map(&output, &module.path, &module.lines))?;
+            Ok(written)
+        });
+        match written {
+            Ok(true) => compiled.push(module.path),
+            Ok(false) => {}
+            Err(error) => errors.push(Error::new(error)),
+        }
+    }
+
+    if !polyfills.is_empty() {
+        let folder = manifest.output.as_ref().unwrap_or(&manifest.root);
+        if let Err(error) = write_if_changed(&folder.join(POLYFILLS_FILE), &polyfills) {
+            errors.push(Error::new(error));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(compiled)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the `.gmpp` files under the source folders without writing anything.
+pub fn check_sources(
+    manifest: &Manifest,
+    declarations: &HashMap<String, Type>,
+    database: &mut Database,
+) -> Result<(), Vec<Error>> {
+    database.check(
+        &source_entries(manifest),
+        &manifest.sources,
+        None,
+        declarations,
+    )
+}
+
+fn source_entries(manifest: &Manifest) -> Vec<(PathBuf, ModuleKind)> {
+    manifest
+        .source_files()
+        .into_iter()
+        .map(|path| (path, ModuleKind::Script))
+        .collect()
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> Result<bool, String> {
+    if std::fs::read_to_string(path).is_ok_and(|old| old == contents) {
+        return Ok(false);
+    }
+
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder).map_err(|err| format!("{}: {err}", folder.display()))?;
+    }
+    std::fs::write(path, contents).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(true)
+}
+
+/// Turns the TOML tables into a manifest, collecting an error for every key
+/// that is unknown or has the wrong type.
+struct Reader {
+    file: String,
+    root: PathBuf,
+    errors: Vec<String>,
+}
+
+impl Reader {
+    fn manifest(&mut self, table: &Table) -> Manifest {
+        let mut manifest = Manifest {
+            root: self.root.clone(),
+            sources: vec![self.root.clone()],
+            output: None,
+            target: None,
+            strict: false,
+            declarations: Vec::new(),
+            indent_width: None,
+            max_width: None,
+            lints: Vec::new(),
+            overrides: Vec::new(),
+        };
+
+        for entry in &table.entries {
+            match entry.key.as_str() {
+                "target" => {
+                    manifest.target = self.string(entry, "target").and_then(|name| {
+                        GmlVersion::from_name(&name)
+                            .map_err(|error| self.invalid(entry, "target", &error))
+                            .ok()
+                    })
+                }
+                "strict" => manifest.strict = self.bool(entry, "strict").unwrap_or(false),
+                "sources" => {
+                    if let Some(folders) = self.strings(entry, "sources") {
+                        manifest.sources = folders.iter().map(|folder| self.path(folder)).collect();
+                    }
+                }
+                "output" => {
+                    manifest.output = self
+                        .string(entry, "output")
+                        .map(|folder| self.path(&folder))
+                }
+                "declarations" => {
+                    manifest.declarations = self
+                        .strings(entry, "declarations")
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|file| self.path(file).display().to_string())
+                        .collect()
+                }
+                "format" => {
+                    for entry in self.table(entry, "format") {
+                        match entry.key.as_str() {
+                            "indent_width" => {
+                                manifest.indent_width = self.number(entry, "format.indent_width")
+                            }
+                            "max_width" => {
+                                manifest.max_width = self.number(entry, "format.max_width")
+                            }
+                            _ => self.unknown(entry, &format!("format.{}", entry.key)),
+                        }
+                    }
+                }
+                "lints" => manifest.lints = self.lints(entry, "lints"),
+                "overrides" => manifest.overrides = self.overrides(entry),
+                _ => self.unknown(entry, &entry.key),
+            }
+        }
+
+        manifest
+    }
+
+    fn overrides(&mut self, entry: &Entry) -> Vec<Override> {
+        let Value::Array(items) = &entry.value else {
+            self.error(
+                entry,
+                "overrides",
+                "should be an array of tables, as [[overrides]]",
+            );
+            return Vec::new();
+        };
+
+        let mut overrides = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            let name = format!("overrides[{index}]");
+            let Value::Table(table) = item else {
+                self.error(entry, &name, "should be a table");
+                continue;
+            };
+
+            let mut folder = Override {
+                path: PathBuf::new(),
+                strict: None,
+                lints: Vec::new(),
+            };
+            let mut has_path = false;
+
+            for entry in &table.entries {
+                let key = format!("{name}.{}", entry.key);
+                match entry.key.as_str() {
+                    "path" => {
+                        if let Some(path) = self.string(entry, &key) {
+                            let path = self.path(&path);
+                            folder.path = std::fs::canonicalize(&path).unwrap_or(path);
+                            has_path = true;
+                        }
+                    }
+                    "strict" => folder.strict = self.bool(entry, &key),
+                    "lints" => folder.lints = self.lints(entry, &key),
+                    _ => self.unknown(entry, &key),
+                }
+            }
+
+            if has_path {
+                overrides.push(folder);
+            } else {
+                self.error(entry, &name, "needs a path");
+            }
+        }
+
+        overrides
+    }
+
+    fn lints(&mut self, entry: &Entry, key: &str) -> Vec<(String, LintLevel)> {
+        let mut lints = Vec::new();
+
+        for entry in self.table(entry, key) {
+            let name = format!("{key}.{}", entry.key);
+            let Some(lint) = find_lint(&entry.key) else {
+                self.invalid(entry, &name, &format!("Unknown lint '{}'", entry.key));
+                continue;
+            };
+            if let Some(level) = self.string(entry, &name) {
+                match LintLevel::from_name(&level) {
+                    Ok(level) => lints.push((lint.name.to_string(), level)),
+                    Err(error) => self.invalid(entry, &name, &error),
+                }
+            }
+        }
+
+        lints
+    }
+
+    fn path(&self, relative: &str) -> PathBuf {
+        let path = self.root.join(relative);
+        std::fs::canonicalize(&path).unwrap_or(path)
+    }
+
+    fn error(&mut self, entry: &Entry, key: &str, message: &str) {
+        self.errors
+            .push(format!("{}:{}: '{key}' {message}", self.file, entry.line));
+    }
+
+    fn invalid(&mut self, entry: &Entry, key: &str, error: &str) {
+        self.error(entry, key, &format!("is invalid: {error}"));
+    }
+
+    fn unknown(&mut self, entry: &Entry, key: &str) {
+        self.errors
+            .push(format!("{}:{}: unknown key '{key}'", self.file, entry.line));
+    }
+
+    fn expected(&mut self, entry: &Entry, key: &str, kind: &str) {
+        let message = format!("should be {kind}, found {}", entry.value.kind());
+        self.error(entry, key, &message);
+    }
+
+    fn string(&mut self, entry: &Entry, key: &str) -> Option<String> {
+        match &entry.value {
+            Value::String(text) => Some(text.clone()),
+            _ => {
+                self.expected(entry, key, "a string");
+                None
+            }
+        }
+    }
+
+    fn bool(&mut self, entry: &Entry, key: &str) -> Option<bool> {
+        match &entry.value {
+            Value::Bool(value) => Some(*value),
+            _ => {
+                self.expected(entry, key, "a boolean");
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, entry: &Entry, key: &str) -> Option<usize> {
+        match &entry.value {
+            Value::Integer(number) if *number > 0 => Some(*number as usize),
+            Value::Integer(_) => {
+                self.error(entry, key, "should be a positive number");
+                None
+            }
+            _ => {
+                self.expected(entry, key, "a number");
+                None
+            }
+        }
+    }
+
+    fn strings(&mut self, entry: &Entry, key: &str) -> Option<Vec<String>> {
+        match &entry.value {
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(text) => Some(text.clone()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<String>>>()
+                .or_else(|| {
+                    self.expected(entry, key, "an array of strings");
+                    None
+                }),
+            _ => {
+                self.expected(entry, key, "an array of strings");
+                None
+            }
+        }
+    }
+
+    fn table<'a>(&mut self, entry: &'a Entry, key: &str) -> &'a [Entry] {
+        match &entry.value {
+            Value::Table(table) => &table.entries,
+            _ => {
+                self.expected(entry, key, "a table");
+                &[]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens a `gmpp.toml` holding `text`, in a folder of its own.
+    fn open(name: &str, text: &str) -> Result<Manifest, Vec<String>> {
+        let folder = std::env::temp_dir().join(format!("gmpp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join(MANIFEST_NAME), text).unwrap();
+        Manifest::open(&folder.join(MANIFEST_NAME)).map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| error.replace(&folder.display().to_string(), ""))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn reads_settings() {
+        let manifest = open(
+            "manifest",
+            "target = \"2023-lts\"\nstrict = true\n\n[format]\nmax_width = 80\n\n[lints]\nself-assignment = \"deny\"\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.target, Some(GmlVersion::Lts2023));
+        assert!(manifest.strict);
+        assert_eq!(manifest.max_width, Some(80));
+        assert_eq!(
+            manifest.lints,
+            [(String::from("self-assignment"), LintLevel::Deny)]
+        );
+    }
+
+    #[test]
+    fn every_invalid_key_is_reported_with_its_line() {
+        let text = "\
+target = \"2.4\"
+strict = \"yes\"
+colour = true
+
+[format]
+indent_width = \"four\"
+tabs = true
+
+[lints]
+self-assignment = \"deny\"
+no-such-lint = \"warn\"
+unused-variable = \"loud\"
+
+[[overrides]]
+strict = true
+";
+        assert_eq!(
+            open("invalid-manifest", text).err().unwrap(),
+            [
+                "/gmpp.toml:1: 'target' is invalid: Unknown GameMaker target '2.4', expected one of: 2.2, 2.3, 2023-lts, latest",
+                "/gmpp.toml:2: 'strict' should be a boolean, found a string",
+                "/gmpp.toml:3: unknown key 'colour'",
+                "/gmpp.toml:6: 'format.indent_width' should be a number, found a string",
+                "/gmpp.toml:7: unknown key 'format.tabs'",
+                "/gmpp.toml:11: 'lints.no-such-lint' is invalid: Unknown lint 'no-such-lint'",
+                "/gmpp.toml:12: 'lints.unused-variable' is invalid: Unknown lint level 'loud', expected one of: allow, warn, deny",
+                "/gmpp.toml:14: 'overrides[0]' needs a path",
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_have_their_line() {
+        assert_eq!(
+            open("broken-manifest", "strict = true\ntarget = \n")
+                .err()
+                .unwrap(),
+            ["/gmpp.toml:2: expected a value"]
+        );
+    }
+}
@@ -0,0 +1,388 @@
+/// A TOML value. Only what a manifest needs is supported: strings, integers,
+/// booleans, arrays and tables, with no dates or floats.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+/// A key of a table, with the line it was written on for error messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub entries: Vec<Entry>,
+}
+
+impl Value {
+    /// How the type of the value is named in error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "a number",
+            Value::Bool(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+impl Table {
+    fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.key == key)
+            .map(|entry| &mut entry.value)
+    }
+}
+
+/// Parses a TOML document. Errors are prefixed by their line number.
+pub fn parse(text: &str) -> Result<Table, String> {
+    let mut parser = TomlParser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::default();
+    // Keys of the table the next entries go to.
+    let mut current: Vec<String> = Vec::new();
+
+    loop {
+        parser.skip_blank();
+        let Some(char) = parser.peek() else {
+            return Ok(root);
+        };
+        let line = parser.line;
+
+        if char == '[' {
+            parser.pos += 1;
+            let array = parser.peek() == Some('[');
+            if array {
+                parser.pos += 1;
+            }
+
+            current = parser.key()?;
+            parser.expect(']')?;
+            if array {
+                parser.expect(']')?;
+            }
+            parser.end_of_line()?;
+
+            let (last, parents) = current.split_last().unwrap();
+            let parent = table_at(&mut root, parents, line)?;
+            match (parent.get_mut(last), array) {
+                (None, false) => parent.entries.push(Entry {
+                    key: last.clone(),
+                    value: Value::Table(Table::default()),
+                    line,
+                }),
+                (None, true) => parent.entries.push(Entry {
+                    key: last.clone(),
+                    value: Value::Array(vec![Value::Table(Table::default())]),
+                    line,
+                }),
+                (Some(Value::Array(tables)), true) => tables.push(Value::Table(Table::default())),
+                _ => return Err(format!("{line}: '{}' is defined twice", current.join("."))),
+            }
+            continue;
+        }
+
+        let key = parser.key()?;
+        parser.expect('=')?;
+        let value = parser.value()?;
+        parser.end_of_line()?;
+
+        let (last, parents) = key.split_last().unwrap();
+        let path: Vec<String> = current.iter().chain(parents).cloned().collect();
+        let table = table_at(&mut root, &path, line)?;
+        if table.get_mut(last).is_some() {
+            return Err(format!("{line}: '{}' is defined twice", key.join(".")));
+        }
+        table.entries.push(Entry {
+            key: last.clone(),
+            value,
+            line,
+        });
+    }
+}
+
+/// The table under `path`, creating the missing ones. Arrays of tables lead
+/// to their last table.
+fn table_at<'a>(
+    table: &'a mut Table,
+    path: &[String],
+    line: usize,
+) -> Result<&'a mut Table, String> {
+    let Some((first, rest)) = path.split_first() else {
+        return Ok(table);
+    };
+
+    if table.get_mut(first).is_none() {
+        table.entries.push(Entry {
+            key: first.clone(),
+            value: Value::Table(Table::default()),
+            line,
+        });
+    }
+
+    match table.get_mut(first).unwrap() {
+        Value::Table(inner) => table_at(inner, rest, line),
+        Value::Array(items) => match items.last_mut() {
+            Some(Value::Table(inner)) => table_at(inner, rest, line),
+            _ => Err(format!("{line}: '{first}' isn't a table")),
+        },
+        _ => Err(format!("{line}: '{first}' isn't a table")),
+    }
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.pos += 1;
+        if char == '\n' {
+            self.line += 1;
+        }
+        Some(char)
+    }
+
+    /// Skips spaces and tabs on the current line.
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.advance();
+                }
+                Some('#') => {
+                    while self.peek().is_some_and(|char| char != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(char) if char == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(char) => Err(format!(
+                "{}: expected '{expected}', found '{char}'",
+                self.line
+            )),
+            None => Err(format!(
+                "{}: expected '{expected}', found the end of the file",
+                self.line
+            )),
+        }
+    }
+
+    /// Only a comment may follow a value or a table header on its line.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            None | Some('\n' | '\r' | '#') => Ok(()),
+            Some(char) => Err(format!(
+                "{}: expected the end of the line, found '{char}'",
+                self.line
+            )),
+        }
+    }
+
+    /// A key, dotted keys being split into their parts.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"' | '\'') => self.string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|char| char.is_ascii_alphanumeric() || "_-".contains(char))
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(format!("{}: expected a key", self.line));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+
+        match self.peek() {
+            Some('"' | '\'') => Ok(Value::String(self.string()?)),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(items));
+                    }
+
+                    items.push(self.value()?);
+
+                    self.skip_blank();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err(format!("{}: expected ',' or ']' in an array", self.line)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut table = Table::default();
+
+                self.skip_spaces();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Value::Table(table));
+                }
+
+                loop {
+                    let line = self.line;
+                    let key = self.key()?;
+                    self.expect('=')?;
+                    let value = self.value()?;
+
+                    let (last, parents) = key.split_last().unwrap();
+                    let inner = table_at(&mut table, parents, line)?;
+                    if inner.get_mut(last).is_some() {
+                        return Err(format!("{line}: '{}' is defined twice", key.join(".")));
+                    }
+                    inner.entries.push(Entry {
+                        key: last.clone(),
+                        value,
+                        line,
+                    });
+
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {
+                            self.pos += 1;
+                            return Ok(Value::Table(table));
+                        }
+                        _ => {
+                            return Err(format!(
+                                "{}: expected ',' or '}}' in an inline table",
+                                self.line
+                            ))
+                        }
+                    }
+                }
+            }
+            Some(char) if char == '-' || char == '+' || char.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|char| char.is_ascii_alphanumeric() || "+-_".contains(char))
+                {
+                    self.pos += 1;
+                }
+
+                let number: String = self.chars[start..self.pos]
+                    .iter()
+                    .filter(|char| **char != '_')
+                    .collect();
+                number
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| format!("{}: invalid number '{number}'", self.line))
+            }
+            Some(char) if char.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|char| char.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(format!(
+                        "{}: invalid value '{word}', strings need quotes",
+                        self.line
+                    )),
+                }
+            }
+            _ => Err(format!("{}: expected a value", self.line)),
+        }
+    }
+
+    /// A basic `"..."` string with escapes, or a literal `'...'` one.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.advance().unwrap();
+        let mut text = String::new();
+
+        loop {
+            let char = match self.advance() {
+                Some('\n') | None => return Err(format!("{}: unterminated string", self.line)),
+                Some(char) => char,
+            };
+
+            match char {
+                char if char == quote => return Ok(text),
+                '\\' if quote == '"' => {
+                    let escaped = self
+                        .advance()
+                        .ok_or(format!("{}: unterminated string", self.line))?;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        't' => text.push('\t'),
+                        'r' => text.push('\r'),
+                        '"' | '\\' => text.push(escaped),
+                        other => return Err(format!("{}: invalid escape '\\{other}'", self.line)),
+                    }
+                }
+                char => text.push(char),
+            }
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use crate::compiler::checker::Type;
+use crate::compiler::codegen::CodegenOptions;
+use crate::compiler::driver::ModuleKind;
+use crate::compiler::error::Error;
+use crate::compiler::incremental::Database;
+use crate::compiler::source_map::{module_map, write_map};
+use crate::compiler::tokenizer::Language;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const POLYFILLS_SCRIPT: &str = "__gmpp_polyfills";
+/// Folder of the project where builds keep what they can reuse.
+pub const CACHE_DIR: &str = ".gmpp-cache";
+
+/// A `.gmpp` file found inside a GameMaker project, next to the resource it
+/// provides code for.
+pub enum ProjectSource {
+    /// `scripts/<name>/<name>.gmpp`
+    Script(String, PathBuf),
+    /// `objects/<object>/<Event>_<num>.gmpp`
+    ObjectEvent(String, String, PathBuf),
+    /// `scripts/<name>/<name>.gml` with no `.gmpp` next to it. It is only read,
+    /// so the rest of the project can call into it.
+    GmlScript(String, PathBuf),
+}
+
+impl ProjectSource {
+    pub fn path(&self) -> &Path {
+        match self {
+            ProjectSource::Script(_, path)
+            | ProjectSource::ObjectEvent(_, _, path)
+            | ProjectSource::GmlScript(_, path) => path,
+        }
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        self.path().with_extension("gml")
+    }
+}
+
+pub struct Project {
+    pub name: String,
+    pub yyp_path: PathBuf,
+    pub root: PathBuf,
+}
+
+impl Project {
+    pub fn open(yyp_path: &str) -> Result<Project, String> {
+        let yyp_path = PathBuf::from(yyp_path);
+
+        if !yyp_path.is_file() {
+            return Err(format!(
+                "Couldn't find the project file: {}",
+                yyp_path.display()
+            ));
+        }
+
+        let name = yyp_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or(format!("Invalid project file name: {}", yyp_path.display()))?;
+        let root = yyp_path
+            .parent()
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_default();
+
+        Ok(Project {
+            name,
+            yyp_path,
+            root,
+        })
+    }
+
+    /// Declaration files (`.d.gmpp`) at the root of the project, describing
+    /// the extensions it uses.
+    pub fn find_declarations(&self) -> Vec<String> {
+        let mut files: Vec<String> = std::fs::read_dir(&self.root)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path().display().to_string())
+                    .filter(|path| path.ends_with(".d.gmpp"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        files.sort();
+        files
+    }
+
+    pub fn find_sources(&self) -> Result<Vec<ProjectSource>, String> {
+        let mut sources = Vec::new();
+
+        for (folder, files) in list_source_files(&self.root.join("scripts"), "gmpp")? {
+            for file in files {
+                sources.push(ProjectSource::Script(folder.clone(), file));
+            }
+        }
+
+        for (folder, files) in list_source_files(&self.root.join("scripts"), "gml")? {
+            for file in files {
+                if !file.with_extension("gmpp").exists() {
+                    sources.push(ProjectSource::GmlScript(folder.clone(), file));
+                }
+            }
+        }
+
+        for (object, files) in list_source_files(&self.root.join("objects"), "gmpp")? {
+            for file in files {
+                let event = file.file_stem().unwrap().to_string_lossy().to_string();
+                sources.push(ProjectSource::ObjectEvent(object.clone(), event, file));
+            }
+        }
+
+        Ok(sources)
+    }
+}
+
+/// Returns every resource folder under `dir` together with the files with the
+/// given extension it contains.
+fn list_source_files(dir: &Path, extension: &str) -> Result<Vec<(String, Vec<PathBuf>)>, String> {
+    let mut folders = Vec::new();
+
+    if !dir.is_dir() {
+        return Ok(folders);
+    }
+
+    let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+
+    for entry in entries.flatten() {
+        let folder = entry.path();
+        if !folder.is_dir() {
+            continue;
+        }
+
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&folder)
+            .map_err(|err| format!("{}: {err}", folder.display()))?
+            .flatten()
+            .map(|file| file.path())
+            .filter(|file| file.extension().is_some_and(|ext| ext == extension))
+            .filter(|file| !file.to_string_lossy().ends_with(".d.gmpp"))
+            .collect();
+
+        if files.is_empty() {
+            continue;
+        }
+
+        files.sort();
+        folders.push((entry.file_name().to_string_lossy().to_string(), files));
+    }
+
+    folders.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(folders)
+}
+
+/// Compiles every `.gmpp` source of the project, writing the `.gml` files in
+/// place and registering new resources so the IDE picks them up. Polyfills
+/// needed by the target go to a single `__gmpp_polyfills` script.
+///
+/// Work done by earlier builds is reused from `database`, so only the files
+/// that changed and those importing them are checked again.
+///
+/// Returns the sources whose `.gml` changed, or every error found, prefixed by
+/// its file.
+pub fn build_project(
+    project: &Project,
+    options: &CodegenOptions,
+    declarations: &HashMap<String, Type>,
+    database: &mut Database,
+) -> Result<Vec<PathBuf>, Vec<Error>> {
+    let sources = project
+        .find_sources()
+        .map_err(|err| vec![Error::new(err)])?;
+    let (outputs, polyfills) = database.build(
+        &project_entries(&sources),
+        &[project.root.join("scripts")],
+        None,
+        declarations,
+        options,
+    )?;
+    let mut compiled = Vec::new();
+    let mut errors = Vec::new();
+
+    for module in outputs {
+        // Plain GML is already what GameMaker runs, it is left untouched.
+        if module.language == Language::Gml {
+            continue;
+        }
+
+        let source = sources.iter().find(|source| {
+            std::fs::canonicalize(source.path()).is_ok_and(|path| path == module.path)
+        });
+
+        let result = match source {
+            Some(source) => write_output(project, source, module.gml).and_then(|written| {
+                let output = source.output_path();
+                write_map(&output, &module_map(&output, &module.path, &module.lines))?;
+                Ok(written.then(|| source.path().to_path_buf()))
+            }),
+            None => Err(format!(
+                "{} is imported but isn't a script of the project",
+                module.path.display()
+            )),
+        };
+
+        match result {
+            Ok(path) => compiled.extend(path),
+            Err(error) => errors.push(Error::new(error)),
+        }
+    }
+
+    if !polyfills.is_empty() {
+        let polyfill_source = ProjectSource::Script(
+            POLYFILLS_SCRIPT.to_string(),
+            project.root.join(format!(
+                "scripts/{POLYFILLS_SCRIPT}/{POLYFILLS_SCRIPT}.gmpp"
+            )),
+        );
+
+        let folder = project.root.join(format!("scripts/{POLYFILLS_SCRIPT}"));
+        let result = std::fs::create_dir_all(&folder)
+            .map_err(|err| format!("{}: {err}", folder.display()))
+            .and_then(|_| write_output(project, &polyfill_source, polyfills));
+
+        if let Err(error) = result {
+            errors.push(Error::new(error));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(compiled)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks every source of the project without writing anything.
+pub fn check_project(
+    project: &Project,
+    declarations: &HashMap<String, Type>,
+    database: &mut Database,
+) -> Result<(), Vec<Error>> {
+    let sources = project
+        .find_sources()
+        .map_err(|err| vec![Error::new(err)])?;
+    database.check(
+        &project_entries(&sources),
+        &[project.root.join("scripts")],
+        None,
+        declarations,
+    )
+}
+
+fn project_entries(sources: &[ProjectSource]) -> Vec<(PathBuf, ModuleKind)> {
+    sources
+        .iter()
+        .map(|source| match source {
+            ProjectSource::Script(_, path) | ProjectSource::GmlScript(_, path) => {
+                (path.clone(), ModuleKind::Script)
+            }
+            ProjectSource::ObjectEvent(_, _, path) => (path.clone(), ModuleKind::Event),
+        })
+        .collect()
+}
+
+/// Writes the GML of a source unless it is already up to date, so the IDE
+/// only reloads what changed. Returns whether the file was written.
+fn write_output(project: &Project, source: &ProjectSource, gml: String) -> Result<bool, String> {
+    let output_path = source.output_path();
+    let written = !std::fs::read_to_string(&output_path).is_ok_and(|old| old == gml);
+    if written {
+        std::fs::write(&output_path, gml)
+            .map_err(|err| format!("{}: {err}", output_path.display()))?;
+    }
+
+    match source {
+        ProjectSource::Script(name, _) => register_script(project, name),
+        ProjectSource::ObjectEvent(object, event, _) => {
+            register_object_event(project, object, event)
+        }
+        ProjectSource::GmlScript(_, _) => Ok(()),
+    }
+    .map(|_| written)
+}
+
+/// Creates the `.yy` file of a script that only exists as `.gmpp` and adds it
+/// to the resources of the `.yyp`.
+fn register_script(project: &Project, name: &str) -> Result<(), String> {
+    let resource_path = format!("scripts/{name}/{name}.yy");
+    let yy_path = project.root.join(&resource_path);
+
+    if !yy_path.exists() {
+        let metadata = format!(
+            "{{
+  \"isDnD\": false,
+  \"isCompatibility\": false,
+  \"parent\": {{
+    \"name\": \"{project_name}\",
+    \"path\": \"{project_name}.yyp\",
+  }},
+  \"resourceVersion\": \"1.0\",
+  \"name\": \"{name}\",
+  \"tags\": [],
+  \"resourceType\": \"GMScript\",
+}}",
+            project_name = project.name
+        );
+
+        std::fs::write(&yy_path, metadata)
+            .map_err(|err| format!("{}: {err}", yy_path.display()))?;
+    }
+
+    let yyp = std::fs::read_to_string(&project.yyp_path)
+        .map_err(|err| format!("{}: {err}", project.yyp_path.display()))?;
+
+    if yyp.contains(&format!("\"{resource_path}\"")) {
+        return Ok(());
+    }
+
+    let entry = format!("\n    {{\"id\":{{\"name\":\"{name}\",\"path\":\"{resource_path}\",}},}},");
+    let updated = insert_after(&yyp, "\"resources\"", '[', &entry).ok_or(format!(
+        "Couldn't find the resources list in {}",
+        project.yyp_path.display()
+    ))?;
+
+    std::fs::write(&project.yyp_path, updated)
+        .map_err(|err| format!("{}: {err}", project.yyp_path.display()))
+}
+
+/// Adds the event to the object's `eventList` if it isn't there yet.
+fn register_object_event(project: &Project, object: &str, event: &str) -> Result<(), String> {
+    let (event_type, event_num, other) = parse_event_name(event)?;
+    let yy_path = project.root.join(format!("objects/{object}/{object}.yy"));
+
+    let collision_object = match other {
+        Some(other) => {
+            let other_path = format!("objects/{other}/{other}.yy");
+            if !project.root.join(&other_path).is_file() {
+                return Err(format!(
+                    "'{other}' in '{event}' isn't an object of the project, {} doesn't exist",
+                    project.root.join(&other_path).display()
+                ));
+            }
+            format!("{{\"name\":\"{other}\",\"path\":\"{other_path}\",}}")
+        }
+        None => String::from("null"),
+    };
+
+    let yy = std::fs::read_to_string(&yy_path).map_err(|_| {
+        format!(
+            "'{object}' isn't an object of the project, {} doesn't exist",
+            yy_path.display()
+        )
+    })?;
+
+    let compact: String = yy.chars().filter(|char| !char.is_whitespace()).collect();
+    let already_registered = match other {
+        Some(other) => compact.contains(&format!(
+            "\"eventNum\":{event_num},\"eventType\":{event_type},\"collisionObjectId\":{{\"name\":\"{other}\","
+        )),
+        None => compact.contains(&format!(
+            "\"eventNum\":{event_num},\"eventType\":{event_type},"
+        )),
+    };
+
+    if already_registered {
+        return Ok(());
+    }
+
+    let entry = format!(
+        "\n    {{\"isDnD\":false,\"eventNum\":{event_num},\"eventType\":{event_type},\"collisionObjectId\":{collision_object},\"resourceVersion\":\"1.0\",\"name\":\"\",\"tags\":[],\"resourceType\":\"GMEvent\",}},"
+    );
+    let updated = insert_after(&yy, "\"eventList\"", '[', &entry).ok_or(format!(
+        "Couldn't find the event list in {}",
+        yy_path.display()
+    ))?;
+
+    std::fs::write(&yy_path, updated).map_err(|err| format!("{}: {err}", yy_path.display()))
+}
+
+/// Maps an event file name like `Step_0` to GameMaker's event type and number.
+/// Collision events are named after the other object, like `Collision_obj_wall`,
+/// which is returned with them.
+pub fn parse_event_name(event: &str) -> Result<(usize, usize, Option<&str>), String> {
+    if let Some(other) = event.strip_prefix("Collision_") {
+        return Ok((4, 0, Some(other)));
+    }
+
+    let (name, num) = event.rsplit_once('_').ok_or(format!(
+        "Invalid event file name '{event}', expected something like 'Step_0'"
+    ))?;
+
+    let event_type = match name {
+        "Create" => 0,
+        "Destroy" => 1,
+        "Alarm" => 2,
+        "Step" => 3,
+        "Keyboard" => 5,
+        "Mouse" => 6,
+        "Other" => 7,
+        "Draw" => 8,
+        "KeyPress" => 9,
+        "KeyRelease" => 10,
+        "CleanUp" => 12,
+        "Gesture" => 13,
+        _ => return Err(format!("Unknown event '{name}' in '{event}'")),
+    };
+
+    let event_num = num
+        .parse()
+        .map_err(|_| format!("Invalid event number '{num}' in '{event}'"))?;
+
+    Ok((event_type, event_num, None))
+}
+
+/// Inserts `text` right after the first `open` that follows `key`.
+fn insert_after(src: &str, key: &str, open: char, text: &str) -> Option<String> {
+    let key_index = src.find(key)?;
+    let open_index = key_index + src[key_index..].find(open)?;
+
+    let mut updated = src.to_string();
+    updated.insert_str(open_index + 1, text);
+
+    Some(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project of its own in the temporary directory, with `objects`.
+    fn project(name: &str, objects: &[&str]) -> Project {
+        let root = std::env::temp_dir().join(format!("gmpp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("scripts/player")).unwrap();
+        std::fs::write(
+            root.join("game.yyp"),
+            "{\n  \"resources\": [\n  ],\n  \"name\": \"game\",\n}",
+        )
+        .unwrap();
+        for object in objects {
+            std::fs::create_dir_all(root.join(format!("objects/{object}"))).unwrap();
+            std::fs::write(
+                root.join(format!("objects/{object}/{object}.yy")),
+                format!("{{\n  \"eventList\": [\n  ],\n  \"name\": \"{object}\",\n}}"),
+            )
+            .unwrap();
+        }
+        Project::open(&root.join("game.yyp").display().to_string()).unwrap()
+    }
+
+    #[test]
+    fn event_names() {
+        assert_eq!(parse_event_name("Step_0"), Ok((3, 0, None)));
+        assert_eq!(parse_event_name("Alarm_11"), Ok((2, 11, None)));
+        assert_eq!(
+            parse_event_name("Collision_obj_wall"),
+            Ok((4, 0, Some("obj_wall")))
+        );
+        assert_eq!(
+            parse_event_name("Jump_0"),
+            Err(String::from("Unknown event 'Jump' in 'Jump_0'"))
+        );
+        assert_eq!(
+            parse_event_name("Step_first"),
+            Err(String::from("Invalid event number 'first' in 'Step_first'"))
+        );
+    }
+
+    #[test]
+    fn scripts_are_added_to_the_project_once() {
+        let project = project("register-script", &[]);
+        register_script(&project, "player").unwrap();
+        register_script(&project, "player").unwrap();
+
+        let yy = std::fs::read_to_string(project.root.join("scripts/player/player.yy")).unwrap();
+        assert!(yy.contains("\"name\": \"player\""));
+        assert!(yy.contains("\"path\": \"game.yyp\""));
+
+        let yyp = std::fs::read_to_string(&project.yyp_path).unwrap();
+        assert_eq!(
+            yyp.matches("{\"id\":{\"name\":\"player\",\"path\":\"scripts/player/player.yy\",},},")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn events_are_added_to_their_object_once() {
+        let project = project("register-event", &["obj_player", "obj_wall"]);
+        for _ in 0..2 {
+            register_object_event(&project, "obj_player", "Step_0").unwrap();
+            register_object_event(&project, "obj_player", "Collision_obj_wall").unwrap();
+        }
+
+        let yy =
+            std::fs::read_to_string(project.root.join("objects/obj_player/obj_player.yy")).unwrap();
+        assert_eq!(
+            yy.matches("\"eventNum\":0,\"eventType\":3,\"collisionObjectId\":null,")
+                .count(),
+            1
+        );
+        assert_eq!(
+            yy.matches("\"eventNum\":0,\"eventType\":4,\"collisionObjectId\":{\"name\":\"obj_wall\",\"path\":\"objects/obj_wall/obj_wall.yy\",},")
+                .count(),
+            1
+        );
+
+        let error = register_object_event(&project, "obj_player", "Collision_obj_ghost");
+        assert!(error.is_err_and(|error| error
+            .starts_with("'obj_ghost' in 'Collision_obj_ghost' isn't an object of the project")));
+    }
+}
@@ -0,0 +1,371 @@
+use crate::compiler::ast::Node;
+use crate::compiler::checker::{analyze, check_module_as, Strictness, Type};
+use crate::compiler::codegen::{generate_mapped, generate_polyfills, CodegenOptions};
+use crate::compiler::error::Error;
+use crate::compiler::parser::{parse_expression_only, parse_with_positions, Positions};
+use crate::compiler::tokenizer::{tokenize_as, Language, TokenType};
+use crate::interp::host::StandardHost;
+use crate::interp::Interpreter;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/// The variable an expression is assigned to, to infer its type.
+const VALUE: &str = "__gmpp_value";
+
+pub const HELP: &str = "\
+Code is run as it is entered, and what it declares stays declared. An
+expression prints its value and type, and input goes on over several lines
+while braces, a string or a comment are left open.
+
+Commands:
+  :type <expr>    Print the type of an expression without running it
+  :ast <code>     Print the syntax tree of some code
+  :gml <code>     Print the GML some code compiles to
+  :load <file>    Run a file, keeping what it declares
+  :help           Print this help
+  :quit           Leave, same as Ctrl-D
+";
+
+/// An interactive session. Every input is checked after the declarations
+/// of the ones before it, then run by the same interpreter, so functions,
+/// classes and globals carry over.
+pub struct Repl {
+    interpreter: Interpreter<StandardHost>,
+    /// The standard declarations, and those of `--declarations` files.
+    declarations: HashMap<String, Type>,
+    /// Top-level declarations of the earlier inputs, checked again before
+    /// each input so it knows their types and class members.
+    history: Vec<Node>,
+    options: CodegenOptions,
+}
+
+impl Repl {
+    pub fn new(declarations: HashMap<String, Type>, options: CodegenOptions) -> Repl {
+        let mut host = StandardHost::new();
+        host.declare(&declarations);
+        host.echo = true;
+
+        Repl {
+            interpreter: Interpreter::new(host),
+            declarations,
+            history: Vec::new(),
+            options,
+        }
+    }
+
+    /// Reads inputs until the end of `input` or `:quit`, prompting for them
+    /// when `interactive`.
+    pub fn run(&mut self, input: impl BufRead, interactive: bool) {
+        if interactive {
+            println!(
+                "gmpp {} REPL, :help lists the commands",
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+
+        let mut pending = String::new();
+        let mut lines = input.lines();
+        loop {
+            if interactive {
+                print!("{}", if pending.is_empty() { "> " } else { "... " });
+                let _ = std::io::stdout().flush();
+            }
+            let Some(Ok(line)) = lines.next() else {
+                break;
+            };
+
+            pending.push_str(&line);
+            pending.push('\n');
+            if is_unfinished(&pending) {
+                continue;
+            }
+            let input = std::mem::take(&mut pending);
+            if !self.input(&input) {
+                return;
+            }
+        }
+
+        if !pending.trim().is_empty() {
+            self.input(&pending);
+        }
+    }
+
+    /// Handles a complete input, code or a command. Returns false once the
+    /// session should end.
+    pub fn input(&mut self, input: &str) -> bool {
+        let input = input.trim();
+        let (command, rest) = match input.strip_prefix(':') {
+            Some(command) => match command.split_once(char::is_whitespace) {
+                Some((command, rest)) => (command, rest.trim()),
+                None => (command, ""),
+            },
+            None => ("", input),
+        };
+
+        let result = match command {
+            "" if rest.is_empty() => Ok(()),
+            "" => self.evaluate(rest, "repl"),
+            "type" => self.print_type(rest),
+            "ast" => parse(rest)
+                .map(|(program, _)| program.display_program(0))
+                .map_err(|error| vec![error]),
+            "gml" => self.print_gml(rest),
+            "load" => self.load(rest),
+            "help" => {
+                print!("{HELP}");
+                Ok(())
+            }
+            "quit" | "q" => return false,
+            _ => Err(vec![Error::new(format!(
+                "Unknown command ':{command}', :help lists them"
+            ))]),
+        };
+
+        if let Err(errors) = result {
+            for error in errors {
+                eprintln!("error: {error}");
+            }
+        }
+        true
+    }
+
+    /// Checks and runs code, printing the value of the expression it ends
+    /// with. `path` is where runtime errors point.
+    fn evaluate(&mut self, code: &str, path: &str) -> Result<(), Vec<Error>> {
+        let (program, positions) = parse(code).map_err(|error| vec![error])?;
+        let Node::Program(mut body) = program else {
+            return Ok(());
+        };
+        self.check(&body)?;
+
+        let last = match body.last() {
+            Some(stmt) if is_expression(stmt) => body.pop(),
+            _ => None,
+        };
+        let kind = last.as_ref().map(|expr| self.type_of(&body, expr));
+        self.remember(&body);
+
+        self.interpreter
+            .load(Rc::new(Node::Program(body)), &positions, code, path)
+            .map_err(|error| vec![Error::new(error.to_string())])?;
+
+        if let (Some(expr), Some(kind)) = (last, kind) {
+            let value = self
+                .interpreter
+                .evaluate(Rc::new(*expr))
+                .map_err(|error| vec![Error::new(error.to_string())])?;
+            if kind != Type::Void {
+                println!("{} : {}", value.repr(), kind.name());
+            }
+        }
+        Ok(())
+    }
+
+    fn print_type(&self, code: &str) -> Result<(), Vec<Error>> {
+        let expr = tokenize_as(code.to_string(), Language::GmlPlusPlus)
+            .and_then(parse_expression_only)
+            .map_err(|error| vec![error])?
+            .to_box();
+        self.check(std::slice::from_ref(&expr))?;
+
+        println!("{}", self.type_of(&[], &expr).name());
+        Ok(())
+    }
+
+    fn print_gml(&self, code: &str) -> Result<(), Vec<Error>> {
+        let (program, positions) = parse(code).map_err(|error| vec![error])?;
+        if let Node::Program(body) = &program {
+            self.check(body)?;
+        }
+
+        let (gml, polyfills, _) = generate_mapped(&program, &self.options, &positions)?;
+        print!("{}{gml}", generate_polyfills(&polyfills));
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), Vec<Error>> {
+        if path.is_empty() {
+            return Err(vec![Error::new(":load needs a file")]);
+        }
+        let source = std::fs::read_to_string(path)
+            .map_err(|error| vec![Error::new(error.to_string()).in_file(Path::new(path))])?;
+
+        self.evaluate(&source, path)
+    }
+
+    /// Checks statements after the declarations of the earlier inputs,
+    /// returning the errors found in either.
+    fn check(&self, body: &[Box<Node>]) -> Result<(), Vec<Error>> {
+        let mut program: Vec<Box<Node>> = self.history_without(body).collect();
+        program.extend(body.iter().cloned());
+
+        check_module_as(
+            &Node::Program(program),
+            &Positions::new(),
+            &self.declarations,
+            &HashSet::new(),
+            Strictness::Off,
+        )
+        .0
+        .map(|_| ())
+    }
+
+    /// The type of an expression run after `before`, inferred as the value
+    /// of a variable.
+    fn type_of(&self, before: &[Box<Node>], expr: &Node) -> Type {
+        let mut program: Vec<Box<Node>> = self.history_without(before).collect();
+        program.extend(before.iter().cloned());
+        program.push(
+            Node::VariableDeclaration(
+                Node::DeclarationType(TokenType::Let).to_box(),
+                VALUE.to_string(),
+                None,
+                expr.clone().to_box(),
+            )
+            .to_box(),
+        );
+
+        analyze(
+            &Node::Program(program),
+            &Positions::new(),
+            &self.declarations,
+            Strictness::Off,
+        )
+        .globals
+        .remove(VALUE)
+        .unwrap_or(Type::Untyped)
+    }
+
+    /// Keeps the declarations of an input, in place of the earlier ones of
+    /// the same names.
+    fn remember(&mut self, body: &[Box<Node>]) {
+        self.history = self.history_without(body).map(|stmt| *stmt).collect();
+        self.history.extend(
+            body.iter()
+                .filter(|stmt| {
+                    declared_name(stmt).is_some()
+                        || matches!(
+                            stmt.declaration(),
+                            Node::DeclarationList(_) | Node::GlobalVarDeclaration(_)
+                        )
+                })
+                .map(|stmt| stmt.as_ref().clone()),
+        );
+    }
+
+    /// The declarations of the earlier inputs, without those `body` declares
+    /// again.
+    fn history_without(&self, body: &[Box<Node>]) -> impl Iterator<Item = Box<Node>> + '_ {
+        let names: Vec<String> = body
+            .iter()
+            .filter_map(|stmt| declared_name(stmt).cloned())
+            .collect();
+
+        self.history
+            .iter()
+            .filter(move |stmt| !declared_name(stmt).is_some_and(|name| names.contains(name)))
+            .map(|stmt| stmt.clone().to_box())
+    }
+}
+
+/// Parses code as statements, then as statements missing their last `;`,
+/// or else as a single expression, which statements can't always start with.
+fn parse(code: &str) -> Result<(Node, Positions), Error> {
+    let tokens = tokenize_as(code.to_string(), Language::GmlPlusPlus)?;
+
+    parse_with_positions(tokens.clone())
+        .or_else(|error| {
+            tokenize_as(format!("{code};"), Language::GmlPlusPlus)
+                .and_then(parse_with_positions)
+                .map_err(|_| error)
+        })
+        .or_else(|error| {
+            parse_expression_only(tokens)
+                .map(|expr| (Node::Program(vec![expr.to_box()]), Positions::new()))
+                .map_err(|_| error)
+        })
+}
+
+/// Whether braces, parentheses, brackets, a string or a comment are left
+/// open, so input goes on over the next line. Other mistakes end the input,
+/// to be reported.
+fn is_unfinished(code: &str) -> bool {
+    let tokens = match tokenize_as(code.to_string(), Language::GmlPlusPlus) {
+        Ok(tokens) => tokens,
+        // What the tokenizer says when the code ends inside a string or a
+        // comment.
+        Err(error) => return error.message.starts_with("Unterminated"),
+    };
+
+    let open: i32 = tokens
+        .iter()
+        .map(|token| match token.kind {
+            TokenType::OpenCurly | TokenType::OpenParenthesis | TokenType::OpenBracket => 1,
+            TokenType::CloseCurly | TokenType::CloseParenthesis | TokenType::CloseBracket => -1,
+            _ => 0,
+        })
+        .sum();
+    open > 0
+}
+
+/// Name of a top-level declaration, `var` variables included since they
+/// live as long as the session too.
+fn declared_name(stmt: &Node) -> Option<&String> {
+    match stmt.declaration() {
+        Node::VariableDeclaration(_, name, _, _) => Some(name),
+        declaration => declaration.declared_name(),
+    }
+}
+
+/// Whether a statement is an expression whose value can be printed.
+fn is_expression(stmt: &Node) -> bool {
+    matches!(
+        stmt,
+        Node::BinaryExpression(_, _, _)
+            | Node::NumericLiteral(_, _)
+            | Node::StringLiteral(_)
+            | Node::BooleanLiteral(_)
+            | Node::TemplateString(_)
+            | Node::Identifier(_)
+            | Node::FunctionCall(_, _)
+            | Node::UnaryExpression(_, _)
+            | Node::UpdateExpression(_, _, _)
+            | Node::TernaryExpression(_, _, _)
+            | Node::MemberAccess(_, _)
+            | Node::IndexAccess(_, _, _)
+            | Node::ArrayLiteral(_)
+            | Node::StructLiteral(_)
+            | Node::FunctionExpression(_, _)
+            | Node::ArrowFunction(_, _, _)
+            | Node::NewExpression(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::optimizer::OptLevel;
+    use crate::compiler::target::GmlVersion;
+
+    #[test]
+    fn input_goes_on_while_something_is_open() {
+        assert!(is_unfinished("function f() {\n"));
+        assert!(is_unfinished("let s = \"abc;\n"));
+        assert!(is_unfinished("/* a comment\n"));
+        assert!(!is_unfinished("let s = \"abc\";\n"));
+        assert!(!is_unfinished("let s = 'abc';\n"));
+    }
+
+    #[test]
+    fn mistakes_keep_the_session() {
+        let options = CodegenOptions::new(GmlVersion::Latest, OptLevel::O1);
+        let mut repl = Repl::new(HashMap::new(), options);
+        let inputs = "let a = 1;\nlet b = 'x';\nlet s = \"abc;\n\";\nlet c = a + 1;\n";
+        repl.run(inputs.as_bytes(), false);
+
+        let names: Vec<&String> = repl.history.iter().filter_map(declared_name).collect();
+        assert_eq!(names, ["a", "s", "c"]);
+    }
+}
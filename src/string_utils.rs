@@ -1,13 +1,11 @@
-pub fn is_alphabetic(txt: &String) -> bool {
+pub fn is_alphabetic(txt: &str) -> bool {
     txt.to_lowercase() != txt.to_uppercase()
 }
 
-pub fn is_numeric(txt: &String) -> bool {
+pub fn is_numeric(txt: &str) -> bool {
     txt.parse::<u8>().is_ok()
 }
 
-pub fn char_at(txt: &String, pos: usize) -> char {
-    let chars: Vec<char> = txt.chars().collect();
-
-    chars[pos]
+pub fn char_at(txt: &str, pos: usize) -> char {
+    txt.chars().nth(pos).unwrap()
 }
@@ -0,0 +1,289 @@
+use crate::compiler::source_map::{relative_path, SourceMap};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// The source maps of a folder, by the names GameMaker gives the code of
+/// their `.gml` file in stack traces.
+pub struct SourceMaps {
+    maps: Vec<(PathBuf, SourceMap)>,
+    /// `gml_Script_name`, `gml_GlobalScript_name` or `gml_Object_name_Step_0`
+    /// to the index of the map.
+    frames: HashMap<String, usize>,
+    /// Sources are printed relative to this folder.
+    root: PathBuf,
+}
+
+impl SourceMaps {
+    /// Finds every `.gml.map` under `root`, like the ones `gmpp build` writes.
+    pub fn load(root: &Path) -> Result<SourceMaps, String> {
+        let root =
+            std::fs::canonicalize(root).map_err(|err| format!("{}: {err}", root.display()))?;
+        let mut paths = Vec::new();
+        find_maps(&root, &mut paths);
+
+        let mut maps = SourceMaps {
+            maps: Vec::new(),
+            frames: HashMap::new(),
+            root,
+        };
+        for path in paths {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+            let map =
+                SourceMap::parse(&text).map_err(|err| format!("{}: {err}", path.display()))?;
+            let gml = path.with_extension("");
+            maps.add(gml, map);
+        }
+
+        Ok(maps)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    fn add(&mut self, gml: PathBuf, map: SourceMap) {
+        let index = self.maps.len();
+        let name = gml
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        // Object events are `objects/<object>/<Event>_<n>.gml`.
+        let object = gml
+            .parent()
+            .filter(|folder| {
+                folder
+                    .parent()
+                    .and_then(|objects| objects.file_name())
+                    .is_some_and(|objects| objects == "objects")
+            })
+            .and_then(|folder| folder.file_name());
+        match object {
+            Some(object) => {
+                let object = object.to_string_lossy();
+                self.frames
+                    .insert(format!("gml_Object_{object}_{name}"), index);
+            }
+            None => {
+                self.frames.insert(format!("gml_Script_{name}"), index);
+                self.frames
+                    .insert(format!("gml_GlobalScript_{name}"), index);
+            }
+        }
+
+        // Functions declared by a script run as `gml_Script_<function>`.
+        for function in std::fs::read_to_string(&gml)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.strip_prefix("function "))
+        {
+            let function: String = function
+                .chars()
+                .take_while(|char| char.is_alphanumeric() || *char == '_')
+                .collect();
+            self.frames
+                .entry(format!("gml_Script_{function}"))
+                .or_insert(index);
+        }
+
+        self.maps.push((gml, map));
+    }
+
+    /// The `.gmpp` file and line a line of generated code comes from.
+    pub fn original(&self, frame: &str, line: usize) -> Option<(String, usize)> {
+        let index = self.find(frame)?;
+        let (gml, map) = &self.maps[index];
+        let (source, line) = map.original(line)?;
+
+        let folder = gml.parent().unwrap_or(Path::new(""));
+        let source = normalize(&folder.join(source));
+        Some((relative_path(&self.root, &source), line))
+    }
+
+    /// The map of a frame. Names of functions declared inside others, like
+    /// `gml_Script_anon_..._gml_GlobalScript_scr_player` or
+    /// `gml_Script_update@Player@scr_player`, end with what declares them.
+    fn find(&self, frame: &str) -> Option<usize> {
+        if let Some(index) = self.frames.get(frame) {
+            return Some(*index);
+        }
+
+        if let Some(start) = frame.rfind("gml_GlobalScript_") {
+            if let Some(index) = self.frames.get(&frame[start..]) {
+                return Some(*index);
+            }
+        }
+
+        frame.rsplit('@').find_map(|part| {
+            [
+                part.to_string(),
+                format!("gml_Script_{part}"),
+                format!("gml_GlobalScript_{part}"),
+            ]
+            .iter()
+            .find_map(|name| self.frames.get(name).copied())
+        })
+    }
+}
+
+fn find_maps(folder: &Path, paths: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(folder)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if entry.is_dir() {
+            find_maps(&entry, paths);
+        } else if entry.to_string_lossy().ends_with(".gml.map") {
+            paths.push(entry);
+        }
+    }
+}
+
+/// Removes the `..` and `.` a source path relative to its map leaves.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Rewrites the `gml_... (line N)` frames of a GameMaker error or stack
+/// trace to the `.gmpp` file and line they come from, returning the new log
+/// and how many frames were rewritten. Frames without a map are kept.
+pub fn trace(log: &str, maps: &SourceMaps) -> (String, usize) {
+    const LINE: &str = " (line ";
+    let mut output = String::with_capacity(log.len());
+    let mut rewritten = 0;
+    let mut rest = log;
+
+    while let Some(start) = rest.find(LINE) {
+        let before = &rest[..start];
+        let after = &rest[start + LINE.len()..];
+        let digits = after
+            .find(|char: char| !char.is_ascii_digit())
+            .unwrap_or(after.len());
+
+        let frame_start = before
+            .rfind(|char: char| !(char.is_alphanumeric() || char == '_' || char == '@'))
+            .map_or(0, |i| i + 1);
+        let frame = &before[frame_start..];
+
+        let original = match (after[..digits].parse(), after[digits..].starts_with(')')) {
+            (Ok(line), true) if frame.starts_with("gml_") => maps.original(frame, line),
+            _ => None,
+        };
+
+        match original {
+            Some((source, line)) => {
+                output.push_str(before);
+                output.push_str(&format!(" ({source}:{line})"));
+                rest = &after[digits + 1..];
+                rewritten += 1;
+            }
+            None => {
+                output.push_str(&rest[..start + LINE.len()]);
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    (output, rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a `.gml` file and its map, whose lines all come from `source`.
+    fn write_gml(root: &Path, gml: &str, code: &str, source: &str, lines: &[usize]) {
+        let path = root.join(gml);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, code).unwrap();
+        let mut map = SourceMap::new(&path.file_name().unwrap().to_string_lossy());
+        let table = lines.iter().map(|line| Some((*line, 0))).collect();
+        map.add(source, 0, &table);
+        std::fs::write(root.join(format!("{gml}.map")), map.to_json()).unwrap();
+    }
+
+    /// A game in a folder of its own, with a script and an object event.
+    fn maps(name: &str) -> SourceMaps {
+        let root = std::env::temp_dir().join(format!("gmpp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        write_gml(
+            &root,
+            "scripts/scr_player/scr_player.gml",
+            "function Player() constructor {\n    hp = 10;\n    hurt = function(damage) {\n        hp -= damage;\n    };\n}\n",
+            "scr_player.gmpp",
+            &[0, 1, 4, 5, 6, 7],
+        );
+        write_gml(
+            &root,
+            "objects/obj_game/Step_0.gml",
+            "x += 1;\ny += 1;\n",
+            "Step_0.gmpp",
+            &[2, 3],
+        );
+        SourceMaps::load(&root).unwrap()
+    }
+
+    #[test]
+    fn frames_point_at_the_gmpp_line() {
+        let log = "\
+ERROR in action number 1
+of Step Event0 for object obj_game:
+############################################################################################
+gml_Script_hurt@Player@scr_player (line 4)
+gml_Object_obj_game_Step_0 (line 2)
+gml_Script_unknown (line 7)
+";
+        let (traced, rewritten) = trace(log, &maps("trace-log"));
+        assert_eq!(
+            traced,
+            "\
+ERROR in action number 1
+of Step Event0 for object obj_game:
+############################################################################################
+gml_Script_hurt@Player@scr_player (scripts/scr_player/scr_player.gmpp:6)
+gml_Object_obj_game_Step_0 (objects/obj_game/Step_0.gmpp:4)
+gml_Script_unknown (line 7)
+"
+        );
+        assert_eq!(rewritten, 2);
+    }
+
+    #[test]
+    fn functions_and_global_scripts_find_their_map() {
+        let maps = maps("trace-frames");
+        let expected = Some((String::from("scripts/scr_player/scr_player.gmpp"), 1));
+        assert_eq!(maps.original("gml_Script_Player", 1), expected);
+        assert_eq!(maps.original("gml_GlobalScript_scr_player", 1), expected);
+        assert_eq!(
+            maps.original(
+                "gml_Script_anon_Player_gml_GlobalScript_scr_player_40_gml_GlobalScript_scr_player",
+                1
+            ),
+            expected
+        );
+        assert_eq!(maps.original("gml_Script_scr_enemy", 1), None);
+        assert_eq!(maps.original("gml_Script_Player", 40), None);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::project::Project;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// How often the project is looked at for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long files must stay untouched before building, so an editor saving
+/// several files at once only triggers one build.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// The modification time and size of every file a build reads.
+#[derive(PartialEq)]
+pub struct Snapshot(HashMap<PathBuf, (SystemTime, u64)>);
+
+impl Snapshot {
+    pub fn take(project: &Project) -> Snapshot {
+        let mut paths: Vec<PathBuf> = project
+            .find_sources()
+            .unwrap_or_default()
+            .iter()
+            .map(|source| source.path().to_path_buf())
+            .collect();
+        paths.extend(project.find_declarations().into_iter().map(PathBuf::from));
+
+        let files = paths
+            .into_iter()
+            .filter_map(|path| {
+                let metadata = std::fs::metadata(&path).ok()?;
+                Some((path, (metadata.modified().ok()?, metadata.len())))
+            })
+            .collect();
+
+        Snapshot(files)
+    }
+
+    /// Files added, changed or removed since `self`, sorted.
+    pub fn changes(&self, newer: &Snapshot) -> Vec<PathBuf> {
+        let mut changes: Vec<PathBuf> = newer
+            .0
+            .iter()
+            .filter(|(path, stamp)| self.0.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .chain(
+                self.0
+                    .keys()
+                    .filter(|path| !newer.0.contains_key(*path))
+                    .cloned(),
+            )
+            .collect();
+
+        changes.sort();
+        changes
+    }
+}
+
+/// Polls the project until its files change and then settle, returning the
+/// files that changed and updating `snapshot`.
+pub fn wait_for_changes(project: &Project, snapshot: &mut Snapshot) -> Vec<PathBuf> {
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let mut current = Snapshot::take(project);
+        if current == *snapshot {
+            continue;
+        }
+
+        loop {
+            std::thread::sleep(DEBOUNCE);
+            let settled = Snapshot::take(project);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+
+        let changes = snapshot.changes(&current);
+        *snapshot = current;
+        return changes;
+    }
+}